@@ -1,8 +1,34 @@
-use clap::{Parser, Subcommand};
+mod top;
+
+use clap::{Parser, Subcommand, ValueEnum};
 use colored::Colorize;
-use std::path::PathBuf;
+use notify::{RecursiveMode, Watcher};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 use tracing::info;
+use turbo_core::config::{LanguageLimits, TurboConfig, CONFIG_FILE};
+use turbo_core::models::{
+    FileRequest, JobRequest, JobResult, JobState, JobStatus, Priority, StageResult, StageStatus,
+};
+use turbo_db::TurboDb;
+use turbo_engine::Engine;
+use turbo_pkg::local::TPKG_EXTENSION;
 use turbo_pkg::manager::PackageManager;
+use turbo_pkg::remote::RemoteRepository;
+use turbo_pkg::PackageDefinition;
+use turbo_server::audit::{self, AuditLog};
+
+/// Quiet period after a change before `execute --watch` runs again, so a burst of
+/// writes from one save triggers a single run.
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Most changed lines `execute --watch` prints per stream.
+const MAX_DIFF_LINES: usize = 20;
+
+/// How often `jobs show --wait` polls the job.
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(500);
 
 #[derive(Parser)]
 #[command(name = "turbo")]
@@ -10,24 +36,101 @@ use turbo_pkg::manager::PackageManager;
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+    /// Output format of `execute`, `jobs`, `config`, `pkg list` and `pkg du`
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    output: OutputFormat,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// Colored, human-readable text
+    Text,
+    /// Structured JSON on stdout, for scripts
+    Json,
 }
 
 #[derive(Subcommand)]
 enum Commands {
     /// Start the Turbo Server
-    Start,
-    /// Execute a file
+    Start {
+        /// Single-node dev mode: no root needed (mock sandbox), bundled `sh` runtime, and
+        /// no Redis (implies `--standalone`)
+        #[arg(long)]
+        dev: bool,
+        /// Run without Redis: an in-process queue, with job results kept in SQLite under
+        /// `TURBO_HOME`. Serves only the job and runtime endpoints.
+        #[arg(long)]
+        standalone: bool,
+        /// Port to listen on (overrides `server.port`)
+        #[arg(long)]
+        port: Option<u16>,
+        /// Number of local queue workers, 0 for an API-only node (overrides `server.workers`)
+        #[arg(long)]
+        workers: Option<usize>,
+        /// Run attached to the terminal (the default)
+        #[arg(long, conflicts_with = "daemon")]
+        foreground: bool,
+        /// Run in the background, logging to `TURBO_HOME/turbo.log`
+        #[arg(long)]
+        daemon: bool,
+    },
+    /// Execute a file on a Turbo Server
+    #[command(visible_alias = "exec")]
     Execute {
         /// Language (e.g. python, java)
+        #[arg(short, long = "lang")]
         language: String,
         /// Version
         #[arg(short, long)]
         version: Option<String>,
         /// Path to file
         file: PathBuf,
-        /// Server URL (default: http://localhost:3000)
+        /// File to feed to the program's stdin
+        #[arg(long)]
+        stdin_file: Option<PathBuf>,
+        /// Server URL
         #[arg(long, default_value = "http://localhost:4000")]
         server: String,
+        /// Scheduling priority: low, normal or high
+        #[arg(short, long)]
+        priority: Option<turbo_core::models::Priority>,
+        /// API key for the server's priority policy
+        #[arg(long)]
+        api_key: Option<String>,
+        /// Re-execute whenever the file (or stdin file) changes, showing what changed
+        #[arg(short, long)]
+        watch: bool,
+        /// Run in this process on the configured sandbox and installed runtimes instead of
+        /// on a server; needs the privileges the sandbox backend does
+        #[arg(long, conflicts_with_all = ["priority", "api_key"])]
+        local: bool,
+    },
+    /// Inspect and cancel jobs on a Turbo Server
+    Jobs {
+        #[command(subcommand)]
+        cmd: JobsCommands,
+        /// Server URL
+        #[arg(long, global = true, default_value = "http://localhost:4000")]
+        server: String,
+    },
+    /// Show and edit the configuration
+    Config {
+        #[command(subcommand)]
+        cmd: ConfigCommands,
+    },
+    /// Live dashboard of a Turbo Server's queue, workers, jobs and caches
+    Top {
+        /// Server URL
+        #[arg(long, default_value = "http://localhost:4000")]
+        server: String,
+        /// Seconds between refreshes
+        #[arg(short, long, default_value_t = 2)]
+        interval: u64,
+    },
+    /// Read the audit log of executed jobs
+    Audit {
+        #[command(subcommand)]
+        cmd: AuditCommands,
     },
     /// Package Management
     Pkg {
@@ -39,6 +142,63 @@ enum Commands {
         #[command(subcommand)]
         cmd: CacheCommands,
     },
+    /// Check that this host can run the configured sandbox backend
+    Doctor,
+}
+
+#[derive(Subcommand)]
+enum AuditCommands {
+    /// Write audit entries to stdout, oldest first
+    Export {
+        /// Audit database (default: `audit.path` from the configuration)
+        #[arg(long)]
+        path: Option<PathBuf>,
+        /// Only entries from this long ago onwards, e.g. `30m`, `24h` or `7d`
+        #[arg(long)]
+        since: Option<String>,
+        #[arg(long, value_enum, default_value_t = AuditFormat::Jsonl)]
+        format: AuditFormat,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum AuditFormat {
+    /// One JSON object per line
+    Jsonl,
+    /// Comma-separated values with a header row
+    Csv,
+}
+
+#[derive(Subcommand)]
+enum JobsCommands {
+    /// List recently queued jobs, newest first
+    List {
+        /// Most jobs to show
+        #[arg(short, long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Show a job's state and, once it is done, its result
+    Show {
+        id: String,
+        /// Keep polling, printing each state change, until the job is done. Output is
+        /// only available then, with the result
+        #[arg(short, long)]
+        wait: bool,
+    },
+    /// Cancel a job that no worker has taken yet
+    Cancel { id: String },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommands {
+    /// Print the effective configuration: defaults, then turbo.toml, then TURBO_* variables
+    Show,
+    /// Print one effective value, e.g. `redis.url`
+    Get { key: String },
+    /// Check a value and write it to turbo.toml in the working directory
+    Set { key: String, value: String },
+    /// Print the path of the turbo.toml that is read and written
+    Path,
 }
 
 #[derive(Subcommand)]
@@ -50,12 +210,73 @@ enum PkgCommands {
         /// Version to install (default: latest)
         #[arg(short, long)]
         version: Option<String>,
-        /// Install from local path (not implemented yet)
+        /// Install from a package directory or .tar/.tar.gz archive instead of the repository
         #[arg(long)]
         local: Option<PathBuf>,
+        /// Print what would be installed without installing it
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Remove an installed package
+    Uninstall {
+        /// Name of the package (e.g. python)
+        name: String,
+        /// Version to remove (default: every installed version)
+        #[arg(short, long)]
+        version: Option<String>,
+        /// Remove even if queued jobs need the package, or the queue can't be checked
+        #[arg(long)]
+        force: bool,
     },
     /// List installed packages
     List,
+    /// Remove all but the newest versions of each installed language
+    Prune {
+        /// Versions to keep per language
+        #[arg(long, default_value_t = 1)]
+        keep: usize,
+        /// Print what would be removed without removing it
+        #[arg(long)]
+        dry_run: bool,
+        /// Remove even if queued jobs need the language, or the queue can't be checked
+        #[arg(long)]
+        force: bool,
+    },
+    /// Show the disk usage of each installed runtime
+    Du,
+    /// Check installed runtimes and suggest fixes
+    Doctor {
+        /// Also run each runtime's smoke test in the sandbox
+        #[arg(long)]
+        smoke: bool,
+    },
+    /// Pack a package directory into a .tpkg archive
+    Pack {
+        /// Package directory containing package.yaml
+        dir: PathBuf,
+        /// Output file (default: <name>-<version>.tpkg in the current directory)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Add a .tpkg archive to a registry directory and re-sign its index
+    Publish {
+        /// Archive created by `turbo pkg pack`
+        file: PathBuf,
+        /// Registry directory holding index.json (created if missing)
+        #[arg(long)]
+        registry: PathBuf,
+        /// File containing the hex-encoded Ed25519 signing key
+        #[arg(long)]
+        key_file: PathBuf,
+    },
+    /// Check a package definition in the repository
+    Validate {
+        /// Name of the package (e.g. python)
+        name: String,
+        /// Version to check (default: latest)
+        #[arg(short, long)]
+        version: Option<String>,
+    },
 }
 
 #[derive(Subcommand)]
@@ -85,130 +306,555 @@ async fn main() -> anyhow::Result<()> {
         .unwrap_or_else(|_| std::env::current_dir().unwrap().join("packages"));
 
     match cli.command {
-        Commands::Start => {
-            let server_bin = if let Ok(exe) = std::env::current_exe() {
-                let candidate = exe.parent().unwrap().join("turbo-server");
-                if candidate.exists() {
-                    candidate.to_string_lossy().to_string()
-                } else {
-                    "turbo-server".to_string()
-                }
-            } else {
-                "turbo-server".to_string()
-            };
-
-            // Check if root using 'id -u'
-            let is_root = if let Ok(output) = std::process::Command::new("id").arg("-u").output() {
-                String::from_utf8_lossy(&output.stdout).trim() == "0"
-            } else {
-                false
-            };
-
-            if !is_root {
+        Commands::Start {
+            dev,
+            standalone,
+            port,
+            workers,
+            foreground: _,
+            daemon,
+        } => {
+            // Outside dev mode the sandbox needs root, so rerun this command under sudo
+            if cfg!(unix) && !dev && !turbo_server::dev::is_root() {
                 info!("Turbo Server requires root privileges.");
                 info!("Requesting sudo access to start server...");
 
                 let mut cmd = std::process::Command::new("sudo");
                 // -E preserves environment variables (HOME) so server sees user's home
-                cmd.arg("-E").arg(&server_bin);
+                cmd.arg("-E")
+                    .arg(std::env::current_exe()?)
+                    .args(std::env::args().skip(1));
+                let status = cmd
+                    .status()
+                    .map_err(|e| anyhow::anyhow!("Failed to start server with sudo: {}", e))?;
+                std::process::exit(status.code().unwrap_or(1));
+            }
 
-                match cmd.status() {
-                    Ok(status) => {
-                        if !status.success() {
-                            tracing::error!("Server process exited with code: {:?}", status.code());
-                        }
-                    }
-                    Err(e) => {
-                        tracing::error!("Failed to start server with sudo: {}", e);
-                    }
-                }
+            let mut config = TurboConfig::new()?;
+            if let Some(port) = port {
+                config.server.port = port;
+            }
+            if let Some(workers) = workers {
+                config.server.workers = Some(workers);
+            }
+
+            if daemon {
+                return daemonize(Path::new(&config.paths.turbo_home));
+            }
+
+            info!(
+                "Starting Turbo Server{}...",
+                if dev { " in dev mode" } else { "" }
+            );
+            if dev || standalone {
+                turbo_server::standalone::run(config, dev).await?;
             } else {
-                info!("Starting Turbo Server...");
-                match std::process::Command::new(&server_bin).status() {
-                    Ok(status) => {
-                        if !status.success() {
-                            tracing::error!("Server process exited with code: {:?}", status.code());
-                        }
-                    }
-                    Err(e) => {
-                        tracing::error!("Failed to start server: {}", e);
-                    }
-                }
+                turbo_server::server::run(config).await?;
             }
         }
         Commands::Execute {
             language,
             version,
             file,
+            stdin_file,
             server,
+            priority,
+            api_key,
+            watch,
+            local,
         } => {
-            use turbo_core::models::{FileRequest, JobRequest};
-
-            let content = std::fs::read_to_string(&file)
-                .map_err(|e| anyhow::anyhow!("Failed to read file {:?}: {}", file, e))?;
-
-            let filename = file
-                .file_name()
-                .and_then(|s| s.to_str())
-                .map(|s| s.to_string());
-
-            let req = JobRequest {
-                language,
-                version,
-                files: vec![FileRequest {
-                    name: filename.clone(),
-                    content,
-                    encoding: Some("utf8".to_string()),
-                }],
-                testcases: None, // Interactive/One-shot mode
-                args: Some(vec![filename.clone().unwrap_or("main".to_string())]),
-                stdin: None, // TODO: Read from stdin if needed?
-                run_timeout: None,
-                compile_timeout: None,
-                run_memory_limit: None,
-                compile_memory_limit: None,
+            let executor = if local {
+                Executor::Local(Engine::new(&TurboConfig::new()?).await?)
+            } else {
+                Executor::Server {
+                    client: reqwest::Client::new(),
+                    url: format!("{}/api/v1/execute", server.trim_end_matches('/')),
+                    api_key,
+                }
+            };
+            let request = || {
+                job_request(
+                    &language,
+                    version.as_deref(),
+                    &file,
+                    stdin_file.as_deref(),
+                    priority,
+                )
             };
 
-            let client = reqwest::Client::new();
-            let url = format!("{}/api/v1/execute", server);
-
-            let res = client.post(&url).json(&req).send().await?;
+            if !watch {
+                let result: anyhow::Result<JobResult> =
+                    async { executor.run(request()?).await }.await;
+                match result {
+                    Ok(result) => print_job_result(&result, cli.output)?,
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        std::process::exit(1);
+                    }
+                }
+                return Ok(());
+            }
 
-            if !res.status().is_success() {
-                let err_text = res.text().await?;
-                eprintln!("Execution failed: {}", err_text);
-                std::process::exit(1);
+            // Editors often save by replacing the file, so watch the directories and
+            // match on the paths instead of watching the files themselves
+            let mut watched = vec![std::fs::canonicalize(&file)?];
+            if let Some(path) = &stdin_file {
+                watched.push(std::fs::canonicalize(path)?);
+            }
+            let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+            let paths = watched.clone();
+            let mut watcher =
+                notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                    if let Ok(event) = event {
+                        if event.paths.iter().any(|p| paths.contains(p)) {
+                            let _ = tx.send(());
+                        }
+                    }
+                })?;
+            for path in &watched {
+                if let Some(dir) = path.parent() {
+                    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+                }
             }
 
-            let job_result: turbo_core::models::JobResult = res.json().await?;
+            let mut previous: Option<JobResult> = None;
+            let mut runs = 0;
+            loop {
+                runs += 1;
+                // Banners go to stderr, so `--output json` leaves one result per run on stdout
+                eprintln!("{}", format!("── Run {} ──", runs).bold());
+                let result: anyhow::Result<JobResult> =
+                    async { executor.run(request()?).await }.await;
+                match result {
+                    Ok(result) => {
+                        print_job_result(&result, cli.output)?;
+                        if cli.output == OutputFormat::Text {
+                            if let Some(previous) = &previous {
+                                print_changes(previous, &result);
+                            }
+                        }
+                        previous = Some(result);
+                    }
+                    Err(e) => eprintln!("{}", e.to_string().red()),
+                }
+                eprintln!("{}", "Watching for changes (Ctrl-C to stop)...".dimmed());
 
-            if let Some(compile) = job_result.compile {
-                if compile.status != turbo_core::models::StageStatus::Success {
-                    println!("{}", "Compilation Failed".red().bold());
-                    println!("{}", compile); // StageResult implements Display
-                    return Ok(());
+                if rx.recv().await.is_none() {
+                    break;
                 }
+                tokio::time::sleep(WATCH_DEBOUNCE).await;
+                while rx.try_recv().is_ok() {}
             }
+        }
+        Commands::Jobs { cmd, server } => {
+            let client = reqwest::Client::new();
+            let jobs_url = format!("{}/api/v1/jobs", server.trim_end_matches('/'));
+            match cmd {
+                JobsCommands::List { limit } => {
+                    let res = client
+                        .get(&jobs_url)
+                        .query(&[("limit", limit)])
+                        .send()
+                        .await?;
+                    let jobs: Vec<JobStatus> = check_response(res).await?.json().await?;
+                    if cli.output == OutputFormat::Json {
+                        println!("{}", serde_json::to_string_pretty(&jobs)?);
+                    } else if jobs.is_empty() {
+                        println!("No recent jobs.");
+                    } else {
+                        for job in jobs {
+                            let queued = job.queued_at.map(format_age).unwrap_or_default();
+                            println!(
+                                "  {}  {:<9}  {:<20}  {}",
+                                job.id,
+                                colored_state(job.state),
+                                job.worker.as_deref().unwrap_or("-"),
+                                queued
+                            );
+                        }
+                    }
+                }
+                JobsCommands::Show { id, wait } => {
+                    let url = format!("{}/{}", jobs_url, id);
+                    let mut last_state = None;
+                    loop {
+                        let res = client.get(&url).send().await?;
+                        let status: JobStatus = check_response(res).await?.json().await?;
+                        if !wait || status.state.is_terminal() {
+                            print_job_status(&status, cli.output)?;
+                            break;
+                        }
+                        // Progress goes to stderr, so stdout only has the final status
+                        if last_state != Some(status.state) {
+                            match &status.worker {
+                                Some(worker) if status.state == JobState::Running => {
+                                    eprintln!("{} on {}", colored_state(status.state), worker)
+                                }
+                                _ => eprintln!("{}", colored_state(status.state)),
+                            }
+                            last_state = Some(status.state);
+                        }
+                        tokio::time::sleep(WAIT_POLL_INTERVAL).await;
+                    }
+                }
+                JobsCommands::Cancel { id } => {
+                    let res = client.delete(format!("{}/{}", jobs_url, id)).send().await?;
+                    check_response(res).await?;
+                    println!("Cancelled job {}", id);
+                }
+            }
+        }
+        Commands::Config { cmd } => {
+            let path = std::env::current_dir()?.join(CONFIG_FILE);
+            match cmd {
+                ConfigCommands::Show => {
+                    let config = TurboConfig::new()?;
+                    if cli.output == OutputFormat::Json {
+                        println!("{}", serde_json::to_string_pretty(&config)?);
+                    } else {
+                        print!("{}", toml::to_string_pretty(&config)?);
+                    }
+                }
+                ConfigCommands::Get { key } => {
+                    let config = serde_json::to_value(TurboConfig::new()?)?;
+                    let value = config_value(&config, &key)
+                        .ok_or_else(|| anyhow::anyhow!("Unknown config key: {}", key))?;
+                    if cli.output == OutputFormat::Json {
+                        println!("{}", serde_json::to_string_pretty(value)?);
+                    } else {
+                        match value {
+                            serde_json::Value::String(s) => println!("{}", s),
+                            // Unset optional value
+                            serde_json::Value::Null => {}
+                            other => println!("{}", other),
+                        }
+                    }
+                }
+                ConfigCommands::Set { key, value } => {
+                    let current = serde_json::to_value(TurboConfig::new()?)?;
+                    let language_limit = key
+                        .strip_prefix("limits.")
+                        .and_then(|rest| rest.split_once('.'))
+                        .is_some_and(|(_, field)| LanguageLimits::KEYS.contains(&field));
+                    let known = match config_value(&current, &key) {
+                        Some(existing) => !existing.is_object(),
+                        // API keys of the priority policy and per-language limits are the
+                        // open-ended tables
+                        None => key.starts_with("priority.keys.") || language_limit,
+                    };
+                    if !known {
+                        anyhow::bail!("Unknown config key: {}", key);
+                    }
+                    let updated = TurboConfig::with_overrides(&[(key.as_str(), value.as_str())])
+                        .map_err(|e| anyhow::anyhow!("Invalid value for {}: {}", key, e))?;
+                    updated.validate()?;
 
-            if let Some(run) = job_result.run {
-                println!("{}", "Execution Result".green().bold());
-                println!("{}", run);
-            } else {
-                println!("No execution result returned.");
+                    let content = match std::fs::read_to_string(&path) {
+                        Ok(content) => content,
+                        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+                        Err(e) => return Err(e.into()),
+                    };
+                    let mut doc: toml_edit::DocumentMut = content
+                        .parse()
+                        .map_err(|e| anyhow::anyhow!("Failed to parse {:?}: {}", path, e))?;
+                    set_toml_key(&mut doc, &key, &value)?;
+                    std::fs::write(&path, doc.to_string())?;
+                    println!("Set {} in {:?}", key, path);
+
+                    let wanted = serde_json::to_value(&updated)?;
+                    let effective = serde_json::to_value(TurboConfig::new()?)?;
+                    if config_value(&effective, &key) != config_value(&wanted, &key) {
+                        eprintln!(
+                            "{}",
+                            format!(
+                                "Warning: {} is still overridden, e.g. by a TURBO_* variable",
+                                key
+                            )
+                            .yellow()
+                        );
+                    }
+                }
+                ConfigCommands::Path => {
+                    println!("{}", path.display());
+                    if !path.exists() {
+                        eprintln!("(not found; only defaults and TURBO_* variables apply)");
+                    }
+                }
             }
         }
+        Commands::Top { server, interval } => {
+            let interval = Duration::from_secs(interval.max(1));
+            top::run(server.trim_end_matches('/'), interval).await?;
+        }
+        Commands::Doctor => {
+            let config = TurboConfig::new()?;
+            sandbox_doctor(&config)?;
+        }
+        Commands::Audit { cmd } => match cmd {
+            AuditCommands::Export {
+                path,
+                since,
+                format,
+            } => {
+                let path = match path {
+                    Some(path) => path,
+                    None => TurboConfig::new()?
+                        .audit
+                        .path
+                        .map(PathBuf::from)
+                        .ok_or_else(|| {
+                            anyhow::anyhow!("No audit log: audit.path is not set; pass --path")
+                        })?,
+                };
+                if !path.exists() {
+                    anyhow::bail!("No audit log at {}", path.display());
+                }
+                let since = match since {
+                    Some(since) => {
+                        let ago = parse_age(&since).ok_or_else(|| {
+                            anyhow::anyhow!("Invalid --since {:?}; use e.g. 30m, 24h or 7d", since)
+                        })?;
+                        unix_ms().saturating_sub(ago.as_millis() as u64)
+                    }
+                    None => 0,
+                };
+
+                let entries = AuditLog::open(&path)?.entries(since)?;
+                let mut out = std::io::stdout().lock();
+                if let AuditFormat::Csv = format {
+                    writeln!(out, "{}", audit::csv_header())?;
+                }
+                for entry in &entries {
+                    match format {
+                        AuditFormat::Jsonl => writeln!(out, "{}", serde_json::to_string(entry)?)?,
+                        AuditFormat::Csv => writeln!(out, "{}", audit::csv_record(entry))?,
+                    }
+                }
+            }
+        },
         Commands::Pkg { cmd } => {
+            let registry = match std::env::var("TURBO_REGISTRY_URL") {
+                Ok(url) => {
+                    let key = std::env::var("TURBO_REGISTRY_KEY").map_err(|_| {
+                        anyhow::anyhow!("TURBO_REGISTRY_KEY must be set with TURBO_REGISTRY_URL")
+                    })?;
+                    Some(RemoteRepository::new(&url, &key, home.join("registry"))?)
+                }
+                Err(_) => None,
+            };
             let pkg_root = home;
-            let manager = PackageManager::new(pkg_root, repo_path);
+            let mut manager = PackageManager::new(pkg_root, repo_path);
+            if let Some(registry) = registry {
+                manager = manager.with_registry(registry);
+            }
 
             match cmd {
                 PkgCommands::Install {
                     name,
                     version,
-                    local: _,
+                    local,
+                    dry_run: true,
+                } => {
+                    let plan = match local {
+                        Some(path) => {
+                            manager
+                                .plan_install_local(&path, &name, version.as_deref())
+                                .await?
+                        }
+                        None => manager.plan_install(&name, version.as_deref()).await?,
+                    };
+                    let size = |bytes: Option<u64>| {
+                        bytes.map_or_else(|| "unknown".to_string(), format_bytes)
+                    };
+
+                    println!("{} {}@{}", "Plan:".bold(), plan.name, plan.version);
+                    if plan.installed {
+                        println!(
+                            "  Already installed at {:?}, nothing to do",
+                            plan.install_dir
+                        );
+                    } else {
+                        println!("  Install to:    {:?}", plan.install_dir);
+                        println!("  Package files: {}", format_bytes(plan.package_bytes));
+                        println!("  Download:      {}", size(plan.download_size));
+                        println!("  Disk usage:    {}", size(plan.install_size));
+                    }
+                }
+                PkgCommands::Install {
+                    name,
+                    version,
+                    local,
+                    dry_run: false,
+                } => {
+                    let manager = match smoke_test_sandbox() {
+                        Ok(sandbox) => manager.with_sandbox(sandbox),
+                        Err(e) => {
+                            eprintln!(
+                                "Warning: no sandbox for smoke tests ({}); running them directly",
+                                e
+                            );
+                            manager
+                        }
+                    };
+                    match local {
+                        Some(path) => {
+                            manager
+                                .install_local(&path, &name, version.as_deref())
+                                .await?;
+                        }
+                        // Pass name and optional version
+                        None => manager.install(&name, version.as_deref()).await?,
+                    }
+                }
+                PkgCommands::Uninstall {
+                    name,
+                    version,
+                    force,
+                } => {
+                    manager.installed_versions(&name, version.as_deref())?;
+                    let db = TurboDb::new(&TurboConfig::new()?).await?;
+                    if !force {
+                        check_queue(&db, &[name.as_str()]).await?;
+                    }
+
+                    for removed in manager.uninstall(&name, version.as_deref()).await? {
+                        // Take it out of GET /api/v1/runtimes
+                        if let Err(e) = db.metadata.remove_runtime(&name, &removed).await {
+                            eprintln!("Warning: failed to unregister {}@{}: {}", name, removed, e);
+                        }
+                        println!("{} {}@{}", "Uninstalled".green().bold(), name, removed);
+                    }
+                }
+                PkgCommands::Validate { name, version } => {
+                    let problems = manager.validate(&name, version.as_deref()).await?;
+                    if problems.is_empty() {
+                        println!("{}", "Package is valid.".green().bold());
+                    } else {
+                        println!("{}", "Package is invalid:".red().bold());
+                        for problem in &problems {
+                            println!("  - {}", problem);
+                        }
+                        std::process::exit(1);
+                    }
+                }
+                PkgCommands::Prune {
+                    keep,
+                    dry_run,
+                    force,
+                } => {
+                    if keep == 0 {
+                        anyhow::bail!("--keep must be at least 1; use `turbo pkg uninstall`");
+                    }
+                    let candidates = manager.prune_candidates(keep)?;
+                    if candidates.is_empty() {
+                        println!("Nothing to prune.");
+                        return Ok(());
+                    }
+                    if dry_run {
+                        for (name, version) in &candidates {
+                            println!("Would remove {}@{}", name, version);
+                        }
+                        return Ok(());
+                    }
+
+                    let db = TurboDb::new(&TurboConfig::new()?).await?;
+                    if !force {
+                        let mut languages: Vec<&str> =
+                            candidates.iter().map(|(name, _)| name.as_str()).collect();
+                        languages.sort_unstable();
+                        languages.dedup();
+                        check_queue(&db, &languages).await?;
+                    }
+                    for (name, version) in manager.prune(keep).await? {
+                        // Take it out of GET /api/v1/runtimes
+                        if let Err(e) = db.metadata.remove_runtime(&name, &version).await {
+                            eprintln!("Warning: failed to unregister {}@{}: {}", name, version, e);
+                        }
+                        println!("{} {}@{}", "Removed".green().bold(), name, version);
+                    }
+                }
+                PkgCommands::Du => {
+                    let usage = manager.disk_usage()?;
+                    if cli.output == OutputFormat::Json {
+                        println!("{}", serde_json::to_string_pretty(&usage)?);
+                        return Ok(());
+                    }
+                    if usage.is_empty() {
+                        println!("No runtimes installed.");
+                        return Ok(());
+                    }
+                    let total: u64 = usage.iter().map(|u| u.bytes).sum();
+                    for runtime in &usage {
+                        let name = format!("{}@{}", runtime.name, runtime.version);
+                        println!("  {:<24} {:>12}", name, format_bytes(runtime.bytes));
+                    }
+                    println!("  {:<24} {:>12}", "Total", format_bytes(total));
+                }
+                PkgCommands::Doctor { smoke } => {
+                    let manager = if smoke {
+                        manager.with_sandbox(smoke_test_sandbox()?)
+                    } else {
+                        manager
+                    };
+                    let reports = manager.doctor(smoke).await?;
+                    if reports.is_empty() {
+                        println!("No runtimes installed.");
+                    }
+                    let mut broken = 0;
+                    for report in &reports {
+                        if report.issues.is_empty() {
+                            println!("{} {}@{}", "✓".green(), report.name, report.version);
+                            continue;
+                        }
+                        broken += 1;
+                        println!("{} {}@{}", "✗".red(), report.name, report.version);
+                        for issue in &report.issues {
+                            println!("    - {}", issue.problem);
+                            println!("      fix: {}", issue.fix);
+                        }
+                    }
+                    if broken > 0 {
+                        let summary = format!("{} runtime(s) need attention.", broken);
+                        println!("{}", summary.red().bold());
+                        std::process::exit(1);
+                    }
+                }
+                PkgCommands::Pack { dir, output } => {
+                    let def = PackageDefinition::from_path(dir)?;
+                    let problems = def.validate();
+                    if !problems.is_empty() {
+                        println!("{}", "Package is invalid:".red().bold());
+                        for problem in &problems {
+                            println!("  - {}", problem);
+                        }
+                        std::process::exit(1);
+                    }
+
+                    let output = output.unwrap_or_else(|| {
+                        PathBuf::from(format!(
+                            "{}-{}.{}",
+                            def.yaml.name, def.yaml.version, TPKG_EXTENSION
+                        ))
+                    });
+                    turbo_pkg::local::pack(&def, &output)?;
+                    println!("{} {:?}", "Packed".green().bold(), output);
+                }
+                PkgCommands::Publish {
+                    file,
+                    registry,
+                    key_file,
                 } => {
-                    // Pass name and optional version
-                    manager.install(&name, version.as_deref()).await?;
+                    let key = std::fs::read_to_string(&key_file).map_err(|e| {
+                        anyhow::anyhow!("Failed to read key file {:?}: {}", key_file, e)
+                    })?;
+                    let entry = turbo_pkg::remote::publish(&registry, &file, &key).await?;
+                    println!(
+                        "{} {}@{} ({})",
+                        "Published".green().bold(),
+                        entry.name,
+                        entry.version,
+                        entry.sha256
+                    );
                 }
                 PkgCommands::List => {
                     use colored::*;
@@ -217,7 +863,9 @@ async fn main() -> anyhow::Result<()> {
                     use turbo_pkg::models::PackageInfo;
 
                     let packages = manager.list_available().await?;
-                    if packages.is_empty() {
+                    if cli.output == OutputFormat::Json {
+                        println!("{}", serde_json::to_string_pretty(&packages)?);
+                    } else if packages.is_empty() {
                         println!("No packages found in repository.");
                     } else {
                         // Group by package name
@@ -254,7 +902,7 @@ async fn main() -> anyhow::Result<()> {
             match cmd {
                 CacheCommands::Clear => {
                     let user = std::env::var("USER").unwrap_or_else(|_| "unknown".to_string());
-    let cache_path = std::env::temp_dir().join(format!("turbo-cache-{}", user));
+                    let cache_path = std::env::temp_dir().join(format!("turbo-cache-{}", user));
                     if cache_path.exists() {
                         match std::fs::remove_dir_all(&cache_path) {
                             Ok(_) => println!("{}", "Cache cleared successfully.".green().bold()),
@@ -273,3 +921,431 @@ async fn main() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+/// Fail if queued jobs need any of `languages`, so removing their runtime would break
+/// them. The check is by language, since a queued job may not name a version.
+async fn check_queue(db: &TurboDb, languages: &[&str]) -> anyhow::Result<()> {
+    let queued = db
+        .queue
+        .queued_languages(Priority::Low)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to check the queue: {} (use --force to skip)", e))?;
+    for language in languages {
+        let waiting = queued.iter().filter(|lang| lang == language).count();
+        if waiting > 0 {
+            anyhow::bail!(
+                "{} queued jobs need {}; retry once they finish or use --force",
+                waiting,
+                language
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Rerun this `turbo start` detached from the terminal, with its output appended to
+/// `turbo.log` and its pid written to `turbo.pid` under `turbo_home`.
+fn daemonize(turbo_home: &Path) -> anyhow::Result<()> {
+    std::fs::create_dir_all(turbo_home)?;
+    let log_path = turbo_home.join("turbo.log");
+    let log = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&log_path)?;
+    let mut command = std::process::Command::new(std::env::current_exe()?);
+    command
+        .args(std::env::args().skip(1).filter(|arg| arg != "--daemon"))
+        .stdin(std::process::Stdio::null())
+        .stdout(log.try_clone()?)
+        .stderr(log);
+    // Own process group, so Ctrl-C in this terminal does not reach it
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        const CREATE_NEW_PROCESS_GROUP: u32 = 0x0000_0200;
+        command.creation_flags(CREATE_NEW_PROCESS_GROUP);
+    }
+    let child = command.spawn()?;
+    std::fs::write(turbo_home.join("turbo.pid"), child.id().to_string())?;
+    println!(
+        "Turbo Server started in the background (pid {}), logging to {:?}",
+        child.id(),
+        log_path
+    );
+    Ok(())
+}
+
+/// Value at dotted `key` (e.g. `sandbox.backend`) of a serialized config.
+fn config_value<'a>(config: &'a serde_json::Value, key: &str) -> Option<&'a serde_json::Value> {
+    key.split('.')
+        .try_fold(config, |value, part| value.get(part))
+}
+
+/// Set dotted `key` in a TOML document, creating its tables as needed. `value` is stored
+/// as the TOML value it parses as (number, boolean, array), or else as a string.
+fn set_toml_key(doc: &mut toml_edit::DocumentMut, key: &str, value: &str) -> anyhow::Result<()> {
+    let parts: Vec<&str> = key.split('.').collect();
+    let Some((last, tables)) = parts.split_last() else {
+        anyhow::bail!("Empty config key");
+    };
+    let mut table = doc.as_table_mut();
+    for part in tables {
+        table = table
+            .entry(part)
+            .or_insert(toml_edit::table())
+            .as_table_mut()
+            .ok_or_else(|| anyhow::anyhow!("{} is not a table in {}", part, CONFIG_FILE))?;
+    }
+    let parsed = value
+        .parse::<toml_edit::Value>()
+        .unwrap_or_else(|_| value.into());
+    table[*last] = toml_edit::value(parsed);
+    Ok(())
+}
+
+/// Sandbox for package smoke tests, built from the server's `sandbox` config.
+fn smoke_test_sandbox() -> anyhow::Result<Arc<dyn turbo_box::Sandbox>> {
+    let config = TurboConfig::new()?;
+    Ok(turbo_box::create_sandbox(&config.sandbox)?)
+}
+
+/// Prints what the configured sandbox backend can do on this host; exits 1 if it lacks
+/// something it needs.
+fn sandbox_doctor(config: &TurboConfig) -> anyhow::Result<()> {
+    let backend = config.sandbox.backend.as_str();
+    let checks = match backend {
+        #[cfg(target_os = "linux")]
+        "linux" => turbo_box::LinuxSandbox::from_config(&config.sandbox).probe(),
+        _ => {
+            println!("Nothing to check for the {:?} sandbox backend.", backend);
+            return Ok(());
+        }
+    };
+    let mut missing = 0;
+    for check in &checks {
+        if check.available {
+            println!("{} {:<16} {}", "✓".green(), check.name, check.detail);
+            continue;
+        }
+        missing += 1;
+        println!("{} {:<16} {}", "✗".red(), check.name, check.detail);
+        if let Some(fix) = &check.fix {
+            println!("  {:<16} fix: {}", "", fix);
+        }
+    }
+    if missing > 0 {
+        let summary = format!("{} sandbox check(s) failed.", missing);
+        println!("{}", summary.red().bold());
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Human-readable byte count, e.g. `182.4 MiB`.
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64;
+    let mut unit = "B";
+    for u in UNITS {
+        if value < 1024.0 {
+            break;
+        }
+        value /= 1024.0;
+        unit = u;
+    }
+    format!("{:.1} {}", value, unit)
+}
+
+/// One-shot job running `file`, with `stdin_file` (if any) as its stdin.
+fn job_request(
+    language: &str,
+    version: Option<&str>,
+    file: &Path,
+    stdin_file: Option<&Path>,
+    priority: Option<Priority>,
+) -> anyhow::Result<JobRequest> {
+    let content = std::fs::read_to_string(file)
+        .map_err(|e| anyhow::anyhow!("Failed to read file {:?}: {}", file, e))?;
+
+    let filename = file
+        .file_name()
+        .and_then(|s| s.to_str())
+        .map(|s| s.to_string());
+
+    let stdin = match stdin_file {
+        Some(path) => Some(
+            std::fs::read_to_string(path)
+                .map_err(|e| anyhow::anyhow!("Failed to read stdin file {:?}: {}", path, e))?,
+        ),
+        None => None,
+    };
+
+    Ok(JobRequest {
+        language: language.to_string(),
+        version: version.map(|v| v.to_string()),
+        files: vec![FileRequest {
+            name: filename,
+            content,
+            encoding: Some("utf8".to_string()),
+            url: None,
+        }],
+        testcases: None, // Interactive/One-shot mode
+        args: None,
+        stdin,
+        run_timeout: None,
+        compile_timeout: None,
+        run_memory_limit: None,
+        compile_memory_limit: None,
+        output_limit: None,
+        pid_limit: None,
+        tty: None,
+        supersedes: None,
+        priority,
+        parallelism: None,
+        stop_on_failure: None,
+        max_total_time_ms: None,
+        idempotency_key: None,
+        workspace: None,
+        source: None,
+        benchmark: None,
+        output_encoding: None,
+        sample_interval_ms: None,
+        timezone: None,
+        locale: None,
+        metadata: None,
+        legacy_args: None,
+        diagnostics: None,
+        analysis: None,
+        profile: None,
+        interactor: None,
+    })
+}
+
+/// Where `turbo execute` runs its jobs.
+enum Executor {
+    /// The `/api/v1/execute` endpoint `url` of a server
+    Server {
+        client: reqwest::Client,
+        url: String,
+        api_key: Option<String>,
+    },
+    /// In this process (`--local`)
+    Local(Engine),
+}
+
+impl Executor {
+    async fn run(&self, req: JobRequest) -> anyhow::Result<JobResult> {
+        match self {
+            Executor::Server {
+                client,
+                url,
+                api_key,
+            } => execute_job(client, url, api_key.as_deref(), &req).await,
+            Executor::Local(engine) => Ok(engine.execute(req).await),
+        }
+    }
+}
+
+/// Run `req` on the server at `url` (its `/api/v1/execute` endpoint) and wait for the result.
+async fn execute_job(
+    client: &reqwest::Client,
+    url: &str,
+    api_key: Option<&str>,
+    req: &JobRequest,
+) -> anyhow::Result<JobResult> {
+    let mut request = client.post(url).json(req);
+    if let Some(key) = api_key {
+        request = request.header("x-turbo-api-key", key);
+    }
+    let res = request.send().await?;
+    if !res.status().is_success() {
+        anyhow::bail!("Execution failed: {}", error_message(res.text().await?));
+    }
+    Ok(res.json().await?)
+}
+
+fn print_job_result(result: &JobResult, output: OutputFormat) -> anyhow::Result<()> {
+    if output == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(result)?);
+        return Ok(());
+    }
+
+    if let Some(compile) = &result.compile {
+        if compile.status != StageStatus::Success {
+            println!("{}", "Compilation Failed".red().bold());
+            println!("{}", compile); // StageResult implements Display
+            return Ok(());
+        }
+    }
+
+    if let Some(run) = &result.run {
+        println!("{}", "Execution Result".green().bold());
+        println!("{}", run);
+    } else {
+        println!("No execution result returned.");
+    }
+    Ok(())
+}
+
+fn print_job_status(status: &JobStatus, output: OutputFormat) -> anyhow::Result<()> {
+    if output == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(status)?);
+        return Ok(());
+    }
+
+    println!("{} {}", "Job".bold(), status.id);
+    println!("  State:    {}", colored_state(status.state));
+    if let Some(worker) = &status.worker {
+        println!("  Worker:   {} (attempt {})", worker, status.attempts);
+    }
+    if let Some(queued_at) = status.queued_at {
+        println!("  Queued:   {}", format_age(queued_at));
+    }
+    if let (Some(started), Some(queued)) = (status.started_at, status.queued_at) {
+        println!("  Waited:   {} ms", started.saturating_sub(queued));
+    }
+    if let (Some(finished), Some(started)) = (status.finished_at, status.started_at) {
+        println!("  Ran:      {} ms", finished.saturating_sub(started));
+    }
+    if let Some(result) = &status.result {
+        println!();
+        print_job_result(result, output)?;
+    }
+    Ok(())
+}
+
+fn colored_state(state: JobState) -> colored::ColoredString {
+    let name = state.as_str();
+    match state {
+        JobState::Queued => name.yellow(),
+        JobState::Running => name.cyan(),
+        JobState::Finished => name.green(),
+        JobState::Cancelled => name.dimmed(),
+        JobState::Failed => name.red(),
+    }
+}
+
+/// How long ago a Unix ms timestamp was, e.g. `5m ago`.
+fn format_age(at: u64) -> String {
+    let secs = unix_ms().saturating_sub(at) / 1000;
+    match secs {
+        0..=59 => format!("{}s ago", secs),
+        60..=3599 => format!("{}m ago", secs / 60),
+        3600..=86399 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    }
+}
+
+/// A span like `90s`, `30m`, `24h` or `7d`.
+fn parse_age(age: &str) -> Option<Duration> {
+    let unit = age.chars().last()?;
+    let number = &age[..age.len() - unit.len_utf8()];
+    let secs = match unit {
+        's' => 1,
+        'm' => 60,
+        'h' => 3600,
+        'd' => 86400,
+        _ => return None,
+    };
+    Some(Duration::from_secs(
+        number.parse::<u64>().ok()?.checked_mul(secs)?,
+    ))
+}
+
+fn unix_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// `res` if it succeeded, else an error with the server's message.
+async fn check_response(res: reqwest::Response) -> anyhow::Result<reqwest::Response> {
+    let status = res.status();
+    if status.is_success() {
+        return Ok(res);
+    }
+    let body = res.text().await.unwrap_or_default();
+    anyhow::bail!("Server returned {}: {}", status, error_message(body))
+}
+
+/// The message of an error response: `message` of a `{code, message, request_id}` body,
+/// else the body as sent.
+fn error_message(body: String) -> String {
+    let json: Option<serde_json::Value> = serde_json::from_str(&body).ok();
+    json.as_ref()
+        .and_then(|v| v.get("message"))
+        .and_then(|m| m.as_str())
+        .map(str::to_string)
+        .unwrap_or(body)
+}
+
+/// The stage that decided a job's outcome: a failed compile, else the run.
+fn outcome(result: &JobResult) -> Option<&StageResult> {
+    match &result.compile {
+        Some(compile) if compile.status != StageStatus::Success => Some(compile),
+        _ => result.run.as_ref(),
+    }
+}
+
+/// Print how the status and output of `next` differ from `previous`.
+fn print_changes(previous: &JobResult, next: &JobResult) {
+    let (Some(before), Some(after)) = (outcome(previous), outcome(next)) else {
+        return;
+    };
+    println!("{}", "Changes since last run".yellow().bold());
+    let mut changed = false;
+    if before.status != after.status || before.exit_code != after.exit_code {
+        println!(
+            "  status: {:?} (exit {:?}) -> {:?} (exit {:?})",
+            before.status, before.exit_code, after.status, after.exit_code
+        );
+        changed = true;
+    }
+    changed |= print_line_diff("stdout", &before.stdout, &after.stdout);
+    changed |= print_line_diff("stderr", &before.stderr, &after.stderr);
+    if !changed {
+        println!("  (no changes)");
+    }
+}
+
+/// Print the lines between the common prefix and suffix of `before` and `after`;
+/// returns whether they differ.
+fn print_line_diff(stream: &str, before: &str, after: &str) -> bool {
+    if before == after {
+        return false;
+    }
+    let old: Vec<&str> = before.lines().collect();
+    let new: Vec<&str> = after.lines().collect();
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let removed = &old[prefix..old.len() - suffix];
+    let added = &new[prefix..new.len() - suffix];
+
+    println!("  {} (from line {}):", stream, prefix + 1);
+    let total = removed.len() + added.len();
+    let lines = removed
+        .iter()
+        .map(|line| format!("    - {}", line).red())
+        .chain(added.iter().map(|line| format!("    + {}", line).green()));
+    for line in lines.take(MAX_DIFF_LINES) {
+        println!("{}", line);
+    }
+    if total > MAX_DIFF_LINES {
+        println!("    ... {} more", total - MAX_DIFF_LINES);
+    }
+    true
+}
@@ -0,0 +1,288 @@
+//! `turbo top`: a live dashboard of a server's queue, workers, recent jobs and caches,
+//! refreshed from `/metrics`, `/api/v1/admin/workers` and `/api/v1/jobs`.
+
+use crate::{check_response, format_bytes, unix_ms};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Cell, Paragraph, Row, Table};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use turbo_core::models::{JobResult, JobState, JobStatus, WorkerHeartbeat};
+
+/// Recent jobs listed.
+const JOBS_SHOWN: usize = 30;
+/// Longest wait for a key press between redraws.
+const INPUT_POLL: Duration = Duration::from_millis(200);
+
+/// What the last refresh fetched from the server.
+#[derive(Default)]
+struct Snapshot {
+    /// Series (name and labels, e.g. `turbo_queue_depth{priority="high"}`) -> value
+    metrics: HashMap<String, f64>,
+    workers: Vec<WorkerHeartbeat>,
+    jobs: Vec<JobStatus>,
+    /// Results of the finished jobs in `jobs`; each is fetched once
+    results: HashMap<String, JobResult>,
+    /// Why the last refresh failed; the previous data stays on screen
+    error: Option<String>,
+}
+
+/// Take over the terminal and show the dashboard for `server` until `q` or Esc.
+pub async fn run(server: &str, interval: Duration) -> anyhow::Result<()> {
+    let mut terminal = ratatui::init();
+    let outcome = dashboard(&mut terminal, server, interval).await;
+    ratatui::restore();
+    outcome
+}
+
+async fn dashboard(
+    terminal: &mut DefaultTerminal,
+    server: &str,
+    interval: Duration,
+) -> anyhow::Result<()> {
+    let client = reqwest::Client::new();
+    let mut snapshot = Snapshot::default();
+    let mut refreshed_at: Option<Instant> = None;
+    loop {
+        if refreshed_at.is_none_or(|at| at.elapsed() >= interval) {
+            snapshot.error = refresh(&client, server, &mut snapshot)
+                .await
+                .err()
+                .map(|e| e.to_string());
+            refreshed_at = Some(Instant::now());
+        }
+        terminal.draw(|frame| draw(frame, server, interval, &snapshot))?;
+
+        if event::poll(INPUT_POLL)? {
+            if let Event::Key(key) = event::read()? {
+                let quit = matches!(key.code, KeyCode::Char('q') | KeyCode::Esc);
+                if key.kind == KeyEventKind::Press && quit {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+async fn refresh(
+    client: &reqwest::Client,
+    server: &str,
+    snapshot: &mut Snapshot,
+) -> anyhow::Result<()> {
+    let get = |path: String| client.get(format!("{}{}", server, path)).send();
+
+    let metrics = check_response(get("/metrics".into()).await?)
+        .await?
+        .text()
+        .await?;
+    snapshot.metrics = parse_metrics(&metrics);
+    let res = get("/api/v1/admin/workers".into()).await?;
+    snapshot.workers = check_response(res).await?.json().await?;
+    snapshot.workers.sort_by(|a, b| a.worker.cmp(&b.worker));
+    let res = get(format!("/api/v1/jobs?limit={}", JOBS_SHOWN)).await?;
+    snapshot.jobs = check_response(res).await?.json().await?;
+
+    snapshot
+        .results
+        .retain(|id, _| snapshot.jobs.iter().any(|job| &job.id == id));
+    for job in &snapshot.jobs {
+        if !job.state.is_terminal() || snapshot.results.contains_key(&job.id) {
+            continue;
+        }
+        // A result that expired or could not be read just leaves the columns empty
+        let res = get(format!("/api/v1/jobs/{}", job.id)).await?;
+        let Ok(status) = res.json::<JobStatus>().await else {
+            continue;
+        };
+        if let Some(result) = status.result {
+            snapshot.results.insert(job.id.clone(), result);
+        }
+    }
+    Ok(())
+}
+
+/// Series -> value from Prometheus text format.
+fn parse_metrics(text: &str) -> HashMap<String, f64> {
+    text.lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let (series, value) = line.rsplit_once(' ')?;
+            Some((series.to_string(), value.parse().ok()?))
+        })
+        .collect()
+}
+
+fn draw(frame: &mut Frame, server: &str, interval: Duration, snapshot: &Snapshot) {
+    let [summary, workers, jobs] = Layout::vertical([
+        Constraint::Length(6),
+        Constraint::Percentage(35),
+        Constraint::Min(5),
+    ])
+    .areas(frame.area());
+    frame.render_widget(summary_panel(server, interval, snapshot), summary);
+    frame.render_widget(workers_table(snapshot), workers);
+    frame.render_widget(jobs_table(snapshot), jobs);
+}
+
+fn summary_panel(server: &str, interval: Duration, snapshot: &Snapshot) -> Paragraph<'static> {
+    let metric = |series: &str| snapshot.metrics.get(series).copied().unwrap_or(0.0) as u64;
+    let bold = Style::default().add_modifier(Modifier::BOLD);
+
+    let depth = |priority: &str| {
+        let depth = metric(&format!("turbo_queue_depth{{priority=\"{}\"}}", priority));
+        format!("{} {}", priority, depth)
+    };
+    let queue = ["high", "normal", "low"].map(depth).join("   ");
+    let workers = format!(
+        "{} busy / {} live",
+        metric("turbo_workers_busy"),
+        metric("turbo_workers")
+    );
+    let cache = |name: &str| {
+        let hits = metric(&format!("turbo_cache_hits_total{{cache=\"{}\"}}", name));
+        let misses = metric(&format!("turbo_cache_misses_total{{cache=\"{}\"}}", name));
+        match hits + misses {
+            0 => format!("{} no lookups", name),
+            total => format!(
+                "{} {} hits / {} misses ({:.0}%)",
+                name,
+                hits,
+                misses,
+                hits as f64 * 100.0 / total as f64
+            ),
+        }
+    };
+
+    let status = match &snapshot.error {
+        Some(e) => Line::from(Span::styled(
+            format!("Refresh failed: {}", e),
+            Style::default().fg(Color::Red),
+        )),
+        None => Line::from(Span::styled(
+            format!("Every {}s · q to quit", interval.as_secs()),
+            Style::default().fg(Color::DarkGray),
+        )),
+    };
+    Paragraph::new(vec![
+        Line::from(vec![Span::styled("Queue    ", bold), Span::raw(queue)]),
+        Line::from(vec![Span::styled("Workers  ", bold), Span::raw(workers)]),
+        Line::from(vec![
+            Span::styled("Caches   ", bold),
+            Span::raw(format!("{}   {}", cache("result"), cache("compile"))),
+        ]),
+        status,
+    ])
+    .block(Block::bordered().title(format!(" turbo top · {} ", server)))
+}
+
+fn workers_table(snapshot: &Snapshot) -> Table<'static> {
+    let rows = snapshot.workers.iter().map(|hb| {
+        let state = match &hb.current_job {
+            Some(_) => Span::styled("busy", Style::default().fg(Color::Yellow)),
+            None => Span::styled("idle", Style::default().fg(Color::Green)),
+        };
+        Row::new([
+            Cell::from(hb.worker.clone()),
+            Cell::from(state),
+            Cell::from(hb.current_job.clone().unwrap_or_else(|| "-".to_string())),
+            Cell::from(format_duration(hb.uptime_secs * 1000)),
+        ])
+    });
+    Table::new(
+        rows,
+        [
+            Constraint::Length(24),
+            Constraint::Length(6),
+            Constraint::Length(38),
+            Constraint::Length(10),
+        ],
+    )
+    .header(header(["Worker", "State", "Job", "Uptime"]))
+    .block(Block::bordered().title(format!(" Workers ({}) ", snapshot.workers.len())))
+}
+
+fn jobs_table(snapshot: &Snapshot) -> Table<'static> {
+    let now = unix_ms();
+    let rows = snapshot.jobs.iter().map(|job| {
+        let result = snapshot.results.get(&job.id);
+        let time = match (job.queued_at, job.started_at, job.finished_at) {
+            (_, Some(started), Some(finished)) => format_duration(finished.saturating_sub(started)),
+            (_, Some(started), None) => format_duration(now.saturating_sub(started)),
+            (Some(queued), None, None) => {
+                format!("waiting {}", format_duration(now.saturating_sub(queued)))
+            }
+            _ => "-".to_string(),
+        };
+        // The sandbox reports usage only once a stage ends, so running jobs have none yet
+        let (cpu, memory) = result.map(usage).unwrap_or_default();
+        Row::new([
+            Cell::from(job.id.chars().take(8).collect::<String>()),
+            Cell::from(Span::styled(job.state.as_str(), state_style(job.state))),
+            Cell::from(job.worker.clone().unwrap_or_else(|| "-".to_string())),
+            Cell::from(result.map(|r| r.language.clone()).unwrap_or_default()),
+            Cell::from(time),
+            Cell::from(cpu.map(|ms| format!("{} ms", ms)).unwrap_or_default()),
+            Cell::from(memory.map(format_bytes).unwrap_or_default()),
+        ])
+    });
+    Table::new(
+        rows,
+        [
+            Constraint::Length(10),
+            Constraint::Length(10),
+            Constraint::Length(24),
+            Constraint::Length(12),
+            Constraint::Length(16),
+            Constraint::Length(10),
+            Constraint::Length(12),
+        ],
+    )
+    .header(header([
+        "Job", "State", "Worker", "Language", "Time", "CPU", "Memory",
+    ]))
+    .block(Block::bordered().title(" Recent jobs "))
+}
+
+fn header<const N: usize>(titles: [&'static str; N]) -> Row<'static> {
+    Row::new(titles).style(Style::default().add_modifier(Modifier::BOLD))
+}
+
+fn state_style(state: JobState) -> Style {
+    let color = match state {
+        JobState::Queued => Color::Yellow,
+        JobState::Running => Color::Cyan,
+        JobState::Finished => Color::Green,
+        JobState::Cancelled => Color::DarkGray,
+        JobState::Failed => Color::Red,
+    };
+    Style::default().fg(color)
+}
+
+/// Total CPU time (ms) and peak memory (bytes) across every stage of a job.
+fn usage(result: &JobResult) -> (Option<u64>, Option<u64>) {
+    let testcases = result.testcases.iter().flatten().map(|tc| &tc.run_details);
+    let stages: Vec<_> = result
+        .compile
+        .iter()
+        .chain(result.run.iter())
+        .chain(testcases)
+        .collect();
+    let cpu = stages
+        .iter()
+        .filter_map(|s| s.cpu_time)
+        .reduce(|a, b| a + b);
+    let memory = stages.iter().filter_map(|s| s.memory_usage).max();
+    (cpu, memory)
+}
+
+/// E.g. `850ms`, `4.2s`, `3m07s`.
+fn format_duration(ms: u64) -> String {
+    match ms {
+        0..=999 => format!("{}ms", ms),
+        1000..=59_999 => format!("{:.1}s", ms as f64 / 1000.0),
+        _ => format!("{}m{:02}s", ms / 60_000, ms / 1000 % 60),
+    }
+}
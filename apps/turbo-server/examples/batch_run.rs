@@ -1,9 +1,8 @@
-use turbo_core::models::{FileRequest, JobRequest, JobResult, Testcase};
+use turbo_client::TurboClient;
+use turbo_client::models::{FileRequest, JobRequest, Testcase};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let client = reqwest::Client::new();
-
     let req = JobRequest {
         language: "python".to_string(),
         version: Some("3.14.2".to_string()),
@@ -12,57 +11,83 @@ async fn main() -> anyhow::Result<()> {
             // Simple python script that doubles input
             content: "import sys; print(sys.stdin.read().strip() * 2)".to_string(),
             encoding: Some("utf8".to_string()),
+            url: None,
         }],
         testcases: Some(vec![
             Testcase {
                 id: "1".into(),
                 input: "A".into(),
                 expected_output: Some("AA".into()),
+                hidden: false,
             },
             Testcase {
                 id: "2".into(),
                 input: "B".into(),
                 expected_output: Some("BB".into()),
+                hidden: false,
             },
             Testcase {
                 id: "3".into(),
                 input: "Hello".into(),
                 expected_output: Some("HelloHello".into()),
+                hidden: false,
             },
             // This one should fail
             Testcase {
                 id: "4".into(),
                 input: "Fail".into(),
                 expected_output: Some("Wrong".into()),
+                hidden: false,
             },
         ]),
-        args: Some(vec!["main.py".to_string()]),
+        args: None,
         stdin: None,
         run_timeout: None,
         compile_timeout: None,
         run_memory_limit: None,
         compile_memory_limit: None,
+        output_limit: None,
+        pid_limit: None,
+        tty: None,
+        supersedes: None,
+        priority: None,
+        parallelism: None,
+        stop_on_failure: None,
+        max_total_time_ms: None,
+        idempotency_key: None,
+        workspace: None,
+        source: None,
+        benchmark: None,
+        output_encoding: None,
+        sample_interval_ms: None,
+        timezone: None,
+        locale: None,
+        metadata: None,
+        legacy_args: None,
+        diagnostics: None,
+        analysis: None,
+        profile: None,
+        interactor: None,
     };
 
     println!("Submitting Batch Run Job...");
     let port = std::env::var("TURBO_SERVER_PORT").unwrap_or_else(|_| "3000".to_string());
-    let url = format!("http://localhost:{}/api/v1/execute", port);
-    let res = client.post(&url).json(&req).send().await?;
-
-    if !res.status().is_success() {
-        eprintln!("Error: {}", res.text().await?);
-        return Ok(());
-    }
-
-    let result: JobResult = res.json().await?;
-
-    if let Some(compile) = &result.compile {
-        if compile.status != turbo_core::models::StageStatus::Success {
-            println!("Compilation Failed!");
-            println!("Status: {:?}", compile.status);
-            println!("Stdout: {}", compile.stdout);
-            println!("Stderr: {}", compile.stderr);
+    let client = TurboClient::new(&format!("http://localhost:{}", port));
+    let result = match client.execute_and_wait(&req).await {
+        Ok(result) => result,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return Ok(());
         }
+    };
+
+    if let Some(compile) = &result.compile
+        && compile.status != turbo_client::models::StageStatus::Success
+    {
+        println!("Compilation Failed!");
+        println!("Status: {:?}", compile.status);
+        println!("Stdout: {}", compile.stdout);
+        println!("Stderr: {}", compile.stderr);
     }
 
     if let Some(testcases) = result.testcases {
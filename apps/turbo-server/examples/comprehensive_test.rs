@@ -1,5 +1,6 @@
 use colored::*;
-use turbo_core::models::{FileRequest, JobRequest, JobResult, StageStatus, Testcase};
+use turbo_client::TurboClient;
+use turbo_client::models::{FileRequest, JobRequest, StageStatus, Testcase};
 
 #[derive(Debug)]
 struct TestConfig {
@@ -19,9 +20,9 @@ struct TestConfig {
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let client = reqwest::Client::new();
     let port = std::env::var("TURBO_SERVER_PORT").unwrap_or_else(|_| "3000".to_string());
-    let url = format!("http://localhost:{}/api/v1/execute", port);
+    let url = format!("http://localhost:{}", port);
+    let client = TurboClient::new(&url);
 
     let tests = vec![
         // ==========================================
@@ -35,11 +36,12 @@ async fn main() -> anyhow::Result<()> {
                 name: Some("main.py".to_string()),
                 content: "print('Hello Python')".to_string(),
                 encoding: Some("utf8".to_string()),
+                url: None,
             }],
             expected_status: StageStatus::Success,
             description: "Basic happy path test".to_string(),
             stdin: None,
-            args: Some(vec!["main.py".to_string()]),
+            args: None,
             expected_output_contains: Some("Hello Python".to_string()),
             expected_stderr_contains: None,
         },
@@ -51,11 +53,12 @@ async fn main() -> anyhow::Result<()> {
                 name: Some("main.py".to_string()),
                 content: "print('Missing closing quote)".to_string(),
                 encoding: Some("utf8".to_string()),
+                url: None,
             }],
             expected_status: StageStatus::RuntimeError, // Python syntax errors are often runtime errors in the sense that the script runs and fails immediately, or compilation failure if strictly compiled. For interpreted, it usually returns exit code 1. Let's see how system handles it. Actually, for python, it's usually a runtime error from the perspective of "run" stage if we consider "compile" stage as empty. Let's assume Runtime Error for now.
             description: "Code with invalid syntax".to_string(),
             stdin: None,
-            args: Some(vec!["main.py".to_string()]),
+            args: None,
             expected_output_contains: None,
             expected_stderr_contains: Some("SyntaxError".to_string()),
         },
@@ -67,11 +70,12 @@ async fn main() -> anyhow::Result<()> {
                 name: Some("main.py".to_string()),
                 content: "print(1/0)".to_string(),
                 encoding: Some("utf8".to_string()),
+                url: None,
             }],
             expected_status: StageStatus::RuntimeError,
             description: "Runtime exception".to_string(),
             stdin: None,
-            args: Some(vec!["main.py".to_string()]),
+            args: None,
             expected_output_contains: None,
             expected_stderr_contains: Some("ZeroDivisionError".to_string()),
         },
@@ -83,11 +87,12 @@ async fn main() -> anyhow::Result<()> {
                 name: Some("main.py".to_string()),
                 content: "while True: pass".to_string(),
                 encoding: Some("utf8".to_string()),
+                url: None,
             }],
             expected_status: StageStatus::TimeLimitExceeded,
             description: "Infinite loop that should timeout".to_string(),
             stdin: None,
-            args: Some(vec!["main.py".to_string()]),
+            args: None,
             expected_output_contains: None,
             expected_stderr_contains: None,
         },
@@ -99,11 +104,12 @@ async fn main() -> anyhow::Result<()> {
                 name: Some("main.py".to_string()),
                 content: "import sys; print(f'Received: {sys.stdin.read().strip()}')".to_string(),
                 encoding: Some("utf8".to_string()),
+                url: None,
             }],
             expected_status: StageStatus::Success,
             description: "Reading from stdin".to_string(),
             stdin: Some("SecretMessage".to_string()),
-            args: Some(vec!["main.py".to_string()]),
+            args: None,
             expected_output_contains: Some("Received: SecretMessage".to_string()),
             expected_stderr_contains: None,
         },
@@ -125,11 +131,12 @@ async fn main() -> anyhow::Result<()> {
                 "#
                 .to_string(),
                 encoding: Some("utf8".to_string()),
+                url: None,
             }],
             expected_status: StageStatus::Success,
             description: "Basic happy path test".to_string(),
             stdin: None,
-            args: Some(vec!["Main.java".to_string()]), // The wrapper script handles compilation
+            args: None,
             expected_output_contains: Some("Hello Java".to_string()),
             expected_stderr_contains: None,
         },
@@ -148,6 +155,7 @@ async fn main() -> anyhow::Result<()> {
                 "#
                 .to_string(),
                 encoding: Some("utf8".to_string()),
+                url: None,
             }],
             // This depends on how the run.sh is implemented.
             // If run.sh compiles and runs in one go, a compile error might result in Runtime Error or just text in stderr.
@@ -158,7 +166,7 @@ async fn main() -> anyhow::Result<()> {
             expected_status: StageStatus::RuntimeError,
             description: "Code that fails to compile".to_string(),
             stdin: None,
-            args: Some(vec!["Main.java".to_string()]),
+            args: None,
             expected_output_contains: None,
             expected_stderr_contains: Some("error:".to_string()), // Java compiler usually says "error:"
         },
@@ -177,11 +185,12 @@ async fn main() -> anyhow::Result<()> {
                 "#
                 .to_string(),
                 encoding: Some("utf8".to_string()),
+                url: None,
             }],
             expected_status: StageStatus::RuntimeError,
             description: "Unhandled exception".to_string(),
             stdin: None,
-            args: Some(vec!["Main.java".to_string()]),
+            args: None,
             expected_output_contains: None,
             expected_stderr_contains: Some("Exception in thread".to_string()),
         },
@@ -213,27 +222,32 @@ async fn main() -> anyhow::Result<()> {
             compile_timeout: None,
             run_memory_limit: None,
             compile_memory_limit: None,
+            output_limit: None,
+            pid_limit: None,
+            tty: None,
+            supersedes: None,
+            priority: None,
+            parallelism: None,
+            stop_on_failure: None,
+            max_total_time_ms: None,
+            idempotency_key: None,
+            workspace: None,
+            source: None,
+            benchmark: None,
+            output_encoding: None,
+            sample_interval_ms: None,
+            timezone: None,
+            locale: None,
+            metadata: None,
+            legacy_args: None,
+            diagnostics: None,
+            analysis: None,
+            profile: None,
+            interactor: None,
         };
 
-        let res = client.post(&url).json(&req).send().await;
-
-        match res {
-            Ok(response) => {
-                if !response.status().is_success() {
-                    println!(
-                        "{} Server returned error: {}",
-                        "FAILED".red(),
-                        response.status()
-                    );
-                    if let Ok(text) = response.text().await {
-                        println!("Body: {}", text);
-                    }
-                    failed_count += 1;
-                    continue;
-                }
-
-                let job_result: JobResult = response.json().await?;
-
+        match client.execute_and_wait(&req).await {
+            Ok(job_result) => {
                 // Analyze results
                 let run_stage = job_result.run.as_ref();
                 let status = run_stage
@@ -255,19 +269,19 @@ async fn main() -> anyhow::Result<()> {
                 }
 
                 // Check Stdout
-                if let Some(expected_out) = &test.expected_output_contains {
-                    if !stdout.contains(expected_out) {
-                        passed = false;
-                        reasons.push(format!("Stdout did not contain '{}'", expected_out));
-                    }
+                if let Some(expected_out) = &test.expected_output_contains
+                    && !stdout.contains(expected_out)
+                {
+                    passed = false;
+                    reasons.push(format!("Stdout did not contain '{}'", expected_out));
                 }
 
                 // Check Stderr
-                if let Some(expected_err) = &test.expected_stderr_contains {
-                    if !stderr.contains(expected_err) {
-                        passed = false;
-                        reasons.push(format!("Stderr did not contain '{}'", expected_err));
-                    }
+                if let Some(expected_err) = &test.expected_stderr_contains
+                    && !stderr.contains(expected_err)
+                {
+                    passed = false;
+                    reasons.push(format!("Stderr did not contain '{}'", expected_err));
                 }
 
                 if passed {
@@ -286,7 +300,7 @@ async fn main() -> anyhow::Result<()> {
                 }
             }
             Err(e) => {
-                println!("{} Connection failed: {}", "ERROR".red(), e);
+                println!("{} Request failed: {}", "ERROR".red(), e);
                 failed_count += 1;
             }
         }
@@ -309,90 +323,105 @@ async fn main() -> anyhow::Result<()> {
             name: Some("main.py".to_string()),
             content: "import sys; print(sys.stdin.read().strip() * 2)".to_string(),
             encoding: Some("utf8".to_string()),
+            url: None,
         }],
         testcases: Some(vec![
             Testcase {
                 id: "1".into(),
                 input: "A".into(),
                 expected_output: Some("AA".into()),
+                hidden: false,
             },
             Testcase {
                 id: "2".into(),
                 input: "B".into(),
                 expected_output: Some("BB".into()),
+                hidden: false,
             },
             Testcase {
                 id: "3".into(),
                 input: "Hello".into(),
                 expected_output: Some("HelloHello".into()),
+                hidden: false,
             },
             Testcase {
                 id: "4".into(),
                 input: "Fail".into(),
                 expected_output: Some("Wrong".into()),
+                hidden: false,
             },
         ]),
-        args: Some(vec!["main.py".to_string()]),
+        args: None,
         stdin: None,
         run_timeout: None,
         compile_timeout: None,
         run_memory_limit: None,
         compile_memory_limit: None,
+        output_limit: None,
+        pid_limit: None,
+        tty: None,
+        supersedes: None,
+        priority: None,
+        parallelism: None,
+        stop_on_failure: None,
+        max_total_time_ms: None,
+        idempotency_key: None,
+        workspace: None,
+        source: None,
+        benchmark: None,
+        output_encoding: None,
+        sample_interval_ms: None,
+        timezone: None,
+        locale: None,
+        metadata: None,
+        legacy_args: None,
+        diagnostics: None,
+        analysis: None,
+        profile: None,
+        interactor: None,
     };
 
-    let batch_res = client.post(&url).json(&batch_req).send().await;
-
-    match batch_res {
-        Ok(response) => {
-            if !response.status().is_success() {
-                println!(
-                    "{} Batch Server returned error: {}",
-                    "FAILED".red(),
-                    response.status()
-                );
-                failed_count += 1;
-            } else {
-                let result: JobResult = response.json().await?;
-                if let Some(tcs) = result.testcases {
-                    let mut batch_passed = true;
-                    // Check if we got 4 testcases
-                    if tcs.len() != 4 {
-                        println!("{} Expected 4 testcases, got {}", "FAILED".red(), tcs.len());
-                        batch_passed = false;
-                    }
+    match client.execute_and_wait(&batch_req).await {
+        Ok(result) => {
+            if let Some(tcs) = result.testcases {
+                let mut batch_passed = true;
+                // Check if we got 4 testcases
+                if tcs.len() != 4 {
+                    println!("{} Expected 4 testcases, got {}", "FAILED".red(), tcs.len());
+                    batch_passed = false;
+                }
 
-                    // Check individual results
-                    let map_res: std::collections::HashMap<_, _> =
-                        tcs.iter().map(|tc| (tc.id.clone(), tc)).collect();
+                // Check individual results
+                let map_res: std::collections::HashMap<_, _> =
+                    tcs.iter().map(|tc| (tc.id.clone(), tc)).collect();
 
-                    if let Some(tc) = map_res.get("1") {
-                        if !tc.passed {
-                            println!("  Testcase 1 failed unexpectedly");
-                            batch_passed = false;
-                        }
-                    }
-                    if let Some(tc) = map_res.get("4") {
-                        if tc.passed {
-                            println!("  Testcase 4 passed unexpectedly (should fail)");
-                            batch_passed = false;
-                        }
-                    }
+                if let Some(tc) = map_res.get("1")
+                    && !tc.passed
+                {
+                    println!("  Testcase 1 failed unexpectedly");
+                    batch_passed = false;
+                }
+                if let Some(tc) = map_res.get("4")
+                    && tc.passed
+                {
+                    println!("  Testcase 4 passed unexpectedly (should fail)");
+                    batch_passed = false;
+                }
 
-                    if batch_passed {
-                        println!("{} {}", "PASSED".green(), "Batch Execution (Python)");
-                        passed_count += 1;
-                    } else {
-                        println!("{} {}", "FAILED".red(), "Batch Execution (Python)");
-                        failed_count += 1;
-                    }
+                if batch_passed {
+                    println!("{} Batch Execution (Python)", "PASSED".green());
+                    passed_count += 1;
                 } else {
-                    println!("{} No testcases returned in batch mode", "FAILED".red());
+                    println!("{} Batch Execution (Python)", "FAILED".red());
                     failed_count += 1;
                 }
+            } else {
+                println!("{} No testcases returned in batch mode", "FAILED".red());
+                failed_count += 1;
             }
         }
         Err(e) => {
-            println!("{} Batch Connection failed: {}", "ERROR".red(), e);
+            println!("{} Batch request failed: {}", "ERROR".red(), e);
             failed_count += 1;
         }
     }
@@ -0,0 +1,223 @@
+//! JSON error bodies and the request ID every response carries.
+
+use axum::{
+    Json,
+    extract::Request,
+    http::{HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use tracing::Instrument;
+use turbo_core::TurboError;
+use turbo_core::models::BatchJobError;
+use uuid::Uuid;
+
+/// Request and response header carrying the request ID. A caller's own ID is kept if it
+/// is usable, so it can correlate our logs with its own.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+/// Longest caller-supplied request ID kept; longer ones are replaced.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+tokio::task_local! {
+    /// ID of the request being handled, for error bodies and queued jobs.
+    static REQUEST_ID: String;
+}
+
+/// ID of the request being handled, if called while handling one.
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok()
+}
+
+/// Assigns each request an ID, handles it in a tracing span carrying the ID, and echoes
+/// the ID in the `X-Request-Id` response header.
+pub async fn request_id(request: Request, next: Next) -> Response {
+    let id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|id| !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN)
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+    let span = tracing::info_span!(
+        "request",
+        request_id = %id,
+        method = %request.method(),
+        path = %request.uri().path(),
+    );
+
+    let mut response = REQUEST_ID
+        .scope(id.clone(), next.run(request))
+        .instrument(span)
+        .await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+/// An API error, sent as `{"code", "message", "request_id"}` with its status.
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub message: String,
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    /// Machine-readable snake_case form of the status, e.g. `not_found`
+    code: String,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+
+    /// Machine-readable snake_case form of the status, e.g. `not_found`
+    pub fn code(&self) -> String {
+        self.status
+            .canonical_reason()
+            .unwrap_or("error")
+            .to_ascii_lowercase()
+            .replace([' ', '-'], "_")
+            .replace('\'', "")
+    }
+}
+
+impl From<(StatusCode, String)> for ApiError {
+    fn from((status, message): (StatusCode, String)) -> Self {
+        Self::new(status, message)
+    }
+}
+
+impl From<TurboError> for ApiError {
+    fn from(e: TurboError) -> Self {
+        let status = match &e {
+            TurboError::RuntimeNotFound(..) => StatusCode::NOT_FOUND,
+            TurboError::CompilationFailed => StatusCode::UNPROCESSABLE_ENTITY,
+            TurboError::Package(_) => StatusCode::BAD_REQUEST,
+            TurboError::Config(_)
+            | TurboError::Io(_)
+            | TurboError::Serialization(_)
+            | TurboError::Sandbox(_)
+            | TurboError::Unknown(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        Self::new(status, e.to_string())
+    }
+}
+
+impl From<ApiError> for BatchJobError {
+    fn from(e: ApiError) -> Self {
+        Self {
+            code: e.code(),
+            message: e.message,
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = ErrorBody {
+            code: self.code(),
+            message: self.message,
+            request_id: current_request_id(),
+        };
+        (self.status, Json(body)).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use turbo_core::SandboxError;
+
+    #[test]
+    fn turbo_errors_map_to_statuses_and_codes() {
+        let table = [
+            (
+                TurboError::Config(config::ConfigError::Message("bad".to_string())),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "internal_server_error",
+            ),
+            (
+                TurboError::Io(std::io::Error::other("disk")),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "internal_server_error",
+            ),
+            (
+                TurboError::Serialization(serde_json::from_str::<u32>("x").unwrap_err()),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "internal_server_error",
+            ),
+            (
+                TurboError::Sandbox(SandboxError::PoolClosed),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "internal_server_error",
+            ),
+            (
+                TurboError::CompilationFailed,
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "unprocessable_entity",
+            ),
+            (
+                TurboError::RuntimeNotFound("python".to_string(), "3.12".to_string()),
+                StatusCode::NOT_FOUND,
+                "not_found",
+            ),
+            (
+                TurboError::Package("bad package".to_string()),
+                StatusCode::BAD_REQUEST,
+                "bad_request",
+            ),
+            (
+                TurboError::Unknown("?".to_string()),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "internal_server_error",
+            ),
+        ];
+        for (error, status, code) in table {
+            let message = error.to_string();
+            let api = ApiError::from(error);
+            assert_eq!(
+                (api.status, api.code().as_str()),
+                (status, code),
+                "{}",
+                message
+            );
+            assert_eq!(api.message, message);
+        }
+    }
+
+    #[test]
+    fn code_is_the_snake_case_reason() {
+        let code = |status| ApiError::new(status, "").code();
+        assert_eq!(code(StatusCode::PAYLOAD_TOO_LARGE), "payload_too_large");
+        assert_eq!(
+            code(StatusCode::NON_AUTHORITATIVE_INFORMATION),
+            "non_authoritative_information"
+        );
+        assert_eq!(code(StatusCode::IM_A_TEAPOT), "im_a_teapot");
+        assert_eq!(code(StatusCode::from_u16(599).unwrap()), "error");
+    }
+
+    #[tokio::test]
+    async fn response_carries_code_and_message() {
+        let response = ApiError::new(StatusCode::NOT_FOUND, "Job x not found").into_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({ "code": "not_found", "message": "Job x not found" })
+        );
+    }
+}
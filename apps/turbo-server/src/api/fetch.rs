@@ -0,0 +1,510 @@
+//! Source files given by `url` instead of inline content, fetched by the server before
+//! their job is queued and cached on local disk.
+
+use crate::api::error::ApiError;
+use axum::http::StatusCode;
+use base64::Engine;
+use hmac::digest::InvalidLength;
+use hmac::{Hmac, Mac};
+use reqwest::Url;
+use reqwest::redirect::{Attempt, Policy};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use turbo_core::config::FetchConfig;
+use turbo_core::models::FileRequest;
+use uuid::Uuid;
+
+/// How long fetching one file may take.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+/// Most redirects followed for one file.
+const MAX_REDIRECTS: usize = 10;
+/// Payload hash S3 accepts for a GET without signing the (empty) body.
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+
+pub struct Fetcher {
+    client: reqwest::Client,
+    config: FetchConfig,
+}
+
+impl Fetcher {
+    pub fn new(config: &FetchConfig) -> anyhow::Result<Self> {
+        let allowed = config.allowed_hosts.clone();
+        // Redirects are held to `allowed_hosts` too, or any allowed host could point the
+        // server at internal services
+        let redirects = Policy::custom(move |attempt: Attempt| {
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                attempt.error("too many redirects")
+            } else if host_allowed(&allowed, attempt.url().host_str().unwrap_or_default()) {
+                attempt.follow()
+            } else {
+                attempt.error("redirected to a host not in fetch.allowed_hosts")
+            }
+        });
+        let client = reqwest::Client::builder()
+            .timeout(FETCH_TIMEOUT)
+            .redirect(redirects)
+            .build()?;
+        Ok(Self {
+            client,
+            config: config.clone(),
+        })
+    }
+
+    /// `fetch.allowed_hosts`, which also limits the hosts git sources are cloned from.
+    pub fn allowed_hosts(&self) -> &[String] {
+        &self.config.allowed_hosts
+    }
+
+    /// Replaces the `url` of every file in `files` with the content it points to. Text is
+    /// kept as UTF-8 and anything else is base64-encoded.
+    pub async fn resolve(&self, files: &mut [FileRequest]) -> Result<(), ApiError> {
+        for file in files.iter_mut() {
+            let Some(url) = file.url.take() else {
+                continue;
+            };
+            if !file.content.is_empty() {
+                return Err(ApiError::new(
+                    StatusCode::BAD_REQUEST,
+                    format!("File {} has both content and a url", url),
+                ));
+            }
+            (file.content, file.encoding) = match String::from_utf8(self.fetch(&url).await?) {
+                Ok(text) => (text, Some("utf8".to_string())),
+                Err(e) => {
+                    let encoded = base64::engine::general_purpose::STANDARD.encode(e.into_bytes());
+                    (encoded, Some("base64".to_string()))
+                }
+            };
+        }
+        Ok(())
+    }
+
+    async fn fetch(&self, url: &str) -> Result<Vec<u8>, ApiError> {
+        let parsed = Url::parse(url).map_err(|e| {
+            ApiError::new(
+                StatusCode::BAD_REQUEST,
+                format!("Invalid file url {}: {}", url, e),
+            )
+        })?;
+        if !matches!(parsed.scheme(), "http" | "https" | "s3") {
+            return Err(ApiError::new(
+                StatusCode::BAD_REQUEST,
+                format!("File url {} must be http(s):// or s3://", url),
+            ));
+        }
+        let host = parsed.host_str().unwrap_or_default();
+        if !host_allowed(&self.config.allowed_hosts, host) {
+            return Err(ApiError::new(
+                StatusCode::FORBIDDEN,
+                format!(
+                    "Fetching files from {:?} is not allowed by fetch.allowed_hosts",
+                    host
+                ),
+            ));
+        }
+
+        let cached = Path::new(&self.config.cache_dir).join(hex::encode(Sha256::digest(url)));
+        if let Some(bytes) = self.read_cache(&cached).await {
+            return Ok(bytes);
+        }
+        let request = match parsed.scheme() {
+            "s3" => self.s3_request(&parsed)?,
+            _ => self.client.get(parsed),
+        };
+        let bytes = self.download(request, url).await?;
+        // A failed write only costs a later refetch
+        if let Err(e) = write_cache(&cached, &bytes).await {
+            tracing::warn!("Failed to cache {} at {:?}: {}", url, cached, e);
+        }
+        Ok(bytes)
+    }
+
+    async fn download(
+        &self,
+        request: reqwest::RequestBuilder,
+        url: &str,
+    ) -> Result<Vec<u8>, ApiError> {
+        let upstream = |e: reqwest::Error| {
+            ApiError::new(
+                StatusCode::BAD_GATEWAY,
+                format!("Failed to fetch {}: {}", url, e),
+            )
+        };
+        let max = self.config.max_bytes;
+        let too_large = || {
+            ApiError::new(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                format!("{} is larger than fetch.max_bytes ({})", url, max),
+            )
+        };
+
+        let mut response = request.send().await.map_err(upstream)?;
+        if !response.status().is_success() {
+            return Err(ApiError::new(
+                StatusCode::BAD_GATEWAY,
+                format!("Failed to fetch {}: {}", url, response.status()),
+            ));
+        }
+        if response
+            .content_length()
+            .is_some_and(|len| len > max as u64)
+        {
+            return Err(too_large());
+        }
+        // Counted as it arrives, since Content-Length may be missing or wrong
+        let mut bytes = Vec::new();
+        while let Some(chunk) = response.chunk().await.map_err(upstream)? {
+            if bytes.len() + chunk.len() > max {
+                return Err(too_large());
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+        Ok(bytes)
+    }
+
+    /// Content of a cached file fetched within `cache_ttl_secs`.
+    async fn read_cache(&self, path: &Path) -> Option<Vec<u8>> {
+        let modified = tokio::fs::metadata(path)
+            .await
+            .and_then(|m| m.modified())
+            .ok()?;
+        let age = SystemTime::now()
+            .duration_since(modified)
+            .unwrap_or_default();
+        if age > Duration::from_secs(self.config.cache_ttl_secs) {
+            return None;
+        }
+        tokio::fs::read(path).await.ok()
+    }
+
+    /// GET for `s3://bucket/key` from `fetch.s3.endpoint` (path-style), signed with AWS
+    /// Signature Version 4 when credentials are configured and anonymous otherwise.
+    fn s3_request(&self, url: &Url) -> Result<reqwest::RequestBuilder, ApiError> {
+        let s3 = &self.config.s3;
+        let endpoint = s3
+            .endpoint
+            .clone()
+            .unwrap_or_else(|| format!("https://s3.{}.amazonaws.com", s3.region));
+        let mut target = Url::parse(&endpoint).map_err(|e| {
+            ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Invalid fetch.s3.endpoint {}: {}", endpoint, e),
+            )
+        })?;
+        let bucket = url.host_str().unwrap_or_default();
+        let key = aws_uri_encode(&percent_decode(url.path()));
+        target.set_path(&format!(
+            "{}/{}{}",
+            target.path().trim_end_matches('/'),
+            bucket,
+            key
+        ));
+
+        let access_key = s3
+            .access_key_id
+            .clone()
+            .or_else(|| std::env::var("AWS_ACCESS_KEY_ID").ok());
+        let secret = s3
+            .secret_access_key
+            .clone()
+            .or_else(|| std::env::var("AWS_SECRET_ACCESS_KEY").ok());
+        let (Some(access_key), Some(secret)) = (access_key, secret) else {
+            return Ok(self.client.get(target));
+        };
+
+        let host = match target.port() {
+            Some(port) => format!("{}:{}", target.host_str().unwrap_or_default(), port),
+            None => target.host_str().unwrap_or_default().to_string(),
+        };
+        let (date, timestamp) = amz_dates(SystemTime::now());
+        // Sorted by name, as the canonical request requires
+        let mut headers = vec![
+            ("host", host),
+            ("x-amz-content-sha256", UNSIGNED_PAYLOAD.to_string()),
+            ("x-amz-date", timestamp.clone()),
+        ];
+        if let Ok(token) = std::env::var("AWS_SESSION_TOKEN") {
+            headers.push(("x-amz-security-token", token));
+        }
+        let signed_headers = signed_headers(&headers);
+        let canonical_request = canonical_request("GET", target.path(), &headers, UNSIGNED_PAYLOAD);
+        let scope = format!("{}/{}/s3/aws4_request", date, s3.region);
+        let signature = signing_key(&secret, &date, &s3.region, "s3")
+            .and_then(|key| signature(&key, &timestamp, &scope, &canonical_request))
+            .map_err(|e| {
+                ApiError::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to sign the request for {}: {}", url, e),
+                )
+            })?;
+
+        let mut request = self.client.get(target).header(
+            "authorization",
+            format!(
+                "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                access_key, scope, signed_headers, signature
+            ),
+        );
+        // `host` is set from the URL by the client
+        for (name, value) in headers.into_iter().skip(1) {
+            request = request.header(name, value);
+        }
+        Ok(request)
+    }
+}
+
+/// Whether `allowed` (`fetch.allowed_hosts`) lets files be fetched from `host`.
+pub(crate) fn host_allowed(allowed: &[String], host: &str) -> bool {
+    !host.is_empty()
+        && allowed
+            .iter()
+            .any(|allowed| allowed == "*" || allowed == host)
+}
+
+/// Writes `bytes` to the cache through a temporary file, so concurrent readers never see
+/// a partial file.
+async fn write_cache(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    let temp = PathBuf::from(format!("{}.{}.tmp", path.display(), Uuid::new_v4()));
+    tokio::fs::write(&temp, bytes).await?;
+    tokio::fs::rename(&temp, path).await
+}
+
+/// `;`-separated names of `headers`, which are lowercase and sorted by name.
+fn signed_headers(headers: &[(&str, String)]) -> String {
+    headers
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+/// SigV4 canonical request for `method` on `path` (already URI-encoded) without a query
+/// string.
+fn canonical_request(
+    method: &str,
+    path: &str,
+    headers: &[(&str, String)],
+    payload_hash: &str,
+) -> String {
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+        .collect();
+    format!(
+        "{}\n{}\n\n{}\n{}\n{}",
+        method,
+        path,
+        canonical_headers,
+        signed_headers(headers),
+        payload_hash
+    )
+}
+
+/// Key derived from `secret` for signing requests to `service` in `region` on `date`
+/// (`YYYYMMDD`).
+fn signing_key(
+    secret: &str,
+    date: &str,
+    region: &str,
+    service: &str,
+) -> Result<Vec<u8>, InvalidLength> {
+    let mut key = hmac(format!("AWS4{}", secret).as_bytes(), date.as_bytes())?;
+    for part in [region, service, "aws4_request"] {
+        key = hmac(&key, part.as_bytes())?;
+    }
+    Ok(key)
+}
+
+/// Hex signature of `canonical_request`, made at `timestamp` for `scope`.
+fn signature(
+    key: &[u8],
+    timestamp: &str,
+    scope: &str,
+    canonical_request: &str,
+) -> Result<String, InvalidLength> {
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        timestamp,
+        scope,
+        hex::encode(Sha256::digest(canonical_request))
+    );
+    Ok(hex::encode(hmac(key, string_to_sign.as_bytes())?))
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Result<Vec<u8>, InvalidLength> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key)?;
+    mac.update(data);
+    Ok(mac.finalize().into_bytes().to_vec())
+}
+
+fn percent_decode(s: &str) -> Vec<u8> {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok());
+        match hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+            Some(byte) if bytes[i] == b'%' => {
+                decoded.push(byte);
+                i += 3;
+            }
+            _ => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    decoded
+}
+
+/// Percent-encodes everything but unreserved characters and `/`, as S3 signing expects.
+fn aws_uri_encode(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|&b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// `(YYYYMMDD, YYYYMMDDTHHMMSSZ)` for `time` in UTC.
+fn amz_dates(time: SystemTime) -> (String, String) {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let (days, rem) = ((secs / 86400) as i64, secs % 86400);
+    // Civil date from days since 1970-01-01 (Howard Hinnant's `civil_from_days`)
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    let date = format!("{:04}{:02}{:02}", year, month, day);
+    let timestamp = format!(
+        "{}T{:02}{:02}{:02}Z",
+        date,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    );
+    (date, timestamp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE_SECRET: &str = "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY";
+
+    #[test]
+    fn signing_key_matches_aws_example() {
+        // "Examples of how to derive a signing key for Signature Version 4"
+        let key = signing_key(EXAMPLE_SECRET, "20120215", "us-east-1", "iam").unwrap();
+        assert_eq!(
+            hex::encode(key),
+            "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d"
+        );
+    }
+
+    #[test]
+    fn signs_get_vanilla() {
+        // `get-vanilla` from the AWS SigV4 test suite
+        let headers = [
+            ("host", "example.amazonaws.com".to_string()),
+            ("x-amz-date", "20150830T123600Z".to_string()),
+        ];
+        let empty_hash = hex::encode(Sha256::digest(b""));
+        let request = canonical_request("GET", "/", &headers, &empty_hash);
+        assert_eq!(
+            request,
+            "GET\n/\n\nhost:example.amazonaws.com\nx-amz-date:20150830T123600Z\n\n\
+             host;x-amz-date\n\
+             e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        let key = signing_key(EXAMPLE_SECRET, "20150830", "us-east-1", "service").unwrap();
+        let scope = "20150830/us-east-1/service/aws4_request";
+        assert_eq!(
+            signature(&key, "20150830T123600Z", scope, &request).unwrap(),
+            "5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+        );
+    }
+
+    #[test]
+    fn signs_s3_get_object() {
+        // "Example: GET Object" from the S3 header-based SigV4 documentation
+        let headers = [
+            ("host", "examplebucket.s3.amazonaws.com".to_string()),
+            ("range", "bytes=0-9".to_string()),
+            (
+                "x-amz-content-sha256",
+                "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855".to_string(),
+            ),
+            ("x-amz-date", "20130524T000000Z".to_string()),
+        ];
+        let request = canonical_request("GET", "/test.txt", &headers, &headers[2].1);
+        assert_eq!(
+            hex::encode(Sha256::digest(&request)),
+            "7344ae5b7ee6c3e7e6b0fe0640412a37625d1fbfff95c48bbb2dc43964946972"
+        );
+        let secret = "wJalrXUtnFEMI/K7MDENG/bPxRfiCYEXAMPLEKEY";
+        let key = signing_key(secret, "20130524", "us-east-1", "s3").unwrap();
+        let scope = "20130524/us-east-1/s3/aws4_request";
+        assert_eq!(
+            signature(&key, "20130524T000000Z", scope, &request).unwrap(),
+            "f0e8bdb87c964420e857bd35b5d6ed310bd44f0170aba48dd91039c6036bdb41"
+        );
+    }
+
+    #[test]
+    fn formats_amz_dates() {
+        let at = |secs| amz_dates(UNIX_EPOCH + Duration::from_secs(secs));
+        assert_eq!(at(0), ("19700101".into(), "19700101T000000Z".into()));
+        assert_eq!(
+            at(1369353600),
+            ("20130524".into(), "20130524T000000Z".into())
+        );
+        // Leap days, including the 400-year rule
+        assert_eq!(
+            at(951782400 + 3723),
+            ("20000229".into(), "20000229T010203Z".into())
+        );
+        assert_eq!(
+            at(1709164800 + 86399),
+            ("20240229".into(), "20240229T235959Z".into())
+        );
+        assert_eq!(
+            at(1709251200),
+            ("20240301".into(), "20240301T000000Z".into())
+        );
+    }
+
+    #[test]
+    fn percent_decodes() {
+        assert_eq!(percent_decode("/a%20b/%E2%82%AC"), "/a b/€".as_bytes());
+        // Malformed escapes are kept as they are
+        assert_eq!(percent_decode("/100%/%zz%4"), b"/100%/%zz%4");
+    }
+
+    #[test]
+    fn encodes_s3_keys() {
+        assert_eq!(
+            aws_uri_encode("/dir/a b+c~_.-€".as_bytes()),
+            "/dir/a%20b%2Bc~_.-%E2%82%AC"
+        );
+    }
+}
@@ -1,36 +1,993 @@
+use crate::api::error::{self, ApiError};
 use crate::api::routes::AppState;
-use axum::{Json, extract::State, http::StatusCode};
+use crate::git;
+use crate::webhooks;
+use crate::worker;
+use axum::{
+    Json,
+    extract::{ConnectInfo, Path, Query, State},
+    http::{HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::net::SocketAddr;
 use std::sync::Arc;
-use turbo_core::models::{Job, JobRequest, JobResult, Runtime};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::task::{self, JoinError, JoinSet};
+use turbo_core::config::MaxLimitsConfig;
+use turbo_core::models::{
+    BatchJob, DeadLetter, FileRequest, InstallPackageRequest, Job, JobAccepted, JobKind,
+    JobRequest, JobResult, JobState, JobStatus, Package, Priority, Runtime, StageResult,
+    StageStatus, UnknownRuntime, WebhookConfig, WorkerHeartbeat, WorkerScore, Workspace,
+    WorkspaceUpdate,
+};
+use turbo_db::QueueMode;
+use turbo_engine::execute::stub_result;
 use uuid::Uuid;
 
+/// Response header carrying the per-`supersedes`-key sequence number of the job.
+pub(crate) const SEQUENCE_HEADER: &str = "x-turbo-sequence";
+/// Request header identifying the caller for the priority policy.
+pub(crate) const API_KEY_HEADER: &str = "x-turbo-api-key";
+/// Request header naming a submission, so a retry of it gets the first one's job.
+pub(crate) const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+/// Response header set when a submission was answered with an earlier one's job.
+pub(crate) const IDEMPOTENT_REPLAYED_HEADER: &str = "idempotent-replayed";
+/// Error response header giving the index of the job in a batch that was refused.
+pub(crate) const BATCH_INDEX_HEADER: &str = "x-turbo-batch-index";
+/// Most jobs one batch submission may hold.
+const MAX_BATCH_JOBS: usize = 1000;
+/// Jobs of a waited-on batch queued or running at once without a `concurrency`.
+const DEFAULT_BATCH_CONCURRENCY: usize = 16;
+/// Longest idempotency key accepted.
+const MAX_IDEMPOTENCY_KEY_LEN: usize = 255;
+/// Jobs `GET /api/v1/jobs` returns without a `limit`.
+const DEFAULT_JOBS_LISTED: usize = 50;
+/// Most jobs `GET /api/v1/jobs` returns.
+const MAX_JOBS_LISTED: usize = 1000;
+
 pub async fn execute(
     State(state): State<Arc<AppState>>,
-    Json(payload): Json<JobRequest>,
-) -> Result<Json<JobResult>, (StatusCode, String)> {
-    let job_id = Uuid::new_v4().to_string();
-    let job = Job {
-        id: job_id.clone(),
-        request: payload,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    request_headers: HeaderMap,
+    Json(mut payload): Json<JobRequest>,
+) -> Result<(HeaderMap, Json<JobResult>), Response> {
+    check_submission(&state, &request_headers, &mut payload).await?;
+    let idempotency =
+        idempotency_key(&request_headers, &payload).map_err(IntoResponse::into_response)?;
+    let client_ip = peer.map(|ConnectInfo(addr)| addr.ip().to_string());
+    let queued =
+        queue_submission(&state, &request_headers, payload, client_ip, idempotency).await?;
+
+    let result = wait_for_result(&state, &queued.id)
+        .await
+        .map_err(IntoResponse::into_response)?;
+    Ok((submission_headers(&queued), Json(result)))
+}
+
+/// Average duration assumed for languages with no finished jobs yet.
+const DEFAULT_JOB_DURATION_MS: f64 = 1000.0;
+
+/// Queues a job without waiting for it and returns an estimate of when it will start.
+///
+/// The result is fetched later from `GET /api/v1/jobs/{id}`.
+pub async fn submit(
+    State(state): State<Arc<AppState>>,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    request_headers: HeaderMap,
+    Json(mut payload): Json<JobRequest>,
+) -> Result<(StatusCode, HeaderMap, Json<JobAccepted>), Response> {
+    check_submission(&state, &request_headers, &mut payload).await?;
+    let idempotency =
+        idempotency_key(&request_headers, &payload).map_err(IntoResponse::into_response)?;
+    let priority = payload.priority.unwrap_or_default();
+
+    // Estimate before queueing so the job does not count itself
+    let estimate = estimate_start(&state, priority).await;
+    let (queue_depth, estimated_start_ms) = estimate.unwrap_or_else(|e| {
+        tracing::warn!("Failed to estimate queue wait: {}", e);
+        (0, 0)
+    });
+    let client_ip = peer.map(|ConnectInfo(addr)| addr.ip().to_string());
+    let queued =
+        queue_submission(&state, &request_headers, payload, client_ip, idempotency).await?;
+
+    Ok((
+        StatusCode::ACCEPTED,
+        submission_headers(&queued),
+        Json(JobAccepted {
+            id: queued.id,
+            sequence: queued.sequence,
+            queue_depth,
+            estimated_start_ms,
+        }),
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct BatchQuery {
+    #[serde(default)]
+    wait: bool,
+    concurrency: Option<usize>,
+}
+
+/// The result of a batch job waited on, with its position in the batch.
+type BatchWait = (usize, Result<JobResult, ApiError>);
+
+/// Queues every job of a batch and returns their IDs in order. Every job is checked and
+/// has its files fetched before any is queued or saved to a workspace, so an invalid one
+/// (its index in `X-Turbo-Batch-Index`) queues none and changes no workspace.
+///
+/// If a job then cannot be queued, it and every later new job come back with an `error`
+/// instead, and their idempotency keys are released; the jobs queued before it still run.
+///
+/// With `wait`, at most `concurrency` of the jobs are queued or running at a time and
+/// the response carries every result, or the `error` of a job whose wait failed.
+pub async fn execute_batch(
+    State(state): State<Arc<AppState>>,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    request_headers: HeaderMap,
+    Query(query): Query<BatchQuery>,
+    Json(mut payloads): Json<Vec<JobRequest>>,
+) -> Result<(StatusCode, Json<Vec<BatchJob>>), Response> {
+    if payloads.len() > MAX_BATCH_JOBS {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            format!(
+                "A batch holds at most {} jobs, not {}",
+                MAX_BATCH_JOBS,
+                payloads.len()
+            ),
+        )
+        .into_response());
+    }
+    let mut idempotency = Vec::with_capacity(payloads.len());
+    for (index, payload) in payloads.iter_mut().enumerate() {
+        let checked = match check_submission(&state, &request_headers, payload).await {
+            Ok(()) => scoped_idempotency_key(&request_headers, payload.idempotency_key.as_deref())
+                .map_err(IntoResponse::into_response),
+            Err(response) => Err(response),
+        };
+        match checked {
+            Ok(key) => idempotency.push(key),
+            Err(response) => return Err(at_batch_index(response, index)),
+        }
+    }
+
+    let mut submissions = Vec::with_capacity(payloads.len());
+    for (index, (payload, key)) in payloads.into_iter().zip(idempotency).enumerate() {
+        match claim_submission(&state, payload, key.as_deref()).await {
+            Ok(claimed) => submissions.push((claimed, key)),
+            Err(response) => {
+                for (claimed, key) in &submissions {
+                    if let Claimed::Prepared { .. } = claimed {
+                        release_idempotency(&state, key.as_deref()).await;
+                    }
+                }
+                return Err(at_batch_index(response, index));
+            }
+        }
+    }
+
+    let client_ip = peer.map(|ConnectInfo(addr)| addr.ip().to_string());
+    let mut failed = None;
+    if !query.wait {
+        let mut jobs = Vec::with_capacity(submissions.len());
+        for (index, submission) in submissions.into_iter().enumerate() {
+            let queued = queue_batch_job(
+                &state,
+                &request_headers,
+                submission,
+                client_ip.clone(),
+                index,
+                &mut failed,
+            )
+            .await;
+            jobs.push(queued);
+        }
+        return Ok((StatusCode::ACCEPTED, Json(jobs)));
+    }
+
+    let concurrency = query
+        .concurrency
+        .unwrap_or(DEFAULT_BATCH_CONCURRENCY)
+        .max(1);
+    let mut jobs = Vec::with_capacity(submissions.len());
+    let mut running: JoinSet<BatchWait> = JoinSet::new();
+    let mut tasks = HashMap::new();
+    for (index, submission) in submissions.into_iter().enumerate() {
+        while running.len() >= concurrency {
+            let Some(done) = running.join_next().await else {
+                break;
+            };
+            record_batch_result(done, &tasks, &mut jobs);
+        }
+        let queued = queue_batch_job(
+            &state,
+            &request_headers,
+            submission,
+            client_ip.clone(),
+            index,
+            &mut failed,
+        )
+        .await;
+        if queued.error.is_none() {
+            let (state, id) = (state.clone(), queued.id.clone());
+            let task = running.spawn(async move { (index, wait_for_result(&state, &id).await) });
+            tasks.insert(task.id(), index);
+        }
+        jobs.push(queued);
+    }
+    while let Some(done) = running.join_next().await {
+        record_batch_result(done, &tasks, &mut jobs);
+    }
+
+    Ok((StatusCode::OK, Json(jobs)))
+}
+
+/// Queues job `index` of a batch with `queue_claimed`, or, once an earlier job `failed`
+/// to queue, releases its idempotency key instead, so the batch stops queueing new jobs.
+/// Replayed jobs are still reported, as they were queued by an earlier submission.
+async fn queue_batch_job(
+    state: &AppState,
+    headers: &HeaderMap,
+    (claimed, key): (Claimed, Option<String>),
+    client_ip: Option<String>,
+    index: usize,
+    failed: &mut Option<usize>,
+) -> BatchJob {
+    let id = claimed.id().to_string();
+    let queued = match (*failed, &claimed) {
+        (Some(first), Claimed::Prepared { .. }) => {
+            release_idempotency(state, key.as_deref()).await;
+            Err(ApiError::new(
+                StatusCode::FAILED_DEPENDENCY,
+                format!("Not queued: job {} of the batch could not be queued", first),
+            ))
+        }
+        _ => queue_claimed(state, headers, claimed, client_ip, key).await,
     };
+    queued.unwrap_or_else(|e| {
+        failed.get_or_insert(index);
+        BatchJob {
+            id,
+            sequence: None,
+            replayed: false,
+            result: None,
+            error: Some(e.into()),
+        }
+    })
+}
 
-    state.db.queue.push_job(job).await.map_err(|e| {
-        tracing::error!("Failed to queue job: {}", e);
+/// `response` to a batch whose job `index` was refused.
+fn at_batch_index(mut response: Response, index: usize) -> Response {
+    response
+        .headers_mut()
+        .insert(BATCH_INDEX_HEADER, HeaderValue::from(index));
+    response
+}
+
+/// Puts a finished batch job's result, or the error of its wait, on its entry in `jobs`.
+/// `tasks` maps each wait's task to its job's index, for a wait that panicked.
+fn record_batch_result(
+    done: Result<BatchWait, JoinError>,
+    tasks: &HashMap<task::Id, usize>,
+    jobs: &mut [BatchJob],
+) {
+    let (index, result) = match done {
+        Ok(done) => done,
+        Err(e) => {
+            let Some(&index) = tasks.get(&e.id()) else {
+                tracing::error!("Batch wait failed: {}", e);
+                return;
+            };
+            let message = format!("Batch task failed: {}", e);
+            (
+                index,
+                Err(ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, message)),
+            )
+        }
+    };
+    match result {
+        Ok(result) => jobs[index].result = Some(result),
+        Err(e) => jobs[index].error = Some(e.into()),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ListJobsQuery {
+    limit: Option<usize>,
+}
+
+/// Recently queued jobs, newest first, without their results.
+pub async fn list_jobs(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<ListJobsQuery>,
+) -> Result<Json<Vec<JobStatus>>, ApiError> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_JOBS_LISTED)
+        .clamp(1, MAX_JOBS_LISTED);
+    let jobs = state
+        .db
+        .metadata
+        .list_job_states(limit)
+        .await
+        .map_err(metadata_error)?;
+    Ok(Json(jobs))
+}
+
+/// Cancels a job no worker has taken yet. A running job cannot be stopped, so it (like a
+/// finished one) is a `409`: workers get no word of a cancellation, and superseding only
+/// drops queued jobs too, so there is nothing to reuse. Dropping a job mid-run would
+/// also strand the spare sessions its testcases borrowed.
+pub async fn cancel_job(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    let removed = state.db.queue.remove_queued(&id).await.map_err(|e| {
+        tracing::error!("Failed to remove job {} from the queue: {}", id, e);
         (
             StatusCode::INTERNAL_SERVER_ERROR,
             format!("Queue error: {}", e),
         )
     })?;
+    if let Some(job) = removed {
+        tracing::info!("Job {} cancelled", id);
+        report_cancelled(&state, &job, "Cancelled by request".to_string()).await;
+        return Ok(StatusCode::NO_CONTENT);
+    }
 
-    let result = state.db.queue.wait_for_result(&job_id).await.map_err(|e| {
-        tracing::error!("Failed to wait for result: {}", e);
+    let status = state
+        .db
+        .metadata
+        .get_job_state(&id)
+        .await
+        .map_err(metadata_error)?;
+    let message = match status.map(|s| s.state) {
+        None => {
+            return Err(ApiError::new(
+                StatusCode::NOT_FOUND,
+                format!("Job {} not found", id),
+            ));
+        }
+        Some(JobState::Queued) if state.db.queue.mode() == QueueMode::Stream => {
+            "queued jobs cannot be removed in stream queue mode".to_string()
+        }
+        Some(JobState::Queued | JobState::Running) => "already running".to_string(),
+        Some(done) => format!("already {}", done.as_str()),
+    };
+    Err(ApiError::new(
+        StatusCode::CONFLICT,
+        format!("Job {} cannot be cancelled: {}", id, message),
+    ))
+}
+
+/// Lifecycle and (once done) result of a job; `202 Accepted` while it has no result yet.
+pub async fn get_job(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<(StatusCode, Json<JobStatus>), ApiError> {
+    let result = state.db.queue.get_result(&id).await.map_err(|e| {
+        tracing::error!("Failed to get result for {}: {}", id, e);
         (
             StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Execution timeout or error: {}", e),
+            format!("Queue error: {}", e),
+        )
+    })?;
+    let status = state.db.metadata.get_job_state(&id).await.map_err(|e| {
+        tracing::error!("Failed to get state of {}: {}", id, e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Metadata error: {}", e),
         )
     })?;
 
-    Ok(Json(result))
+    match (status, result) {
+        (Some(mut status), Some(result)) => {
+            status.result = Some(result);
+            Ok((StatusCode::OK, Json(status)))
+        }
+        (Some(status), None) => Ok((StatusCode::ACCEPTED, Json(status))),
+        // Finished before lifecycle tracking, or its record expired first
+        (None, Some(result)) => Ok((
+            StatusCode::OK,
+            Json(JobStatus {
+                id,
+                state: JobState::Finished,
+                worker: None,
+                attempts: 0,
+                queued_at: None,
+                started_at: None,
+                finished_at: None,
+                result: Some(result),
+            }),
+        )),
+        (None, None) => Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            format!("Job {} not found", id),
+        )),
+    }
+}
+
+fn api_key(headers: &HeaderMap) -> Option<&str> {
+    headers.get(API_KEY_HEADER).and_then(|v| v.to_str().ok())
+}
+
+fn forwarded_for(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get("x-forwarded-for")
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+}
+
+/// The submission's `Idempotency-Key` (header, else request field), scoped to its API
+/// key so different callers' keys cannot collide.
+pub(crate) fn idempotency_key(
+    headers: &HeaderMap,
+    request: &JobRequest,
+) -> Result<Option<String>, ApiError> {
+    let key = match headers.get(IDEMPOTENCY_KEY_HEADER) {
+        Some(value) => Some(value.to_str().map_err(|_| {
+            ApiError::new(
+                StatusCode::BAD_REQUEST,
+                "Idempotency-Key must be visible ASCII",
+            )
+        })?),
+        None => request.idempotency_key.as_deref(),
+    };
+    scoped_idempotency_key(headers, key)
+}
+
+/// `key` scoped to the caller's API key, after checking its length.
+fn scoped_idempotency_key(
+    headers: &HeaderMap,
+    key: Option<&str>,
+) -> Result<Option<String>, ApiError> {
+    let Some(key) = key.filter(|key| !key.is_empty()) else {
+        return Ok(None);
+    };
+    if key.len() > MAX_IDEMPOTENCY_KEY_LEN {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Idempotency-Key is longer than {} bytes",
+                MAX_IDEMPOTENCY_KEY_LEN
+            ),
+        ));
+    }
+    // Hashed so the API key itself is not written into Redis key names
+    let caller = hex::encode(Sha256::digest(api_key(headers).unwrap_or_default()));
+    Ok(Some(format!("{}:{}", caller, key)))
+}
+
+/// Claims the idempotency `key`, if any, for `job_id`; returns the job an earlier
+/// submission with the key claimed it for. If Redis fails the submission just runs.
+async fn claim_idempotency(state: &AppState, key: Option<&str>, job_id: &str) -> Option<String> {
+    let key = key?;
+    let ttl = state.idempotency_ttl_secs;
+    match state
+        .db
+        .metadata
+        .claim_idempotency_key(key, job_id, ttl)
+        .await
+    {
+        Ok(original) => original,
+        Err(e) => {
+            tracing::warn!("Failed to claim idempotency key: {}", e);
+            None
+        }
+    }
+}
+
+/// Resolves the runtime of a submission and checks it against the caller's priority
+/// allowance and the server's limits, changing nothing. The runtime of a submission
+/// without a language is left to `prepare_submission`, which has its files.
+async fn check_submission(
+    state: &AppState,
+    headers: &HeaderMap,
+    request: &mut JobRequest,
+) -> Result<(), Response> {
+    if !request.language.is_empty() {
+        resolve_runtime(state, request).await?;
+    }
+    check_priority(state, headers, request).map_err(IntoResponse::into_response)?;
+    check_limits(&state.max_limits, request).map_err(IntoResponse::into_response)?;
+    if request.benchmark.is_some() && request.testcases.is_some() {
+        let msg = "benchmark cannot be combined with testcases";
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, msg).into_response());
+    }
+    if request.analysis.is_some() && (request.testcases.is_some() || request.benchmark.is_some()) {
+        let msg = "analysis cannot be combined with testcases or benchmark";
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, msg).into_response());
+    }
+    if request.interactor.is_some()
+        && (request.stdin.is_some()
+            || request.testcases.is_some()
+            || request.benchmark.is_some()
+            || request.tty == Some(true))
+    {
+        let msg = "interactor cannot be combined with stdin, testcases, benchmark or tty";
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, msg).into_response());
+    }
+    check_locale(request).map_err(IntoResponse::into_response)?;
+    if let Some(source) = &request.source {
+        git::validate(&source.git, state.fetcher.allowed_hosts())
+            .map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, e).into_response())?;
+    }
+    Ok(())
+}
+
+/// Files a submission writes to its workspace once it is sure to be queued.
+struct WorkspaceWrite {
+    id: String,
+    files: Vec<FileRequest>,
+}
+
+/// Fetches the files of a checked submission, adds the files of its workspace, and
+/// resolves the runtime its files suggest if it names no language. Returns the
+/// workspace write still to make.
+async fn prepare_submission(
+    state: &AppState,
+    request: &mut JobRequest,
+) -> Result<Option<WorkspaceWrite>, Response> {
+    state
+        .fetcher
+        .resolve(&mut request.files)
+        .await
+        .map_err(IntoResponse::into_response)?;
+    let mut write = None;
+    if let Some(id) = request.workspace.clone() {
+        let files = std::mem::take(&mut request.files);
+        request.files = workspace_files(state, &id, files.clone())
+            .await
+            .map_err(IntoResponse::into_response)?;
+        write = Some(WorkspaceWrite { id, files });
+    }
+    if request.language.is_empty() {
+        resolve_runtime(state, request).await?;
+    }
+    Ok(write)
+}
+
+/// Checks that `timezone` and `locale` look like a time zone or locale name, since they
+/// are passed to the job's processes as environment variables.
+fn check_locale(request: &JobRequest) -> Result<(), ApiError> {
+    let valid = |value: &str| {
+        !value.is_empty()
+            && value.len() <= 64
+            && !value.contains("..")
+            && value
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "_+-/.:@".contains(c))
+    };
+    for (field, value) in [("timezone", &request.timezone), ("locale", &request.locale)] {
+        if let Some(value) = value.as_deref().filter(|value| !valid(value)) {
+            let msg = format!("{} {:?} is not a valid {} name", field, value, field);
+            return Err(ApiError::new(StatusCode::BAD_REQUEST, msg));
+        }
+    }
+    Ok(())
+}
+
+/// Every file workspace `id` holds once a submission's `files` are saved to it, the
+/// submitted ones first.
+async fn workspace_files(
+    state: &AppState,
+    id: &str,
+    files: Vec<FileRequest>,
+) -> Result<Vec<FileRequest>, ApiError> {
+    let ttl = state.workspaces.ttl_secs;
+    let stored = state
+        .db
+        .metadata
+        .get_workspace(id, ttl)
+        .await
+        .map_err(metadata_error)?;
+    let Some(stored) = stored else {
+        return Err(workspace_not_found(id));
+    };
+    merge_workspace(state.workspaces.max_bytes, stored, files, &[])
+}
+
+/// A checked submission after claiming its idempotency key.
+enum Claimed {
+    /// An earlier submission claimed the key, for this job
+    Replayed(String),
+    /// The key (if any) is claimed for `id` and the files are ready to queue
+    Prepared {
+        id: String,
+        request: Box<JobRequest>,
+        workspace: Option<WorkspaceWrite>,
+    },
+}
+
+impl Claimed {
+    /// The job's ID: the earlier submission's for a replayed one.
+    fn id(&self) -> &str {
+        match self {
+            Claimed::Replayed(original) => original,
+            Claimed::Prepared { id, .. } => id,
+        }
+    }
+}
+
+/// Claims the idempotency key, if any, of a checked submission, then prepares it unless
+/// an earlier submission claimed the key. A key whose submission cannot be prepared is
+/// released.
+async fn claim_submission(
+    state: &AppState,
+    mut request: JobRequest,
+    idempotency: Option<&str>,
+) -> Result<Claimed, Response> {
+    let id = Uuid::new_v4().to_string();
+    if let Some(original) = claim_idempotency(state, idempotency, &id).await {
+        tracing::info!("Idempotent retry of job {}", original);
+        return Ok(Claimed::Replayed(original));
+    }
+    match prepare_submission(state, &mut request).await {
+        Ok(workspace) => Ok(Claimed::Prepared {
+            id,
+            request: Box::new(request),
+            workspace,
+        }),
+        Err(response) => {
+            release_idempotency(state, idempotency).await;
+            Err(response)
+        }
+    }
+}
+
+/// Queues a checked submission as a new job, unless its idempotency key maps it to an
+/// earlier submission's job, and returns the job without a result.
+async fn queue_submission(
+    state: &AppState,
+    headers: &HeaderMap,
+    request: JobRequest,
+    client_ip: Option<String>,
+    idempotency: Option<String>,
+) -> Result<BatchJob, Response> {
+    let claimed = claim_submission(state, request, idempotency.as_deref()).await?;
+    queue_claimed(state, headers, claimed, client_ip, idempotency)
+        .await
+        .map_err(IntoResponse::into_response)
+}
+
+/// Saves a claimed submission's files to its workspace and queues it, or returns the
+/// earlier job of a replayed one; the job comes without a result.
+async fn queue_claimed(
+    state: &AppState,
+    headers: &HeaderMap,
+    claimed: Claimed,
+    client_ip: Option<String>,
+    idempotency: Option<String>,
+) -> Result<BatchJob, ApiError> {
+    let (id, request, workspace) = match claimed {
+        Claimed::Replayed(original) => {
+            return Ok(BatchJob {
+                id: original,
+                sequence: None,
+                replayed: true,
+                result: None,
+                error: None,
+            });
+        }
+        Claimed::Prepared {
+            id,
+            request,
+            workspace,
+        } => (id, request, workspace),
+    };
+    if let Some(write) = workspace {
+        let ttl = state.workspaces.ttl_secs;
+        let saved = state
+            .db
+            .metadata
+            .update_workspace(&write.id, &write.files, &[], ttl)
+            .await;
+        if let Err(e) = saved {
+            release_idempotency(state, idempotency.as_deref()).await;
+            return Err(metadata_error(e));
+        }
+    }
+    let job = Job {
+        id: id.clone(),
+        request: *request,
+        kind: JobKind::Execute,
+        api_key: api_key(headers).map(str::to_string),
+        request_id: error::current_request_id(),
+        client_ip,
+        forwarded_for: forwarded_for(headers),
+    };
+    let sequence = enqueue_claimed(state, job, idempotency.as_deref()).await?;
+    Ok(BatchJob {
+        id,
+        sequence,
+        replayed: false,
+        result: None,
+        error: None,
+    })
+}
+
+/// `X-Turbo-Sequence` and `Idempotent-Replayed` for a queued submission.
+fn submission_headers(queued: &BatchJob) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    if let Some(sequence) = queued.sequence {
+        headers.insert(SEQUENCE_HEADER, HeaderValue::from(sequence));
+    }
+    if queued.replayed {
+        headers.insert(IDEMPOTENT_REPLAYED_HEADER, HeaderValue::from_static("true"));
+    }
+    headers
+}
+
+async fn wait_for_result(state: &AppState, job_id: &str) -> Result<JobResult, ApiError> {
+    state.db.queue.wait_for_result(job_id).await.map_err(|e| {
+        tracing::error!("Failed to wait for result: {}", e);
+        ApiError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Execution timeout or error: {}", e),
+        )
+    })
+}
+
+/// `enqueue`, releasing the idempotency key the job claimed if it can't be queued, so a
+/// retry runs instead of waiting on a job that never will.
+async fn enqueue_claimed(
+    state: &AppState,
+    job: Job,
+    idempotency: Option<&str>,
+) -> Result<Option<u64>, ApiError> {
+    let queued = enqueue(state, job).await;
+    if queued.is_err() {
+        release_idempotency(state, idempotency).await;
+    }
+    queued
+}
+
+/// Releases the idempotency `key`, if any, of a submission that was not queued, so a
+/// retry can run.
+async fn release_idempotency(state: &AppState, key: Option<&str>) {
+    let Some(key) = key else {
+        return;
+    };
+    if let Err(e) = state.db.metadata.release_idempotency_key(key).await {
+        tracing::warn!("Failed to release idempotency key: {}", e);
+    }
+}
+
+/// Reject a request asking for a higher priority than its API key allows.
+fn check_priority(
+    state: &AppState,
+    headers: &HeaderMap,
+    request: &JobRequest,
+) -> Result<(), ApiError> {
+    let requested = request.priority.unwrap_or_default();
+    let allowed = state.priority.max_for(api_key(headers));
+    if requested > allowed {
+        return Err(ApiError::new(
+            StatusCode::FORBIDDEN,
+            format!(
+                "Priority '{}' is not allowed for this API key (max '{}')",
+                requested.as_str(),
+                allowed.as_str()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// With `max_limits.reject`, refuse a request for more than the server maximums rather
+/// than letting the worker clamp it.
+fn check_limits(max: &MaxLimitsConfig, request: &JobRequest) -> Result<(), ApiError> {
+    if !max.reject {
+        return Ok(());
+    }
+    let memory_max = max.memory_limit_mb.map(|mb| mb * 1024 * 1024);
+    let checks = [
+        ("run_timeout", request.run_timeout, max.timeout_ms),
+        ("compile_timeout", request.compile_timeout, max.timeout_ms),
+        ("run_memory_limit", request.run_memory_limit, memory_max),
+        (
+            "compile_memory_limit",
+            request.compile_memory_limit,
+            memory_max,
+        ),
+        ("output_limit", request.output_limit, max.output_limit_bytes),
+        ("pid_limit", request.pid_limit, max.pid_limit),
+    ];
+    let exceeded: Vec<String> = checks
+        .into_iter()
+        .filter_map(|(field, requested, limit)| match (requested, limit) {
+            (Some(requested), Some(limit)) if requested > limit => {
+                Some(format!("{} {} (max {})", field, requested, limit))
+            }
+            _ => None,
+        })
+        .collect();
+    if exceeded.is_empty() {
+        return Ok(());
+    }
+    Err(ApiError::new(
+        StatusCode::UNPROCESSABLE_ENTITY,
+        format!(
+            "Requested limits exceed the server maximums: {}",
+            exceeded.join(", ")
+        ),
+    ))
+}
+
+/// Queue depth and estimated wait (ms) for a job of `priority` queued now: the average
+/// durations of the languages ahead of it, spread across the registered workers by their
+/// calibration scores. The averages are in reference-machine time (see the worker's
+/// `reference_ms`), so a worker with score 2.0 takes on twice the share of one with 1.0.
+async fn estimate_start(state: &AppState, priority: Priority) -> anyhow::Result<(u64, u64)> {
+    let queued = state.db.queue.queued_languages(priority).await?;
+    let averages = state.db.metadata.get_average_durations().await?;
+    let workers = state.db.metadata.list_workers().await?;
+    let scores = state.db.metadata.get_worker_scores().await?;
+    Ok(estimate_wait(&queued, &averages, &workers, &scores))
+}
+
+/// Queue depth and estimated wait (ms) behind the `queued` languages, from their average
+/// durations and the `workers`' calibration `scores`.
+fn estimate_wait(
+    queued: &[String],
+    averages: &HashMap<String, f64>,
+    workers: &[String],
+    scores: &[WorkerScore],
+) -> (u64, u64) {
+    let capacity: f64 = workers
+        .iter()
+        .map(|worker| worker::speed(scores.iter().find(|s| &s.worker == worker)))
+        .sum();
+    let capacity = if workers.is_empty() { 1.0 } else { capacity };
+
+    let total_ms: f64 = queued
+        .iter()
+        .map(|lang| {
+            averages
+                .get(lang)
+                .copied()
+                .unwrap_or(DEFAULT_JOB_DURATION_MS)
+        })
+        .sum();
+    (queued.len() as u64, (total_ms / capacity) as u64)
+}
+
+/// Push a job onto the queue, handling `supersedes`; returns the job's sequence number
+/// when it has a `supersedes` key.
+async fn enqueue(state: &AppState, job: Job) -> Result<Option<u64>, ApiError> {
+    let queue_error = |e: turbo_db::QueueError| {
+        tracing::error!("Failed to queue job: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Queue error: {}", e),
+        )
+    };
+
+    // Before the push, so a fast worker's `running` cannot be overwritten
+    if let Err(e) = state
+        .db
+        .metadata
+        .set_job_state(&job.id, JobState::Queued, None)
+        .await
+    {
+        tracing::warn!("Failed to record job {} as queued: {}", job.id, e);
+    }
+
+    let Some(key) = job.request.supersedes.clone() else {
+        state.db.queue.push_job(job).await.map_err(queue_error)?;
+        return Ok(None);
+    };
+
+    let job_id = job.id.clone();
+    let (sequence, cancelled) = state
+        .db
+        .queue
+        .push_job_superseding(&key, job)
+        .await
+        .map_err(queue_error)?;
+
+    if let Some(cancelled) = cancelled {
+        tracing::info!("Job {} superseded by {}", cancelled.id, job_id);
+        report_cancelled(state, &cancelled, format!("Superseded by job {}", job_id)).await;
+    }
+
+    Ok(Some(sequence))
+}
+
+/// Tell the waiter, the job's lifecycle record and its webhook that a job taken off the
+/// queue was cancelled, with `reason` as the run's stderr.
+async fn report_cancelled(state: &AppState, job: &Job, reason: String) {
+    let result = cancelled_result(job, reason);
+    if let Err(e) = state.db.queue.publish_result(&job.id, &result).await {
+        tracing::error!("Failed to publish cancellation for {}: {}", job.id, e);
+    }
+    if let Err(e) = state
+        .db
+        .metadata
+        .set_job_state(&job.id, JobState::Cancelled, None)
+        .await
+    {
+        tracing::warn!("Failed to record job {} as cancelled: {}", job.id, e);
+    }
+    webhooks::notify(&state.db, job, JobState::Cancelled, &result).await;
+}
+
+/// Rewrites a language alias (e.g. `py`) in `request` to the runtime's language name, so
+/// the queue, workers and results only see registered names, and rejects languages and
+/// versions with no registered runtime with a `422` listing the available ones. A
+/// request without a language gets the one its files suggest, or a `422`.
+///
+/// If the runtimes cannot be read the request is let through; the worker still reports
+/// a missing runtime.
+async fn resolve_runtime(state: &AppState, request: &mut JobRequest) -> Result<(), Response> {
+    if request.language.is_empty() {
+        request.language = state.packages.cache.detect(&request.files).map_err(|e| {
+            ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, e.to_string()).into_response()
+        })?;
+    }
+    let runtimes = match state.db.metadata.get_runtimes().await {
+        Ok(runtimes) => runtimes,
+        Err(e) => {
+            tracing::warn!("Failed to get runtimes to check a submission: {}", e);
+            return Ok(());
+        }
+    };
+    let known = runtimes.iter().any(|r| r.language == request.language);
+    let canonical = runtimes
+        .iter()
+        .find(|r| !known && r.aliases.contains(&request.language));
+    if let Some(runtime) = canonical {
+        request.language = runtime.language.clone();
+    }
+
+    let version = request.version.as_deref();
+    let found = runtimes
+        .iter()
+        .any(|r| r.language == request.language && version.is_none_or(|v| r.version == v));
+    if found {
+        return Ok(());
+    }
+    let message = match version {
+        Some(version) => format!("Runtime {}@{} is not installed", request.language, version),
+        None => format!("Runtime {} is not installed", request.language),
+    };
+    let error = ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, message);
+    let body = UnknownRuntime {
+        code: error.code(),
+        message: error.message,
+        request_id: error::current_request_id(),
+        language: request.language.clone(),
+        version: request.version.clone(),
+        available: runtimes,
+    };
+    Err((StatusCode::UNPROCESSABLE_ENTITY, Json(body)).into_response())
+}
+
+/// Result reported to the waiter of a job dropped from the queue.
+fn cancelled_result(job: &Job, reason: String) -> JobResult {
+    JobResult {
+        language: job.request.language.clone(),
+        version: job.request.version.clone().unwrap_or_default(),
+        run: Some(StageResult {
+            status: StageStatus::Cancelled,
+            stderr: reason,
+            ..stub_result()
+        }),
+        compile: None,
+        diagnostics: None,
+        analysis: None,
+        testcases: None,
+        effective_limits: None,
+        cached: false,
+        benchmark: None,
+        interactor: None,
+        metadata: job.request.metadata.clone(),
+    }
 }
 
 pub async fn get_runtimes(State(state): State<Arc<AppState>>) -> Json<Vec<Runtime>> {
@@ -47,3 +1004,818 @@ pub async fn health() -> StatusCode {
     StatusCode::OK
 }
 
+/// Wall-clock time (ms) of the calibration workload on the reference machine (score 1.0).
+const CALIBRATION_REFERENCE_MS: f64 = 500.0;
+/// How long to wait for a worker to report its calibration run.
+const CALIBRATION_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Runs the calibration workload on every registered worker and records their scores.
+///
+/// Workers that do not answer within `CALIBRATION_TIMEOUT` are left out of the response
+/// and keep their previous score.
+pub async fn benchmark(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<WorkerScore>>, ApiError> {
+    let workers = state.db.metadata.list_workers().await.map_err(|e| {
+        tracing::error!("Failed to list workers: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Metadata error: {}", e),
+        )
+    })?;
+
+    let mut pending = JoinSet::new();
+    for worker in workers {
+        let job = Job {
+            id: Uuid::new_v4().to_string(),
+            request: JobRequest {
+                language: "calibration".to_string(),
+                version: Some("1".to_string()),
+                ..Default::default()
+            },
+            kind: JobKind::Calibration,
+            api_key: None,
+            request_id: error::current_request_id(),
+            client_ip: None,
+            forwarded_for: None,
+        };
+        let job_id = job.id.clone();
+
+        state
+            .db
+            .queue
+            .push_job_to_worker(&worker, job)
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to queue calibration for {}: {}", worker, e);
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Queue error: {}", e),
+                )
+            })?;
+
+        let db = state.db.clone();
+        pending.spawn(async move {
+            let result =
+                tokio::time::timeout(CALIBRATION_TIMEOUT, db.queue.wait_for_result(&job_id)).await;
+            (worker, result)
+        });
+    }
+
+    let mut scores = Vec::new();
+    while let Some(joined) = pending.join_next().await {
+        let Ok((worker, result)) = joined else {
+            continue;
+        };
+        let run = match result {
+            Ok(Ok(JobResult { run: Some(run), .. })) if run.status == StageStatus::Success => run,
+            Ok(Ok(_)) => {
+                tracing::warn!("Calibration run failed on {}", worker);
+                continue;
+            }
+            Ok(Err(e)) => {
+                tracing::warn!("Failed to get calibration result from {}: {}", worker, e);
+                continue;
+            }
+            Err(_) => {
+                tracing::warn!("Worker {} did not answer calibration in time", worker);
+                continue;
+            }
+        };
+
+        let execution_time = run.execution_time.unwrap_or(0);
+        let score = WorkerScore {
+            worker,
+            score: CALIBRATION_REFERENCE_MS / execution_time.max(1) as f64,
+            cpu_time: run.cpu_time.unwrap_or(0),
+            execution_time,
+            measured_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+        };
+        if let Err(e) = state.db.metadata.set_worker_score(&score).await {
+            tracing::error!("Failed to store score for {}: {}", score.worker, e);
+        }
+        scores.push(score);
+    }
+
+    scores.sort_by(|a, b| a.worker.cmp(&b.worker));
+    Ok(Json(scores))
+}
+
+/// Prometheus text exposition of per-worker calibration scores.
+pub async fn metrics(State(state): State<Arc<AppState>>) -> String {
+    let scores = state
+        .db
+        .metadata
+        .get_worker_scores()
+        .await
+        .unwrap_or_else(|e| {
+            tracing::error!("Failed to get worker scores: {}", e);
+            vec![]
+        });
+
+    let mut out = String::new();
+    out.push_str(
+        "# HELP turbo_worker_score Relative worker performance from the last calibration run.\n",
+    );
+    out.push_str("# TYPE turbo_worker_score gauge\n");
+    for s in &scores {
+        out.push_str(&format!(
+            "turbo_worker_score{{worker=\"{}\"}} {}\n",
+            s.worker, s.score
+        ));
+    }
+    out.push_str(
+        "# HELP turbo_worker_calibration_ms Wall-clock time of the last calibration run.\n",
+    );
+    out.push_str("# TYPE turbo_worker_calibration_ms gauge\n");
+    for s in &scores {
+        out.push_str(&format!(
+            "turbo_worker_calibration_ms{{worker=\"{}\"}} {}\n",
+            s.worker, s.execution_time
+        ));
+    }
+
+    match state.db.queue.queue_depths().await {
+        Ok(depths) => {
+            out.push_str("# HELP turbo_queue_depth Jobs waiting for a worker.\n");
+            out.push_str("# TYPE turbo_queue_depth gauge\n");
+            for (priority, depth) in depths {
+                out.push_str(&format!(
+                    "turbo_queue_depth{{priority=\"{}\"}} {}\n",
+                    priority.as_str(),
+                    depth
+                ));
+            }
+        }
+        Err(e) => tracing::error!("Failed to get queue depths: {}", e),
+    }
+
+    match state.db.metadata.worker_heartbeats().await {
+        Ok(heartbeats) => {
+            let live: Vec<_> = heartbeats.into_iter().filter_map(|(_, hb)| hb).collect();
+            let busy = live.iter().filter(|hb| hb.current_job.is_some()).count();
+            out.push_str("# HELP turbo_workers Workers with a live heartbeat.\n");
+            out.push_str("# TYPE turbo_workers gauge\n");
+            out.push_str(&format!("turbo_workers {}\n", live.len()));
+            out.push_str("# HELP turbo_workers_busy Workers holding a job.\n");
+            out.push_str("# TYPE turbo_workers_busy gauge\n");
+            out.push_str(&format!("turbo_workers_busy {}\n", busy));
+        }
+        Err(e) => tracing::error!("Failed to get worker heartbeats: {}", e),
+    }
+
+    match state.db.metadata.get_stats().await {
+        Ok(stats) => {
+            let stat = |name: &str| stats.get(name).copied().unwrap_or(0);
+            for (outcome, help) in [("hits", "served from"), ("misses", "not found in")] {
+                out.push_str(&format!(
+                    "# HELP turbo_cache_{}_total Lookups {} the result or compile cache.\n",
+                    outcome, help
+                ));
+                out.push_str(&format!("# TYPE turbo_cache_{}_total counter\n", outcome));
+                for cache in ["result", "compile"] {
+                    out.push_str(&format!(
+                        "turbo_cache_{}_total{{cache=\"{}\"}} {}\n",
+                        outcome,
+                        cache,
+                        stat(&format!("{}_cache_{}", cache, outcome))
+                    ));
+                }
+            }
+        }
+        Err(e) => tracing::error!("Failed to get stats: {}", e),
+    }
+    out
+}
+
+/// Workers with a live heartbeat, with the job each holds and its uptime.
+pub async fn get_workers(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<WorkerHeartbeat>>, ApiError> {
+    let heartbeats = state
+        .db
+        .metadata
+        .worker_heartbeats()
+        .await
+        .map_err(metadata_error)?;
+    Ok(Json(
+        heartbeats.into_iter().filter_map(|(_, hb)| hb).collect(),
+    ))
+}
+
+/// Re-scan this node's runtimes directory and sync the registered runtimes with it.
+pub async fn refresh_runtimes(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<Runtime>>, ApiError> {
+    let installed = crate::packages::refresh(&state.packages, &state.db)
+        .await
+        .map_err(metadata_error)?;
+    Ok(Json(installed))
+}
+
+/// Packages in this node's repository and whether each is installed.
+pub async fn get_packages(State(state): State<Arc<AppState>>) -> Json<Vec<Package>> {
+    Json(package_list(&state.packages.cache))
+}
+
+/// Re-scan the repository and runtimes directory; returns the refreshed package list.
+pub async fn refresh_packages(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<Package>>, ApiError> {
+    crate::packages::refresh(&state.packages, &state.db)
+        .await
+        .map_err(metadata_error)?;
+    Ok(Json(package_list(&state.packages.cache)))
+}
+
+/// How long to wait for a worker host to report its install.
+const INSTALL_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// Install a package from this node's repository, and from their own on the hosts of the
+/// workers running elsewhere, streaming progress and build output as plain text lines.
+/// Another host's lines come prefixed with its name once its install is done. The last
+/// line is `DONE` or `FAILED: <reason>`.
+pub async fn install_package(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<InstallPackageRequest>,
+) -> Result<Response, ApiError> {
+    let plain = |s: &str| {
+        !s.is_empty()
+            && !s.starts_with('.')
+            && s.chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_' | '+'))
+    };
+    if !plain(&request.name) || !request.version.as_deref().is_none_or(plain) {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "Invalid package name or version".to_string(),
+        ));
+    }
+    let Ok(guard) = state.packages.installing.clone().try_lock_owned() else {
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            "Another install is running on this node".to_string(),
+        ));
+    };
+    // This node installs into its own TURBO_HOME; one worker of each other host installs
+    // into that host's. Workers predating `host` cannot run installs.
+    let host = worker::hostname();
+    let heartbeats = state
+        .db
+        .metadata
+        .worker_heartbeats()
+        .await
+        .map_err(metadata_error)?;
+    let mut hosts = BTreeMap::new();
+    for heartbeat in heartbeats
+        .into_iter()
+        .filter_map(|(_, heartbeat)| heartbeat)
+    {
+        if !heartbeat.host.is_empty() && heartbeat.host != host {
+            hosts.entry(heartbeat.host).or_insert(heartbeat.worker);
+        }
+    }
+    let mut remote = JoinSet::new();
+    for (worker_host, worker) in hosts {
+        let db = state.db.clone();
+        let request = request.clone();
+        remote.spawn(async move {
+            let outcome = install_on_worker(&db, &worker, request).await;
+            (worker_host, outcome)
+        });
+    }
+
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<String>();
+    let packages = &state.packages;
+    let mut manager = turbo_pkg::manager::PackageManager::new(
+        packages.turbo_home.clone(),
+        packages.repo_path.clone(),
+    )
+    .with_log(tx.clone());
+    if let Some(sandbox) = &packages.sandbox {
+        manager = manager.with_sandbox(sandbox.clone());
+    }
+
+    let state = state.clone();
+    tokio::spawn(async move {
+        let _guard = guard;
+        let installed = manager
+            .install(&request.name, request.version.as_deref())
+            .await;
+        let mut failed = Vec::new();
+        match installed {
+            Ok(()) => {
+                // Usable right away, without waiting for the runtimes watcher
+                if let Err(e) = crate::packages::refresh(&state.packages, &state.db).await {
+                    tracing::error!("Failed to refresh packages after install: {}", e);
+                }
+            }
+            Err(e) => failed.push(e.to_string()),
+        }
+        while let Some(joined) = remote.join_next().await {
+            let Ok((worker_host, (log, outcome))) = joined else {
+                continue;
+            };
+            for line in log.lines() {
+                let _ = tx.send(format!("{}: {}", worker_host, line));
+            }
+            match outcome {
+                Ok(()) => {
+                    let _ = tx.send(format!("{}: DONE", worker_host));
+                }
+                Err(reason) => {
+                    let _ = tx.send(format!("{}: FAILED: {}", worker_host, reason));
+                    failed.push(format!("{}: {}", worker_host, reason));
+                }
+            }
+        }
+        let outcome = if failed.is_empty() {
+            "DONE".to_string()
+        } else {
+            format!("FAILED: {}", failed.join("; "))
+        };
+        let _ = tx.send(outcome);
+    });
+
+    let lines = futures_util::stream::poll_fn(move |cx| {
+        rx.poll_recv(cx)
+            .map(|line| line.map(|line| Ok::<_, std::convert::Infallible>(line + "\n")))
+    });
+    Ok((
+        [(
+            axum::http::header::CONTENT_TYPE,
+            "text/plain; charset=utf-8",
+        )],
+        axum::body::Body::from_stream(lines),
+    )
+        .into_response())
+}
+
+/// Queues the install of `request` for `worker`, which runs it on its host, and waits for
+/// it; returns the install log and whether it succeeded.
+async fn install_on_worker(
+    db: &turbo_db::TurboDb,
+    worker: &str,
+    request: InstallPackageRequest,
+) -> (String, Result<(), String>) {
+    let job = Job {
+        id: Uuid::new_v4().to_string(),
+        request: JobRequest {
+            language: request.name,
+            version: request.version,
+            ..Default::default()
+        },
+        kind: JobKind::Install,
+        api_key: None,
+        request_id: error::current_request_id(),
+        client_ip: None,
+        forwarded_for: None,
+    };
+    let job_id = job.id.clone();
+    if let Err(e) = db.queue.push_job_to_worker(worker, job).await {
+        tracing::error!("Failed to queue install for {}: {}", worker, e);
+        return (String::new(), Err(format!("Queue error: {}", e)));
+    }
+    let result = tokio::time::timeout(INSTALL_TIMEOUT, db.queue.wait_for_result(&job_id)).await;
+    match result {
+        Ok(Ok(JobResult { run: Some(run), .. })) if run.status == StageStatus::Success => {
+            (run.stdout, Ok(()))
+        }
+        Ok(Ok(JobResult { run: Some(run), .. })) => (run.stdout, Err(run.stderr)),
+        Ok(Ok(_)) => (String::new(), Err("No install result".to_string())),
+        Ok(Err(e)) => (
+            String::new(),
+            Err(format!("Failed to get the result: {}", e)),
+        ),
+        Err(_) => (
+            String::new(),
+            Err(format!("{} did not finish the install in time", worker)),
+        ),
+    }
+}
+
+fn package_list(cache: &turbo_pkg::PackageCache) -> Vec<Package> {
+    cache
+        .list()
+        .into_iter()
+        .map(|p| Package {
+            language: p.name,
+            language_version: p.version,
+            installed: p.installed,
+        })
+        .collect()
+}
+
+fn metadata_error(e: anyhow::Error) -> ApiError {
+    tracing::error!("Metadata error: {}", e);
+    ApiError::new(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!("Metadata error: {}", e),
+    )
+}
+
+/// Configure the webhook notified about jobs submitted with API key `key`.
+pub async fn put_webhook(
+    State(state): State<Arc<AppState>>,
+    Path(key): Path<String>,
+    Json(webhook): Json<WebhookConfig>,
+) -> Result<Json<WebhookConfig>, ApiError> {
+    if !webhook.url.starts_with("http://") && !webhook.url.starts_with("https://") {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "url must be http(s)",
+        ));
+    }
+    if webhook.batch_size == 0 {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "batch_size must be at least 1",
+        ));
+    }
+    if let Some(event) = webhook.events.iter().find(|s| !s.is_terminal()) {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            format!("'{}' is not a terminal state", event.as_str()),
+        ));
+    }
+
+    state
+        .db
+        .metadata
+        .set_webhook(&key, &webhook)
+        .await
+        .map_err(metadata_error)?;
+    Ok(Json(webhook))
+}
+
+pub async fn get_webhook(
+    State(state): State<Arc<AppState>>,
+    Path(key): Path<String>,
+) -> Result<Json<WebhookConfig>, ApiError> {
+    match state
+        .db
+        .metadata
+        .get_webhook(&key)
+        .await
+        .map_err(metadata_error)?
+    {
+        Some(webhook) => Ok(Json(webhook)),
+        None => Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            "No webhook for this key",
+        )),
+    }
+}
+
+pub async fn delete_webhook(
+    State(state): State<Arc<AppState>>,
+    Path(key): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    if state
+        .db
+        .metadata
+        .delete_webhook(&key)
+        .await
+        .map_err(metadata_error)?
+    {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            "No webhook for this key",
+        ))
+    }
+}
+
+/// Batches that could not be delivered to the key's webhook, newest first.
+pub async fn get_dead_letters(
+    State(state): State<Arc<AppState>>,
+    Path(key): Path<String>,
+) -> Result<Json<Vec<DeadLetter>>, ApiError> {
+    let letters = state
+        .db
+        .metadata
+        .get_dead_letters(&key)
+        .await
+        .map_err(metadata_error)?;
+    Ok(Json(letters))
+}
+
+/// Uploads a project's files as a new workspace, for submissions to reference by ID.
+pub async fn create_workspace(
+    State(state): State<Arc<AppState>>,
+    Json(mut update): Json<WorkspaceUpdate>,
+) -> Result<(StatusCode, Json<Workspace>), ApiError> {
+    if update.files.is_empty() {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "A workspace needs at least one file",
+        ));
+    }
+    state.fetcher.resolve(&mut update.files).await?;
+    let id = Uuid::new_v4().to_string();
+    let files = merge_workspace(state.workspaces.max_bytes, Vec::new(), update.files, &[])?;
+    let ttl = state.workspaces.ttl_secs;
+    state
+        .db
+        .metadata
+        .update_workspace(&id, &files, &[], ttl)
+        .await
+        .map_err(metadata_error)?;
+    Ok((
+        StatusCode::CREATED,
+        Json(workspace_summary(&state, id, &files)),
+    ))
+}
+
+/// Writes and removes files of a workspace. Removing its last file deletes it.
+pub async fn update_workspace(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Json(mut update): Json<WorkspaceUpdate>,
+) -> Result<Json<Workspace>, ApiError> {
+    state.fetcher.resolve(&mut update.files).await?;
+    let ttl = state.workspaces.ttl_secs;
+    let stored = state
+        .db
+        .metadata
+        .get_workspace(&id, ttl)
+        .await
+        .map_err(metadata_error)?;
+    let Some(stored) = stored else {
+        return Err(workspace_not_found(&id));
+    };
+    let merged = merge_workspace(
+        state.workspaces.max_bytes,
+        stored,
+        update.files.clone(),
+        &update.remove,
+    )?;
+    state
+        .db
+        .metadata
+        .update_workspace(&id, &update.files, &update.remove, ttl)
+        .await
+        .map_err(metadata_error)?;
+    Ok(Json(workspace_summary(&state, id, &merged)))
+}
+
+pub async fn get_workspace(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<Workspace>, ApiError> {
+    let ttl = state.workspaces.ttl_secs;
+    match state
+        .db
+        .metadata
+        .get_workspace(&id, ttl)
+        .await
+        .map_err(metadata_error)?
+    {
+        Some(files) => Ok(Json(workspace_summary(&state, id, &files))),
+        None => Err(workspace_not_found(&id)),
+    }
+}
+
+pub async fn delete_workspace(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    if state
+        .db
+        .metadata
+        .delete_workspace(&id)
+        .await
+        .map_err(metadata_error)?
+    {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(workspace_not_found(&id))
+    }
+}
+
+/// `files` written over the `stored` files of a workspace, less those named in `remove`,
+/// checked against `workspaces.max_bytes` (`max_bytes`). The written files come first.
+fn merge_workspace(
+    max_bytes: usize,
+    stored: Vec<FileRequest>,
+    files: Vec<FileRequest>,
+    remove: &[String],
+) -> Result<Vec<FileRequest>, ApiError> {
+    let name = |file: &FileRequest| file.name.clone().unwrap_or_else(|| "main".to_string());
+    let mut merged: Vec<FileRequest> = Vec::new();
+    for file in files {
+        // A name given twice keeps the later file, as it does in Redis
+        merged.retain(|written| name(written) != name(&file));
+        merged.push(file);
+    }
+    for file in stored {
+        let replaced = merged.iter().any(|written| name(written) == name(&file));
+        if !replaced && !remove.contains(&name(&file)) {
+            merged.push(file);
+        }
+    }
+
+    let bytes: usize = merged.iter().map(|file| file.content.len()).sum();
+    if bytes > max_bytes {
+        return Err(ApiError::new(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!(
+                "Workspace would hold {} bytes, more than workspaces.max_bytes ({})",
+                bytes, max_bytes
+            ),
+        ));
+    }
+    Ok(merged)
+}
+
+fn workspace_summary(state: &AppState, id: String, files: &[FileRequest]) -> Workspace {
+    let mut names: Vec<String> = files
+        .iter()
+        .map(|file| file.name.clone().unwrap_or_else(|| "main".to_string()))
+        .collect();
+    names.sort();
+    Workspace {
+        id,
+        files: names,
+        bytes: files.iter().map(|file| file.content.len() as u64).sum(),
+        expires_in_secs: state.workspaces.ttl_secs,
+    }
+}
+
+fn workspace_not_found(id: &str) -> ApiError {
+    ApiError::new(
+        StatusCode::NOT_FOUND,
+        format!("Workspace {} not found or expired", id),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(name: &str, content: &str) -> FileRequest {
+        FileRequest {
+            name: Some(name.to_string()),
+            content: content.to_string(),
+            encoding: None,
+            url: None,
+        }
+    }
+
+    fn names_and_contents(files: &[FileRequest]) -> Vec<(String, String)> {
+        files
+            .iter()
+            .map(|file| (file.name.clone().unwrap(), file.content.clone()))
+            .collect()
+    }
+
+    fn max_limits(reject: bool) -> MaxLimitsConfig {
+        MaxLimitsConfig {
+            timeout_ms: Some(10_000),
+            memory_limit_mb: Some(256),
+            pid_limit: Some(64),
+            output_limit_bytes: None,
+            reject,
+        }
+    }
+
+    #[test]
+    fn merge_workspace_writes_over_stored_files_and_removes() {
+        let stored = vec![file("a", "old a"), file("b", "b"), file("c", "c")];
+        let files = vec![file("a", "first a"), file("d", "d"), file("a", "new a")];
+        let remove = ["c".to_string(), "d".to_string()];
+        let merged = merge_workspace(1024, stored, files, &remove).unwrap();
+        // A removed name that is also written is kept; a name given twice keeps the later
+        assert_eq!(
+            names_and_contents(&merged),
+            [
+                ("d".to_string(), "d".to_string()),
+                ("a".to_string(), "new a".to_string()),
+                ("b".to_string(), "b".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn merge_workspace_checks_the_merged_size() {
+        let stored = vec![file("a", "1234"), file("b", "1234")];
+        assert!(merge_workspace(8, stored.clone(), vec![file("a", "12")], &[]).is_ok());
+        let error = merge_workspace(8, stored, vec![file("c", "1")], &[]).unwrap_err();
+        assert_eq!(error.status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert!(error.message.contains("9 bytes"), "{}", error.message);
+    }
+
+    #[test]
+    fn check_limits_rejects_only_above_the_maximums_when_asked() {
+        let request = JobRequest {
+            run_timeout: Some(20_000),
+            compile_timeout: Some(10_000),
+            run_memory_limit: Some(512 << 20),
+            output_limit: Some(1 << 30),
+            ..Default::default()
+        };
+        assert!(check_limits(&max_limits(false), &request).is_ok());
+
+        let error = check_limits(&max_limits(true), &request).unwrap_err();
+        assert_eq!(error.status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            error.message,
+            "Requested limits exceed the server maximums: run_timeout 20000 (max 10000), \
+             run_memory_limit 536870912 (max 268435456)"
+        );
+
+        let within = JobRequest {
+            run_timeout: Some(10_000),
+            pid_limit: Some(64),
+            ..Default::default()
+        };
+        assert!(check_limits(&max_limits(true), &within).is_ok());
+    }
+
+    #[test]
+    fn check_locale_accepts_names_and_refuses_anything_else() {
+        let request = |timezone: &str, locale: &str| JobRequest {
+            timezone: Some(timezone.to_string()),
+            locale: Some(locale.to_string()),
+            ..Default::default()
+        };
+        assert!(check_locale(&JobRequest::default()).is_ok());
+        assert!(check_locale(&request("America/Argentina/Buenos_Aires", "C.UTF-8")).is_ok());
+        assert!(check_locale(&request("Etc/GMT+5", "sr_RS.UTF-8@latin")).is_ok());
+
+        let long = "A".repeat(65);
+        for (timezone, locale) in [
+            ("", "C"),
+            ("../../etc/passwd", "C"),
+            ("UTC", "en US"),
+            ("UTC", "C\nLD_PRELOAD=x"),
+            ("UTC", long.as_str()),
+        ] {
+            let error = check_locale(&request(timezone, locale)).unwrap_err();
+            assert_eq!(
+                error.status,
+                StatusCode::BAD_REQUEST,
+                "{:?}",
+                (timezone, locale)
+            );
+        }
+    }
+
+    #[test]
+    fn scoped_idempotency_key_is_per_api_key() {
+        let headers = |api_key: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(API_KEY_HEADER, HeaderValue::from_str(api_key).unwrap());
+            headers
+        };
+        let alice = headers("alice");
+        assert_eq!(scoped_idempotency_key(&alice, None).unwrap(), None);
+        assert_eq!(scoped_idempotency_key(&alice, Some("")).unwrap(), None);
+
+        let key = scoped_idempotency_key(&alice, Some("retry-1"))
+            .unwrap()
+            .unwrap();
+        assert!(key.ends_with(":retry-1"));
+        assert!(!key.contains("alice"));
+        assert_eq!(
+            scoped_idempotency_key(&alice, Some("retry-1")).unwrap(),
+            Some(key.clone())
+        );
+        let bob = scoped_idempotency_key(&headers("bob"), Some("retry-1")).unwrap();
+        assert_ne!(bob, Some(key.clone()));
+        let anonymous = scoped_idempotency_key(&HeaderMap::new(), Some("retry-1")).unwrap();
+        assert_ne!(anonymous, Some(key));
+
+        let long = "k".repeat(MAX_IDEMPOTENCY_KEY_LEN + 1);
+        let error = scoped_idempotency_key(&alice, Some(&long)).unwrap_err();
+        assert_eq!(error.status, StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn estimate_wait_spreads_queued_work_across_worker_speeds() {
+        let queued = ["python", "rust", "cobol"].map(str::to_string);
+        let averages = HashMap::from([("python".to_string(), 500.0), ("rust".to_string(), 2500.0)]);
+        let score = |worker: &str, score: f64| WorkerScore {
+            worker: worker.to_string(),
+            score,
+            cpu_time: 0,
+            execution_time: 0,
+            measured_at: 0,
+        };
+
+        // No workers yet: as if one reference-speed worker
+        assert_eq!(estimate_wait(&queued, &averages, &[], &[]), (3, 4000));
+        // An uncalibrated worker counts as reference speed
+        let workers = ["w1", "w2"].map(str::to_string);
+        let scores = [score("w1", 3.0)];
+        assert_eq!(
+            estimate_wait(&queued, &averages, &workers, &scores),
+            (3, 1000)
+        );
+        assert_eq!(estimate_wait(&[], &averages, &workers, &scores), (0, 0));
+    }
+}
@@ -1,2 +1,4 @@
+pub mod error;
+pub mod fetch;
 pub mod handlers;
 pub mod routes;
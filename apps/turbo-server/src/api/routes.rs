@@ -1,20 +1,135 @@
-use crate::api::handlers;
+use crate::api::fetch::Fetcher;
+use crate::api::{error, handlers};
+use crate::packages::Packages;
 use axum::{
     Router,
-    routing::{get, post},
+    extract::DefaultBodyLimit,
+    http::{HeaderName, HeaderValue, Method, header::CONTENT_TYPE},
+    middleware,
+    routing::{get, post, put},
 };
 use std::sync::Arc;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+use tower_http::limit::RequestBodyLimitLayer;
+use turbo_core::config::{
+    CorsConfig, MaxLimitsConfig, PriorityConfig, TurboConfig, WorkspacesConfig,
+};
 use turbo_db::TurboDb;
 pub struct AppState {
     pub db: TurboDb,
+    pub priority: PriorityConfig,
+    /// Server maximums, with the `sandbox` fallbacks applied
+    pub max_limits: MaxLimitsConfig,
+    pub packages: Packages,
+    /// `results.idempotency_ttl_secs`
+    pub idempotency_ttl_secs: u64,
+    pub workspaces: WorkspacesConfig,
+    pub fetcher: Fetcher,
 }
 
-pub fn app(db: TurboDb) -> Router {
-    let state = Arc::new(AppState { db });
+pub fn app(db: TurboDb, config: &TurboConfig, packages: Packages) -> anyhow::Result<Router> {
+    let state = Arc::new(AppState {
+        db,
+        priority: config.priority.clone(),
+        max_limits: config.max_limits.or_sandbox(&config.sandbox),
+        packages,
+        idempotency_ttl_secs: config.results.idempotency_ttl_secs(),
+        workspaces: config.workspaces.clone(),
+        fetcher: Fetcher::new(&config.fetch)?,
+    });
 
-    Router::new()
+    let router = Router::new()
         .route("/api/v1/execute", post(handlers::execute))
+        .route("/api/v1/execute/batch", post(handlers::execute_batch))
+        .route(
+            "/api/v1/jobs",
+            post(handlers::submit).get(handlers::list_jobs),
+        )
+        .route(
+            "/api/v1/jobs/:id",
+            get(handlers::get_job).delete(handlers::cancel_job),
+        )
+        .route("/api/v1/workspaces", post(handlers::create_workspace))
+        .route(
+            "/api/v1/workspaces/:id",
+            put(handlers::update_workspace)
+                .get(handlers::get_workspace)
+                .delete(handlers::delete_workspace),
+        )
         .route("/api/v1/runtimes", get(handlers::get_runtimes))
+        .route("/api/v1/packages", get(handlers::get_packages))
+        .route("/api/v1/packages/refresh", post(handlers::refresh_packages))
+        .route("/api/v1/packages/install", post(handlers::install_package))
+        .route("/api/v1/admin/benchmark", post(handlers::benchmark))
+        .route("/api/v1/admin/workers", get(handlers::get_workers))
+        .route(
+            "/api/v1/admin/runtimes/refresh",
+            post(handlers::refresh_runtimes),
+        )
+        .route(
+            "/api/v1/admin/webhooks/:key",
+            put(handlers::put_webhook)
+                .get(handlers::get_webhook)
+                .delete(handlers::delete_webhook),
+        )
+        .route(
+            "/api/v1/admin/webhooks/:key/dead-letters",
+            get(handlers::get_dead_letters),
+        )
         .route("/health", get(handlers::health))
+        .route("/metrics", get(handlers::metrics))
         .with_state(state)
+        // Refuses oversized bodies from their Content-Length before reading them; the
+        // extractors' own (smaller) default limit is lifted so this one applies
+        .layer(DefaultBodyLimit::disable())
+        .layer(RequestBodyLimitLayer::new(config.server.max_body_bytes))
+        .layer(middleware::from_fn(error::request_id));
+
+    // Outermost, so preflight requests are answered before anything else runs
+    Ok(match cors_layer(&config.server.cors)? {
+        Some(cors) => router.layer(cors),
+        None => router,
+    })
+}
+
+/// CORS for `cors.allowed_origins`; `None` when no origin is allowed.
+pub(crate) fn cors_layer(cors: &CorsConfig) -> anyhow::Result<Option<CorsLayer>> {
+    if cors.allowed_origins.is_empty() {
+        return Ok(None);
+    }
+    let origins = if cors.allowed_origins.iter().any(|origin| origin == "*") {
+        AllowOrigin::any()
+    } else {
+        let origins = cors
+            .allowed_origins
+            .iter()
+            .map(|origin| HeaderValue::from_str(origin))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| anyhow::anyhow!("Invalid server.cors.allowed_origins: {}", e))?;
+        AllowOrigin::list(origins)
+    };
+    let methods = cors
+        .allowed_methods
+        .iter()
+        .map(|method| Method::from_bytes(method.to_ascii_uppercase().as_bytes()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| anyhow::anyhow!("Invalid server.cors.allowed_methods: {}", e))?;
+
+    Ok(Some(
+        CorsLayer::new()
+            .allow_origin(origins)
+            .allow_methods(methods)
+            .allow_headers([
+                CONTENT_TYPE,
+                HeaderName::from_static(handlers::API_KEY_HEADER),
+                HeaderName::from_static(handlers::IDEMPOTENCY_KEY_HEADER),
+                HeaderName::from_static(error::REQUEST_ID_HEADER),
+            ])
+            .expose_headers([
+                HeaderName::from_static(handlers::SEQUENCE_HEADER),
+                HeaderName::from_static(handlers::IDEMPOTENT_REPLAYED_HEADER),
+                HeaderName::from_static(handlers::BATCH_INDEX_HEADER),
+                HeaderName::from_static(error::REQUEST_ID_HEADER),
+            ]),
+    ))
 }
@@ -0,0 +1,466 @@
+//! Append-only audit trail of executed jobs, kept in SQLite by the workers when
+//! `audit.path` is set, for deployments that must account for every program they run.
+//!
+//! Each worker host writes its own file; rows cannot be updated or deleted through SQLite.
+
+use rusqlite::{Connection, params};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+use turbo_core::models::{Job, JobResult, JobState, StageResult, StageStatus};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS audit (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        finished_at INTEGER NOT NULL,
+        job_id TEXT NOT NULL,
+        request_id TEXT,
+        client_ip TEXT,
+        forwarded_for TEXT,
+        api_key_sha256 TEXT,
+        worker TEXT NOT NULL,
+        language TEXT NOT NULL,
+        version TEXT NOT NULL,
+        code_sha256 TEXT NOT NULL,
+        state TEXT NOT NULL,
+        status TEXT,
+        testcases_passed INTEGER,
+        testcases_total INTEGER,
+        cpu_time_ms INTEGER,
+        peak_memory_bytes INTEGER,
+        wall_time_ms INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS audit_finished_at ON audit (finished_at);
+    CREATE TRIGGER IF NOT EXISTS audit_no_update BEFORE UPDATE ON audit
+        BEGIN SELECT RAISE(ABORT, 'the audit log is append-only'); END;
+    CREATE TRIGGER IF NOT EXISTS audit_no_delete BEFORE DELETE ON audit
+        BEGIN SELECT RAISE(ABORT, 'the audit log is append-only'); END;
+";
+
+/// Columns in `SCHEMA` order, without `id`.
+const COLUMNS: &str = "finished_at, job_id, request_id, client_ip, forwarded_for, \
+    api_key_sha256, worker, language, version, code_sha256, state, status, \
+    testcases_passed, testcases_total, cpu_time_ms, peak_memory_bytes, wall_time_ms";
+
+/// One executed job.
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditEntry {
+    /// Unix time (ms) the result was published
+    pub finished_at: u64,
+    pub job_id: String,
+    /// `X-Request-Id` of the submission
+    pub request_id: Option<String>,
+    /// Peer address of the submitting connection; none over a Unix socket
+    pub client_ip: Option<String>,
+    /// `X-Forwarded-For` of the submission, as sent (it is not verified)
+    pub forwarded_for: Option<String>,
+    /// SHA-256 of the API key, so keys can be told apart without being stored
+    pub api_key_sha256: Option<String>,
+    pub worker: String,
+    pub language: String,
+    pub version: String,
+    /// SHA-256 over the submitted files' names and contents
+    pub code_sha256: String,
+    /// Final job state, e.g. `finished`
+    pub state: String,
+    /// Status of the stage that decided the outcome (a failed compile, the first failing
+    /// testcase, else the run), e.g. `TIME_LIMIT_EXCEEDED`
+    pub status: Option<String>,
+    pub testcases_passed: Option<u64>,
+    pub testcases_total: Option<u64>,
+    /// CPU time across all stages
+    pub cpu_time_ms: Option<u64>,
+    /// Highest memory use of any stage
+    pub peak_memory_bytes: Option<u64>,
+    /// Time from the worker taking the job to publishing its result
+    pub wall_time_ms: u64,
+}
+
+impl AuditEntry {
+    pub fn new(
+        job: &Job,
+        result: &JobResult,
+        state: JobState,
+        worker: &str,
+        wall_time_ms: u64,
+    ) -> Self {
+        let mut code = Sha256::new();
+        for file in &job.request.files {
+            code.update(file.name.as_deref().unwrap_or_default().as_bytes());
+            code.update([0]);
+            code.update(file.content.as_bytes());
+            code.update([0]);
+        }
+
+        let testcases = result.testcases.as_deref();
+        let failed_testcase = testcases
+            .into_iter()
+            .flatten()
+            .find(|tc| !tc.passed)
+            .map(|tc| &tc.run_details);
+        let deciding = match &result.compile {
+            Some(compile) if compile.status != StageStatus::Success => Some(compile),
+            _ => failed_testcase.or(result.run.as_ref()),
+        };
+        let stages: Vec<&StageResult> = result
+            .compile
+            .iter()
+            .chain(result.run.iter())
+            .chain(testcases.into_iter().flatten().map(|tc| &tc.run_details))
+            .collect();
+
+        Self {
+            finished_at: unix_ms(),
+            job_id: job.id.clone(),
+            request_id: job.request_id.clone(),
+            client_ip: job.client_ip.clone(),
+            forwarded_for: job.forwarded_for.clone(),
+            api_key_sha256: job
+                .api_key
+                .as_ref()
+                .map(|key| hex::encode(Sha256::digest(key))),
+            worker: worker.to_string(),
+            language: result.language.clone(),
+            version: result.version.clone(),
+            code_sha256: hex::encode(code.finalize()),
+            state: state.as_str().to_string(),
+            status: deciding.and_then(|stage| {
+                serde_json::to_value(&stage.status)
+                    .ok()?
+                    .as_str()
+                    .map(str::to_string)
+            }),
+            testcases_passed: testcases.map(|tcs| tcs.iter().filter(|tc| tc.passed).count() as u64),
+            testcases_total: testcases.map(|tcs| tcs.len() as u64),
+            cpu_time_ms: stages
+                .iter()
+                .filter_map(|s| s.cpu_time)
+                .reduce(|a, b| a + b),
+            peak_memory_bytes: stages.iter().filter_map(|s| s.memory_usage).max(),
+            wall_time_ms,
+        }
+    }
+}
+
+pub struct AuditLog {
+    conn: Mutex<Connection>,
+}
+
+impl AuditLog {
+    /// Opens (creating if needed) the audit database at `path`.
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        let conn = Connection::open(path)?;
+        // Several worker processes on a host may share the file
+        conn.busy_timeout(Duration::from_secs(5))?;
+        conn.execute_batch("PRAGMA journal_mode = WAL;")?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Appends `entry`. Blocks on SQLite; call from a blocking task.
+    pub fn record(&self, entry: &AuditEntry) -> anyhow::Result<()> {
+        let conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        conn.execute(
+            &format!(
+                "INSERT INTO audit ({}) VALUES \
+                 (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17)",
+                COLUMNS
+            ),
+            params![
+                entry.finished_at as i64,
+                entry.job_id,
+                entry.request_id,
+                entry.client_ip,
+                entry.forwarded_for,
+                entry.api_key_sha256,
+                entry.worker,
+                entry.language,
+                entry.version,
+                entry.code_sha256,
+                entry.state,
+                entry.status,
+                entry.testcases_passed.map(|n| n as i64),
+                entry.testcases_total.map(|n| n as i64),
+                entry.cpu_time_ms.map(|n| n as i64),
+                entry.peak_memory_bytes.map(|n| n as i64),
+                entry.wall_time_ms as i64,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Entries that finished at or after `since` (unix ms), oldest first.
+    pub fn entries(&self, since: u64) -> anyhow::Result<Vec<AuditEntry>> {
+        let conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        let mut statement = conn.prepare(&format!(
+            "SELECT {} FROM audit WHERE finished_at >= ?1 ORDER BY id",
+            COLUMNS
+        ))?;
+        let unsigned = |n: Option<i64>| n.map(|n| n as u64);
+        let rows = statement.query_map(params![since as i64], |row| {
+            Ok(AuditEntry {
+                finished_at: row.get::<_, i64>(0)? as u64,
+                job_id: row.get(1)?,
+                request_id: row.get(2)?,
+                client_ip: row.get(3)?,
+                forwarded_for: row.get(4)?,
+                api_key_sha256: row.get(5)?,
+                worker: row.get(6)?,
+                language: row.get(7)?,
+                version: row.get(8)?,
+                code_sha256: row.get(9)?,
+                state: row.get(10)?,
+                status: row.get(11)?,
+                testcases_passed: unsigned(row.get(12)?),
+                testcases_total: unsigned(row.get(13)?),
+                cpu_time_ms: unsigned(row.get(14)?),
+                peak_memory_bytes: unsigned(row.get(15)?),
+                wall_time_ms: row.get::<_, i64>(16)? as u64,
+            })
+        })?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+}
+
+/// Column names for a CSV export, matching the fields of `AuditEntry`.
+pub fn csv_header() -> String {
+    COLUMNS
+        .split(',')
+        .map(str::trim)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// `entry` as a CSV record.
+pub fn csv_record(entry: &AuditEntry) -> String {
+    let opt = |v: &Option<String>| v.clone().unwrap_or_default();
+    let num = |v: Option<u64>| v.map(|n| n.to_string()).unwrap_or_default();
+    [
+        entry.finished_at.to_string(),
+        entry.job_id.clone(),
+        opt(&entry.request_id),
+        opt(&entry.client_ip),
+        opt(&entry.forwarded_for),
+        opt(&entry.api_key_sha256),
+        entry.worker.clone(),
+        entry.language.clone(),
+        entry.version.clone(),
+        entry.code_sha256.clone(),
+        entry.state.clone(),
+        opt(&entry.status),
+        num(entry.testcases_passed),
+        num(entry.testcases_total),
+        num(entry.cpu_time_ms),
+        num(entry.peak_memory_bytes),
+        entry.wall_time_ms.to_string(),
+    ]
+    .iter()
+    .map(|field| csv_field(field))
+    .collect::<Vec<_>>()
+    .join(",")
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn unix_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use turbo_core::models::{FileRequest, JobKind, JobRequest, TestcaseResult};
+    use turbo_engine::execute::{fail_job, stub_result};
+
+    fn job(files: &[(&str, &str)]) -> Job {
+        Job {
+            id: "job-1".to_string(),
+            request: JobRequest {
+                language: "python".to_string(),
+                files: files
+                    .iter()
+                    .map(|(name, content)| FileRequest {
+                        name: Some(name.to_string()),
+                        content: content.to_string(),
+                        encoding: None,
+                        url: None,
+                    })
+                    .collect(),
+                ..Default::default()
+            },
+            kind: JobKind::Execute,
+            api_key: Some("secret-key".to_string()),
+            request_id: Some("req-1".to_string()),
+            client_ip: Some("10.0.0.1".to_string()),
+            forwarded_for: None,
+        }
+    }
+
+    fn stage(status: StageStatus, cpu_time: u64, memory_usage: u64) -> StageResult {
+        StageResult {
+            status,
+            cpu_time: Some(cpu_time),
+            memory_usage: Some(memory_usage),
+            ..stub_result()
+        }
+    }
+
+    fn testcase(id: &str, passed: bool, run_details: StageResult) -> TestcaseResult {
+        TestcaseResult {
+            id: id.to_string(),
+            passed,
+            actual_output: String::new(),
+            expected_output: None,
+            diff: None,
+            hidden: false,
+            run_details,
+        }
+    }
+
+    fn job_result(
+        compile: Option<StageResult>,
+        testcases: Option<Vec<TestcaseResult>>,
+    ) -> JobResult {
+        JobResult {
+            compile,
+            run: None,
+            testcases,
+            ..fail_job(&JobRequest::default(), String::new())
+        }
+    }
+
+    /// A fresh directory for a test's database, which comes with WAL files.
+    fn scratch(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("turbo-audit-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn entry_reports_the_first_failing_testcase_and_totals() {
+        let testcases = vec![
+            testcase("1", true, stage(StageStatus::Success, 10, 100)),
+            testcase("2", false, stage(StageStatus::TimeLimitExceeded, 30, 300)),
+            testcase("3", false, stage(StageStatus::RuntimeError, 5, 50)),
+        ];
+        let compile = stage(StageStatus::Success, 200, 200);
+        let result = job_result(Some(compile), Some(testcases));
+        let entry = AuditEntry::new(&job(&[]), &result, JobState::Finished, "w1", 7);
+
+        assert_eq!(entry.status.as_deref(), Some("TIME_LIMIT_EXCEEDED"));
+        assert_eq!(entry.state, "finished");
+        assert_eq!(
+            (entry.testcases_passed, entry.testcases_total),
+            (Some(1), Some(3))
+        );
+        assert_eq!(entry.cpu_time_ms, Some(245));
+        assert_eq!(entry.peak_memory_bytes, Some(300));
+        assert_eq!(entry.wall_time_ms, 7);
+        assert_eq!(
+            entry.api_key_sha256.as_deref(),
+            Some(hex::encode(Sha256::digest("secret-key")).as_str())
+        );
+    }
+
+    #[test]
+    fn entry_reports_a_failed_compile_over_the_run() {
+        let compile = stage(StageStatus::CompilationError, 1, 1);
+        let mut result = job_result(Some(compile), None);
+        result.run = Some(stage(StageStatus::Skipped, 0, 0));
+        let entry = AuditEntry::new(&job(&[]), &result, JobState::Finished, "w1", 0);
+        assert_eq!(entry.status.as_deref(), Some("COMPILATION_ERROR"));
+        assert_eq!(
+            (entry.testcases_passed, entry.testcases_total),
+            (None, None)
+        );
+
+        let entry = AuditEntry::new(
+            &job(&[]),
+            &job_result(None, None),
+            JobState::Failed,
+            "w1",
+            0,
+        );
+        assert_eq!(entry.status, None);
+        assert_eq!(entry.cpu_time_ms, None);
+    }
+
+    #[test]
+    fn code_hash_tells_file_boundaries_apart() {
+        let result = job_result(None, None);
+        let hash = |files: &[(&str, &str)]| {
+            AuditEntry::new(&job(files), &result, JobState::Finished, "w1", 0).code_sha256
+        };
+        assert_eq!(hash(&[("a", "bc")]), hash(&[("a", "bc")]));
+        assert_ne!(hash(&[("a", "bc")]), hash(&[("ab", "c")]));
+        assert_ne!(hash(&[("a", "b"), ("c", "")]), hash(&[("a", "bc")]));
+    }
+
+    #[test]
+    fn log_keeps_entries_and_refuses_changes() {
+        let dir = scratch("log");
+        let log = AuditLog::open(&dir.join("audit.db")).unwrap();
+        let result = job_result(None, None);
+        let mut first = AuditEntry::new(&job(&[]), &result, JobState::Finished, "w1", 1);
+        first.finished_at = 1000;
+        let mut second = AuditEntry {
+            job_id: "job-2".to_string(),
+            ..first.clone()
+        };
+        second.finished_at = 2000;
+        log.record(&first).unwrap();
+        log.record(&second).unwrap();
+
+        let entries = log.entries(0).unwrap();
+        assert_eq!(
+            entries
+                .iter()
+                .map(|e| e.job_id.as_str())
+                .collect::<Vec<_>>(),
+            ["job-1", "job-2"]
+        );
+        assert_eq!(csv_record(&entries[0]), csv_record(&first));
+        let since: Vec<String> = log
+            .entries(1500)
+            .unwrap()
+            .into_iter()
+            .map(|e| e.job_id)
+            .collect();
+        assert_eq!(since, ["job-2"]);
+
+        let conn = log.conn.lock().unwrap();
+        assert!(conn.execute("UPDATE audit SET worker = 'w2'", []).is_err());
+        assert!(conn.execute("DELETE FROM audit", []).is_err());
+        drop(conn);
+        assert_eq!(log.entries(0).unwrap().len(), 2);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn csv_quotes_fields_that_need_it() {
+        let result = job_result(None, None);
+        let mut entry = AuditEntry::new(&job(&[]), &result, JobState::Finished, "w1", 0);
+        entry.forwarded_for = Some("1.2.3.4, 5.6.7.8".to_string());
+        entry.request_id = Some("say \"hi\"".to_string());
+        let record = csv_record(&entry);
+        assert!(record.contains(",\"say \"\"hi\"\"\",10.0.0.1,\"1.2.3.4, 5.6.7.8\","));
+        assert_eq!(csv_header().split(',').count(), 17);
+        assert!(csv_header().starts_with("finished_at,job_id,request_id,"));
+    }
+}
@@ -0,0 +1,43 @@
+//! Runs only the worker loops, so execution capacity can scale on machines separate from
+//! the API tier. Needs the same Redis and a runtimes directory with the same packages.
+
+use std::path::PathBuf;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use turbo_core::config::TurboConfig;
+use turbo_db::TurboDb;
+use turbo_server::{gc, runtimes, worker};
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::EnvFilter::new(
+            std::env::var("RUST_LOG").unwrap_or_else(|_| "turbo_server=debug".into()),
+        ))
+        .with(tracing_subscriber::fmt::layer())
+        .init();
+
+    tracing::info!("Starting Turbo Worker...");
+
+    let config = TurboConfig::new()?;
+    config.validate()?;
+    let runtimes_dir = PathBuf::from(&config.paths.turbo_home).join("runtimes");
+
+    let db = TurboDb::new(&config).await?;
+    tracing::info!("Connected to Redis ({:?} queue)", db.queue.mode());
+
+    // Only adds: other hosts may have runtimes this one lacks
+    match runtimes::register(&db, &runtimes_dir).await {
+        Ok(installed) => tracing::info!("Runtimes registered ({} installed)", installed.len()),
+        Err(e) => tracing::error!("Failed to register runtimes: {}", e),
+    }
+
+    if worker::spawn_workers(&config, &db, &runtimes_dir).await? == 0 {
+        anyhow::bail!("server.workers is 0; nothing to run");
+    }
+
+    tokio::spawn(gc::start_gc(config.results.clone()));
+
+    tokio::signal::ctrl_c().await?;
+    tracing::info!("Shutting down");
+    Ok(())
+}
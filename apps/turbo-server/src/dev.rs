@@ -0,0 +1,67 @@
+use std::path::Path;
+use tokio::fs;
+use tracing::{info, warn};
+use turbo_core::config::TurboConfig;
+
+/// Name and version of the runtime bundled for dev mode.
+const DEV_RUNTIME: (&str, &str) = ("sh", "1");
+const DEV_RUNTIME_YAML: &str = "name: sh
+version: \"1\"
+description: \"POSIX shell (bundled for dev mode)\"
+aliases:
+  - shell
+extensions:
+  - sh
+";
+/// Runs the submitted file with the host's /bin/sh; with `legacy_args`, `main` unless
+/// args name one.
+const DEV_RUNTIME_RUN: &str = "#!/bin/sh
+[ -n \"$TURBO_ENTRYPOINT\" ] && exec /bin/sh \"$TURBO_ENTRYPOINT\" \"$@\"
+[ $# -eq 0 ] && set -- main
+exec /bin/sh \"$@\"
+";
+/// Adjusts `config` so the server runs on a single unprivileged machine (`--dev`).
+/// Dev mode always runs the standalone server, so it needs no Redis either.
+///
+/// - Without root the sandbox falls back to the "mock" backend under `TURBO_HOME`.
+/// - The bundled `sh` runtime is installed if missing or outdated.
+pub async fn prepare(config: &mut TurboConfig, turbo_home: &Path) -> anyhow::Result<()> {
+    warn!("Dev mode: not for production use");
+
+    if !is_root() {
+        warn!("Dev mode: not running as root, using the mock sandbox backend");
+        config.sandbox.backend = "mock".to_string();
+        config.sandbox.root_path = turbo_home.join("sandbox").to_string_lossy().to_string();
+    }
+
+    let (name, version) = DEV_RUNTIME;
+    let runtime_dir = turbo_home.join("runtimes").join(name).join(version);
+    // Also replaces one an older version of turbo installed
+    let installed = fs::read_to_string(runtime_dir.join("run.sh")).await.ok();
+    if installed.as_deref() != Some(DEV_RUNTIME_RUN) {
+        install_dev_runtime(&runtime_dir).await?;
+        info!("Dev mode: installed bundled '{}' runtime", name);
+    }
+    Ok(())
+}
+
+/// Whether this process runs with an effective uid of 0; always false off Unix.
+pub fn is_root() -> bool {
+    #[cfg(unix)]
+    return nix::unistd::geteuid().is_root();
+    #[cfg(not(unix))]
+    false
+}
+
+async fn install_dev_runtime(dir: &Path) -> anyhow::Result<()> {
+    fs::create_dir_all(dir).await?;
+    fs::write(dir.join("package.yaml"), DEV_RUNTIME_YAML).await?;
+    let run = dir.join("run.sh");
+    fs::write(&run, DEV_RUNTIME_RUN).await?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&run, std::fs::Permissions::from_mode(0o755)).await?;
+    }
+    Ok(())
+}
@@ -1,14 +1,25 @@
-use std::path::PathBuf;
-use std::time::Duration;
+use crate::git::GIT_CACHE_DIR;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 use tokio::fs;
 use tracing::{error, info};
+use turbo_box::{Sandbox, SandboxPool};
+use turbo_core::config::{FetchConfig, ResultsConfig};
 
 const CACHE_DIR: &str = "/tmp/turbo-cache";
 const MAX_CACHE_ENTRIES: usize = 500;
 const GC_INTERVAL: u64 = 300; // 5 minutes
+/// Sandboxes the pool does not own are only removed once untouched for this long.
+const ORPHAN_MIN_AGE: Duration = Duration::from_secs(3600);
 
-pub async fn start_gc() {
-    info!("Garbage Collector started. Max entries: {}, Interval: {}s", MAX_CACHE_ENTRIES, GC_INTERVAL);
+/// Periodically trims the compile cache and, when results are offloaded to disk,
+/// removes offloaded results older than their TTL.
+pub async fn start_gc(results: ResultsConfig) {
+    info!(
+        "Garbage Collector started. Max entries: {}, Interval: {}s",
+        MAX_CACHE_ENTRIES, GC_INTERVAL
+    );
     let cache_path = PathBuf::from(CACHE_DIR);
 
     // Create cache dir if it doesn't exist, to avoid errors
@@ -21,7 +32,89 @@ pub async fn start_gc() {
         if let Err(e) = run_gc_pass(&cache_path).await {
             error!("GC Pass failed: {}", e);
         }
+        let git_cache = PathBuf::from(GIT_CACHE_DIR);
+        if git_cache.exists()
+            && let Err(e) = run_gc_pass(&git_cache).await
+        {
+            error!("Git checkout cache cleanup failed: {}", e);
+        }
+        if results.offload_threshold_bytes.is_some() {
+            let ttl = Duration::from_secs(results.ttl_secs);
+            if let Err(e) = prune_older_than(Path::new(&results.offload_dir), ttl).await {
+                error!("Offloaded result cleanup failed: {}", e);
+            }
+        }
+    }
+}
+
+/// Periodically removes files fetched for submissions (`fetch.cache_dir`) once they are
+/// too old to be reused.
+pub async fn start_fetch_cache_gc(fetch: FetchConfig) {
+    let ttl = Duration::from_secs(fetch.cache_ttl_secs);
+    loop {
+        tokio::time::sleep(Duration::from_secs(GC_INTERVAL)).await;
+        if let Err(e) = prune_older_than(Path::new(&fetch.cache_dir), ttl).await {
+            error!("Fetched file cache cleanup failed: {}", e);
+        }
+    }
+}
+
+/// Periodically removes sandbox working directories and cgroups that no session of `pool`
+/// owns, e.g. ones leaked by a crash or left over from a larger pool.
+///
+/// Assumes it is the only Turbo process on the host using `sandbox.root_path`.
+pub async fn start_sandbox_gc(
+    pool: Arc<SandboxPool<dyn Sandbox>>,
+    pool_size: usize,
+    workers: usize,
+) {
+    let in_use = move |id: &str| owned(id, pool_size, workers);
+    loop {
+        tokio::time::sleep(Duration::from_secs(GC_INTERVAL)).await;
+        match pool.sandbox().sweep_orphans(&in_use, ORPHAN_MIN_AGE).await {
+            Ok(0) => {}
+            Ok(n) => info!("GC: Removed {} orphaned sandboxes", n),
+            Err(e) => error!("Sandbox GC pass failed: {}", e),
+        }
+    }
+}
+
+/// Whether `id` is a sandbox this process keeps open: a pool slot (`pool-{n}`) or a
+/// worker's warm process (`warm-{worker}-...`), or the testcase snapshot beside one.
+fn owned(id: &str, pool_size: usize, workers: usize) -> bool {
+    let id = id
+        .strip_suffix(turbo_engine::execute::SNAPSHOT_SUFFIX)
+        .unwrap_or(id);
+    if let Some(n) = id.strip_prefix("pool-") {
+        return n.parse::<usize>().is_ok_and(|n| n < pool_size);
+    }
+    if let Some(rest) = id.strip_prefix("warm-") {
+        let worker = rest.split('-').next().and_then(|w| w.parse::<usize>().ok());
+        return worker.is_some_and(|w| w < workers);
+    }
+    false
+}
+
+/// Removes the files in `dir` last modified more than `ttl` ago, e.g. offloaded results
+/// whose Redis pointer has expired.
+async fn prune_older_than(dir: &Path, ttl: Duration) -> std::io::Result<()> {
+    if !dir.exists() {
+        return Ok(());
     }
+    let mut entries = fs::read_dir(dir).await?;
+    let now = SystemTime::now();
+
+    while let Some(entry) = entries.next_entry().await? {
+        let Ok(modified) = entry.metadata().await.and_then(|m| m.modified()) else {
+            continue;
+        };
+        if now.duration_since(modified).unwrap_or_default() > ttl
+            && let Err(e) = fs::remove_file(entry.path()).await
+        {
+            error!("Failed to remove {:?}: {}", entry.path(), e);
+        }
+    }
+    Ok(())
 }
 
 async fn run_gc_pass(path: &PathBuf) -> std::io::Result<()> {
@@ -29,12 +122,12 @@ async fn run_gc_pass(path: &PathBuf) -> std::io::Result<()> {
     let mut cache_items = Vec::new();
 
     while let Some(entry) = entries.next_entry().await? {
-        if let Ok(metadata) = entry.metadata().await {
-            if metadata.is_dir() {
-                // Use modified time of the directory itself
-                if let Ok(modified) = metadata.modified() {
-                    cache_items.push((entry.path(), modified));
-                }
+        if let Ok(metadata) = entry.metadata().await
+            && metadata.is_dir()
+        {
+            // Use modified time of the directory itself
+            if let Ok(modified) = metadata.modified() {
+                cache_items.push((entry.path(), modified));
             }
         }
     }
@@ -44,7 +137,7 @@ async fn run_gc_pass(path: &PathBuf) -> std::io::Result<()> {
     }
 
     // Sort by modified time (oldest first)
-    cache_items.sort_by(|a, b| a.1.cmp(&b.1));
+    cache_items.sort_by_key(|a| a.1);
 
     let to_remove = cache_items.len() - MAX_CACHE_ENTRIES;
     info!("GC: Cleaning up {} items", to_remove);
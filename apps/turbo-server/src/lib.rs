@@ -0,0 +1,16 @@
+//! Shared by the `turbo-server` (API and workers) and `turbo-worker` (workers only)
+//! binaries, and by `turbo start`, which embeds the full server.
+
+pub mod api;
+pub mod audit;
+pub mod dev;
+pub mod gc;
+pub mod git;
+pub mod packages;
+pub mod requeue;
+pub mod runtimes;
+pub mod server;
+pub mod standalone;
+pub mod tls;
+pub mod webhooks;
+pub mod worker;
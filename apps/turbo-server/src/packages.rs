@@ -0,0 +1,86 @@
+//! Keeps the package cache and the registered runtimes current as runtimes are installed
+//! and removed, without a restart.
+
+use notify::{RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use turbo_box::Sandbox;
+use turbo_core::models::Runtime;
+use turbo_db::TurboDb;
+use turbo_pkg::PackageCache;
+
+/// Changes are batched for this long, since an install writes many files.
+const DEBOUNCE: Duration = Duration::from_secs(2);
+
+/// This node's packages, for the package endpoints.
+pub struct Packages {
+    pub cache: Arc<PackageCache>,
+    /// Package definitions (`paths.packages_path`)
+    pub repo_path: PathBuf,
+    /// `paths.turbo_home`; runtimes are installed in its `runtimes` directory
+    pub turbo_home: PathBuf,
+    /// Where smoke tests of server-side installs run, if this node can create a sandbox
+    pub sandbox: Option<Arc<dyn Sandbox>>,
+    /// Held while a server-side install runs, so only one runs at a time
+    pub installing: Arc<Mutex<()>>,
+}
+
+impl Packages {
+    pub fn runtimes_dir(&self) -> PathBuf {
+        self.turbo_home.join("runtimes")
+    }
+}
+
+/// Refreshes the cache and the registered runtimes whenever `runtimes_dir` changes.
+///
+/// Does nothing if `runtimes_dir` does not exist (an API-only node); use the refresh
+/// endpoints there.
+pub async fn watch_runtimes(cache: Arc<PackageCache>, db: TurboDb, runtimes_dir: PathBuf) {
+    if !runtimes_dir.is_dir() {
+        tracing::info!("Not watching {:?}: no such directory", runtimes_dir);
+        return;
+    }
+
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        if event.is_ok() {
+            let _ = tx.send(());
+        }
+    });
+    let mut watcher = match watcher {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            tracing::warn!("Failed to watch {:?}: {}", runtimes_dir, e);
+            return;
+        }
+    };
+    // Recursive, so the final writes of an install (package.yaml) are seen too
+    if let Err(e) = watcher.watch(&runtimes_dir, RecursiveMode::Recursive) {
+        tracing::warn!("Failed to watch {:?}: {}", runtimes_dir, e);
+        return;
+    }
+
+    while rx.recv().await.is_some() {
+        tokio::time::sleep(DEBOUNCE).await;
+        while rx.try_recv().is_ok() {}
+        if let Err(e) = rescan(&cache, &db, &runtimes_dir).await {
+            tracing::error!("Failed to refresh packages: {}", e);
+        }
+    }
+}
+
+/// Rescan the package cache and sync the registered runtimes; returns the installed runtimes.
+pub async fn refresh(packages: &Packages, db: &TurboDb) -> anyhow::Result<Vec<Runtime>> {
+    rescan(&packages.cache, db, &packages.runtimes_dir()).await
+}
+
+async fn rescan(
+    cache: &PackageCache,
+    db: &TurboDb,
+    runtimes_dir: &Path,
+) -> anyhow::Result<Vec<Runtime>> {
+    cache.refresh().await?;
+    crate::runtimes::sync(db, runtimes_dir).await
+}
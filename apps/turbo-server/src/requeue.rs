@@ -0,0 +1,72 @@
+use std::time::Duration;
+use tracing::{error, info, warn};
+use turbo_db::TurboDb;
+
+/// Returns jobs held by dead workers to the queue once their visibility timeout passes.
+pub async fn start_requeuer(db: TurboDb, visibility_timeout: Duration) {
+    info!(
+        "Requeuer started. Visibility timeout: {}s",
+        visibility_timeout.as_secs()
+    );
+    // Check often enough that an expired job waits at most a quarter of the timeout more
+    let interval = (visibility_timeout / 4).max(Duration::from_secs(1));
+
+    loop {
+        tokio::time::sleep(interval).await;
+
+        let workers = match db.metadata.list_workers().await {
+            Ok(workers) => workers,
+            Err(e) => {
+                error!("Failed to list workers: {}", e);
+                continue;
+            }
+        };
+        match db.queue.requeue_expired(&workers, visibility_timeout).await {
+            Ok(0) => {}
+            Ok(n) => warn!("Requeued {} jobs past their visibility timeout", n),
+            Err(e) => error!("Requeue pass failed: {}", e),
+        }
+    }
+}
+
+/// How often registered workers are checked for an expired heartbeat.
+const REAP_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Unregisters workers whose heartbeat expired and puts the jobs they held back on the
+/// queue straight away, instead of after the visibility timeout.
+///
+/// A worker that was only stalled (e.g. cut off from Redis) re-registers with its next
+/// heartbeat; a job it was still running may then run twice.
+pub async fn start_reaper(db: TurboDb) {
+    info!("Reaper started");
+    loop {
+        tokio::time::sleep(REAP_INTERVAL).await;
+
+        let heartbeats = match db.metadata.worker_heartbeats().await {
+            Ok(heartbeats) => heartbeats,
+            Err(e) => {
+                error!("Failed to read worker heartbeats: {}", e);
+                continue;
+            }
+        };
+        let dead = heartbeats.into_iter().filter(|(_, hb)| hb.is_none());
+        for (worker, _) in dead {
+            // Whoever unregisters the worker reaps it, so concurrent servers don't race
+            match db.metadata.remove_worker(&worker).await {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(e) => {
+                    error!("Failed to unregister worker {}: {}", worker, e);
+                    continue;
+                }
+            }
+            match db.queue.requeue_dead_worker(&worker).await {
+                Ok(n) => warn!(
+                    "Worker {} stopped sending heartbeats; requeued {} jobs",
+                    worker, n
+                ),
+                Err(e) => error!("Failed to requeue jobs of dead worker {}: {}", worker, e),
+            }
+        }
+    }
+}
@@ -0,0 +1,100 @@
+//! Keeps the registered runtimes (`GET /api/v1/runtimes`) in line with what is installed.
+
+use std::collections::HashSet;
+use std::path::Path;
+use tokio::fs;
+use turbo_core::models::Runtime;
+use turbo_db::TurboDb;
+use turbo_pkg::installer::ARCH_FILE;
+use turbo_pkg::models::PackageDefinition;
+
+/// Registers every runtime installed under `runtimes_dir` and unregisters the ones that
+/// are not installed any more; returns the installed runtimes.
+///
+/// The registry is shared by every node, so only the API node syncs; workers, which may
+/// have fewer runtimes installed, only `register` theirs. Nothing is unregistered when
+/// `runtimes_dir` does not exist, so an API-only node without runtimes leaves the ones
+/// its workers registered alone.
+pub async fn sync(db: &TurboDb, runtimes_dir: &Path) -> anyhow::Result<Vec<Runtime>> {
+    if !runtimes_dir.exists() {
+        tracing::warn!("Runtimes directory not found: {:?}", runtimes_dir);
+        return Ok(Vec::new());
+    }
+
+    let installed = register(db, runtimes_dir).await?;
+
+    let keep: HashSet<_> = installed
+        .iter()
+        .map(|r| (r.language.as_str(), r.version.as_str()))
+        .collect();
+    for registered in db.metadata.get_runtimes().await? {
+        if !keep.contains(&(registered.language.as_str(), registered.version.as_str())) {
+            tracing::info!(
+                "Unregistering {}@{}, no longer installed",
+                registered.language,
+                registered.version
+            );
+            db.metadata
+                .remove_runtime(&registered.language, &registered.version)
+                .await?;
+        }
+    }
+    Ok(installed)
+}
+
+/// Registers every runtime installed under `runtimes_dir`, leaving the others registered;
+/// returns the installed runtimes.
+pub async fn register(db: &TurboDb, runtimes_dir: &Path) -> anyhow::Result<Vec<Runtime>> {
+    if !runtimes_dir.exists() {
+        tracing::warn!("Runtimes directory not found: {:?}", runtimes_dir);
+        return Ok(Vec::new());
+    }
+    let installed = scan(runtimes_dir).await?;
+    for runtime in &installed {
+        db.metadata.add_runtime(runtime).await?;
+    }
+    Ok(installed)
+}
+
+/// Runtimes installed under `runtimes_dir`, as `<language>/<version>/package.yaml`.
+pub(crate) async fn scan(runtimes_dir: &Path) -> anyhow::Result<Vec<Runtime>> {
+    let mut runtimes = Vec::new();
+    let mut entries = fs::read_dir(runtimes_dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let lang_path = entry.path();
+        if !lang_path.is_dir() {
+            continue;
+        }
+        let lang = entry.file_name().to_string_lossy().to_string();
+        let mut ver_entries = fs::read_dir(&lang_path).await?;
+        while let Some(ver_entry) = ver_entries.next_entry().await? {
+            let ver_path = ver_entry.path();
+            if !ver_path.is_dir() {
+                continue;
+            }
+            let version = ver_entry.file_name().to_string_lossy().to_string();
+
+            // Note: PackageDefinition::from_path uses std::fs (blocking)
+            match PackageDefinition::from_path(ver_path.clone()) {
+                Ok(pkg_def) => runtimes.push(Runtime {
+                    language: lang.clone(),
+                    version,
+                    aliases: pkg_def.yaml.aliases.clone().unwrap_or_default(),
+                    runtime: None,
+                    arch: installed_arch(&ver_path).await,
+                }),
+                Err(e) => {
+                    tracing::warn!("Skipping invalid runtime at {:?}: {}", ver_path, e);
+                }
+            }
+        }
+    }
+    runtimes.sort_by(|a, b| (&a.language, &a.version).cmp(&(&b.language, &b.version)));
+    Ok(runtimes)
+}
+
+/// Architecture recorded by the installer; `None` for runtimes installed before it did.
+async fn installed_arch(dir: &Path) -> Option<String> {
+    let arch = fs::read_to_string(dir.join(ARCH_FILE)).await.ok()?;
+    Some(arch.trim().to_string())
+}
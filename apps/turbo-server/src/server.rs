@@ -0,0 +1,132 @@
+use crate::{api, gc, packages, requeue, runtimes, tls, webhooks, worker};
+use axum::Router;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use hyper_util::service::TowerToHyperService;
+use std::net::SocketAddr;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use turbo_core::config::{Listen, TurboConfig};
+use turbo_db::TurboDb;
+use turbo_pkg::PackageCache;
+
+/// Runs the full server (API, local workers and background tasks) until the listener fails.
+///
+/// Used by the `turbo-server` binary and by `turbo start`; dev mode runs
+/// [`crate::standalone::run`] instead.
+pub async fn run(config: TurboConfig) -> anyhow::Result<()> {
+    config.validate()?;
+
+    // Use paths from config (which can be overridden via turbo.toml or TURBO_PATHS_* env vars)
+    let turbo_home = PathBuf::from(&config.paths.turbo_home);
+    let runtimes_dir = turbo_home.join("runtimes");
+
+    tracing::info!("Turbo home: {:?}", turbo_home);
+
+    let db = TurboDb::new(&config).await?;
+    tracing::info!("Combined DB/Queue connected ({:?} queue)", db.queue.mode());
+
+    // Populate runtimes
+    match runtimes::sync(&db, &runtimes_dir).await {
+        Ok(installed) => tracing::info!("Runtimes synced ({} installed)", installed.len()),
+        Err(e) => tracing::error!("Failed to sync runtimes: {}", e),
+    }
+
+    // With `server.workers = 0` this is an API-only node; `turbo-worker` processes run jobs
+    if worker::spawn_workers(&config, &db, &runtimes_dir).await? == 0 {
+        tracing::info!("No local workers; jobs run on separate turbo-worker processes");
+    }
+
+    let visibility_timeout = Duration::from_secs(config.redis.visibility_timeout_secs);
+    tokio::spawn(requeue::start_requeuer(db.clone(), visibility_timeout));
+    tokio::spawn(requeue::start_reaper(db.clone()));
+    tokio::spawn(webhooks::start_dispatcher(db.clone()));
+
+    // Spawn Garbage Collector
+    tokio::spawn(gc::start_gc(config.results.clone()));
+    if !config.fetch.allowed_hosts.is_empty() {
+        tokio::spawn(gc::start_fetch_cache_gc(config.fetch.clone()));
+    }
+
+    let repo_path = PathBuf::from(&config.paths.packages_path);
+    let cache = Arc::new(PackageCache::from_paths(repo_path.clone(), runtimes_dir.clone()).await?);
+    tokio::spawn(packages::watch_runtimes(
+        cache.clone(),
+        db.clone(),
+        runtimes_dir.clone(),
+    ));
+    let sandbox = turbo_box::create_sandbox(&config.sandbox)
+        .inspect_err(|e| tracing::warn!("No sandbox for package smoke tests: {}", e))
+        .ok();
+    let packages = packages::Packages {
+        cache,
+        repo_path,
+        turbo_home,
+        sandbox,
+        installing: Default::default(),
+    };
+
+    let app = api::routes::app(db, &config, packages)?;
+    serve(&config, app).await
+}
+
+/// Serves `app` on `server.listen` (TCP, with TLS if `server.tls` is set, or a Unix
+/// socket) until the listener fails.
+pub(crate) async fn serve(config: &TurboConfig, app: Router) -> anyhow::Result<()> {
+    let addr = match config.server.listen_on().map_err(anyhow::Error::msg)? {
+        Listen::Tcp(addr) => addr,
+        Listen::Unix(path) => {
+            let mode = config.server.socket_mode().map_err(anyhow::Error::msg)?;
+            return serve_unix(&path, mode, app).await;
+        }
+    };
+    if let Some(tls_config) = config.server.tls.clone() {
+        let rustls = tls::load(&tls_config).await?;
+        tokio::spawn(tls::watch_certificate(rustls.clone(), tls_config));
+        tracing::info!("Listening on {} (HTTPS)", addr);
+        axum_server::bind_rustls(addr, rustls)
+            .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+            .await?;
+        return Ok(());
+    }
+
+    tracing::info!("Listening on {}", addr);
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Serves `app` on a Unix domain socket at `path`, replacing a socket left by an earlier
+/// run, with permissions `mode` if given.
+async fn serve_unix(path: &Path, mode: Option<u32>, app: Router) -> anyhow::Result<()> {
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_socket() => std::fs::remove_file(path)?,
+        Ok(_) => anyhow::bail!("{} exists and is not a socket", path.display()),
+        Err(_) => {}
+    }
+    let listener = tokio::net::UnixListener::bind(path)?;
+    if let Some(mode) = mode {
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    }
+    tracing::info!("Listening on unix:{}", path.display());
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let service = TowerToHyperService::new(app.clone());
+        tokio::spawn(async move {
+            let connection = auto::Builder::new(TokioExecutor::new())
+                .serve_connection_with_upgrades(TokioIo::new(stream), service)
+                .await;
+            if let Err(e) = connection {
+                tracing::debug!("Unix socket connection failed: {}", e);
+            }
+        });
+    }
+}
@@ -0,0 +1,794 @@
+//! `--standalone`: the server in a single process, without Redis.
+//!
+//! Submissions go through an in-process channel to workers running jobs on an embedded
+//! [`Engine`], and `POST /api/v1/execute` waits for its job's result on a oneshot channel.
+//! Each job's lifecycle and result are kept in SQLite (`TURBO_HOME/standalone.db`) for
+//! `GET /api/v1/jobs/{id}`, so nothing but the binary is needed.
+//!
+//! Only the job, runtime and health endpoints are served. Priorities, batches, workspaces,
+//! packages, webhooks and the admin endpoints need the full server. As with the Redis
+//! queue, an `Idempotency-Key` retry gets the first submission's job, and jobs still
+//! queued or running when the server stops are queued again on restart.
+
+use crate::api::error::{self, ApiError};
+use crate::api::{handlers, routes};
+use crate::{dev, runtimes, server, worker};
+use axum::{
+    Json, Router,
+    extract::{DefaultBodyLimit, Path, State},
+    http::{HeaderMap, HeaderValue, StatusCode},
+    middleware,
+    routing::{get, post},
+};
+use rusqlite::{Connection, OptionalExtension, params};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::{mpsc, oneshot};
+use tower_http::limit::RequestBodyLimitLayer;
+use tracing::{Instrument, error, info};
+use turbo_core::config::TurboConfig;
+use turbo_core::models::{JobAccepted, JobRequest, JobResult, JobState, JobStatus, Runtime};
+use turbo_engine::Engine;
+use turbo_pkg::PackageCache;
+use uuid::Uuid;
+
+/// File under `TURBO_HOME` holding the jobs.
+const DB_FILE: &str = "standalone.db";
+/// Jobs waiting for a worker before further submissions are refused with `503`.
+const QUEUE_CAPACITY: usize = 1024;
+/// How often an idempotent retry of `POST /api/v1/execute` checks for the result of the
+/// job it waits on.
+const RESULT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS jobs (
+        id TEXT PRIMARY KEY,
+        state TEXT NOT NULL,
+        queued_at INTEGER NOT NULL,
+        started_at INTEGER,
+        finished_at INTEGER,
+        result TEXT
+    );
+    CREATE INDEX IF NOT EXISTS jobs_finished_at ON jobs (finished_at);
+    CREATE TABLE IF NOT EXISTS idempotency (
+        key TEXT PRIMARY KEY,
+        job_id TEXT NOT NULL,
+        expires_at INTEGER NOT NULL
+    );
+";
+
+/// Columns added to `jobs` since it was first created, with their types.
+const ADDED_COLUMNS: [(&str, &str); 2] = [
+    ("request", "TEXT"),
+    ("attempts", "INTEGER NOT NULL DEFAULT 0"),
+];
+
+/// A submission waiting for a worker.
+struct Queued {
+    id: String,
+    request: JobRequest,
+    /// Where `POST /api/v1/execute` waits for the result; none for `POST /api/v1/jobs`
+    done: Option<oneshot::Sender<JobResult>>,
+}
+
+/// Lifecycle and results of jobs, kept for `results.ttl_secs` after they finish.
+struct JobStore {
+    conn: Mutex<Connection>,
+    ttl_secs: u64,
+}
+
+impl JobStore {
+    /// Opens (creating if needed) the job database at `path`, putting the jobs an earlier
+    /// run left unfinished back in the queue (see `unfinished`).
+    fn open(path: &std::path::Path, ttl_secs: u64) -> anyhow::Result<Self> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        let conn = Connection::open(path)?;
+        conn.execute_batch("PRAGMA journal_mode = WAL;")?;
+        conn.execute_batch(SCHEMA)?;
+        add_columns(&conn)?;
+        // Queued before requests were kept, so there is nothing to run again
+        let abandoned = conn.execute(
+            "UPDATE jobs SET state = ?1, finished_at = ?2 \
+             WHERE finished_at IS NULL AND request IS NULL",
+            params![JobState::Failed.as_str(), unix_ms() as i64],
+        )?;
+        if abandoned > 0 {
+            info!(
+                "Marked {} jobs left unfinished by the last run as failed",
+                abandoned
+            );
+        }
+        conn.execute(
+            "UPDATE jobs SET state = ?1 WHERE finished_at IS NULL",
+            params![JobState::Queued.as_str()],
+        )?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+            ttl_secs,
+        })
+    }
+
+    fn queued(&self, id: &str, request: &JobRequest) -> anyhow::Result<()> {
+        let conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        conn.execute(
+            "INSERT INTO jobs (id, state, queued_at, request) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                id,
+                JobState::Queued.as_str(),
+                unix_ms() as i64,
+                serde_json::to_string(request)?,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Records a worker taking job `id`, counting the attempt.
+    fn started(&self, id: &str) -> anyhow::Result<()> {
+        let conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        conn.execute(
+            "UPDATE jobs SET state = ?2, started_at = ?3, attempts = attempts + 1 WHERE id = ?1",
+            params![id, JobState::Running.as_str(), unix_ms() as i64],
+        )?;
+        Ok(())
+    }
+
+    /// Jobs without a result, oldest first, for handing to the workers again after a
+    /// restart.
+    fn unfinished(&self) -> anyhow::Result<Vec<(String, JobRequest)>> {
+        let conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        let mut statement = conn.prepare(
+            "SELECT id, request FROM jobs \
+             WHERE finished_at IS NULL AND request IS NOT NULL ORDER BY queued_at, rowid",
+        )?;
+        let rows = statement.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?;
+        let mut jobs = Vec::new();
+        for row in rows {
+            let (id, request) = row?;
+            jobs.push((id, serde_json::from_str(&request)?));
+        }
+        Ok(jobs)
+    }
+
+    /// Remembers `job_id` under the idempotency `key` for `ttl_secs`, unless the key is
+    /// already taken; returns the job it was taken for.
+    fn claim_idempotency(
+        &self,
+        key: &str,
+        job_id: &str,
+        ttl_secs: u64,
+    ) -> anyhow::Result<Option<String>> {
+        let now = unix_ms();
+        let conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        conn.execute(
+            "DELETE FROM idempotency WHERE expires_at <= ?1",
+            params![now as i64],
+        )?;
+        let claimed = conn.execute(
+            "INSERT OR IGNORE INTO idempotency (key, job_id, expires_at) VALUES (?1, ?2, ?3)",
+            params![key, job_id, (now + ttl_secs * 1000) as i64],
+        )?;
+        if claimed > 0 {
+            return Ok(None);
+        }
+        let original = conn.query_row(
+            "SELECT job_id FROM idempotency WHERE key = ?1",
+            params![key],
+            |row| row.get(0),
+        )?;
+        Ok(Some(original))
+    }
+
+    /// Forgets the idempotency `key`, e.g. when the job claiming it could not be queued.
+    fn release_idempotency(&self, key: &str) -> anyhow::Result<()> {
+        let conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        conn.execute("DELETE FROM idempotency WHERE key = ?1", params![key])?;
+        Ok(())
+    }
+
+    /// Records the result of job `id` and drops the jobs that finished more than
+    /// `ttl_secs` ago.
+    fn finished(&self, id: &str, result: &JobResult) -> anyhow::Result<()> {
+        let now = unix_ms();
+        let expired = now.saturating_sub(self.ttl_secs * 1000);
+        let conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        conn.execute(
+            "UPDATE jobs SET state = ?2, finished_at = ?3, result = ?4 WHERE id = ?1",
+            params![
+                id,
+                worker::final_state(result).as_str(),
+                now as i64,
+                serde_json::to_string(result)?,
+            ],
+        )?;
+        conn.execute(
+            "DELETE FROM jobs WHERE finished_at < ?1",
+            params![expired as i64],
+        )?;
+        Ok(())
+    }
+
+    fn get(&self, id: &str) -> anyhow::Result<Option<JobStatus>> {
+        let conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        let row = conn
+            .query_row(
+                "SELECT state, queued_at, started_at, finished_at, result, attempts \
+                 FROM jobs WHERE id = ?1",
+                params![id],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, i64>(1)?,
+                        row.get::<_, Option<i64>>(2)?,
+                        row.get::<_, Option<i64>>(3)?,
+                        row.get::<_, Option<String>>(4)?,
+                        row.get::<_, i64>(5)?,
+                    ))
+                },
+            )
+            .optional()?;
+        let Some((state, queued_at, started_at, finished_at, result, attempts)) = row else {
+            return Ok(None);
+        };
+        Ok(Some(JobStatus {
+            id: id.to_string(),
+            state: serde_json::from_value(serde_json::Value::String(state))?,
+            worker: None,
+            attempts: attempts as u64,
+            queued_at: Some(queued_at as u64),
+            started_at: started_at.map(|t| t as u64),
+            finished_at: finished_at.map(|t| t as u64),
+            result: result.map(|r| serde_json::from_str(&r)).transpose()?,
+        }))
+    }
+}
+
+/// Adds the `ADDED_COLUMNS` a job database from an older version lacks.
+fn add_columns(conn: &Connection) -> anyhow::Result<()> {
+    let mut statement = conn.prepare("SELECT name FROM pragma_table_info('jobs')")?;
+    let existing = statement
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    for (column, definition) in ADDED_COLUMNS {
+        if !existing.iter().any(|name| name == column) {
+            conn.execute_batch(&format!(
+                "ALTER TABLE jobs ADD COLUMN {} {}",
+                column, definition
+            ))?;
+        }
+    }
+    Ok(())
+}
+
+/// Runs `f` on the store in a blocking task.
+async fn blocking<T: Send + 'static>(
+    store: &Arc<JobStore>,
+    f: impl FnOnce(&JobStore) -> anyhow::Result<T> + Send + 'static,
+) -> anyhow::Result<T> {
+    let store = store.clone();
+    tokio::task::spawn_blocking(move || f(&store)).await?
+}
+
+struct StandaloneState {
+    queue: mpsc::Sender<Queued>,
+    store: Arc<JobStore>,
+    runtimes_dir: PathBuf,
+    /// Packages of `paths.packages_path`, for inferring a submission's language
+    packages: PackageCache,
+    /// `results.idempotency_ttl_secs`
+    idempotency_ttl_secs: u64,
+}
+
+/// Runs the standalone server until the listener fails.
+///
+/// `dev` applies [`dev::prepare`] to `config` first. Used by `turbo-server --standalone`
+/// and `turbo start --standalone`, and by either with `--dev`.
+pub async fn run(mut config: TurboConfig, dev: bool) -> anyhow::Result<()> {
+    config.validate()?;
+
+    let turbo_home = PathBuf::from(&config.paths.turbo_home);
+    let runtimes_dir = turbo_home.join("runtimes");
+    tracing::info!("Turbo home: {:?}", turbo_home);
+    if dev {
+        dev::prepare(&mut config, &turbo_home).await?;
+    }
+
+    let workers = config
+        .server
+        .workers
+        .unwrap_or(config.sandbox.max_concurrent_jobs);
+    if workers == 0 {
+        anyhow::bail!("Standalone mode runs jobs itself and needs server.workers above 0");
+    }
+
+    let engine = Arc::new(Engine::new(&config).await?);
+    info!("Using '{}' sandbox backend", config.sandbox.backend);
+    let store = Arc::new(JobStore::open(
+        &turbo_home.join(DB_FILE),
+        config.results.ttl_secs,
+    )?);
+
+    let (queue, jobs) = mpsc::channel(QUEUE_CAPACITY);
+    let jobs = Arc::new(tokio::sync::Mutex::new(jobs));
+    info!(
+        "Starting {} in-process workers (max {} concurrent jobs)",
+        workers, config.sandbox.max_concurrent_jobs
+    );
+    for _ in 0..workers {
+        tokio::spawn(work(engine.clone(), jobs.clone(), store.clone()));
+    }
+    let unfinished = store.unfinished()?;
+    if !unfinished.is_empty() {
+        info!(
+            "Requeueing {} jobs left unfinished by the last run",
+            unfinished.len()
+        );
+        let queue = queue.clone();
+        tokio::spawn(async move {
+            for (id, request) in unfinished {
+                let job = Queued {
+                    id,
+                    request,
+                    done: None,
+                };
+                if queue.send(job).await.is_err() {
+                    return;
+                }
+            }
+        });
+    }
+
+    let packages = PackageCache::from_paths(
+        PathBuf::from(&config.paths.packages_path),
+        runtimes_dir.clone(),
+    )
+    .await?;
+    let state = Arc::new(StandaloneState {
+        queue,
+        store,
+        runtimes_dir,
+        packages,
+        idempotency_ttl_secs: config.results.idempotency_ttl_secs(),
+    });
+    server::serve(&config, app(state, &config)?).await
+}
+
+fn app(state: Arc<StandaloneState>, config: &TurboConfig) -> anyhow::Result<Router> {
+    let router = Router::new()
+        .route("/api/v1/execute", post(execute))
+        .route("/api/v1/jobs", post(submit))
+        .route("/api/v1/jobs/:id", get(get_job))
+        .route("/api/v1/runtimes", get(get_runtimes))
+        .route("/health", get(handlers::health))
+        .with_state(state)
+        .layer(DefaultBodyLimit::disable())
+        .layer(RequestBodyLimitLayer::new(config.server.max_body_bytes))
+        .layer(middleware::from_fn(error::request_id));
+
+    Ok(match routes::cors_layer(&config.server.cors)? {
+        Some(cors) => router.layer(cors),
+        None => router,
+    })
+}
+
+/// Takes jobs off the channel one at a time and runs them until the channel closes.
+async fn work(
+    engine: Arc<Engine>,
+    jobs: Arc<tokio::sync::Mutex<mpsc::Receiver<Queued>>>,
+    store: Arc<JobStore>,
+) {
+    loop {
+        let Some(job) = jobs.lock().await.recv().await else {
+            return;
+        };
+        let span = tracing::info_span!("job", id = %job.id);
+        async {
+            let id = job.id.clone();
+            if let Err(e) = blocking(&store, move |store| store.started(&id)).await {
+                error!("Failed to record the start of the job: {}", e);
+            }
+            let result = engine.execute(job.request).await;
+
+            let id = job.id.clone();
+            let recorded = result.clone();
+            if let Err(e) = blocking(&store, move |store| store.finished(&id, &recorded)).await {
+                error!("Failed to record the result of the job: {}", e);
+            }
+            if let Some(done) = job.done {
+                // The caller may have gone away; the result is still recorded
+                let _ = done.send(result);
+            }
+        }
+        .instrument(span)
+        .await;
+    }
+}
+
+async fn execute(
+    State(state): State<Arc<StandaloneState>>,
+    request_headers: HeaderMap,
+    Json(mut payload): Json<JobRequest>,
+) -> Result<(HeaderMap, Json<JobResult>), ApiError> {
+    let idempotency = handlers::idempotency_key(&request_headers, &payload)?;
+    resolve_runtime(&state, &mut payload).await?;
+    let (done, result) = oneshot::channel();
+    let id = match enqueue(&state, payload, idempotency, Some(done)).await? {
+        Enqueued::New(_) => {
+            let result = result.await.map_err(|_| {
+                ApiError::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "The job was dropped before it finished",
+                )
+            })?;
+            return Ok((HeaderMap::new(), Json(result)));
+        }
+        Enqueued::Replayed(original) => original,
+    };
+    let result = wait_for_result(&state.store, &id).await?;
+    Ok((replayed_headers(), Json(result)))
+}
+
+async fn submit(
+    State(state): State<Arc<StandaloneState>>,
+    request_headers: HeaderMap,
+    Json(mut payload): Json<JobRequest>,
+) -> Result<(StatusCode, HeaderMap, Json<JobAccepted>), ApiError> {
+    let idempotency = handlers::idempotency_key(&request_headers, &payload)?;
+    resolve_runtime(&state, &mut payload).await?;
+    let queue_depth = (QUEUE_CAPACITY - state.queue.capacity()) as u64;
+    let (id, headers) = match enqueue(&state, payload, idempotency, None).await? {
+        Enqueued::New(id) => (id, HeaderMap::new()),
+        Enqueued::Replayed(original) => (original, replayed_headers()),
+    };
+    Ok((
+        StatusCode::ACCEPTED,
+        headers,
+        Json(JobAccepted {
+            id,
+            sequence: None,
+            queue_depth,
+            estimated_start_ms: 0,
+        }),
+    ))
+}
+
+/// The job a submission was answered with.
+enum Enqueued {
+    /// A new job, with this ID
+    New(String),
+    /// An earlier submission's job, which claimed the idempotency key
+    Replayed(String),
+}
+
+/// Records a new job for `request` and hands it to the workers, unless an earlier
+/// submission claimed its `idempotency` key.
+async fn enqueue(
+    state: &StandaloneState,
+    request: JobRequest,
+    idempotency: Option<String>,
+    done: Option<oneshot::Sender<JobResult>>,
+) -> Result<Enqueued, ApiError> {
+    let permit = state.queue.try_reserve().map_err(|_| {
+        ApiError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Queue is full, try again later",
+        )
+    })?;
+    let id = Uuid::new_v4().to_string();
+    let ttl_secs = state.idempotency_ttl_secs;
+    let (queued, stored) = (id.clone(), request.clone());
+    let original = blocking(&state.store, move |store| {
+        let Some(key) = &idempotency else {
+            return store.queued(&queued, &stored).map(|()| None);
+        };
+        if let Some(original) = store.claim_idempotency(key, &queued, ttl_secs)? {
+            return Ok(Some(original));
+        }
+        let recorded = store.queued(&queued, &stored);
+        if recorded.is_err() {
+            // So a retry runs instead of waiting on a job that never will
+            let _ = store.release_idempotency(key);
+        }
+        recorded.map(|()| None)
+    })
+    .await
+    .map_err(store_error)?;
+    if let Some(original) = original {
+        info!("Idempotent retry of job {}", original);
+        return Ok(Enqueued::Replayed(original));
+    }
+    permit.send(Queued {
+        id: id.clone(),
+        request,
+        done,
+    });
+    Ok(Enqueued::New(id))
+}
+
+/// The result of job `id`, once it has one.
+async fn wait_for_result(store: &Arc<JobStore>, id: &str) -> Result<JobResult, ApiError> {
+    loop {
+        let lookup = id.to_string();
+        let status = blocking(store, move |store| store.get(&lookup))
+            .await
+            .map_err(store_error)?
+            .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, format!("Job {} not found", id)))?;
+        if let Some(result) = status.result {
+            return Ok(result);
+        }
+        tokio::time::sleep(RESULT_POLL_INTERVAL).await;
+    }
+}
+
+/// `Idempotent-Replayed` for a submission answered with an earlier one's job.
+fn replayed_headers() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        handlers::IDEMPOTENT_REPLAYED_HEADER,
+        HeaderValue::from_static("true"),
+    );
+    headers
+}
+
+/// Lifecycle and (once done) result of a job; `202 Accepted` while it has no result yet.
+async fn get_job(
+    State(state): State<Arc<StandaloneState>>,
+    Path(id): Path<String>,
+) -> Result<(StatusCode, Json<JobStatus>), ApiError> {
+    let lookup = id.clone();
+    let status = blocking(&state.store, move |store| store.get(&lookup))
+        .await
+        .map_err(store_error)?
+        .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, format!("Job {} not found", id)))?;
+    let code = if status.state.is_terminal() {
+        StatusCode::OK
+    } else {
+        StatusCode::ACCEPTED
+    };
+    Ok((code, Json(status)))
+}
+
+async fn get_runtimes(State(state): State<Arc<StandaloneState>>) -> Json<Vec<Runtime>> {
+    match installed_runtimes(&state).await {
+        Ok(runtimes) => Json(runtimes),
+        Err(e) => {
+            error!("Failed to get runtimes: {}", e);
+            Json(vec![])
+        }
+    }
+}
+
+async fn installed_runtimes(state: &StandaloneState) -> anyhow::Result<Vec<Runtime>> {
+    if !state.runtimes_dir.exists() {
+        return Ok(Vec::new());
+    }
+    runtimes::scan(&state.runtimes_dir).await
+}
+
+/// Replaces an alias in `request.language` with the runtime's name, infers a missing one
+/// from the files, and rejects runtimes that are not installed with a `422`.
+async fn resolve_runtime(
+    state: &StandaloneState,
+    request: &mut JobRequest,
+) -> Result<(), ApiError> {
+    if request.language.is_empty() {
+        request.language = state
+            .packages
+            .detect(&request.files)
+            .map_err(|e| ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, e.to_string()))?;
+    }
+    let runtimes = installed_runtimes(state).await.map_err(|e| {
+        ApiError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to read runtimes: {}", e),
+        )
+    })?;
+    let known = runtimes.iter().any(|r| r.language == request.language);
+    if let Some(runtime) = runtimes
+        .iter()
+        .find(|r| !known && r.aliases.contains(&request.language))
+    {
+        request.language = runtime.language.clone();
+    }
+
+    let version = request.version.as_deref();
+    if runtimes
+        .iter()
+        .any(|r| r.language == request.language && version.is_none_or(|v| r.version == v))
+    {
+        return Ok(());
+    }
+    let message = match version {
+        Some(version) => format!("Runtime {}@{} is not installed", request.language, version),
+        None => format!("Runtime {} is not installed", request.language),
+    };
+    Err(ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, message))
+}
+
+fn store_error(e: anyhow::Error) -> ApiError {
+    error!("Job store error: {}", e);
+    ApiError::new(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!("Job store error: {}", e),
+    )
+}
+
+fn unix_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use turbo_engine::execute::fail_job;
+
+    /// A fresh directory for a test's database, which comes with WAL files.
+    fn scratch(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("turbo-standalone-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn request(language: &str) -> JobRequest {
+        JobRequest {
+            language: language.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn state_of(store: &JobStore, id: &str) -> (JobState, u64) {
+        let status = store.get(id).unwrap().unwrap();
+        (status.state, status.attempts)
+    }
+
+    #[test]
+    fn a_claimed_job_counts_its_attempt_until_it_finishes() {
+        let dir = scratch("claim");
+        let store = JobStore::open(&dir.join(DB_FILE), 60).unwrap();
+        store.queued("a", &request("python")).unwrap();
+        assert_eq!(state_of(&store, "a"), (JobState::Queued, 0));
+
+        store.started("a").unwrap();
+        assert_eq!(state_of(&store, "a"), (JobState::Running, 1));
+        assert!(store.get("a").unwrap().unwrap().started_at.is_some());
+
+        store
+            .finished("a", &fail_job(&request("python"), String::new()))
+            .unwrap();
+        let status = store.get("a").unwrap().unwrap();
+        assert_eq!(status.state, JobState::Failed);
+        assert!(status.result.is_some() && status.finished_at.is_some());
+        assert!(store.unfinished().unwrap().is_empty());
+        assert!(store.get("missing").unwrap().is_none());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn unfinished_jobs_are_requeued_on_restart() {
+        let dir = scratch("requeue");
+        let path = dir.join(DB_FILE);
+        let store = JobStore::open(&path, 60).unwrap();
+        store.queued("running", &request("python")).unwrap();
+        store.queued("waiting", &request("rust")).unwrap();
+        store.queued("done", &request("c")).unwrap();
+        store.started("running").unwrap();
+        store
+            .finished("done", &fail_job(&request("c"), String::new()))
+            .unwrap();
+        drop(store);
+
+        let store = JobStore::open(&path, 60).unwrap();
+        let requeued: Vec<(String, String)> = store
+            .unfinished()
+            .unwrap()
+            .into_iter()
+            .map(|(id, request)| (id, request.language))
+            .collect();
+        assert_eq!(
+            requeued,
+            [
+                ("running".to_string(), "python".to_string()),
+                ("waiting".to_string(), "rust".to_string()),
+            ]
+        );
+        assert_eq!(state_of(&store, "running"), (JobState::Queued, 1));
+        assert_eq!(state_of(&store, "done").0, JobState::Failed);
+
+        // Taken again, the job counts a second attempt
+        store.started("running").unwrap();
+        assert_eq!(state_of(&store, "running"), (JobState::Running, 2));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn jobs_queued_before_requests_were_kept_fail_on_restart() {
+        let dir = scratch("upgrade");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(DB_FILE);
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE jobs (id TEXT PRIMARY KEY, state TEXT NOT NULL, \
+             queued_at INTEGER NOT NULL, started_at INTEGER, finished_at INTEGER, result TEXT);
+             INSERT INTO jobs (id, state, queued_at) VALUES ('old', 'queued', 1);",
+        )
+        .unwrap();
+        drop(conn);
+
+        let store = JobStore::open(&path, 60).unwrap();
+        assert_eq!(state_of(&store, "old"), (JobState::Failed, 0));
+        assert!(store.unfinished().unwrap().is_empty());
+        store.queued("new", &request("python")).unwrap();
+        assert_eq!(store.unfinished().unwrap().len(), 1);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn idempotency_keys_map_retries_to_the_first_job() {
+        let dir = scratch("idempotency");
+        let store = JobStore::open(&dir.join(DB_FILE), 60).unwrap();
+        assert_eq!(store.claim_idempotency("k", "a", 60).unwrap(), None);
+        assert_eq!(
+            store.claim_idempotency("k", "b", 60).unwrap(),
+            Some("a".to_string())
+        );
+        assert_eq!(store.claim_idempotency("other", "c", 60).unwrap(), None);
+
+        // A released key is free for a retry
+        store.release_idempotency("k").unwrap();
+        assert_eq!(store.claim_idempotency("k", "d", 60).unwrap(), None);
+
+        // And so is one past its TTL
+        assert_eq!(store.claim_idempotency("brief", "e", 0).unwrap(), None);
+        assert_eq!(store.claim_idempotency("brief", "f", 60).unwrap(), None);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn an_idempotent_retry_queues_no_second_job() {
+        let dir = scratch("enqueue");
+        let (queue, mut jobs) = mpsc::channel(QUEUE_CAPACITY);
+        let state = StandaloneState {
+            queue,
+            store: Arc::new(JobStore::open(&dir.join(DB_FILE), 60).unwrap()),
+            runtimes_dir: dir.join("runtimes"),
+            packages: PackageCache::from_paths(dir.join("packages"), dir.join("runtimes"))
+                .await
+                .unwrap(),
+            idempotency_ttl_secs: 60,
+        };
+        let key = Some("retry".to_string());
+
+        let Enqueued::New(first) = enqueue(&state, request("python"), key.clone(), None)
+            .await
+            .unwrap()
+        else {
+            panic!("the first submission was replayed");
+        };
+        let Enqueued::Replayed(replayed) =
+            enqueue(&state, request("python"), key, None).await.unwrap()
+        else {
+            panic!("the retry was queued again");
+        };
+        assert_eq!(replayed, first);
+        assert_eq!(jobs.recv().await.unwrap().id, first);
+        assert!(jobs.try_recv().is_err());
+
+        // Without a key every submission is a new job
+        enqueue(&state, request("python"), None, None)
+            .await
+            .unwrap();
+        enqueue(&state, request("python"), None, None)
+            .await
+            .unwrap();
+        assert_eq!(jobs.len(), 2);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,48 @@
+//! HTTPS for deployments without a TLS-terminating proxy, with certificates reloaded from
+//! disk when they are renewed.
+
+use axum_server::tls_rustls::RustlsConfig;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use turbo_core::config::TlsConfig;
+
+/// How often the certificate and key files are checked for changes.
+const RELOAD_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Loads the certificate chain and private key named by `tls`.
+pub async fn load(tls: &TlsConfig) -> anyhow::Result<RustlsConfig> {
+    RustlsConfig::from_pem_file(&tls.cert, &tls.key)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to load TLS certificate {}: {}", tls.cert, e))
+}
+
+/// Reloads `rustls` whenever the certificate or key file is modified, so renewed
+/// certificates take effect for new connections without a restart.
+///
+/// A reload that fails (e.g. the key was replaced but the certificate not yet) keeps the
+/// current certificate and is retried on every check until it succeeds.
+pub async fn watch_certificate(rustls: RustlsConfig, tls: TlsConfig) {
+    let mut loaded = modified(&tls);
+    let mut interval = tokio::time::interval(RELOAD_CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        let current = modified(&tls);
+        if current == loaded {
+            continue;
+        }
+        match rustls.reload_from_pem_file(&tls.cert, &tls.key).await {
+            Ok(()) => {
+                tracing::info!("Reloaded TLS certificate {}", tls.cert);
+                loaded = current;
+            }
+            Err(e) => tracing::warn!("Failed to reload TLS certificate {}: {}", tls.cert, e),
+        }
+    }
+}
+
+/// Modification times of the certificate and key; `None` for one that can't be read.
+fn modified(tls: &TlsConfig) -> [Option<SystemTime>; 2] {
+    // `metadata` follows symlinks, so certificates swapped by relinking are noticed too
+    let mtime = |path: &str| Path::new(path).metadata().and_then(|m| m.modified()).ok();
+    [mtime(&tls.cert), mtime(&tls.key)]
+}
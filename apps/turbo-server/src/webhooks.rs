@@ -0,0 +1,333 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::Semaphore;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+use turbo_core::models::{DeadLetter, Job, JobResult, JobState, WebhookConfig, WebhookEvent};
+use turbo_db::TurboDb;
+use uuid::Uuid;
+
+/// How often queued events are checked for delivery.
+const DISPATCH_INTERVAL: Duration = Duration::from_secs(2);
+/// Attempts per batch before it is moved to the dead-letter log.
+const MAX_ATTEMPTS: u32 = 4;
+/// Delay before the first retry; doubled for each further one.
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+/// Webhooks delivered to at once by one dispatcher.
+const MAX_CONCURRENT_WEBHOOKS: usize = 32;
+/// How long a dispatcher's lease on a webhook lasts; renewed before each batch, and well
+/// above the time one batch can take with every retry.
+const LEASE_TTL_SECS: u64 = 120;
+/// Header carrying `sha256=<hex HMAC-SHA256 of the body>` when the webhook has a secret.
+const SIGNATURE_HEADER: &str = "x-turbo-signature";
+
+fn unix_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Queue an event for the webhook of the API key `job` was submitted with, if it has one
+/// that wants `state`.
+pub async fn notify(db: &TurboDb, job: &Job, state: JobState, result: &JobResult) {
+    let Some(api_key) = &job.api_key else {
+        return;
+    };
+    let webhook = match db.metadata.get_webhook(api_key).await {
+        Ok(Some(webhook)) => webhook,
+        Ok(None) => return,
+        Err(e) => {
+            error!("Failed to look up webhook for job {}: {}", job.id, e);
+            return;
+        }
+    };
+    if !webhook.accepts(state) {
+        return;
+    }
+
+    let event = WebhookEvent {
+        job_id: job.id.clone(),
+        state,
+        result: result.clone(),
+        at: unix_ms(),
+    };
+    if let Err(e) = db.metadata.push_webhook_event(api_key, &event).await {
+        error!("Failed to queue webhook event for job {}: {}", job.id, e);
+    }
+}
+
+/// Delivers queued webhook events in batches of each webhook's `batch_size`.
+///
+/// Webhooks are delivered to concurrently, up to `MAX_CONCURRENT_WEBHOOKS` at once, so a
+/// slow endpoint only holds up its own events. Each webhook is drained by whichever
+/// dispatcher holds its lease, so several servers can run this without delivering the
+/// same batch twice. A batch stays in the webhook's processing list until it has been
+/// delivered or, after `MAX_ATTEMPTS`, recorded in the dead-letter log; one left there by
+/// a server that died is delivered again once its lease expires.
+pub async fn start_dispatcher(db: TurboDb) {
+    info!("Webhook dispatcher started");
+    let client = reqwest::Client::builder()
+        .timeout(DELIVERY_TIMEOUT)
+        .build()
+        .unwrap_or_default();
+    let owner = Arc::new(Uuid::new_v4().to_string());
+    let slots = Arc::new(Semaphore::new(MAX_CONCURRENT_WEBHOOKS));
+    let mut draining: HashMap<String, JoinHandle<()>> = HashMap::new();
+
+    loop {
+        tokio::time::sleep(DISPATCH_INTERVAL).await;
+        draining.retain(|_, task| !task.is_finished());
+
+        let webhooks = match db.metadata.list_webhooks().await {
+            Ok(webhooks) => webhooks,
+            Err(e) => {
+                error!("Failed to list webhooks: {}", e);
+                continue;
+            }
+        };
+        for (api_key, webhook) in webhooks {
+            if draining.contains_key(&api_key) {
+                continue;
+            }
+            let db = db.clone();
+            let client = client.clone();
+            let owner = owner.clone();
+            let slots = slots.clone();
+            let key = api_key.clone();
+            let task = tokio::spawn(async move {
+                let Ok(_slot) = slots.acquire_owned().await else {
+                    return;
+                };
+                drain(&db, &client, &owner, &key, &webhook).await;
+            });
+            draining.insert(api_key, task);
+        }
+    }
+}
+
+/// Deliver `api_key`'s queued events batch by batch while this dispatcher (`owner`)
+/// holds the webhook's lease.
+async fn drain(
+    db: &TurboDb,
+    client: &reqwest::Client,
+    owner: &str,
+    api_key: &str,
+    webhook: &WebhookConfig,
+) {
+    loop {
+        match db
+            .metadata
+            .lease_webhook(api_key, owner, LEASE_TTL_SECS)
+            .await
+        {
+            Ok(true) => {}
+            Ok(false) => return,
+            Err(e) => {
+                error!("Failed to lease webhook {}: {}", webhook.url, e);
+                return;
+            }
+        }
+        let batch_size = webhook.batch_size.max(1);
+        let events = match db.metadata.take_webhook_events(api_key, batch_size).await {
+            Ok(events) if events.is_empty() => break,
+            Ok(events) => events,
+            Err(e) => {
+                error!("Failed to take webhook events: {}", e);
+                break;
+            }
+        };
+        deliver(db, client, api_key, webhook, events).await;
+        if let Err(e) = db.metadata.ack_webhook_events(api_key).await {
+            error!("Failed to ack webhook batch for {}: {}", webhook.url, e);
+            break;
+        }
+    }
+    if let Err(e) = db.metadata.release_webhook(api_key, owner).await {
+        warn!("Failed to release webhook {}: {}", webhook.url, e);
+    }
+}
+
+/// Send one batch, retrying with exponential backoff, and dead-letter it on failure.
+async fn deliver(
+    db: &TurboDb,
+    client: &reqwest::Client,
+    api_key: &str,
+    webhook: &WebhookConfig,
+    events: Vec<WebhookEvent>,
+) {
+    let body = match serde_json::to_vec(&events) {
+        Ok(body) => body,
+        Err(e) => {
+            error!("Failed to serialize webhook batch: {}", e);
+            return;
+        }
+    };
+
+    let mut last_error = String::new();
+    for attempt in 0..MAX_ATTEMPTS {
+        if attempt > 0 {
+            tokio::time::sleep(RETRY_BASE_DELAY * 2u32.pow(attempt - 1)).await;
+        }
+        match send(client, webhook, &body).await {
+            Ok(()) => return,
+            Err(e) => {
+                let attempt = attempt + 1;
+                warn!(
+                    "Webhook delivery to {} failed (attempt {}): {}",
+                    webhook.url, attempt, e
+                );
+                last_error = e.to_string();
+            }
+        }
+    }
+
+    let letter = DeadLetter {
+        events,
+        error: last_error,
+        attempts: MAX_ATTEMPTS,
+        failed_at: unix_ms(),
+    };
+    if let Err(e) = db.metadata.push_dead_letter(api_key, &letter).await {
+        error!("Failed to record dead letter for {}: {}", webhook.url, e);
+    }
+}
+
+/// `SIGNATURE_HEADER`'s value for `body` signed with `secret`.
+fn signature(secret: &str, body: &[u8]) -> anyhow::Result<String> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .map_err(|e| anyhow::anyhow!("Invalid webhook secret: {}", e))?;
+    mac.update(body);
+    Ok(format!(
+        "sha256={}",
+        hex::encode(mac.finalize().into_bytes())
+    ))
+}
+
+async fn send(
+    client: &reqwest::Client,
+    webhook: &WebhookConfig,
+    body: &[u8],
+) -> anyhow::Result<()> {
+    let mut request = client
+        .post(&webhook.url)
+        .header("content-type", "application/json")
+        .body(body.to_vec());
+    if let Some(secret) = &webhook.secret {
+        request = request.header(SIGNATURE_HEADER, signature(secret, body)?);
+    }
+
+    let response = request.send().await?;
+    if !response.status().is_success() {
+        anyhow::bail!("HTTP {}", response.status());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::{Router, body::Bytes, extract::State, routing::post};
+    use tokio::sync::mpsc;
+    use turbo_core::models::JobRequest;
+    use turbo_engine::execute::fail_job;
+
+    type Delivery = (HeaderMap, Bytes);
+
+    /// An endpoint on localhost answering every delivery with `status`; returns its URL
+    /// and the deliveries it got.
+    async fn endpoint(status: StatusCode) -> (String, mpsc::UnboundedReceiver<Delivery>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let app = Router::new()
+            .route(
+                "/hook",
+                post(
+                    move |State(tx): State<mpsc::UnboundedSender<Delivery>>,
+                          headers: HeaderMap,
+                          body: Bytes| async move {
+                        let _ = tx.send((headers, body));
+                        status
+                    },
+                ),
+            )
+            .with_state(tx);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        (format!("http://{}/hook", addr), rx)
+    }
+
+    fn webhook(url: String, secret: Option<&str>) -> WebhookConfig {
+        WebhookConfig {
+            url,
+            secret: secret.map(str::to_string),
+            events: Vec::new(),
+            batch_size: 2,
+        }
+    }
+
+    fn event(job_id: &str, state: JobState) -> WebhookEvent {
+        WebhookEvent {
+            job_id: job_id.to_string(),
+            state,
+            result: fail_job(&JobRequest::default(), String::new()),
+            at: 1,
+        }
+    }
+
+    #[test]
+    fn signature_is_the_hmac_sha256_of_the_body() {
+        // The HMAC-SHA256 example from Wikipedia
+        assert_eq!(
+            signature("key", b"The quick brown fox jumps over the lazy dog").unwrap(),
+            "sha256=f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+        );
+    }
+
+    #[tokio::test]
+    async fn a_batch_is_sent_as_one_signed_request() {
+        let (url, mut deliveries) = endpoint(StatusCode::OK).await;
+        let events = [event("a", JobState::Finished), event("b", JobState::Failed)];
+        let body = serde_json::to_vec(&events).unwrap();
+        let client = reqwest::Client::new();
+        send(&client, &webhook(url, Some("s3cret")), &body)
+            .await
+            .unwrap();
+
+        let (headers, received) = deliveries.recv().await.unwrap();
+        assert!(deliveries.try_recv().is_err());
+        assert_eq!(headers["content-type"], "application/json");
+        assert_eq!(
+            headers[SIGNATURE_HEADER].to_str().unwrap(),
+            signature("s3cret", &received).unwrap()
+        );
+        let batch: Vec<WebhookEvent> = serde_json::from_slice(&received).unwrap();
+        let batch: Vec<(&str, JobState)> = batch
+            .iter()
+            .map(|event| (event.job_id.as_str(), event.state))
+            .collect();
+        assert_eq!(batch, [("a", JobState::Finished), ("b", JobState::Failed)]);
+    }
+
+    #[tokio::test]
+    async fn deliveries_without_a_secret_are_unsigned() {
+        let (url, mut deliveries) = endpoint(StatusCode::NO_CONTENT).await;
+        let client = reqwest::Client::new();
+        send(&client, &webhook(url, None), b"[]").await.unwrap();
+        let (headers, _) = deliveries.recv().await.unwrap();
+        assert!(!headers.contains_key(SIGNATURE_HEADER));
+    }
+
+    #[tokio::test]
+    async fn a_refused_delivery_is_an_error() {
+        let (url, _deliveries) = endpoint(StatusCode::INTERNAL_SERVER_ERROR).await;
+        let client = reqwest::Client::new();
+        let error = send(&client, &webhook(url, None), b"[]").await.unwrap_err();
+        assert_eq!(error.to_string(), "HTTP 500 Internal Server Error");
+    }
+}
@@ -1,38 +1,277 @@
+use crate::audit::{AuditEntry, AuditLog};
+use crate::gc;
+use crate::git;
+use crate::runtimes;
+use crate::webhooks;
 use sha2::{Digest, Sha256};
-use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
-use std::time::Duration;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tokio::fs;
-use tracing::{error, info};
-use turbo_box::{LinuxSandbox, Sandbox};
+use tokio::sync::watch;
+use tracing::{Instrument, error, info};
+use turbo_box::{Sandbox, SandboxPool};
+use turbo_core::config::TurboConfig;
 use turbo_core::models::{
-    ExecutionLimits, Job, JobResult, StageResult, StageStatus, TestcaseResult,
+    JobKind, JobRequest, JobResult, JobSource, JobState, StageResult, StageStatus, WorkerHeartbeat,
+    WorkerScore,
 };
 use turbo_db::TurboDb;
-use turbo_pkg::models::PackageDefinition;
+use turbo_engine::LimitCaps;
+use turbo_engine::execute::{
+    RESTORED_FROM_CACHE, calculate_job_hash, execute_job, fail_job, get_runtime_path,
+    run_calibration, stub_result,
+};
+use turbo_engine::warm::WarmPool;
+use uuid::Uuid;
+
+/// Opens the sandbox pool and starts `server.workers` worker loops (default:
+/// `sandbox.max_concurrent_jobs`); returns how many were started.
+///
+/// With zero workers no sandbox is created, so an API-only server needs no privileges.
+pub async fn spawn_workers(
+    config: &TurboConfig,
+    db: &TurboDb,
+    runtimes_dir: &Path,
+) -> anyhow::Result<usize> {
+    let max_jobs = config.sandbox.max_concurrent_jobs;
+    let workers = config.server.workers.unwrap_or(max_jobs);
+    if workers == 0 {
+        return Ok(0);
+    }
+
+    // The pool's sessions are the only sandboxes jobs run in, so its size caps the
+    // number of in-flight jobs however many workers there are.
+    let sandbox = turbo_box::create_sandbox(&config.sandbox)?;
+    info!("Using '{}' sandbox backend", config.sandbox.backend);
+    let packages = HostPackages {
+        repo_path: PathBuf::from(&config.paths.packages_path),
+        turbo_home: PathBuf::from(&config.paths.turbo_home),
+        sandbox: sandbox.clone(),
+    };
+    let pool = Arc::new(SandboxPool::new(sandbox, max_jobs).await?);
+    tokio::spawn(gc::start_sandbox_gc(pool.clone(), max_jobs, workers));
+
+    info!(
+        "Starting {} workers (max {} concurrent jobs)",
+        workers, max_jobs
+    );
+
+    let caps = LimitCaps::from_config(config);
+
+    let audit = match &config.audit.path {
+        Some(path) => {
+            info!("Recording executed jobs in the audit log {}", path);
+            Some(Arc::new(AuditLog::open(Path::new(path))?))
+        }
+        None => None,
+    };
+
+    let dedup_window = config.results.dedup_window_secs;
+    let instance = config
+        .server
+        .worker_id
+        .clone()
+        .unwrap_or_else(|| Uuid::new_v4().simple().to_string()[..8].to_string());
+    for i in 0..workers {
+        let name = worker_name(&instance, i);
+        let db_clone = db.clone();
+        let runtimes_dir_clone = runtimes_dir.to_path_buf();
+        let pool_clone = pool.clone();
+        let caps = caps.clone();
+        let audit = audit.clone();
+        let packages = packages.clone();
+        tokio::spawn(async move {
+            let runtimes_dir = runtimes_dir_clone;
+            start_worker(
+                i,
+                name,
+                db_clone,
+                runtimes_dir,
+                pool_clone,
+                caps,
+                dedup_window,
+                audit,
+                packages,
+            )
+            .await;
+        });
+    }
+    Ok(workers)
+}
 
-fn get_runtime_path(runtimes_dir: &Path, lang: &str, ver: &str) -> PathBuf {
-    runtimes_dir.join(lang).join(ver)
+/// How many times faster than the reference machine a worker with calibration `score` is;
+/// 1.0 for a worker not calibrated yet.
+pub(crate) fn speed(score: Option<&WorkerScore>) -> f64 {
+    score
+        .map(|s| s.score)
+        .filter(|score| score.is_finite() && *score > 0.0)
+        .unwrap_or(1.0)
+}
+
+/// `elapsed` on worker `name` as the time the reference machine would have taken, so
+/// the job durations of faster and slower workers average out to comparable figures.
+async fn reference_ms(db: &TurboDb, name: &str, elapsed: Duration) -> u64 {
+    let score = db
+        .metadata
+        .get_worker_score(name)
+        .await
+        .unwrap_or_else(|e| {
+            error!("Failed to get the calibration score of {}: {}", name, e);
+            None
+        });
+    (elapsed.as_millis() as f64 * speed(score.as_ref())) as u64
 }
 
 /// Starts the worker loop, polling the Redis queue for new jobs.
 ///
-/// This function runs indefinitely, processing jobs one by one.
-pub async fn start_worker(id: usize, db: TurboDb, runtimes_dir: PathBuf) {
-    info!("Worker {} started", id);
-    let sandbox = LinuxSandbox::new("/var/turbo/sandbox".to_string());
+/// This function runs indefinitely, processing jobs one by one. Each job borrows a
+/// pre-opened sandbox session from `pool` and returns it once finished. The session is
+/// taken before popping the queue, so a worker never holds a job it has no sandbox for
+/// when more workers than sessions are configured.
+///
+/// Jobs stay in the worker's processing list until acked after their result is
+/// published; see `RedisQueue::pop_job_for`.
+#[allow(clippy::too_many_arguments)]
+pub async fn start_worker(
+    id: usize,
+    name: String,
+    db: TurboDb,
+    runtimes_dir: PathBuf,
+    pool: Arc<SandboxPool<dyn Sandbox>>,
+    caps: LimitCaps,
+    dedup_window_secs: Option<u64>,
+    audit: Option<Arc<AuditLog>>,
+    packages: HostPackages,
+) {
+    info!("Worker {} started as {}", id, name);
+    // Registered with a live heartbeat before anything else, so the reaper never sees us dead
+    let started_at = unix_secs();
+    beat(&db, &name, started_at, None).await;
+    let (current_job, job_rx) = watch::channel(None);
+    tokio::spawn(heartbeat(db.clone(), name.clone(), started_at, job_rx));
+    // With a configured worker_id the name is the same as before a restart, so anything
+    // still in our processing list was cut off
+    match db.queue.requeue_worker(&name).await {
+        Ok(0) => {}
+        Ok(n) => info!("Requeued {} interrupted jobs for {}", n, name),
+        Err(e) => error!("Failed to requeue interrupted jobs for {}: {}", name, e),
+    }
+    let mut warm = WarmPool::new(id);
 
+    // Kept across empty polls; only a session that ran a job needs a reset
+    let mut idle_session = None;
     loop {
-        match db.queue.pop_job().await {
-            Ok(Some(job)) => {
-                info!("Processing job {}", job.id);
-                let result = execute_job(&job, &sandbox, &runtimes_dir).await;
+        let session = match idle_session.take() {
+            Some(session) => session,
+            None => match pool.acquire().await {
+                Ok(session) => session,
+                Err(e) => {
+                    error!("Worker {} has no sandbox session: {}", id, e);
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    continue;
+                }
+            },
+        };
+        match db.queue.pop_job_for(&name).await {
+            Ok(Some(delivery)) => {
+                let job = &delivery.job;
+                let taken = Instant::now();
+                let request_id = job.request_id.as_deref().unwrap_or("-");
+                info!(request_id, "Processing job {}", job.id);
+                current_job.send_replace(Some(job.id.clone()));
+                let running = db
+                    .metadata
+                    .set_job_state(&job.id, JobState::Running, Some(&name));
+                if let Err(e) = running.await {
+                    error!("Failed to record job {} as running: {}", job.id, e);
+                }
+                let reuse_key = match dedup_window_secs {
+                    Some(_) if job.kind == JobKind::Execute => {
+                        dedup_key(&job.request, &runtimes_dir).await
+                    }
+                    _ => None,
+                };
+                let cached = match &reuse_key {
+                    Some(key) => reuse_result(&db, key).await,
+                    None => None,
+                };
+                let result = if let Some(result) = cached {
+                    info!("Reusing the result of an identical job for {}", job.id);
+                    idle_session = Some(session);
+                    JobResult {
+                        metadata: job.request.metadata.clone(),
+                        ..result
+                    }
+                } else {
+                    let started = Instant::now();
+                    let result = match job.kind {
+                        JobKind::Execute => {
+                            let runtimes_dir = &runtimes_dir;
+                            let req = &job.request;
+                            execute_job(req, &pool, &session, &mut warm, runtimes_dir, &caps)
+                                .instrument(tracing::info_span!("job", id = %job.id))
+                                .await
+                        }
+                        JobKind::Calibration => {
+                            run_calibration(&job.request, pool.sandbox(), &session).await
+                        }
+                        JobKind::Install => run_install(&job.request, &packages, &db).await,
+                    };
+                    pool.release(session).await;
+                    if job.kind == JobKind::Execute {
+                        let duration_ms = reference_ms(&db, &name, started.elapsed()).await;
+                        let language = &job.request.language;
+                        let recorded = db.metadata.record_job_duration(language, duration_ms);
+                        if let Err(e) = recorded.await {
+                            error!("Failed to record duration for job {}: {}", job.id, e);
+                        }
+                    }
+                    result
+                };
+                if job.kind == JobKind::Execute {
+                    record_cache_stats(&db, reuse_key.is_some(), &result).await;
+                }
                 if let Err(e) = db.queue.publish_result(&job.id, &result).await {
                     error!("Failed to publish result for {}: {}", job.id, e);
                 }
+                let state = final_state(&result);
+                let cache_key = reuse_key
+                    .as_deref()
+                    .filter(|_| !result.cached && state == JobState::Finished);
+                if let (Some(key), Some(window)) = (cache_key, dedup_window_secs) {
+                    let cached = db.queue.cache_result(key, &result, window);
+                    if let Err(e) = cached.await {
+                        error!("Failed to cache result of job {}: {}", job.id, e);
+                    }
+                }
+                if let Err(e) = db.metadata.set_job_state(&job.id, state, None).await {
+                    error!(
+                        "Failed to record job {} as {}: {}",
+                        job.id,
+                        state.as_str(),
+                        e
+                    );
+                }
+                webhooks::notify(&db, job, state, &result).await;
+                if let (Some(audit), JobKind::Execute) = (&audit, job.kind) {
+                    let wall_time_ms = taken.elapsed().as_millis() as u64;
+                    let entry = AuditEntry::new(job, &result, state, &name, wall_time_ms);
+                    let audit = audit.clone();
+                    let recorded = tokio::task::spawn_blocking(move || audit.record(&entry));
+                    if let Err(e) = recorded.await.map_err(anyhow::Error::from).and_then(|r| r) {
+                        error!("Failed to add job {} to the audit log: {}", job.id, e);
+                    }
+                }
+                // Ack only once the result is out, so a crash before here redelivers the job
+                if let Err(e) = db.queue.ack(&name, &delivery).await {
+                    error!("Failed to ack job {}: {}", job.id, e);
+                }
+                current_job.send_replace(None);
             }
-            Ok(None) => {} // Busy loop or small sleep? DB blpop blocks.
+            Ok(None) => idle_session = Some(session),
             Err(e) => {
+                idle_session = Some(session);
                 error!("Queue error: {}", e);
                 tokio::time::sleep(Duration::from_secs(1)).await;
             }
@@ -40,331 +279,323 @@ pub async fn start_worker(id: usize, db: TurboDb, runtimes_dir: PathBuf) {
     }
 }
 
-/// Executes a single job within the sandbox.
-///
-/// 1. Creates a temporary directory for source files.
-/// 2. Resolves the runtime package (e.g., Python, C++).
-/// 3. Initializes the sandbox.
-/// 4. Compiles the code (if `build.sh` exists).
-/// 5. Runs the code (single run or batched testcases).
-/// 6. Cleans up resources.
-async fn execute_job(job: &Job, sandbox: &impl Sandbox, runtimes_dir: &Path) -> JobResult {
-    let job_id = &job.id;
-    let req = &job.request;
+/// Where a worker's `JobKind::Install` jobs install packages from and to: this host's
+/// `paths.packages_path` and `paths.turbo_home`, smoke-testing in the workers' sandbox.
+#[derive(Clone)]
+pub struct HostPackages {
+    pub repo_path: PathBuf,
+    pub turbo_home: PathBuf,
+    pub sandbox: Arc<dyn Sandbox>,
+}
 
-    let user = std::env::var("USER").unwrap_or_else(|_| "unknown".to_string());
-    let temp_dir = std::env::temp_dir().join(format!("turbo-{}", user)).join(job_id);
-    if let Err(e) = fs::create_dir_all(&temp_dir).await {
-        return fail_job(job, format!("Failed to create temp dir: {}", e));
+/// Held while a `JobKind::Install` job runs, so this process's workers install one at a time.
+static INSTALLING: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// Installs package `req.language` at `req.version` (the latest if unset) on this host and
+/// registers its runtimes. The install log is the run stage's stdout; a failed install is
+/// an `InternalError` with the reason on stderr.
+async fn run_install(req: &JobRequest, packages: &HostPackages, db: &TurboDb) -> JobResult {
+    let _guard = INSTALLING.lock().await;
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let installed = turbo_pkg::manager::PackageManager::new(
+        packages.turbo_home.clone(),
+        packages.repo_path.clone(),
+    )
+    .with_sandbox(packages.sandbox.clone())
+    .with_log(tx)
+    .install(&req.language, req.version.as_deref())
+    .await;
+    let mut log = String::new();
+    while let Ok(line) = rx.try_recv() {
+        log.push_str(&line);
+        log.push('\n');
     }
 
-    for file in &req.files {
-        let path = temp_dir.join(file.name.as_deref().unwrap_or("main"));
-        if let Err(e) = fs::write(&path, &file.content).await {
-            return fail_job(job, format!("Failed to write file: {}", e));
+    let run = match installed {
+        Ok(()) => {
+            let runtimes_dir = packages.turbo_home.join("runtimes");
+            if let Err(e) = runtimes::register(db, &runtimes_dir).await {
+                error!("Failed to register runtimes after install: {}", e);
+            }
+            StageResult {
+                status: StageStatus::Success,
+                stdout: log,
+                ..stub_result()
+            }
         }
+        Err(e) => StageResult {
+            status: StageStatus::InternalError,
+            stdout: log,
+            stderr: e.to_string(),
+            ..stub_result()
+        },
+    };
+    JobResult {
+        run: Some(run),
+        ..fail_job(req, String::new())
     }
+}
 
+/// Key under which a job's result is reused for identical submissions: the compile cache
+/// hash of `req`, extended with the runtime's run script, env file and the rest of the
+/// request. `None` if the runtime is not installed.
+async fn dedup_key(req: &JobRequest, runtimes_dir: &Path) -> Option<String> {
     let version = req.version.as_deref().unwrap_or("latest");
     let runtime_path = get_runtime_path(runtimes_dir, &req.language, version);
-
-    // Check if runtime exists
-    if !runtime_path.exists() {
-        return fail_job(job, format!("Runtime not found at {:?}", runtime_path));
+    let run_script = fs::read_to_string(runtime_path.join("run.sh")).await.ok()?;
+    let compile_script = fs::read_to_string(runtime_path.join("compile.sh"))
+        .await
+        .unwrap_or_default();
+    let env_file = fs::read_to_string(runtime_path.join("env"))
+        .await
+        .unwrap_or_default();
+    // A branch or tag may move, so only git sources pinned to a commit are reused
+    let pinned = |source: &JobSource| source.git.reference.as_deref().is_some_and(git::is_commit);
+    if req.source.as_ref().is_some_and(|source| !pinned(source)) {
+        return None;
     }
-
-    let pkg_def = match PackageDefinition::from_path(runtime_path.clone()) {
-        Ok(d) => d,
-        Err(e) => return fail_job(job, format!("Invalid runtime definition: {}", e)),
-    };
-
-    if let Err(e) = sandbox.init(job_id).await {
-        return fail_job(job, format!("Sandbox init failed: {}", e));
+    // Timings are what a benchmark is for, so it always runs
+    if req.benchmark.is_some() {
+        return None;
     }
 
-    let mut compile_result = None;
-    let compile_script = pkg_def.path.join("compile.sh");
-    
-    // Attempt caching if compile script exists
-    let user = std::env::var("USER").unwrap_or_else(|_| "unknown".to_string());
-    let cache_dir = std::env::temp_dir().join(format!("turbo-cache-{}", user));
-    let mut cache_path = None;
+    let mut hasher = Sha256::new();
+    hasher.update(calculate_job_hash(req, &compile_script).as_bytes());
+    hasher.update(run_script.as_bytes());
+    hasher.update(env_file.as_bytes());
+    hasher.update(serde_json::to_vec(&dedup_fields(req)).ok()?);
+    Some(hex::encode(hasher.finalize()))
+}
 
-    if compile_script.exists() {
-        // Calculate hash
-        let compile_script_content = fs::read_to_string(&compile_script).await.unwrap_or_default();
-        let hash = calculate_job_hash(req, &compile_script_content);
-        let job_cache_path = cache_dir.join(&hash);
-        
-        if job_cache_path.exists() {
-            info!("Cache hit for job {}, hash {}", job_id, hash);
-             // Restore from cache
-                if let Err(e) = hard_link_recursive(&job_cache_path, &temp_dir).await {
-                error!("Failed to restore from cache: {}", e);
-                // Fallback to normal compile if restore fails
-            } else {
-                 // Touch cache to update modification time for LRU
-                 let _ = fs::set_permissions(&job_cache_path, std::fs::Permissions::from_mode(0o755)).await;
-                 let _ = fs::write(job_cache_path.join(".touch"), "").await;
-                 
-                 compile_result = Some(StageResult {
-                    status: StageStatus::Success,
-                    stdout: "Restored from cache".to_string(),
-                    stderr: "".to_string(),
-                    ..stub_result()
-                 });
-            }
-        }
-        
-        cache_path = Some(job_cache_path);
+/// `req` without what `dedup_key` leaves out of its last part: files, covered by the
+/// compile hash, and the scheduling fields and caller's metadata, which don't change the
+/// result.
+fn dedup_fields(req: &JobRequest) -> JobRequest {
+    JobRequest {
+        files: Vec::new(),
+        supersedes: None,
+        priority: None,
+        parallelism: None,
+        idempotency_key: None,
+        workspace: None,
+        metadata: None,
+        ..req.clone()
     }
+}
 
-
-    if compile_result.is_none() && compile_script.exists() {
-        let wrapper_cmd = "sh";
-        let mut compile_cmd = format!("cd {} && {}", temp_dir.display(), compile_script.display());
-        for file in &req.files {
-            let filename = file.name.as_deref().unwrap_or("main");
-            compile_cmd.push_str(&format!(" \"{}\"", filename));
+/// Cached result for `key`, marked as reused.
+async fn reuse_result(db: &TurboDb, key: &str) -> Option<JobResult> {
+    match db.queue.get_cached_result(key).await {
+        Ok(Some(mut result)) => {
+            result.cached = true;
+            Some(result)
         }
-
-        let wrapper_args = vec![
-            "-c".to_string(),
-            compile_cmd,
-        ];
-
-        let limits = ExecutionLimits {
-            timeout_ms: req.compile_timeout.unwrap_or(10000),
-            memory_limit_bytes: req.compile_memory_limit.unwrap_or(512 * 1024 * 1024),
-            ..Default::default()
-        };
-
-        match sandbox
-            .run(job_id, wrapper_cmd, &wrapper_args, &[], Some(limits))
-            .await
-        {
-            Ok(res) => {
-                let success = res.status == StageStatus::Success;
-                compile_result = Some(res.clone());
-                if !success {
-                    let mut failed_res = res;
-                    failed_res.status = StageStatus::CompilationError;
-                    compile_result = Some(failed_res);
-                    let _ = sandbox.cleanup(job_id).await;
-                    return JobResult {
-                        language: req.language.clone(),
-                        version: version.to_string(),
-                        run: None,
-                        compile: compile_result,
-                        testcases: None,
-                    };
-                }
-                
-                // Save to cache on success
-                if let Some(path) = cache_path {
-                     if let Err(e) = copy_dir_recursive(&temp_dir, &path).await {
-                         error!("Failed to save to cache: {}", e);
-                     } else {
-                         // Touch newly created cache to ensure timestamp is fresh
-                         let _ = fs::write(path.join(".touch"), "").await;
-                     }
-                }
-            }
-            Err(e) => {
-                let _ = sandbox.cleanup(job_id).await;
-                return fail_job(job, format!("Compile execution failed: {}", e));
-            }
+        Ok(None) => None,
+        Err(e) => {
+            error!("Failed to look up cached result: {}", e);
+            None
         }
     }
+}
 
-    let run_script = pkg_def.path.join("run.sh");
-    if !run_script.exists() {
-        let _ = sandbox.cleanup(job_id).await;
-        return fail_job(job, format!("Run script not found at {:?}", run_script));
-    }
-
-    let mut testcase_results = Vec::new();
-    let mut single_run_result = None;
-
-    if let Some(testcases) = &req.testcases {
-        for tc in testcases {
-            let input_file = temp_dir.join(format!("input_{}.txt", tc.id));
-            let _ = fs::write(&input_file, &tc.input).await;
-
-            let mut cmd_str = format!(
-                "cd {} && {} < {}",
-                temp_dir.display(),
-                run_script.display(),
-                input_file.display()
-            );
-            if let Some(args) = &req.args {
-                for arg in args {
-                    cmd_str.push_str(&format!(" \"{}\"", arg));
-                }
-            }
-            info!("Batch Exec Cmd: {}", cmd_str);
-            let wrapper_args = vec!["-c".to_string(), cmd_str];
-
-            let limits = ExecutionLimits {
-                timeout_ms: req.run_timeout.unwrap_or(3000),
-                memory_limit_bytes: req.run_memory_limit.unwrap_or(512 * 1024 * 1024),
-                ..Default::default()
-            };
-
-            let stage_res = match sandbox
-                .run(job_id, "sh", &wrapper_args, &[], Some(limits))
-                .await
-            {
-                Ok(r) => r,
-                Err(e) => StageResult {
-                    status: StageStatus::RuntimeError,
-                    stdout: "".to_string(),
-                    stderr: format!("Sandbox error: {}", e),
-                    ..stub_result()
-                },
-            };
-
-            let passed = if let Some(expected) = &tc.expected_output {
-                stage_res.stdout.trim() == expected.trim()
-            } else {
-                true
-            };
-
-            testcase_results.push(TestcaseResult {
-                id: tc.id.clone(),
-                passed,
-                actual_output: stage_res.stdout.clone(),
-                run_details: stage_res,
-            });
-        }
-    } else {
-        let input_file = temp_dir.join("input.txt");
-        let _ = fs::write(&input_file, req.stdin.as_deref().unwrap_or("")).await;
-
-        let mut cmd_str = format!(
-            "cd {} && {} < {}",
-            temp_dir.display(),
-            run_script.display(),
-            input_file.display()
-        );
-        if let Some(args) = &req.args {
-            for arg in args {
-                cmd_str.push_str(&format!(" \"{}\"", arg));
-            }
-        }
-        let wrapper_args = vec!["-c".to_string(), cmd_str];
-
-        let limits = ExecutionLimits {
-            timeout_ms: req.run_timeout.unwrap_or(3000),
-            memory_limit_bytes: req.run_memory_limit.unwrap_or(512 * 1024 * 1024),
-            ..Default::default()
-        };
-
-        single_run_result = sandbox
-            .run(job_id, "sh", &wrapper_args, &[], Some(limits))
-            .await
-            .ok();
+/// Count result cache lookups (made when deduplication is on) and compile cache lookups
+/// for `/metrics`.
+async fn record_cache_stats(db: &TurboDb, looked_up: bool, result: &JobResult) {
+    let mut stats = Vec::new();
+    if looked_up {
+        stats.push(if result.cached {
+            "result_cache_hits"
+        } else {
+            "result_cache_misses"
+        });
     }
-
-    let _ = sandbox.cleanup(job_id).await;
-    let _ = fs::remove_dir_all(&temp_dir).await;
-
-    JobResult {
-        language: req.language.clone(),
-        version: version.to_string(),
-        compile: compile_result,
-        run: single_run_result,
-        testcases: if testcase_results.is_empty() {
-            None
+    // A reused result ran nothing, so it says nothing about the compile cache
+    if let Some(compile) = result.compile.as_ref().filter(|_| !result.cached) {
+        stats.push(if compile.stdout == RESTORED_FROM_CACHE {
+            "compile_cache_hits"
         } else {
-            Some(testcase_results)
-        },
+            "compile_cache_misses"
+        });
+    }
+    for stat in stats {
+        if let Err(e) = db.metadata.incr_stat(stat, 1).await {
+            error!("Failed to record {}: {}", stat, e);
+        }
     }
 }
 
-fn fail_job(job: &Job, err: String) -> JobResult {
-    JobResult {
-        language: job.request.language.clone(),
-        version: job.request.version.clone().unwrap_or_default(),
-        run: Some(StageResult {
-            status: StageStatus::RuntimeError,
-            stdout: "".to_string(),
-            stderr: err,
-            ..stub_result()
-        }),
-        compile: None,
-        testcases: None,
+/// Terminal lifecycle state for a result: `Failed` when the judge could not run the job
+/// (see `turbo_engine::execute::fail_job`), `Finished` otherwise.
+pub(crate) fn final_state(result: &JobResult) -> JobState {
+    match result.run.as_ref().map(|run| &run.status) {
+        Some(StageStatus::Cancelled) => JobState::Cancelled,
+        Some(StageStatus::InternalError) => JobState::Failed,
+        _ => JobState::Finished,
     }
 }
 
-fn stub_result() -> StageResult {
-    StageResult {
-        status: StageStatus::Pending,
-        stdout: "".into(),
-        stderr: "".into(),
-        exit_code: None,
-        signal: None,
-        memory_usage: None,
-        cpu_time: None,
-        execution_time: None,
-    }
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+/// A worker whose heartbeat is older than this is considered dead and reaped.
+const HEARTBEAT_TTL_SECS: u64 = 15;
+
+fn unix_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
 }
 
-// Helper for async recursive copy
-async fn copy_dir_recursive(src: &Path, dst: &Path) -> std::io::Result<()> {
-    if !dst.exists() {
-        fs::create_dir_all(dst).await?;
-    }
-    let mut entries = fs::read_dir(src).await?;
-    while let Some(entry) = entries.next_entry().await? {
-        let ty = entry.file_type().await?;
-        let src_path = entry.path();
-        let dst_path = dst.join(entry.file_name());
-        if ty.is_dir() {
-            Box::pin(copy_dir_recursive(&src_path, &dst_path)).await?;
-        } else {
-            fs::copy(&src_path, &dst_path).await?;
-        }
+async fn beat(db: &TurboDb, name: &str, started_at: u64, current_job: Option<String>) {
+    let last_seen = unix_secs();
+    let heartbeat = WorkerHeartbeat {
+        worker: name.to_string(),
+        host: hostname(),
+        current_job,
+        started_at,
+        last_seen,
+        uptime_secs: last_seen.saturating_sub(started_at),
+    };
+    if let Err(e) = db
+        .metadata
+        .heartbeat_worker(&heartbeat, HEARTBEAT_TTL_SECS)
+        .await
+    {
+        error!("Failed to send heartbeat for {}: {}", name, e);
     }
-    Ok(())
 }
 
-// Helper for async recursive hard link with fallback to copy
-async fn hard_link_recursive(src: &Path, dst: &Path) -> std::io::Result<()> {
-    if !dst.exists() {
-        fs::create_dir_all(dst).await?;
-    }
-    let mut entries = fs::read_dir(src).await?;
-    while let Some(entry) = entries.next_entry().await? {
-        let ty = entry.file_type().await?;
-        let src_path = entry.path();
-        let dst_path = dst.join(entry.file_name());
-        if ty.is_dir() {
-            Box::pin(hard_link_recursive(&src_path, &dst_path)).await?;
-        } else {
-            if let Err(_) = fs::hard_link(&src_path, &dst_path).await {
-                // Fallback to copy if hard link fails
-                 fs::copy(&src_path, &dst_path).await?;
+/// Refreshes the worker's heartbeat every `HEARTBEAT_INTERVAL` and whenever its current
+/// job changes. Stops when the worker loop is gone, letting the heartbeat expire.
+async fn heartbeat(
+    db: TurboDb,
+    name: String,
+    started_at: u64,
+    mut current_job: watch::Receiver<Option<String>>,
+) {
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(HEARTBEAT_INTERVAL) => {}
+            changed = current_job.changed() => {
+                if changed.is_err() {
+                    break;
+                }
             }
         }
+        let job = current_job.borrow_and_update().clone();
+        beat(&db, &name, started_at, job).await;
     }
-    Ok(())
 }
 
-fn calculate_job_hash(req: &turbo_core::models::JobRequest, compile_script_content: &str) -> String {
-    let mut hasher = Sha256::new();
-    hasher.update(req.language.as_bytes());
-    hasher.update(req.version.as_deref().unwrap_or("latest").as_bytes());
-    hasher.update(compile_script_content.as_bytes());
+/// Name of worker `id` of this process: `<hostname>-<instance>-<id>`, where `instance`
+/// is `server.worker_id` or a random id.
+fn worker_name(instance: &str, id: usize) -> String {
+    format!("{}-{}-{}", hostname(), instance, id)
+}
 
-    // Sort files to ensure stable hash
-    let mut files = req.files.clone();
-    files.sort_by(|a, b| a.name.cmp(&b.name));
+/// Name of the machine this process runs on.
+pub(crate) fn hostname() -> String {
+    std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .map(|h| h.trim().to_string())
+        .unwrap_or_else(|_| "localhost".to_string())
+}
 
-    for file in files {
-        hasher.update(file.name.as_deref().unwrap_or("main").as_bytes());
-        hasher.update(&file.content);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use turbo_core::models::{
+        AnalysisTool, BenchmarkRequest, Buffering, FileRequest, GitSource, Interactor,
+        OutputEncoding, Priority, Testcase,
+    };
+
+    /// A request with every field set, listed without `..` so a new field has to be added
+    /// here, and so to the test below.
+    fn full_request() -> JobRequest {
+        JobRequest {
+            language: "python".to_string(),
+            version: Some("3.12".to_string()),
+            files: vec![FileRequest {
+                name: Some("main.py".to_string()),
+                content: "print(input())".to_string(),
+                encoding: Some("utf8".to_string()),
+                url: None,
+            }],
+            testcases: Some(vec![Testcase {
+                id: "1".to_string(),
+                input: "1".to_string(),
+                expected_output: Some("1".to_string()),
+                hidden: false,
+            }]),
+            args: Some(vec!["-v".to_string()]),
+            stdin: Some("hello".to_string()),
+            run_timeout: Some(1000),
+            compile_timeout: Some(2000),
+            run_memory_limit: Some(64 << 20),
+            compile_memory_limit: Some(128 << 20),
+            output_limit: Some(1024),
+            pid_limit: Some(8),
+            tty: Some(false),
+            supersedes: Some("buffer-1".to_string()),
+            priority: Some(Priority::High),
+            parallelism: Some(2),
+            stop_on_failure: Some(true),
+            max_total_time_ms: Some(5000),
+            idempotency_key: Some("retry-1".to_string()),
+            workspace: Some("ws".to_string()),
+            source: Some(JobSource {
+                git: GitSource {
+                    url: "https://example.com/repo.git".to_string(),
+                    reference: Some("a".repeat(40)),
+                    subdir: Some("src".to_string()),
+                },
+            }),
+            benchmark: Some(BenchmarkRequest {
+                iterations: 3,
+                warmup: 1,
+            }),
+            output_encoding: Some(OutputEncoding::Base64),
+            sample_interval_ms: Some(10),
+            profile: Some(true),
+            timezone: Some("Europe/Berlin".to_string()),
+            locale: Some("C.UTF-8".to_string()),
+            metadata: Some(serde_json::json!({ "submission": 1 })),
+            legacy_args: Some(false),
+            diagnostics: Some(true),
+            analysis: Some(AnalysisTool::Valgrind),
+            interactor: Some(Interactor {
+                cmd: "python3".to_string(),
+                args: vec!["judge.py".to_string()],
+                buffering: Buffering::Raw,
+                message_timeout_ms: Some(500),
+                max_exchanges: Some(10),
+            }),
+        }
     }
 
-    hex::encode(hasher.finalize())
+    #[test]
+    fn dedup_ignores_exactly_files_scheduling_and_metadata() {
+        let full = serde_json::to_value(full_request()).unwrap();
+        let kept = serde_json::to_value(dedup_fields(&full_request())).unwrap();
+        let full = full.as_object().unwrap();
+        let kept = kept.as_object().unwrap();
+        assert!(full.values().all(|value| !value.is_null()));
+
+        let mut dropped: Vec<&str> = full
+            .keys()
+            .filter(|key| full[*key] != kept[*key])
+            .map(String::as_str)
+            .collect();
+        dropped.sort_unstable();
+        assert_eq!(
+            dropped,
+            [
+                "files",
+                "idempotency_key",
+                "metadata",
+                "parallelism",
+                "priority",
+                "supersedes",
+                "workspace",
+            ]
+        );
+    }
 }
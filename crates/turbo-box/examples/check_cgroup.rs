@@ -1,5 +1,7 @@
+#[cfg(target_os = "linux")]
 use std::path::Path;
 
+#[cfg(target_os = "linux")]
 fn main() {
     let uid = nix::unistd::getuid().as_raw();
     println!("UID: {}", uid);
@@ -14,7 +16,7 @@ fn main() {
     if path.exists() {
         println!("Path exists!");
         // Check write permission by trying to open dir? Or just metadata.
-        match std::fs::metadata(&path) {
+        match std::fs::metadata(path) {
             Ok(md) => {
                 println!("Metadata: {:?}", md.permissions());
                 println!("Is Dir: {}", md.is_dir());
@@ -25,3 +27,8 @@ fn main() {
         println!("Path DOES NOT exist via std::path::Path::exists()");
     }
 }
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    eprintln!("This example needs the Linux sandbox backend");
+}
@@ -1,25 +1,30 @@
-use turbo_box::{LinuxSandbox, Sandbox};
+#[cfg(target_os = "linux")]
+use turbo_box::{CommandSpec, LinuxSandbox, Sandbox};
 
+#[cfg(target_os = "linux")]
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let sandbox = LinuxSandbox::new("/tmp/turbo-box".to_string());
+    let sandbox = LinuxSandbox::new("/tmp/turbo-box".to_string()).with_user(None, None, true);
 
     println!("Initializing sandbox...");
     sandbox.init("test-01").await?;
 
     println!("Running echo...");
-    let result = sandbox
-        .run(
-            "test-01",
-            "echo",
-            &["Hello from Turbo!".to_string()],
-            &[],
-            None,
-        )
-        .await?;
+    let args = ["Hello from Turbo!".to_string()];
+    let spec = CommandSpec {
+        cmd: "echo",
+        args: &args,
+        ..Default::default()
+    };
+    let result = sandbox.run("test-01", spec, None).await?;
 
     println!("Result: {:?}", result);
 
     sandbox.cleanup("test-01").await?;
     Ok(())
 }
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    eprintln!("This example needs the Linux sandbox backend");
+}
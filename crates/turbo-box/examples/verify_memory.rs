@@ -1,10 +1,14 @@
+#[cfg(target_os = "linux")]
 use turbo_box::linux::LinuxSandbox;
-use turbo_box::traits::Sandbox;
+#[cfg(target_os = "linux")]
+use turbo_box::traits::{CommandSpec, Sandbox};
+#[cfg(target_os = "linux")]
 use turbo_core::models::StageStatus;
 
+#[cfg(target_os = "linux")]
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let box_impl = LinuxSandbox::new("/tmp/turbo-root".to_string());
+    let box_impl = LinuxSandbox::new("/tmp/turbo-root".to_string()).with_user(None, None, true);
 
     let id = "verify_memory";
     box_impl.init(id).await?;
@@ -14,18 +18,18 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     println!("Running Memory Hog (Expect MemoryLimitExceeded)...");
 
-    let mut limits = turbo_core::models::ExecutionLimits::default();
-    limits.memory_limit_bytes = 512 * 1024 * 1024; // 512 MB
+    let limits = turbo_core::models::ExecutionLimits {
+        memory_limit_bytes: 512 * 1024 * 1024, // 512 MB
+        ..Default::default()
+    };
 
-    let result = box_impl
-        .run(
-            id,
-            cmd,
-            &["-e".to_string(), script.to_string()],
-            &[],
-            Some(limits),
-        )
-        .await;
+    let args = ["-e".to_string(), script.to_string()];
+    let spec = CommandSpec {
+        cmd,
+        args: &args,
+        ..Default::default()
+    };
+    let result = box_impl.run(id, spec, Some(limits)).await;
 
     match result {
         Ok(res) => {
@@ -45,3 +49,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    eprintln!("This example needs the Linux sandbox backend");
+}
@@ -1,9 +1,12 @@
+#[cfg(target_os = "linux")]
 use turbo_box::linux::LinuxSandbox;
-use turbo_box::traits::Sandbox;
+#[cfg(target_os = "linux")]
+use turbo_box::traits::{CommandSpec, Sandbox};
 
+#[cfg(target_os = "linux")]
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let box_impl = LinuxSandbox::new("/tmp/turbo-root".to_string());
+    let box_impl = LinuxSandbox::new("/tmp/turbo-root".to_string()).with_user(None, None, true);
 
     let id = "verify_output";
     box_impl.init(id).await?;
@@ -14,10 +17,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Or we can use head to limit it but we want to test output cap on the internal reader.
     // Let's rely on timeout to kill 'yes' but check if stdout size is <= 1024.
 
-    let mut limits = turbo_core::models::ExecutionLimits::default();
-    limits.output_limit_bytes = 1024; // 1KB
+    let limits = turbo_core::models::ExecutionLimits {
+        output_limit_bytes: 1024, // 1KB
+        ..Default::default()
+    };
 
-    let result = box_impl.run(id, "yes", &[], &[], Some(limits)).await?;
+    let spec = CommandSpec {
+        cmd: "yes",
+        ..Default::default()
+    };
+    let result = box_impl.run(id, spec, Some(limits)).await?;
 
     println!("Stdout Length: {}", result.stdout.len());
 
@@ -34,3 +43,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    eprintln!("This example needs the Linux sandbox backend");
+}
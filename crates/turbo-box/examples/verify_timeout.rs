@@ -1,21 +1,31 @@
+#[cfg(target_os = "linux")]
 use turbo_box::linux::LinuxSandbox;
-use turbo_box::traits::Sandbox;
+#[cfg(target_os = "linux")]
+use turbo_box::traits::{CommandSpec, Sandbox};
+#[cfg(target_os = "linux")]
 use turbo_core::models::StageStatus;
 
+#[cfg(target_os = "linux")]
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let box_impl = LinuxSandbox::new("/tmp/turbo-root".to_string());
+    let box_impl = LinuxSandbox::new("/tmp/turbo-root".to_string()).with_user(None, None, true);
 
     let id = "verify_timeout";
     box_impl.init(id).await?;
 
-    let mut limits = turbo_core::models::ExecutionLimits::default();
-    limits.timeout_ms = 3000; // 3s
+    let limits = turbo_core::models::ExecutionLimits {
+        timeout_ms: 3000, // 3s
+        ..Default::default()
+    };
 
     println!("Running 'sleep 10' (Expect TimeLimitExceeded)...");
-    let result = box_impl
-        .run(id, "sleep", &["10".to_string()], &[], Some(limits))
-        .await?;
+    let args = ["10".to_string()];
+    let spec = CommandSpec {
+        cmd: "sleep",
+        args: &args,
+        ..Default::default()
+    };
+    let result = box_impl.run(id, spec, Some(limits)).await?;
 
     println!("Result:\n{}", result);
 
@@ -29,3 +39,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    eprintln!("This example needs the Linux sandbox backend");
+}
@@ -1,7 +1,9 @@
+#[cfg(target_os = "linux")]
 use turbo_box::linux::LinuxSandbox;
-use turbo_box::traits::Sandbox;
-use turbo_core::models::StageStatus;
+#[cfg(target_os = "linux")]
+use turbo_box::traits::{CommandSpec, Sandbox};
 
+#[cfg(target_os = "linux")]
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let box_impl = LinuxSandbox::new("/tmp/turbo-root".to_string());
@@ -10,13 +12,19 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     box_impl.init(id).await?;
 
     // Limits with UID set to 65534 (nobody)
-    let mut limits = turbo_core::models::ExecutionLimits::default();
-    limits.uid = Some(65534);
-    limits.gid = Some(65534);
+    let limits = turbo_core::models::ExecutionLimits {
+        uid: Some(65534),
+        gid: Some(65534),
+        ..Default::default()
+    };
 
     println!("Running 'id' as user 65534 (Expect uid=65534(nobody))...");
 
-    let result = box_impl.run(id, "id", &[], &[], Some(limits)).await?;
+    let spec = CommandSpec {
+        cmd: "id",
+        ..Default::default()
+    };
+    let result = box_impl.run(id, spec, Some(limits)).await?;
 
     println!("Stdout: {}", result.stdout);
 
@@ -30,3 +38,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    eprintln!("This example needs the Linux sandbox backend");
+}
@@ -0,0 +1,654 @@
+use crate::linux::{signal_name, SANDBOX_HOSTNAME};
+use crate::traits::{CommandSpec, Sandbox};
+use crate::workdir;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tracing::{info, instrument, warn};
+use turbo_core::config::{ExternalSandboxConfig, SandboxConfig};
+use turbo_core::models::{StageStatus, TerminationReason};
+use turbo_core::{ExecutionLimits, Result, SandboxError, StageResult};
+
+/// nsjail exits with 128 plus the signal number when its child is killed by a signal
+const NSJAIL_SIGNAL_BASE: i32 = 128;
+/// nsjail's exit code when it fails itself, which a program can also exit with
+const NSJAIL_FAILURE: i32 = 255;
+/// isolate's exit code when the program failed; anything else non-zero is its own error
+const ISOLATE_PROGRAM_FAILED: i32 = 1;
+/// How long past a run's timeout the tool itself is killed, should it not return
+const TOOL_GRACE: Duration = Duration::from_secs(2);
+const MIB: u64 = 1024 * 1024;
+
+/// External sandbox tool a `ExternalSandbox` drives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExternalTool {
+    /// Google's nsjail, started once per command (`--mode o`)
+    Nsjail,
+    /// The IOI `isolate`, with one box (and control group) per sandbox
+    Isolate,
+}
+
+impl ExternalTool {
+    fn binary_name(self) -> &'static str {
+        match self {
+            Self::Nsjail => "nsjail",
+            Self::Isolate => "isolate",
+        }
+    }
+}
+
+/// Sandbox that runs every command through `nsjail` or `isolate`, for operators who
+/// already trust one of them and want turbo only as the orchestration layer.
+///
+/// Working directories live under `root_path` as with the other backends and are bound
+/// into the tool's sandbox at the same path, so paths mean the same inside and out.
+/// Runs on pipes only: terminals and warm processes are not supported.
+pub struct ExternalSandbox {
+    /// Root path under which working directories are created.
+    pub root_path: String,
+    tool: ExternalTool,
+    binary: String,
+    config: ExternalSandboxConfig,
+    /// User and group nsjail runs commands as; isolate uses each box's own
+    uid: Option<u32>,
+    gid: Option<u32>,
+    /// isolate box of each initialized sandbox
+    boxes: Mutex<HashMap<String, u32>>,
+}
+
+impl ExternalSandbox {
+    /// The sandbox `[sandbox]` describes for `tool`, as `create_sandbox` builds it.
+    pub fn from_config(tool: ExternalTool, config: &SandboxConfig) -> Self {
+        let external = config.external.clone();
+        let binary = external
+            .binary
+            .clone()
+            .unwrap_or_else(|| tool.binary_name().to_string());
+        info!("Running sandboxed commands through {}", binary);
+        Self {
+            root_path: config.root_path.clone(),
+            tool,
+            binary,
+            config: external,
+            uid: config.uid,
+            gid: config.gid,
+            boxes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Runs the tool itself (not a sandboxed command), failing with its stderr.
+    async fn tool_command(&self, args: &[String]) -> Result<String> {
+        let output = tokio::process::Command::new(&self.binary)
+            .args(args)
+            .stdin(Stdio::null())
+            .output()
+            .await
+            .map_err(|source| SandboxError::SpawnFailed {
+                command: self.binary.clone(),
+                source,
+            })?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(SandboxError::Setup {
+                operation: format!("run {} {}", self.binary, args.join(" ")),
+                source: std::io::Error::other(stderr.trim().to_string()),
+            }
+            .into());
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    /// isolate box of sandbox `id`, taking the lowest free one of this process's range
+    /// if it has none yet.
+    fn allocate_box(&self, id: &str) -> Result<u32> {
+        let mut boxes = self.boxes.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(&box_id) = boxes.get(id) {
+            return Ok(box_id);
+        }
+        let first = self.config.first_box;
+        let free = (first..first.saturating_add(self.config.boxes))
+            .find(|b| !boxes.values().any(|used| used == b));
+        let box_id = free.ok_or_else(|| SandboxError::Setup {
+            operation: "allocate an isolate box".to_string(),
+            source: std::io::Error::other(format!("all {} boxes are in use", self.config.boxes)),
+        })?;
+        boxes.insert(id.to_string(), box_id);
+        Ok(box_id)
+    }
+
+    fn box_id(&self, id: &str) -> Result<u32> {
+        let boxes = self.boxes.lock().unwrap_or_else(|e| e.into_inner());
+        boxes
+            .get(id)
+            .copied()
+            .ok_or_else(|| SandboxError::NotInitialized(id.to_string()).into())
+    }
+
+    fn release_box(&self, id: &str) -> Option<u32> {
+        let mut boxes = self.boxes.lock().unwrap_or_else(|e| e.into_inner());
+        boxes.remove(id)
+    }
+
+    /// Where isolate writes the run statistics of `box_id`, outside the box.
+    fn meta_path(box_id: u32) -> PathBuf {
+        std::env::temp_dir().join(format!("turbo-isolate-{}.meta", box_id))
+    }
+
+    /// nsjail flags for one command, logging nsjail's own errors to `log`; limits nsjail
+    /// would otherwise default (such as its 1 MB RLIMIT_FSIZE) are always given.
+    fn nsjail_args(
+        &self,
+        work_dir: &Path,
+        cwd: &Path,
+        log: &Path,
+        spec: &CommandSpec<'_>,
+        limits: &ExecutionLimits,
+    ) -> Vec<String> {
+        let mut args: Vec<String> = vec![
+            "--mode".into(),
+            "o".into(),
+            "--quiet".into(),
+            "--log".into(),
+            log.to_string_lossy().into_owned(),
+            "--chroot".into(),
+            "/".into(),
+            "--bindmount".into(),
+            work_dir.to_string_lossy().into_owned(),
+            "--cwd".into(),
+            cwd.to_string_lossy().into_owned(),
+            "--hostname".into(),
+            SANDBOX_HOSTNAME.into(),
+            "--time_limit".into(),
+            limits.timeout_ms.div_ceil(1000).to_string(),
+            "--rlimit_as".into(),
+            "inf".into(),
+            "--rlimit_nproc".into(),
+            "inf".into(),
+            "--rlimit_nofile".into(),
+            limits.file_limit.to_string(),
+            "--rlimit_cpu".into(),
+            limits
+                .cpu_rlimit_secs()
+                .map_or_else(|| "inf".into(), |secs| secs.to_string()),
+            "--rlimit_fsize".into(),
+            match limits.file_size_limit_bytes {
+                0 => "inf".into(),
+                bytes => bytes.div_ceil(MIB).to_string(),
+            },
+            "--use_cgroupv2".into(),
+            "--cgroup_mem_max".into(),
+            limits.memory_limit_bytes.to_string(),
+            "--cgroup_pids_max".into(),
+            limits.pid_limit.to_string(),
+            "--keep_env".into(),
+        ];
+        if let Some(uid) = limits.uid.or(self.uid) {
+            args.extend(["--user".into(), uid.to_string()]);
+        }
+        if let Some(gid) = limits.gid.or(self.gid) {
+            args.extend(["--group".into(), gid.to_string()]);
+        }
+        for var in spec.env {
+            args.extend(["--env".into(), var.clone()]);
+        }
+        args.push("--".into());
+        args.push(spec.cmd.to_string());
+        args.extend(spec.args.iter().cloned());
+        args
+    }
+
+    /// isolate flags for one command in `box_id`.
+    fn isolate_args(
+        &self,
+        box_id: u32,
+        work_dir: &Path,
+        cwd: &Path,
+        spec: &CommandSpec<'_>,
+        limits: &ExecutionLimits,
+    ) -> Vec<String> {
+        let work_dir = work_dir.to_string_lossy();
+        let mut args = vec![
+            "--cg".to_string(),
+            format!("--box-id={}", box_id),
+            format!("--meta={}", Self::meta_path(box_id).display()),
+            format!("--processes={}", limits.pid_limit),
+            format!("--open-files={}", limits.file_limit),
+            format!("--cg-mem={}", limits.memory_limit_bytes / 1024),
+            format!("--wall-time={:.3}", limits.timeout_ms as f64 / 1000.0),
+            format!("--dir={}={}:rw", work_dir, work_dir),
+            format!("--chdir={}", cwd.display()),
+            "--full-env".to_string(),
+        ];
+        if let Some(secs) = limits.cpu_rlimit_secs() {
+            args.push(format!("--time={}", secs));
+        }
+        if limits.file_size_limit_bytes > 0 {
+            args.push(format!(
+                "--fsize={}",
+                limits.file_size_limit_bytes.div_ceil(1024)
+            ));
+        }
+        for dir in &self.config.bind {
+            args.push(format!("--dir={}", dir));
+        }
+        for var in spec.env {
+            args.push(format!("--env={}", var));
+        }
+        args.push("--run".to_string());
+        args.push("--".to_string());
+        args.push(spec.cmd.to_string());
+        args.extend(spec.args.iter().cloned());
+        args
+    }
+
+    /// Result of an nsjail run from its exit status and `log`, where nsjail reports its
+    /// own failures; nsjail reports no usage.
+    fn nsjail_result(
+        status: ExitStatus,
+        elapsed_ms: u64,
+        limits: &ExecutionLimits,
+        log: &str,
+    ) -> Result<Outcome> {
+        let signal = match status.code() {
+            Some(NSJAIL_FAILURE) if !log.trim().is_empty() => {
+                return Err(SandboxError::Setup {
+                    operation: "run a command in nsjail".to_string(),
+                    source: std::io::Error::other(log.trim().to_string()),
+                }
+                .into());
+            }
+            Some(code) if code > NSJAIL_SIGNAL_BASE && code < NSJAIL_FAILURE => {
+                Some(code - NSJAIL_SIGNAL_BASE)
+            }
+            Some(_) => None,
+            None => status.signal(),
+        };
+        let Some(signal) = signal else {
+            let code = status.code().unwrap_or(-1);
+            return Ok(Outcome::exited(code));
+        };
+        let name = signal_name(signal);
+        let timed_out = limits.timeout_ms > 0 && elapsed_ms >= limits.timeout_ms;
+        let (status, reason) = match signal {
+            nix::libc::SIGKILL if timed_out => {
+                (StageStatus::TimeLimitExceeded, TerminationReason::TimeLimit)
+            }
+            // As with the linux backend, a SIGKILL nobody else sent is the OOM killer
+            nix::libc::SIGKILL => (
+                StageStatus::MemoryLimitExceeded,
+                TerminationReason::MemoryLimit,
+            ),
+            nix::libc::SIGXCPU => (StageStatus::TimeLimitExceeded, TerminationReason::TimeLimit),
+            _ => (
+                StageStatus::RuntimeError,
+                TerminationReason::from_signal(&name),
+            ),
+        };
+        Ok(Outcome {
+            status,
+            exit_code: None,
+            signal: Some(name),
+            termination_reason: reason,
+            cpu_time: None,
+            memory_usage: None,
+            execution_time: None,
+        })
+    }
+
+    /// Result of an isolate run from its exit status and the meta file it wrote.
+    fn isolate_result(status: ExitStatus, meta: &str) -> Result<Outcome> {
+        let meta: HashMap<&str, &str> = meta
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .collect();
+        let failed = |message: String| -> Result<Outcome> {
+            Err(SandboxError::Setup {
+                operation: "run a command in isolate".to_string(),
+                source: std::io::Error::other(message),
+            }
+            .into())
+        };
+        // Without a verdict, isolate failed before or while starting the program
+        let code = status.code();
+        if code != Some(0) && (code != Some(ISOLATE_PROGRAM_FAILED) || !meta.contains_key("status"))
+        {
+            let message = meta.get("message").copied().unwrap_or("no meta file");
+            return failed(format!("isolate exited with {}: {}", status, message));
+        }
+        let number = |key: &str| meta.get(key).and_then(|v| v.trim().parse::<f64>().ok());
+        let kib = |key: &str| number(key).map(|kib| kib as u64 * 1024);
+
+        let mut outcome = match meta.get("status").copied() {
+            Some("TO") => Outcome::timed_out(),
+            Some("SG") => {
+                let name = number("exitsig")
+                    .map_or_else(|| "SIGKILL".to_string(), |s| signal_name(s as i32));
+                let (status, reason) = if meta.contains_key("cg-oom-killed") {
+                    (
+                        StageStatus::MemoryLimitExceeded,
+                        TerminationReason::MemoryLimit,
+                    )
+                } else {
+                    (
+                        StageStatus::RuntimeError,
+                        TerminationReason::from_signal(&name),
+                    )
+                };
+                Outcome {
+                    status,
+                    exit_code: None,
+                    signal: Some(name),
+                    termination_reason: reason,
+                    ..Outcome::exited(0)
+                }
+            }
+            Some("XX") => {
+                let message = meta.get("message").copied().unwrap_or("internal error");
+                return failed(message.to_string());
+            }
+            _ => Outcome::exited(number("exitcode").unwrap_or(0.0) as i32),
+        };
+        outcome.cpu_time = number("time").map(|secs| (secs * 1_000_000.0) as u64);
+        outcome.memory_usage = kib("cg-mem").or_else(|| kib("max-rss"));
+        outcome.execution_time = number("time-wall").map(|secs| (secs * 1000.0) as u64);
+        Ok(outcome)
+    }
+}
+
+/// How a run ended, before its output is attached.
+struct Outcome {
+    status: StageStatus,
+    exit_code: Option<i32>,
+    signal: Option<String>,
+    termination_reason: TerminationReason,
+    cpu_time: Option<u64>,
+    memory_usage: Option<u64>,
+    execution_time: Option<u64>,
+}
+
+impl Outcome {
+    fn exited(code: i32) -> Self {
+        Self {
+            status: if code == 0 {
+                StageStatus::Success
+            } else {
+                StageStatus::RuntimeError
+            },
+            exit_code: Some(code),
+            signal: None,
+            termination_reason: TerminationReason::Exited,
+            cpu_time: None,
+            memory_usage: None,
+            execution_time: None,
+        }
+    }
+
+    fn timed_out() -> Self {
+        Self {
+            status: StageStatus::TimeLimitExceeded,
+            exit_code: None,
+            signal: Some("SIGKILL".to_string()),
+            termination_reason: TerminationReason::TimeLimit,
+            ..Self::exited(0)
+        }
+    }
+}
+
+/// Reads `pipe` to its end, keeping at most `cap` bytes.
+async fn read_capped<R: AsyncRead + Unpin>(pipe: Option<R>, cap: u64) -> Vec<u8> {
+    let mut buf = Vec::new();
+    if let Some(pipe) = pipe {
+        let _ = pipe.take(cap).read_to_end(&mut buf).await;
+    }
+    buf
+}
+
+#[async_trait]
+impl Sandbox for ExternalSandbox {
+    async fn init(&self, id: &str) -> Result<()> {
+        let dir = workdir::job_dir(Path::new(&self.root_path), id)?;
+        tokio::fs::create_dir_all(&dir).await?;
+        // The tool runs commands as another user, who must be able to write build output
+        tokio::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o777)).await?;
+
+        if self.tool == ExternalTool::Isolate {
+            let box_id = self.allocate_box(id)?;
+            let args = [
+                "--cg".into(),
+                format!("--box-id={}", box_id),
+                "--init".into(),
+            ];
+            if let Err(e) = self.tool_command(&args).await {
+                self.release_box(id);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    fn work_dir(&self, id: &str) -> PathBuf {
+        Path::new(&self.root_path).join(id)
+    }
+
+    #[instrument(skip(self))]
+    async fn run(
+        &self,
+        id: &str,
+        spec: CommandSpec<'_>,
+        limits: Option<ExecutionLimits>,
+    ) -> Result<StageResult> {
+        if spec.tty {
+            return Err(SandboxError::Unsupported("tty".to_string()).into());
+        }
+        info!(
+            "Running command through {} in {}: {} {:?}",
+            self.binary, id, spec.cmd, spec.args
+        );
+
+        let limits = limits.unwrap_or_default();
+        let work_dir = self.work_dir(id);
+        let cwd = spec.cwd.map_or_else(|| work_dir.clone(), Path::to_path_buf);
+        // Where the tool reports on the run, outside the sandbox
+        let (args, report) = match self.tool {
+            ExternalTool::Nsjail => {
+                let log =
+                    std::env::temp_dir().join(format!("turbo-nsjail-{}.log", uuid::Uuid::new_v4()));
+                let args = self.nsjail_args(&work_dir, &cwd, &log, &spec, &limits);
+                (args, log)
+            }
+            ExternalTool::Isolate => {
+                let box_id = self.box_id(id)?;
+                let args = self.isolate_args(box_id, &work_dir, &cwd, &spec, &limits);
+                // A stale file from an earlier run must not stand in for a missing one
+                let meta = Self::meta_path(box_id);
+                let _ = tokio::fs::remove_file(&meta).await;
+                (args, meta)
+            }
+        };
+
+        let started = Instant::now();
+        let mut child = tokio::process::Command::new(&self.binary)
+            .args(&args)
+            .stdin(if spec.stdin.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|source| SandboxError::SpawnFailed {
+                command: self.binary.clone(),
+                source,
+            })?;
+        if let (Some(input), Some(mut stdin)) = (spec.stdin, child.stdin.take()) {
+            let input = input.to_vec();
+            tokio::spawn(async move {
+                let _ = stdin.write_all(&input).await;
+            });
+        }
+        let cap = limits.output_limit_bytes;
+        let stdout = tokio::spawn(read_capped(child.stdout.take(), cap));
+        let stderr = tokio::spawn(read_capped(child.stderr.take(), cap));
+
+        // The tool enforces the timeout; this only guards against the tool hanging
+        let wait = Duration::from_millis(limits.timeout_ms) + TOOL_GRACE;
+        let status = match tokio::time::timeout(wait, child.wait()).await {
+            Ok(status) => Some(status?),
+            Err(_) => {
+                warn!(
+                    "{} still running {:?} past the timeout",
+                    self.binary, TOOL_GRACE
+                );
+                let _ = child.kill().await;
+                None
+            }
+        };
+        let elapsed_ms = started.elapsed().as_millis() as u64;
+        let stdout = stdout.await.unwrap_or_default();
+        let stderr = stderr.await.unwrap_or_default();
+
+        // A report that was never written reads as empty
+        let report_text = tokio::fs::read_to_string(&report).await.unwrap_or_default();
+        if self.tool == ExternalTool::Nsjail {
+            let _ = tokio::fs::remove_file(&report).await;
+        }
+        let outcome = match status {
+            None => Outcome::timed_out(),
+            Some(status) => match self.tool {
+                ExternalTool::Nsjail => {
+                    Self::nsjail_result(status, elapsed_ms, &limits, &report_text)?
+                }
+                ExternalTool::Isolate => Self::isolate_result(status, &report_text)?,
+            },
+        };
+
+        Ok(StageResult {
+            status: outcome.status,
+            stdout: limits.output_encoding.encode(&stdout),
+            stderr: limits.output_encoding.encode(&stderr),
+            exit_code: outcome.exit_code,
+            signal: outcome.signal,
+            memory_usage: outcome.memory_usage,
+            cpu_time: outcome.cpu_time,
+            execution_time: Some(outcome.execution_time.unwrap_or(elapsed_ms)),
+            termination_reason: Some(outcome.termination_reason),
+            io_read_bytes: None,
+            io_write_bytes: None,
+            samples: None,
+            perf_counters: None,
+        })
+    }
+
+    async fn cleanup(&self, id: &str) -> Result<()> {
+        if let Some(box_id) = self.release_box(id) {
+            let args = [
+                "--cg".into(),
+                format!("--box-id={}", box_id),
+                "--cleanup".into(),
+            ];
+            if let Err(e) = self.tool_command(&args).await {
+                warn!("Failed to clean up isolate box {}: {}", box_id, e);
+            }
+            let _ = tokio::fs::remove_file(Self::meta_path(box_id)).await;
+        }
+        let _ = tokio::fs::remove_dir_all(self.work_dir(id)).await;
+        Ok(())
+    }
+
+    async fn sweep_orphans(
+        &self,
+        in_use: &(dyn for<'a> Fn(&'a str) -> bool + Sync),
+        min_age: Duration,
+    ) -> Result<usize> {
+        let stale = workdir::stale_dirs(Path::new(&self.root_path), min_age).await?;
+        let mut removed = 0;
+        for id in stale.iter().filter(|id| !in_use(id)) {
+            self.cleanup(id).await?;
+            removed += 1;
+        }
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exit(code: i32) -> ExitStatus {
+        ExitStatus::from_raw(code << 8)
+    }
+
+    #[test]
+    fn isolate_success() {
+        let meta = "time:0.012\ntime-wall:0.034\nmax-rss:2048\nexitcode:0\n";
+        let outcome = ExternalSandbox::isolate_result(exit(0), meta).unwrap();
+        assert_eq!(outcome.status, StageStatus::Success);
+        assert_eq!(outcome.exit_code, Some(0));
+        assert_eq!(outcome.cpu_time, Some(12_000));
+        assert_eq!(outcome.execution_time, Some(34));
+        assert_eq!(outcome.memory_usage, Some(2048 * 1024));
+    }
+
+    #[test]
+    fn isolate_program_failures() {
+        let meta = "status:RE\nexitcode:3\ncg-mem:4096\n";
+        let outcome = ExternalSandbox::isolate_result(exit(1), meta).unwrap();
+        assert_eq!(outcome.status, StageStatus::RuntimeError);
+        assert_eq!(outcome.exit_code, Some(3));
+        assert_eq!(outcome.memory_usage, Some(4096 * 1024));
+
+        let outcome = ExternalSandbox::isolate_result(exit(1), "status:TO\n").unwrap();
+        assert_eq!(outcome.status, StageStatus::TimeLimitExceeded);
+
+        let meta = "status:SG\nexitsig:9\ncg-oom-killed:1\n";
+        let outcome = ExternalSandbox::isolate_result(exit(1), meta).unwrap();
+        assert_eq!(outcome.status, StageStatus::MemoryLimitExceeded);
+        assert_eq!(outcome.signal.as_deref(), Some("SIGKILL"));
+
+        let outcome = ExternalSandbox::isolate_result(exit(1), "status:SG\nexitsig:11\n").unwrap();
+        assert_eq!(outcome.status, StageStatus::RuntimeError);
+        assert_eq!(
+            outcome.termination_reason,
+            TerminationReason::SegmentationFault
+        );
+    }
+
+    #[test]
+    fn isolate_own_failures_are_errors() {
+        // Setup failure: no meta file at all
+        assert!(ExternalSandbox::isolate_result(exit(2), "").is_err());
+        // Program-failure exit code but no verdict
+        assert!(ExternalSandbox::isolate_result(exit(1), "").is_err());
+        assert!(ExternalSandbox::isolate_result(exit(1), "status:XX\nmessage:oops\n").is_err());
+    }
+
+    #[test]
+    fn nsjail_results() {
+        let limits = ExecutionLimits::default();
+        let outcome = ExternalSandbox::nsjail_result(exit(0), 5, &limits, "").unwrap();
+        assert_eq!(outcome.status, StageStatus::Success);
+
+        let outcome = ExternalSandbox::nsjail_result(exit(128 + 11), 5, &limits, "").unwrap();
+        assert_eq!(outcome.signal.as_deref(), Some("SIGSEGV"));
+
+        let outcome =
+            ExternalSandbox::nsjail_result(exit(137), limits.timeout_ms, &limits, "").unwrap();
+        assert_eq!(outcome.status, StageStatus::TimeLimitExceeded);
+
+        // A program exiting with 255 is not nsjail failing
+        let outcome = ExternalSandbox::nsjail_result(exit(255), 5, &limits, "").unwrap();
+        assert_eq!(outcome.status, StageStatus::RuntimeError);
+        assert_eq!(outcome.exit_code, Some(255));
+
+        let log = "[F][2024-01-01T00:00:00+0000] Couldn't mount '/box'";
+        assert!(ExternalSandbox::nsjail_result(exit(255), 5, &limits, log).is_err());
+    }
+}
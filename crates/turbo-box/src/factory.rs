@@ -0,0 +1,37 @@
+use crate::mock::MockSandbox;
+use crate::traits::Sandbox;
+use std::path::Path;
+use std::sync::Arc;
+use turbo_core::config::SandboxConfig;
+use turbo_core::{Result, SandboxError};
+
+/// Build the sandbox backend selected by `sandbox.backend`.
+///
+/// The sandbox root directory (`sandbox.root_path`) is validated and created first.
+pub fn create_sandbox(config: &SandboxConfig) -> Result<Arc<dyn Sandbox>> {
+    crate::workdir::init_root(Path::new(&config.root_path))?;
+
+    match config.backend.as_str() {
+        #[cfg(target_os = "linux")]
+        "linux" => Ok(Arc::new(crate::LinuxSandbox::from_config(config))),
+        #[cfg(target_os = "linux")]
+        "nsjail" => Ok(Arc::new(crate::ExternalSandbox::from_config(
+            crate::ExternalTool::Nsjail,
+            config,
+        ))),
+        #[cfg(target_os = "linux")]
+        "isolate" => Ok(Arc::new(crate::ExternalSandbox::from_config(
+            crate::ExternalTool::Isolate,
+            config,
+        ))),
+        #[cfg(windows)]
+        "windows" => Ok(Arc::new(crate::WindowsSandbox::new(
+            config.root_path.clone(),
+        ))),
+        "mock" => Ok(Arc::new(MockSandbox::new(
+            config.root_path.clone(),
+            config.mock.clone(),
+        ))),
+        other => Err(SandboxError::UnsupportedBackend(other.to_string()).into()),
+    }
+}
@@ -0,0 +1,251 @@
+//! Relays messages between a run and its interactor for `Sandbox::exec_interactive`,
+//! keeping what a watchdog needs to end a stuck exchange: when the last message passed
+//! and whether the run went past its round trips.
+
+use crate::traits::Protocol;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Longest line held back in line mode; a longer one is relayed as it is.
+const MAX_LINE_BYTES: usize = 1 << 20;
+
+/// Which way a message goes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Direction {
+    ToInteractor,
+    ToRun,
+}
+
+/// State shared by both directions of a relay and the watchdog.
+pub(crate) struct Exchange {
+    protocol: Protocol,
+    state: Mutex<ExchangeState>,
+    exceeded: AtomicBool,
+}
+
+struct ExchangeState {
+    last_message: Instant,
+    last_direction: Option<Direction>,
+    /// Messages of the run answered by the interactor
+    round_trips: u32,
+}
+
+impl Exchange {
+    pub(crate) fn new(protocol: Protocol) -> Self {
+        Self {
+            protocol,
+            state: Mutex::new(ExchangeState {
+                last_message: Instant::now(),
+                last_direction: None,
+                round_trips: 0,
+            }),
+            exceeded: AtomicBool::new(false),
+        }
+    }
+
+    pub(crate) fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    /// Records a message going `direction`; false once the run has gone past
+    /// `max_exchanges`, after which nothing more is relayed.
+    fn record(&self, direction: Direction) -> bool {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.last_message = Instant::now();
+        if direction == Direction::ToRun && state.last_direction == Some(Direction::ToInteractor) {
+            state.round_trips += 1;
+        }
+        state.last_direction = Some(direction);
+        if self
+            .protocol
+            .max_exchanges
+            .is_some_and(|max| state.round_trips > max)
+        {
+            self.exceeded.store(true, Ordering::Relaxed);
+        }
+        !self.exceeded()
+    }
+
+    /// The run went past `max_exchanges`.
+    pub(crate) fn exceeded(&self) -> bool {
+        self.exceeded.load(Ordering::Relaxed)
+    }
+
+    /// Time since the last message, or since the exchange began.
+    pub(crate) fn idle_for(&self) -> Duration {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.last_message.elapsed()
+    }
+}
+
+/// Relays `from` to `to` as messages going `direction` until `from` ends, then closes
+/// `to`, which the other side reads as EOF. Returns the first `cap` bytes relayed and
+/// whether there were more. A `to` that can no longer be written is let go, and the rest
+/// of `from` is drained so its writer never blocks.
+pub(crate) async fn relay(
+    mut from: impl AsyncRead + Unpin,
+    to: impl AsyncWrite + Unpin,
+    direction: Direction,
+    exchange: &Exchange,
+    cap: u64,
+) -> (Vec<u8>, bool) {
+    let cap = usize::try_from(cap).unwrap_or(usize::MAX);
+    let line_buffered = exchange.protocol.line_buffered;
+    let mut to = Some(to);
+    let mut sent = Vec::new();
+    let mut overflowed = false;
+    let mut pending = Vec::new();
+    let mut chunk = [0u8; 8192];
+    loop {
+        let (messages, done) = match from.read(&mut chunk).await {
+            Ok(n @ 1..) => (
+                split_messages(&mut pending, &chunk[..n], line_buffered),
+                false,
+            ),
+            _ => (finish_messages(&mut pending), true),
+        };
+        for message in messages {
+            if !exchange.record(direction) {
+                to = None;
+            }
+            if let Some(writer) = &mut to {
+                let written = async {
+                    writer.write_all(&message).await?;
+                    writer.flush().await
+                };
+                if written.await.is_err() {
+                    to = None;
+                }
+            }
+            let room = cap.saturating_sub(sent.len()).min(message.len());
+            sent.extend_from_slice(&message[..room]);
+            overflowed |= room < message.len();
+        }
+        if done {
+            return (sent, overflowed);
+        }
+    }
+}
+
+/// Messages complete once `chunk` is added to `pending`: the chunk itself when raw, or
+/// every finished line, trimmed, in line mode. A partial line stays in `pending`.
+fn split_messages(pending: &mut Vec<u8>, chunk: &[u8], line_buffered: bool) -> Vec<Vec<u8>> {
+    if !line_buffered {
+        return vec![chunk.to_vec()];
+    }
+    pending.extend_from_slice(chunk);
+    let mut messages = Vec::new();
+    while let Some(end) = pending.iter().position(|&b| b == b'\n') {
+        let line: Vec<u8> = pending.drain(..=end).collect();
+        messages.push(trimmed_line(&line));
+    }
+    if pending.len() > MAX_LINE_BYTES {
+        messages.push(std::mem::take(pending));
+    }
+    messages
+}
+
+/// The partial line left in `pending` when its stream ends, as a last message.
+fn finish_messages(pending: &mut Vec<u8>) -> Vec<Vec<u8>> {
+    if pending.is_empty() {
+        return Vec::new();
+    }
+    let line = std::mem::take(pending);
+    vec![trimmed_line(&line)]
+}
+
+/// `line` without its trailing whitespace (and `\r`), ended with `\n`.
+fn trimmed_line(line: &[u8]) -> Vec<u8> {
+    let end = line
+        .iter()
+        .rposition(|b| !b.is_ascii_whitespace())
+        .map_or(0, |i| i + 1);
+    let mut message = line[..end].to_vec();
+    message.push(b'\n');
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn protocol(line_buffered: bool, max_exchanges: Option<u32>) -> Protocol {
+        Protocol {
+            line_buffered,
+            message_timeout: Duration::from_secs(1),
+            max_exchanges,
+        }
+    }
+
+    #[test]
+    fn line_mode_relays_whole_trimmed_lines() {
+        let mut pending = Vec::new();
+        assert!(split_messages(&mut pending, b"1 2  ", true).is_empty());
+        assert_eq!(
+            split_messages(&mut pending, b"\r\n3\n4", true),
+            vec![b"1 2\n".to_vec(), b"3\n".to_vec()]
+        );
+        assert_eq!(finish_messages(&mut pending), vec![b"4\n".to_vec()]);
+        assert!(finish_messages(&mut pending).is_empty());
+    }
+
+    #[test]
+    fn raw_mode_relays_bytes_as_read() {
+        let mut pending = Vec::new();
+        assert_eq!(
+            split_messages(&mut pending, b"1 2  \r\n3", false),
+            vec![b"1 2  \r\n3".to_vec()]
+        );
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn overlong_line_is_relayed_as_is() {
+        let mut pending = Vec::new();
+        let long = vec![b'x'; MAX_LINE_BYTES + 1];
+        assert_eq!(split_messages(&mut pending, &long, true), vec![long]);
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn round_trips_count_answers_to_the_run() {
+        let exchange = Exchange::new(protocol(true, Some(1)));
+        assert!(exchange.record(Direction::ToRun));
+        assert!(exchange.record(Direction::ToInteractor));
+        assert!(exchange.record(Direction::ToInteractor));
+        assert!(exchange.record(Direction::ToRun));
+        assert!(exchange.record(Direction::ToInteractor));
+        assert!(!exchange.record(Direction::ToRun));
+        assert!(exchange.exceeded());
+    }
+
+    #[tokio::test]
+    async fn relay_forwards_until_eof_and_caps_the_copy() {
+        let exchange = Exchange::new(protocol(true, None));
+        let (to, mut other_side) = tokio::io::duplex(64);
+        let (sent, overflowed) =
+            relay(&b"ab  \ncd"[..], to, Direction::ToInteractor, &exchange, 4).await;
+        assert_eq!(sent, b"ab\nc");
+        assert!(overflowed);
+
+        let mut received = Vec::new();
+        other_side.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received, b"ab\ncd\n");
+    }
+
+    #[tokio::test]
+    async fn relay_stops_forwarding_past_max_exchanges() {
+        let exchange = Exchange::new(protocol(true, Some(0)));
+        exchange.record(Direction::ToInteractor);
+        let (to, mut other_side) = tokio::io::duplex(64);
+        let (sent, _) = relay(&b"1\n2\n"[..], to, Direction::ToRun, &exchange, 64).await;
+        assert_eq!(sent, b"1\n2\n");
+        assert!(exchange.exceeded());
+
+        let mut received = Vec::new();
+        other_side.read_to_end(&mut received).await.unwrap();
+        assert!(received.is_empty());
+    }
+}
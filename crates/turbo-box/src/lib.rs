@@ -1,5 +1,33 @@
+//! Sandboxed process execution.
+//!
+//! The native backends are compiled per target: `LinuxSandbox` on Linux and
+//! `WindowsSandbox` on Windows. Other targets (e.g. macOS clients that only need the
+//! types) get `MockSandbox` alone, which is also the default `sandbox.backend` there.
+
+#[cfg(target_os = "linux")]
+pub mod external;
+pub mod factory;
+#[cfg(target_os = "linux")]
+mod interactive;
+#[cfg(target_os = "linux")]
 pub mod linux;
+pub mod mock;
+#[cfg(target_os = "linux")]
+mod perf;
+pub mod pool;
 pub mod traits;
+#[cfg(windows)]
+pub mod windows;
+pub mod workdir;
 
-pub use linux::LinuxSandbox;
-pub use traits::Sandbox;
+#[cfg(target_os = "linux")]
+pub use external::{ExternalSandbox, ExternalTool};
+pub use factory::create_sandbox;
+#[cfg(target_os = "linux")]
+pub use linux::{Capability, LinuxSandbox};
+pub use mock::MockSandbox;
+pub use pool::SandboxPool;
+pub use traits::{CommandSpec, Protocol, Sandbox, Session, WarmProcess};
+#[cfg(windows)]
+pub use windows::WindowsSandbox;
+pub use workdir::DirEntry;
@@ -1,55 +1,419 @@
-use crate::traits::Sandbox;
+use crate::interactive::{self, Direction, Exchange};
+use crate::perf::CgroupCounters;
+use crate::traits::{CommandSpec, Protocol, Sandbox, Session, WarmProcess};
+use crate::workdir;
 use async_trait::async_trait;
+use std::ffi::{CStr, CString};
 use std::fs;
-use std::io::Write;
+use std::io::{ErrorKind, Write};
 use std::os::unix::process::ExitStatusExt;
 use std::path::{Path, PathBuf};
 use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncWrite;
 use tracing::{info, instrument, warn};
-use turbo_core::{models::StageStatus, Result, StageResult, TurboError};
+use turbo_core::models::{StageStatus, TerminationReason, UsageSample};
+use turbo_core::{Result, SandboxError, StageResult, TurboError};
 
 const CGROUP_ROOT: &str = "/sys/fs/cgroup";
 const MANAGER_DIR: &str = "turbo_executor";
+/// Ctrl-D: ends a read on a terminal in canonical mode
+const EOT: u8 = 0x04;
+/// Shortest interval at which a run's usage is sampled
+const MIN_SAMPLE_INTERVAL_MS: u64 = 10;
+/// Most samples kept per run; past this, every other one is dropped and the interval
+/// doubled, so long runs are covered end to end at a coarser resolution
+const MAX_SAMPLES: usize = 512;
+/// Longest a run's teardown waits for its cgroup to empty and its output to close
+const KILL_WAIT: Duration = Duration::from_secs(2);
+/// How often an emptying cgroup is checked
+const KILL_POLL_INTERVAL: Duration = Duration::from_millis(5);
+/// How often an interactive run is checked for idleness
+const IDLE_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// Hostname every run sees, whichever worker it runs on
+pub(crate) const SANDBOX_HOSTNAME: &str = "turbo";
+
+/// Where a run's stdin is written: a pipe, or the master side of its pty
+type StdinWriter = Box<dyn AsyncWrite + Send + Unpin>;
 
 /// Sandbox implementation for Linux utilizing Cgroups V2 and Namespaces.
 ///
 /// This implementation relies on:
 /// - `cgroup_no_v1=all` or unified cgroup hierarchy.
-/// - Root privileges to create cgroups and use `unshare` for namespaces.
+/// - Root privileges to use `unshare` for namespaces, and to create cgroups unless a
+///   writable (e.g. systemd-delegated) part of the hierarchy is available.
 pub struct LinuxSandbox {
-    /// Root path where the sandbox environment (temp dirs) will be created (not used for cgroups).
+    /// Root path where per-sandbox working directories are created (not used for cgroups).
     pub root_path: String,
+    /// Cgroup the per-sandbox `turbo-box-{id}` cgroups are created in
+    manager_path: PathBuf,
+    /// User and group runs switch to when their limits don't name one
+    uid: Option<u32>,
+    gid: Option<u32>,
+    /// Whether runs may keep running as root when the server itself is root
+    allow_root: bool,
 }
 
 impl LinuxSandbox {
-    /// Create a new LinuxSandbox instance.
+    /// Create a new LinuxSandbox instance, creating its cgroups in the first writable
+    /// place found (see `detect_manager_path`).
     pub fn new(root_path: String) -> Self {
-        Self { root_path }
+        Self::with_cgroup_path(root_path, Self::detect_manager_path())
+    }
+
+    /// Create a new LinuxSandbox instance creating its cgroups in `manager_path`, e.g.
+    /// under a systemd-delegated `user@UID.service` slice.
+    pub fn with_cgroup_path(root_path: String, manager_path: PathBuf) -> Self {
+        info!("Creating sandbox cgroups in {:?}", manager_path);
+        Self {
+            root_path,
+            manager_path,
+            uid: None,
+            gid: None,
+            allow_root: false,
+        }
+    }
+
+    /// The sandbox `[sandbox]` describes, as `create_sandbox` builds it.
+    pub fn from_config(config: &turbo_core::config::SandboxConfig) -> Self {
+        let sandbox = match &config.cgroup_path {
+            Some(path) => Self::with_cgroup_path(config.root_path.clone(), path.into()),
+            None => Self::new(config.root_path.clone()),
+        };
+        sandbox.with_user(config.uid, config.gid, config.allow_root)
+    }
+
+    /// Switch runs to `uid`/`gid` unless their limits name a user. With neither, a server
+    /// running as root refuses to start runs unless `allow_root` is set.
+    pub fn with_user(mut self, uid: Option<u32>, gid: Option<u32>, allow_root: bool) -> Self {
+        self.uid = uid;
+        self.gid = gid;
+        self.allow_root = allow_root;
+        self
+    }
+
+    /// Fills in the configured user for `limits`, refusing to run as root unless allowed.
+    fn resolve_user(
+        &self,
+        limits: turbo_core::models::ExecutionLimits,
+    ) -> Result<turbo_core::models::ExecutionLimits> {
+        let limits = turbo_core::models::ExecutionLimits {
+            uid: limits.uid.or(self.uid),
+            gid: limits.gid.or(self.gid),
+            ..limits
+        };
+        let as_root = match limits.uid {
+            Some(uid) => uid == 0,
+            None => nix::unistd::geteuid().is_root(),
+        };
+        if as_root && !self.allow_root {
+            return Err(SandboxError::RunAsRoot.into());
+        }
+        Ok(limits)
+    }
+
+    /// `turbo_executor` under the root of the cgroup hierarchy if that is writable (i.e.
+    /// running as root), else under the nearest writable ancestor of this process's own
+    /// cgroup, such as the `user@UID.service` slice systemd delegates to each user.
+    /// Falls back to the root of the hierarchy, failing later on, if neither is.
+    fn detect_manager_path() -> PathBuf {
+        let root = Path::new(CGROUP_ROOT);
+        let own = fs::read_to_string("/proc/self/cgroup")
+            .ok()
+            .and_then(|content| {
+                content
+                    .lines()
+                    .find_map(|line| line.strip_prefix("0::"))
+                    .map(|path| root.join(path.trim().trim_start_matches('/')))
+            });
+        // The own cgroup holds processes, so it cannot hold child cgroups with controllers
+        let ancestors = own
+            .iter()
+            .flat_map(|own| own.ancestors().skip(1))
+            .filter(|dir| dir.starts_with(root) && *dir != root);
+        std::iter::once(root)
+            .chain(ancestors)
+            .find(|dir| Self::cgroup_writable(dir))
+            .unwrap_or(root)
+            .join(MANAGER_DIR)
+    }
+
+    /// Whether child cgroups can be created in `dir` and controllers enabled for them.
+    fn cgroup_writable(dir: &Path) -> bool {
+        use nix::unistd::{access, AccessFlags};
+        access(dir, AccessFlags::W_OK).is_ok()
+            && access(&dir.join("cgroup.subtree_control"), AccessFlags::W_OK).is_ok()
+    }
+
+    /// Fails, naming the problem, if the cgroup `manager_path` would be created in is not
+    /// part of a cgroup v2 hierarchy offering the cpu, memory and pids controllers. Hosts
+    /// without any cgroup hierarchy pass, and run without cgroups.
+    fn check_hierarchy(manager_path: &Path) -> Result<()> {
+        let Some(parent) = manager_path.parent() else {
+            return Ok(());
+        };
+        let controllers_file = parent.join("cgroup.controllers");
+        let Ok(controllers) = fs::read_to_string(&controllers_file) else {
+            let root = Path::new(CGROUP_ROOT);
+            let layout = if root.join("unified/cgroup.controllers").exists() {
+                "a hybrid cgroup hierarchy (controllers on v1, v2 only at /sys/fs/cgroup/unified)"
+            } else if ["memory", "pids", "cpuacct"]
+                .iter()
+                .any(|c| root.join(c).is_dir())
+            {
+                "a legacy cgroup v1 hierarchy"
+            } else {
+                return Ok(());
+            };
+            let reason = format!(
+                "{:?} is not a cgroup v2 directory: this host mounts {}",
+                parent, layout
+            );
+            return Err(SandboxError::CgroupV1(reason).into());
+        };
+        for controller in ["cpu", "memory", "pids"] {
+            if !controllers.split_whitespace().any(|c| c == controller) {
+                return Err(SandboxError::ControllerMissing {
+                    controller: controller.to_string(),
+                    path: controllers_file,
+                }
+                .into());
+            }
+        }
+        Ok(())
     }
 
-    fn get_manager_path() -> PathBuf {
-        Path::new(CGROUP_ROOT).join(MANAGER_DIR)
+    /// Checks what this host lets the sandbox do: cgroup v2, delegated controllers, a
+    /// writable cgroup, namespaces, dropping privileges and switching user. Spawns a few
+    /// short-lived `true` processes; changes nothing on the host.
+    pub fn probe(&self) -> Vec<Capability> {
+        let mut checks = Vec::new();
+        let parent = self.manager_path.parent().unwrap_or(Path::new(CGROUP_ROOT));
+
+        let name = "cgroup v2";
+        checks.push(match Self::check_hierarchy(&self.manager_path) {
+            Err(TurboError::Sandbox(SandboxError::CgroupV1(reason))) => Capability::missing(
+                name,
+                reason,
+                "boot with systemd.unified_cgroup_hierarchy=1 (or cgroup_no_v1=all)",
+            ),
+            _ if !Path::new(CGROUP_ROOT).join("cgroup.controllers").exists() => {
+                Capability::missing(
+                    name,
+                    format!("no cgroup2 filesystem at {}", CGROUP_ROOT),
+                    format!("mount -t cgroup2 none {}", CGROUP_ROOT),
+                )
+            }
+            _ => Capability::ok(name, format!("unified hierarchy at {}", CGROUP_ROOT)),
+        });
+
+        let name = "controllers";
+        let available = fs::read_to_string(parent.join("cgroup.controllers")).unwrap_or_default();
+        let available: Vec<&str> = available.split_whitespace().collect();
+        let missing: Vec<&str> = ["cpu", "memory", "pids"]
+            .into_iter()
+            .filter(|c| !available.contains(c))
+            .collect();
+        checks.push(if missing.is_empty() {
+            let io = if available.contains(&"io") {
+                ""
+            } else {
+                " (no io: I/O is not reported)"
+            };
+            Capability::ok(
+                name,
+                format!("{} in {:?}{}", available.join(" "), parent, io),
+            )
+        } else {
+            Capability::missing(
+                name,
+                format!("{} not delegated to {:?}", missing.join(", "), parent),
+                "enable them in the parent's cgroup.subtree_control, or set Delegate=yes on \
+                 the service",
+            )
+        });
+
+        let name = "cgroup writable";
+        let dir = if self.manager_path.exists() {
+            self.manager_path.as_path()
+        } else {
+            parent
+        };
+        checks.push(if Self::cgroup_writable(dir) {
+            Capability::ok(
+                name,
+                format!("sandbox cgroups go in {:?}", self.manager_path),
+            )
+        } else {
+            Capability::missing(
+                name,
+                format!("{:?} is not writable; runs would not be limited", dir),
+                "run as root, under a systemd-delegated slice, or set sandbox.cgroup_path",
+            )
+        });
+
+        let name = "namespaces";
+        let probe = MountPlan::new().and_then(|plan| {
+            Self::probe_spawn(move || {
+                nix::sched::unshare(
+                    nix::sched::CloneFlags::CLONE_NEWNET
+                        | nix::sched::CloneFlags::CLONE_NEWNS
+                        | nix::sched::CloneFlags::CLONE_NEWIPC
+                        | nix::sched::CloneFlags::CLONE_NEWUTS,
+                )?;
+                private_mounts(&plan)?;
+                nix::unistd::sethostname(SANDBOX_HOSTNAME)?;
+                Ok(())
+            })
+        });
+        checks.push(match probe {
+            Ok(()) => Capability::ok(name, "net, mount, ipc and uts; private /dev and /proc"),
+            Err(e) => Capability::missing(
+                name,
+                format!("{}; runs would share the host's network and mounts", e),
+                "run the server as root (or with CAP_SYS_ADMIN)",
+            ),
+        });
+
+        let name = "privilege drop";
+        checks.push(
+            match Self::probe_spawn(|| {
+                drop_capability_bounds()?;
+                nix::sys::prctl::set_no_new_privs()?;
+                Ok(())
+            }) {
+                Ok(()) => Capability::ok(name, "no_new_privs and an empty capability bounding set"),
+                Err(e) => Capability::missing(name, e.to_string(), "run the server as root"),
+            },
+        );
+
+        checks.push(self.probe_user());
+        checks
     }
 
-    fn get_job_path(id: &str) -> PathBuf {
-        Self::get_manager_path().join(format!("turbo-box-{}", id))
+    /// Whether runs can switch to the configured user, or may run as the server's own.
+    fn probe_user(&self) -> Capability {
+        let name = "user switch";
+        let root = nix::unistd::geteuid().is_root();
+        let (uid, gid) = (self.uid, self.gid);
+        if uid.is_none() && gid.is_none() {
+            return match (root, self.allow_root) {
+                (false, _) => Capability::ok(
+                    name,
+                    format!("runs keep the server's uid {}", nix::unistd::geteuid()),
+                ),
+                (true, true) => Capability::ok(name, "runs stay root (sandbox.allow_root)"),
+                (true, false) => Capability::missing(
+                    name,
+                    "the server is root and sandbox.uid is unset, so runs are refused",
+                    "set sandbox.uid and sandbox.gid to an unprivileged user, e.g. 65534",
+                ),
+            };
+        }
+        let result = Self::probe_spawn(move || {
+            if root {
+                nix::unistd::setgroups(&[])?;
+            }
+            if let Some(g) = gid {
+                nix::unistd::setgid(nix::unistd::Gid::from_raw(g))?;
+            }
+            if let Some(u) = uid {
+                nix::unistd::setuid(nix::unistd::Uid::from_raw(u))?;
+            }
+            Ok(())
+        });
+        match result {
+            Ok(()) => Capability::ok(name, format!("runs switch to uid {:?}, gid {:?}", uid, gid)),
+            Err(e) => Capability::missing(
+                name,
+                format!("cannot switch to uid {:?}, gid {:?}: {}", uid, gid, e),
+                "run the server as root, or set sandbox.uid to the server's own user",
+            ),
+        }
+    }
+
+    /// Runs `true` with `setup` in its `pre_exec`, succeeding if both do.
+    fn probe_spawn<F>(setup: F) -> std::io::Result<()>
+    where
+        F: FnMut() -> std::io::Result<()> + Send + Sync + 'static,
+    {
+        use std::os::unix::process::CommandExt;
+
+        let mut command = std::process::Command::new("true");
+        command
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        // SAFETY: like prepare_command's pre_exec, the probes only make async-signal-safe
+        // syscalls, on paths and values prepared before the fork
+        unsafe {
+            command.pre_exec(setup);
+        }
+        let status = command.status()?;
+        if !status.success() {
+            return Err(std::io::Error::other(format!("probe process {}", status)));
+        }
+        Ok(())
+    }
+
+    fn get_manager_path(&self) -> &Path {
+        &self.manager_path
+    }
+
+    fn get_job_path(&self, id: &str) -> PathBuf {
+        self.get_manager_path().join(format!("turbo-box-{}", id))
     }
 
     // Helper to handle simple file writes
     fn write_cgroup_file(path: &Path, content: &str) -> Result<()> {
-        let mut file = fs::OpenOptions::new().write(true).open(path).map_err(|e| {
-            TurboError::Sandbox(format!("Failed to open cgroup file {:?}: {}", path, e))
-        })?;
-        file.write_all(content.as_bytes()).map_err(|e| {
-            TurboError::Sandbox(format!("Failed to write to cgroup file {:?}: {}", path, e))
-        })?;
+        #[cfg(feature = "chaos")]
+        if turbo_core::chaos::inject(turbo_core::chaos::Fault::FailCgroupWrite) {
+            let injected = std::io::Error::other("injected fault");
+            return Err(Self::cgroup_error(path, "write", injected));
+        }
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .open(path)
+            .map_err(|e| Self::cgroup_error(path, "open", e))?;
+        file.write_all(content.as_bytes())
+            .map_err(|e| Self::cgroup_error(path, "write", e))?;
         Ok(())
     }
 
     fn read_cgroup_file(path: &Path) -> Result<String> {
-        fs::read_to_string(path).map_err(|e| {
-            TurboError::Sandbox(format!("Failed to read cgroup file {:?}: {}", path, e))
-        })
+        fs::read_to_string(path).map_err(|e| Self::cgroup_error(path, "read", e))
+    }
+
+    /// Classifies an I/O failure on a cgroup interface file.
+    ///
+    /// A missing `<controller>.*` file inside an existing cgroup means the controller
+    /// was not delegated; anything else missing means the hierarchy itself is unusable.
+    fn cgroup_error(path: &Path, operation: &str, e: std::io::Error) -> TurboError {
+        let controller = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .and_then(|n| n.split('.').next())
+            .filter(|c| matches!(*c, "cpu" | "memory" | "pids" | "io"));
+
+        let err = match e.kind() {
+            ErrorKind::PermissionDenied => SandboxError::PermissionDenied {
+                operation: operation.to_string(),
+                path: path.to_path_buf(),
+            },
+            ErrorKind::NotFound
+                if controller.is_some() && path.parent().is_some_and(|p| p.exists()) =>
+            {
+                SandboxError::ControllerMissing {
+                    controller: controller.unwrap_or_default().to_string(),
+                    path: path.to_path_buf(),
+                }
+            }
+            _ => SandboxError::CgroupUnavailable {
+                path: path.to_path_buf(),
+                source: e,
+            },
+        };
+        err.into()
     }
 }
 
@@ -57,10 +421,15 @@ impl LinuxSandbox {
 impl Sandbox for LinuxSandbox {
     /// Initialize a new sandbox for the given job ID.
     ///
-    /// This creates the necessary Cgroup hierarchy under `/sys/fs/cgroup/turbo_executor/turbo-box-{id}`.
+    /// This creates the working directory `{root_path}/{id}` and the necessary Cgroup
+    /// hierarchy under `{manager_path}/turbo-box-{id}` (by default
+    /// `/sys/fs/cgroup/turbo_executor/turbo-box-{id}`).
     #[instrument(skip(self))]
     async fn init(&self, id: &str) -> Result<()> {
-        let manager_path = Self::get_manager_path();
+        let work_dir = workdir::job_dir(Path::new(&self.root_path), id)?;
+        tokio::fs::create_dir_all(&work_dir).await?;
+
+        let manager_path = self.get_manager_path();
         info!(
             "Initializing Linux Sandbox for {} in manager {:?}",
             id, manager_path
@@ -68,8 +437,12 @@ impl Sandbox for LinuxSandbox {
 
         // 1. Setup Manager Cgroup
         if !manager_path.exists() {
-            if let Err(e) = fs::create_dir_all(&manager_path) {
-                warn!("Failed to create manager cgroup at {:?}: {}. Running without cgroups.", manager_path, e);
+            Self::check_hierarchy(manager_path)?;
+            if let Err(e) = fs::create_dir_all(manager_path) {
+                warn!(
+                    "Failed to create manager cgroup at {:?}: {}. Running without cgroups.",
+                    manager_path, e
+                );
                 return Ok(());
             }
 
@@ -81,56 +454,295 @@ impl Sandbox for LinuxSandbox {
                     e
                 );
             }
+            // Separately, as not every host delegates it; without it runs report no I/O
+            if let Err(e) = Self::write_cgroup_file(&subtree_control, "+io") {
+                warn!("Failed to enable the io controller in manager: {}", e);
+            }
         }
 
         // 2. Create Job Cgroup
-        let job_path = Self::get_job_path(id);
+        let job_path = self.get_job_path(id);
         if !job_path.exists() {
-             if let Err(e) = fs::create_dir(&job_path) {
-                 warn!("Failed to create job cgroup at {:?}: {}. Running without cgroups.", job_path, e);
-                 return Ok(());
-             }
+            if let Err(e) = fs::create_dir(&job_path) {
+                warn!(
+                    "Failed to create job cgroup at {:?}: {}. Running without cgroups.",
+                    job_path, e
+                );
+                return Ok(());
+            }
         }
 
         // 3. Set Default Limits (Can be overridden in run)
-        // Memory Max: 512 MB default
-        let limit = (512 * 1024 * 1024).to_string();
-        let _ = Self::write_cgroup_file(&job_path.join("memory.max"), &limit);
-        let _ = Self::write_cgroup_file(&job_path.join("memory.swap.max"), "0");
-
-        // Pids Max: 256 default
-        let _ = Self::write_cgroup_file(&job_path.join("pids.max"), "256");
+        Self::apply_default_limits(&job_path);
 
         Ok(())
     }
 
+    fn work_dir(&self, id: &str) -> PathBuf {
+        Path::new(&self.root_path).join(id)
+    }
+
     /// Run a command in the sandbox
     #[instrument(skip(self))]
     async fn run(
         &self,
         id: &str,
-        cmd: &str,
-        args: &[String],
-        env: &[String],
+        spec: CommandSpec<'_>,
         limits: Option<turbo_core::models::ExecutionLimits>,
     ) -> Result<StageResult> {
-        info!("Running command in sandbox {}: {} {:?}", id, cmd, args);
+        info!(
+            "Running command in sandbox {}: {} {:?}",
+            id, spec.cmd, spec.args
+        );
 
-        let limits = limits.unwrap_or_default();
-        let job_path = Self::get_job_path(id);
+        let limits = self.resolve_user(limits.unwrap_or_default())?;
+        let job_path = self.get_job_path(id);
+
+        self.apply_limits(&job_path, &limits)?;
+
+        // cpu.stat and io.stat are cumulative for the lifetime of the cgroup (which may be
+        // reused across runs and jobs), so measure this run relative to the current usage.
+        let baseline = Usage::read(&job_path);
+        let counters = Self::open_counters(&job_path, &limits);
+
+        let cpu_rlimit = limits.cpu_rlimit_secs();
+        let mut command = self.prepare_command(
+            spec.cmd, spec.args, spec.env, &job_path, &limits, cpu_rlimit,
+        )?;
+        command.current_dir(
+            spec.cwd
+                .map_or_else(|| self.work_dir(id), Path::to_path_buf),
+        );
+        let pty = if spec.tty {
+            Some(Self::attach_pty(&mut command)?)
+        } else {
+            command.stdin(if spec.stdin.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            });
+            None
+        };
+        let mut child = command.spawn().map_err(|e| SandboxError::SpawnFailed {
+            command: spec.cmd.to_string(),
+            source: e,
+        })?;
+        // The command keeps its copies of the pty slave; drop them so the master sees
+        // EOF once the child exits.
+        drop(command);
+
+        if let Some(input) = spec.stdin {
+            let mut input = input.to_vec();
+            let pipe: Option<StdinWriter> = match &pty {
+                Some(master) => {
+                    // A terminal has no EOF to close; end the input with Ctrl-D, doubled
+                    // if it has to flush a partial line first.
+                    if !input.ends_with(b"\n") {
+                        input.push(EOT);
+                    }
+                    input.push(EOT);
+                    let master = master.try_clone().ok().map(tokio::fs::File::from_std);
+                    master.map(|f| Box::new(f) as StdinWriter)
+                }
+                None => child.stdin.take().map(|p| Box::new(p) as StdinWriter),
+            };
+            if let Some(mut pipe) = pipe {
+                // Write from a task so a program that never reads stdin cannot stall the monitor.
+                tokio::spawn(async move {
+                    use tokio::io::AsyncWriteExt;
+                    let _ = pipe.write_all(&input).await;
+                    let _ = pipe.flush().await;
+                });
+            }
+        }
 
+        self.monitor_child(&mut child, pty, &job_path, &limits, baseline, counters)
+            .await
+    }
+
+    /// The interactor runs in a cgroup of its own next to the session's, so the run's
+    /// teardown leaves it to give its verdict and each is measured separately.
+    #[instrument(skip(self, session, protocol, limits))]
+    async fn exec_interactive(
+        &self,
+        session: &Session,
+        spec: CommandSpec<'_>,
+        interactor: CommandSpec<'_>,
+        protocol: Protocol,
+        limits: Option<turbo_core::models::ExecutionLimits>,
+    ) -> Result<(StageResult, StageResult)> {
+        let id = session.id();
+        info!(
+            "Running command in sandbox {} against interactor {} {:?}: {} {:?}",
+            id, interactor.cmd, interactor.args, spec.cmd, spec.args
+        );
+
+        let limits = self.resolve_user(limits.unwrap_or_default())?;
+        let job_path = self.get_job_path(id);
+        let judge_path = self.interactor_cgroup(id);
         self.apply_limits(&job_path, &limits)?;
+        self.apply_limits(&judge_path, &limits)?;
+        let baseline = Usage::read(&job_path);
+        let judge_baseline = Usage::read(&judge_path);
+        let counters = Self::open_counters(&job_path, &limits);
+        let cpu_rlimit = limits.cpu_rlimit_secs();
+        let started = std::time::Instant::now();
+
+        let mut judge = {
+            let mut command = self.prepare_command(
+                interactor.cmd,
+                interactor.args,
+                interactor.env,
+                &judge_path,
+                &limits,
+                cpu_rlimit,
+            )?;
+            command
+                .current_dir(
+                    interactor
+                        .cwd
+                        .map_or_else(|| self.work_dir(id), Path::to_path_buf),
+                )
+                .stdin(Stdio::piped());
+            command.spawn().map_err(|e| SandboxError::SpawnFailed {
+                command: interactor.cmd.to_string(),
+                source: e,
+            })?
+        };
+        let spawned = self
+            .prepare_command(
+                spec.cmd, spec.args, spec.env, &job_path, &limits, cpu_rlimit,
+            )
+            .and_then(|mut command| {
+                command
+                    .current_dir(
+                        spec.cwd
+                            .map_or_else(|| self.work_dir(id), Path::to_path_buf),
+                    )
+                    .stdin(Stdio::piped());
+                command.spawn().map_err(|e| {
+                    SandboxError::SpawnFailed {
+                        command: spec.cmd.to_string(),
+                        source: e,
+                    }
+                    .into()
+                })
+            });
+        let mut child = match spawned {
+            Ok(child) => child,
+            Err(e) => {
+                Self::kill_tree(&judge_path).await;
+                let _ = judge.kill().await;
+                return Err(e);
+            }
+        };
+
+        let pipes = (
+            child.stdin.take(),
+            child.stdout.take(),
+            child.stderr.take(),
+            judge.stdin.take(),
+            judge.stdout.take(),
+            judge.stderr.take(),
+        );
+        let (
+            Some(run_in),
+            Some(run_out),
+            Some(run_err),
+            Some(judge_in),
+            Some(judge_out),
+            Some(judge_err),
+        ) = pipes
+        else {
+            for (path, process) in [(&job_path, &mut child), (&judge_path, &mut judge)] {
+                Self::kill_tree(path).await;
+                let _ = process.kill().await;
+            }
+            return Err(TurboError::Io(std::io::Error::new(
+                std::io::ErrorKind::BrokenPipe,
+                "Failed to capture the interactive pipes",
+            )));
+        };
+
+        // What each side sent is kept up to the output limit, but an exchange longer than
+        // that is no output overflow; only stderr is
+        let exchange = std::sync::Arc::new(Exchange::new(protocol));
+        let cap = limits.output_limit_bytes;
+        let relay_task = |from, to, direction, stderr| {
+            let exchange = exchange.clone();
+            tokio::spawn(async move {
+                let ((stdout, _), (stderr, overflowed)) = tokio::join!(
+                    interactive::relay(from, to, direction, &exchange, cap),
+                    drain_capped(stderr, cap)
+                );
+                Captured {
+                    stdout,
+                    stderr,
+                    overflowed,
+                }
+            })
+        };
+        let run_task = relay_task(
+            Box::new(run_out) as RelayReader,
+            Box::new(judge_in) as RelayWriter,
+            Direction::ToInteractor,
+            Box::new(run_err) as RelayReader,
+        );
+        let judge_task = relay_task(
+            Box::new(judge_out),
+            Box::new(run_in),
+            Direction::ToRun,
+            Box::new(judge_err),
+        );
 
-        let mut command = self.prepare_command(cmd, args, env, &job_path, &limits);
-        let mut child = command.spawn().map_err(TurboError::Io)?;
+        let run = self.wait_child(&mut child, run_task, &job_path, &limits, baseline, counters);
+        tokio::pin!(run);
+        let result = tokio::select! {
+            result = &mut run => result?,
+            status = Self::watch_exchange(&exchange, [&job_path, &judge_path]) => {
+                Self::kill_tree(&job_path).await;
+                let mut result = run.await?;
+                result.termination_reason = Some(match status {
+                    StageStatus::IdlenessLimitExceeded => TerminationReason::IdlenessLimit,
+                    _ => TerminationReason::Signal,
+                });
+                result.status = status;
+                result
+            }
+        };
 
-        self.monitor_child(&mut child, &job_path, &limits).await
+        // With the run gone the interactor reads EOF, and has the message timeout to
+        // give its verdict
+        let judge_limits = turbo_core::models::ExecutionLimits {
+            timeout_ms: protocol.message_timeout.as_millis() as u64,
+            sample_interval_ms: None,
+            ..limits.clone()
+        };
+        let mut judge_result = self
+            .wait_child(
+                &mut judge,
+                judge_task,
+                &judge_path,
+                &judge_limits,
+                judge_baseline,
+                None,
+            )
+            .await?;
+        judge_result.execution_time = Some(started.elapsed().as_millis() as u64);
+        Ok((result, judge_result))
     }
 
     #[instrument(skip(self))]
     async fn cleanup(&self, id: &str) -> Result<()> {
         info!("Cleaning up sandbox {}", id);
-        let job_path = Self::get_job_path(id);
+        let work_dir = self.work_dir(id);
+        if work_dir.exists() {
+            if let Err(e) = tokio::fs::remove_dir_all(&work_dir).await {
+                warn!("Failed to remove working directory {:?}: {}", work_dir, e);
+            }
+        }
+
+        let job_path = self.get_job_path(id);
 
         if job_path.exists() {
             // In V2, we might need to kill processes first if any are lingering?
@@ -158,14 +770,262 @@ impl Sandbox for LinuxSandbox {
             }
         }
 
+        // Left by `exec_interactive`; its interactor has been waited on
+        let judge_path = self.get_job_path(&format!("{}-interactor", id));
+        if judge_path.exists() {
+            Self::kill_tree(&judge_path).await;
+            if let Err(e) = fs::remove_dir(&judge_path) {
+                warn!("Failed to delete cgroup {:?}: {}", judge_path, e);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Covers both working directories and `turbo-box-*` cgroups, so either is swept even
+    /// if a crash left only one of them. Cgroups that still hold processes are kept.
+    async fn sweep_orphans(
+        &self,
+        in_use: &(dyn for<'a> Fn(&'a str) -> bool + Sync),
+        min_age: Duration,
+    ) -> Result<usize> {
+        let mut ids = workdir::stale_dirs(Path::new(&self.root_path), min_age).await?;
+        let cgroups = workdir::stale_dirs(self.get_manager_path(), min_age).await?;
+        ids.extend(
+            cgroups
+                .iter()
+                .filter_map(|name| name.strip_prefix("turbo-box-"))
+                .map(str::to_string),
+        );
+        ids.sort();
+        ids.dedup();
+
+        let mut removed = 0;
+        for id in ids.iter().filter(|id| !in_use(id)) {
+            let procs = self.get_job_path(id).join("cgroup.procs");
+            if fs::read_to_string(procs).is_ok_and(|p| !p.trim().is_empty()) {
+                continue;
+            }
+            self.cleanup(id).await?;
+            removed += 1;
+        }
+        Ok(removed)
+    }
+
+    /// Spawn a warm process attached to this sandbox's cgroup, with stdin kept open.
+    #[instrument(skip(self))]
+    async fn spawn_warm(
+        &self,
+        id: &str,
+        cmd: &str,
+        args: &[String],
+        env: &[String],
+        limits: Option<turbo_core::models::ExecutionLimits>,
+    ) -> Result<WarmProcess> {
+        let limits = self.resolve_user(limits.unwrap_or_default())?;
+        let job_path = self.get_job_path(id);
+
+        self.apply_limits(&job_path, &limits)?;
+
+        // RLIMIT_CPU counts the process's whole life, start-up and idle waits included,
+        // so a warm process only gets one if it was asked for
+        let cpu_rlimit = limits.cpu_time_limit_secs;
+        let mut command = self.prepare_command(cmd, args, env, &job_path, &limits, cpu_rlimit)?;
+        command.stdin(Stdio::piped());
+        let child = command.spawn().map_err(|e| SandboxError::SpawnFailed {
+            command: cmd.to_string(),
+            source: e,
+        })?;
+
+        Ok(WarmProcess {
+            id: id.to_string(),
+            child,
+        })
+    }
+
+    /// Re-apply the job's limits, write `input` to the warm process and monitor it.
+    #[instrument(skip(self, process, input))]
+    async fn run_warm(
+        &self,
+        process: WarmProcess,
+        input: Vec<u8>,
+        limits: Option<turbo_core::models::ExecutionLimits>,
+    ) -> Result<StageResult> {
+        let limits = limits.unwrap_or_default();
+        let job_path = self.get_job_path(&process.id);
+        let mut child = process.child;
+
+        let handoff_failed = |source: std::io::Error| SandboxError::Setup {
+            operation: "hand off to warm process".to_string(),
+            source,
+        };
+        if let Ok(Some(status)) = child.try_wait() {
+            return Err(handoff_failed(std::io::Error::other(format!(
+                "warm process exited while idle: {}",
+                status
+            )))
+            .into());
+        }
+        let mut stdin = child.stdin.take().ok_or_else(|| {
+            handoff_failed(std::io::Error::new(
+                ErrorKind::BrokenPipe,
+                "stdin not captured",
+            ))
+        })?;
+
+        self.apply_limits(&job_path, &limits)?;
+        let baseline = Usage::read(&job_path);
+        let counters = Self::open_counters(&job_path, &limits);
+
+        // Write from a task so a program that never reads stdin cannot stall the monitor.
+        tokio::spawn(async move {
+            use tokio::io::AsyncWriteExt;
+            let _ = stdin.write_all(&input).await;
+        });
+
+        self.monitor_child(&mut child, None, &job_path, &limits, baseline, counters)
+            .await
+    }
+
+    /// Reset a pooled cgroup in place instead of removing and recreating it.
+    ///
+    /// Kills any stragglers, empties the working directory, restores the default limits
+    /// and resets the memory watermark. CPU usage is not resettable and is instead
+    /// measured per run.
+    #[instrument(skip(self))]
+    async fn reset(&self, id: &str) -> Result<()> {
+        let job_path = self.get_job_path(id);
+        if !job_path.exists() {
+            return self.init(id).await;
+        }
+
+        let kill_file = job_path.join("cgroup.kill");
+        if kill_file.exists() {
+            let _ = Self::write_cgroup_file(&kill_file, "1");
+        }
+
+        let work_dir = self.work_dir(id);
+        if let Err(e) = workdir::clear_dir(&work_dir).await {
+            warn!("Failed to clear working directory {:?}: {}", work_dir, e);
+            tokio::fs::create_dir_all(&work_dir).await?;
+        }
+
+        Self::apply_default_limits(&job_path);
+
+        // Writing to memory.peak resets it (Linux 6.12+); older kernels reject the write.
+        let _ = Self::write_cgroup_file(&job_path.join("memory.peak"), "reset");
+
         Ok(())
     }
 }
 
+/// Either end of a relay, boxed so both directions share one task body.
+type RelayReader = Box<dyn tokio::io::AsyncRead + Unpin + Send>;
+type RelayWriter = Box<dyn tokio::io::AsyncWrite + Unpin + Send>;
+
 impl LinuxSandbox {
+    /// Cgroup the interactor of session `id` runs in, next to the session's own; created
+    /// (with the default limits) if the session has a cgroup.
+    fn interactor_cgroup(&self, id: &str) -> PathBuf {
+        let path = self.get_job_path(&format!("{}-interactor", id));
+        if self.get_job_path(id).exists() && !path.exists() {
+            match fs::create_dir(&path) {
+                Ok(()) => Self::apply_default_limits(&path),
+                Err(e) => warn!("Failed to create interactor cgroup {:?}: {}", path, e),
+            }
+        }
+        path
+    }
+
+    /// Resolves once an exchange is stuck, with the status the run ends with:
+    /// `IdlenessLimitExceeded` when no message passed and neither `cgroups` used CPU
+    /// for the message timeout, `OutputLimitExceeded` when the run went past its round
+    /// trips.
+    async fn watch_exchange(exchange: &Exchange, cgroups: [&Path; 2]) -> StageStatus {
+        let cpu_usage = || cgroups.iter().map(|p| Self::read_cpu_usage(p)).sum::<u64>();
+        let timeout = exchange.protocol().message_timeout;
+        let mut cpu = cpu_usage();
+        let mut busy = std::time::Instant::now();
+        loop {
+            tokio::time::sleep(IDLE_POLL_INTERVAL).await;
+            if exchange.exceeded() {
+                return StageStatus::OutputLimitExceeded;
+            }
+            let now = cpu_usage();
+            if now != cpu {
+                cpu = now;
+                busy = std::time::Instant::now();
+            }
+            if exchange.idle_for().min(busy.elapsed()) >= timeout {
+                return StageStatus::IdlenessLimitExceeded;
+            }
+        }
+    }
+
+    /// Writes the default limits (512 MB memory, no swap, 256 pids) to a job cgroup.
+    fn apply_default_limits(job_path: &Path) {
+        let limit = (512 * 1024 * 1024).to_string();
+        let _ = Self::write_cgroup_file(&job_path.join("memory.max"), &limit);
+        let _ = Self::write_cgroup_file(&job_path.join("memory.swap.max"), "0");
+        let _ = Self::write_cgroup_file(&job_path.join("pids.max"), "256");
+    }
+
+    /// Reads the current memory usage of the cgroup in bytes (0 if unavailable).
+    fn read_memory_usage(job_path: &Path) -> u64 {
+        Self::read_cgroup_file(&job_path.join("memory.current"))
+            .ok()
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .unwrap_or(0)
+    }
+
+    /// Processes of the cgroup (and its descendants) the OOM killer has killed (0 if
+    /// unavailable).
+    fn read_oom_kills(job_path: &Path) -> u64 {
+        Self::read_cgroup_file(&job_path.join("memory.events"))
+            .map(|content| oom_kills(&content))
+            .unwrap_or(0)
+    }
+
+    /// Reads the cumulative CPU usage of the cgroup in microseconds (0 if unavailable).
+    fn read_cpu_usage(job_path: &Path) -> u64 {
+        Self::read_cgroup_file(&job_path.join("cpu.stat"))
+            .ok()
+            .and_then(|content| {
+                content
+                    .lines()
+                    .find(|l| l.starts_with("usage_usec"))
+                    .and_then(|l| l.split_whitespace().nth(1))
+                    .and_then(|v| v.parse::<u64>().ok())
+            })
+            .unwrap_or(0)
+    }
+
+    /// Reads the cumulative bytes read and written by the cgroup across all block devices,
+    /// or `None` if the io controller is not enabled for it.
+    fn read_io_usage(job_path: &Path) -> Option<(u64, u64)> {
+        let content = Self::read_cgroup_file(&job_path.join("io.stat")).ok()?;
+        Some(io_totals(&content))
+    }
+
+    /// Starts counting hardware events in the job's cgroup if `limits.profile` asks for
+    /// it and the host allows it.
+    fn open_counters(
+        job_path: &Path,
+        limits: &turbo_core::models::ExecutionLimits,
+    ) -> Option<CgroupCounters> {
+        limits
+            .profile
+            .then(|| CgroupCounters::open(job_path))
+            .flatten()
+    }
+
     /// Applies resource limits to the job's cgroup based on the provided `ExecutionLimits`.
     /// This includes memory and PID limits.
-    fn apply_limits(&self, job_path: &Path, limits: &turbo_core::models::ExecutionLimits) -> Result<()> {
+    fn apply_limits(
+        &self,
+        job_path: &Path,
+        limits: &turbo_core::models::ExecutionLimits,
+    ) -> Result<()> {
         // If cgroup doesn't exist, we can't apply limits.
         if !job_path.exists() {
             return Ok(());
@@ -177,19 +1037,68 @@ impl LinuxSandbox {
             if let Err(e) = Self::write_cgroup_file(&job_path.join("memory.max"), &limit) {
                 warn!("Failed to set memory limit: {}", e);
             }
-             let _ = Self::write_cgroup_file(&job_path.join("memory.swap.max"), "0");
+            let _ = Self::write_cgroup_file(&job_path.join("memory.swap.max"), "0");
         }
         if limits.pid_limit > 0 {
-            if let Err(e) = Self::write_cgroup_file(&job_path.join("pids.max"), &limits.pid_limit.to_string()) {
+            if let Err(e) =
+                Self::write_cgroup_file(&job_path.join("pids.max"), &limits.pid_limit.to_string())
+            {
                 warn!("Failed to set pid limit: {}", e);
             }
         }
         Ok(())
     }
 
+    /// Connect the command's stdio to a new pseudo-terminal and return the master side.
+    ///
+    /// The child also starts a new session with the pty as its controlling terminal, so
+    /// `isatty` and `/dev/tty` behave as they would in an interactive shell.
+    fn attach_pty(command: &mut tokio::process::Command) -> Result<std::fs::File> {
+        let setup_failed = |source: std::io::Error| SandboxError::Setup {
+            operation: "allocate pseudo-terminal".to_string(),
+            source,
+        };
+        let pty = nix::pty::openpty(None, None).map_err(|e| setup_failed(e.into()))?;
+        // Without ONLCR the program's "\n" reaches stdout as written rather than as "\r\n"
+        let mut termios =
+            nix::sys::termios::tcgetattr(&pty.slave).map_err(|e| setup_failed(e.into()))?;
+        termios
+            .output_flags
+            .remove(nix::sys::termios::OutputFlags::ONLCR);
+        nix::sys::termios::tcsetattr(&pty.slave, nix::sys::termios::SetArg::TCSANOW, &termios)
+            .map_err(|e| setup_failed(e.into()))?;
+        let slave = std::fs::File::from(pty.slave);
+        let (stdin, stdout) = (
+            slave.try_clone().map_err(setup_failed)?,
+            slave.try_clone().map_err(setup_failed)?,
+        );
+        command
+            .stdin(Stdio::from(stdin))
+            .stdout(Stdio::from(stdout))
+            .stderr(Stdio::from(slave));
+
+        unsafe {
+            command.pre_exec(|| {
+                nix::unistd::setsid()?;
+                if nix::libc::ioctl(0, nix::libc::TIOCSCTTY, 0) < 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+
+        Ok(std::fs::File::from(pty.master))
+    }
+
     /// Prepares a `tokio::process::Command` for execution within the sandbox.
     /// This includes setting arguments, environment variables, stdout/stderr piping,
     /// and the critical `pre_exec` hook for namespace isolation and cgroup attachment.
+    ///
+    /// The rlimits set there (`cpu_rlimit_secs` seconds of CPU, file sizes, open files)
+    /// hold even if the cgroup could not be set up.
+    ///
+    /// The hook runs between fork and exec, where only async-signal-safe calls are sound,
+    /// so the mount plan and cgroup path it uses are prepared here.
     fn prepare_command(
         &self,
         cmd: &str,
@@ -197,7 +1106,18 @@ impl LinuxSandbox {
         env: &[String],
         job_path: &Path,
         limits: &turbo_core::models::ExecutionLimits,
-    ) -> tokio::process::Command {
+        cpu_rlimit_secs: Option<u64>,
+    ) -> Result<tokio::process::Command> {
+        use std::os::unix::ffi::OsStrExt;
+
+        let setup_failed = |source: std::io::Error| SandboxError::Setup {
+            operation: "prepare the sandbox mounts".to_string(),
+            source,
+        };
+        let plan = MountPlan::new().map_err(setup_failed)?;
+        let procs_path = CString::new(job_path.join("cgroup.procs").as_os_str().as_bytes())
+            .map_err(|e| setup_failed(e.into()))?;
+
         let mut command = tokio::process::Command::new(cmd);
         command
             .args(args)
@@ -215,9 +1135,9 @@ impl LinuxSandbox {
         // CRITICAL: We use unsafe pre_exec to setup isolation in the CHILD process
         unsafe {
             let file_limit = limits.file_limit;
+            let file_size_limit = limits.file_size_limit_bytes;
             let uid = limits.uid;
             let gid = limits.gid;
-            let job_path_clone = job_path.to_path_buf(); // PathBuf is cloneable
 
             command.pre_exec(move || {
                 // 1. Unshare Namespaces (PID, NET, IPC, UTS, MOUNT)
@@ -228,6 +1148,10 @@ impl LinuxSandbox {
                         | nix::sched::CloneFlags::CLONE_NEWUTS,
                 ) {
                     // warn!("Failed to unshare: {}", e); // Can't log easily in pre_exec
+                } else {
+                    // Only ever remount or rename inside our own namespaces, never the host's
+                    private_mounts(&plan)?;
+                    nix::unistd::sethostname(SANDBOX_HOSTNAME).map_err(std::io::Error::from)?;
                 }
 
                 // 2. Set RLIMITs
@@ -237,25 +1161,116 @@ impl LinuxSandbox {
                     nofile,
                     nofile,
                 );
+                // SIGXCPU at the soft limit, SIGKILL a second later if it is caught
+                if let Some(secs) = cpu_rlimit_secs {
+                    let _ = nix::sys::resource::setrlimit(
+                        nix::sys::resource::Resource::RLIMIT_CPU,
+                        secs,
+                        secs + 1,
+                    );
+                }
+                if file_size_limit > 0 {
+                    let _ = nix::sys::resource::setrlimit(
+                        nix::sys::resource::Resource::RLIMIT_FSIZE,
+                        file_size_limit,
+                        file_size_limit,
+                    );
+                }
+
+                // 3. Attach to Cgroup (v2) by writing "0" (current process) to procs.
+                // This happens before the privilege drop, while we can still write the file.
+                let procs = nix::fcntl::open(
+                    procs_path.as_c_str(),
+                    nix::fcntl::OFlag::O_WRONLY | nix::fcntl::OFlag::O_CLOEXEC,
+                    nix::sys::stat::Mode::empty(),
+                );
+                if let Ok(fd) = procs {
+                    let procs = <std::os::fd::OwnedFd as std::os::fd::FromRawFd>::from_raw_fd(fd);
+                    let _ = nix::unistd::write(&procs, b"0");
+                }
+
+                // 4. Drop capabilities and forbid privilege gain through setuid binaries
+                drop_capability_bounds()?;
+                nix::sys::prctl::set_no_new_privs().map_err(std::io::Error::from)?;
 
-                // 3. Switch User
+                // 5. Switch User. A failed switch aborts the spawn rather than running as root.
+                if (uid.is_some() || gid.is_some()) && nix::unistd::geteuid().is_root() {
+                    nix::unistd::setgroups(&[]).map_err(std::io::Error::from)?;
+                }
                 if let Some(g) = gid {
-                    let _ = nix::unistd::setgid(nix::unistd::Gid::from_raw(g));
+                    nix::unistd::setgid(nix::unistd::Gid::from_raw(g))
+                        .map_err(std::io::Error::from)?;
                 }
                 if let Some(u) = uid {
-                    let _ = nix::unistd::setuid(nix::unistd::Uid::from_raw(u));
+                    nix::unistd::setuid(nix::unistd::Uid::from_raw(u))
+                        .map_err(std::io::Error::from)?;
                 }
 
-                // 4. Attach to Cgroup (v2) by writing "0" (current process) to procs
-                let procs_path = job_path_clone.join("cgroup.procs");
-                if let Ok(mut file) = std::fs::OpenOptions::new().write(true).open(&procs_path) {
-                    let _ = write!(file, "0");
+                // 6. A process still running as root (allow_root) keeps no capabilities
+                if nix::unistd::geteuid().is_root() {
+                    clear_capabilities()?;
                 }
 
                 Ok(())
             });
         }
-        command
+        Ok(command)
+    }
+
+    /// Kills every process in the job cgroup and waits, up to `KILL_WAIT`, until it is
+    /// empty. Uses `cgroup.kill` where the kernel has it (5.14+), else SIGKILLs whatever
+    /// `cgroup.procs` lists until nothing is left. Returns at once without a cgroup.
+    async fn kill_tree(job_path: &Path) {
+        let kill_file = job_path.join("cgroup.kill");
+        let killed = kill_file.exists() && Self::write_cgroup_file(&kill_file, "1").is_ok();
+        let deadline = std::time::Instant::now() + KILL_WAIT;
+        loop {
+            let pids = Self::cgroup_pids(job_path);
+            if pids.is_empty() {
+                return;
+            }
+            if std::time::Instant::now() >= deadline {
+                warn!("{} processes survived killing {:?}", pids.len(), job_path);
+                return;
+            }
+            if !killed {
+                for pid in pids {
+                    let _ = nix::sys::signal::kill(pid, nix::sys::signal::Signal::SIGKILL);
+                }
+            }
+            tokio::time::sleep(KILL_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Processes in the job cgroup; none if it can't be read.
+    fn cgroup_pids(job_path: &Path) -> Vec<nix::unistd::Pid> {
+        Self::read_cgroup_file(&job_path.join("cgroup.procs"))
+            .map(|content| {
+                content
+                    .lines()
+                    .filter_map(|line| line.trim().parse().ok())
+                    .map(nix::unistd::Pid::from_raw)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Output the readers captured. Without a cgroup to empty, a process outside the
+    /// tree may still hold a pipe, so readers still running after `KILL_WAIT` are
+    /// abandoned rather than waited on.
+    async fn collect_output(read_task: tokio::task::JoinHandle<Captured>) -> Captured {
+        let abort = read_task.abort_handle();
+        match tokio::time::timeout(KILL_WAIT, read_task).await {
+            Ok(output) => output.unwrap_or_default(),
+            Err(_) => {
+                warn!(
+                    "Output still open {:?} after the run was torn down",
+                    KILL_WAIT
+                );
+                abort.abort();
+                Captured::default()
+            }
+        }
     }
 
     /// Monitors a spawned child process, handles output capturing, applies timeouts,
@@ -263,40 +1278,80 @@ impl LinuxSandbox {
     async fn monitor_child(
         &self,
         child: &mut tokio::process::Child,
+        pty: Option<std::fs::File>,
         job_path: &Path,
         limits: &turbo_core::models::ExecutionLimits,
+        baseline: Usage,
+        counters: Option<CgroupCounters>,
     ) -> Result<StageResult> {
-        // Output Capping & Timeouts
-        let stdout = child
-            .stdout
-            .take()
-            .ok_or_else(|| TurboError::Io(std::io::Error::new(std::io::ErrorKind::BrokenPipe, "Failed to capture stdout")))?;
-        let stderr = child
-            .stderr
-            .take()
-            .ok_or_else(|| TurboError::Io(std::io::Error::new(std::io::ErrorKind::BrokenPipe, "Failed to capture stderr")))?;
-
-        let stdout_reader = tokio::io::BufReader::new(stdout);
-        let stderr_reader = tokio::io::BufReader::new(stderr);
+        let output_cap = limits.output_limit_bytes; // No need for `as u64`
 
-        use tokio::io::AsyncReadExt;
+        // A pty carries stdout and stderr on one stream; the master reads EIO once the
+        // last slave fd is closed, which ends the read like EOF on a pipe.
+        if let Some(master) = pty {
+            let read_task = tokio::spawn(async move {
+                let (stdout, overflowed) =
+                    drain_capped(tokio::fs::File::from_std(master), output_cap).await;
+                Captured {
+                    stdout,
+                    stderr: Vec::new(),
+                    overflowed,
+                }
+            });
+            return self
+                .wait_child(child, read_task, job_path, limits, baseline, counters)
+                .await;
+        }
 
-        let output_cap = limits.output_limit_bytes; // No need for `as u64`
+        // Output Capping & Timeouts
+        let stdout = child.stdout.take().ok_or_else(|| {
+            TurboError::Io(std::io::Error::new(
+                std::io::ErrorKind::BrokenPipe,
+                "Failed to capture stdout",
+            ))
+        })?;
+        let stderr = child.stderr.take().ok_or_else(|| {
+            TurboError::Io(std::io::Error::new(
+                std::io::ErrorKind::BrokenPipe,
+                "Failed to capture stderr",
+            ))
+        })?;
 
+        // Both at once, so a program filling the stderr pipe while we wait on stdout (or
+        // the other way round) doesn't stall until its timeout
         let read_task = tokio::spawn(async move {
-            let mut stdout_buf = Vec::new();
-            let mut stderr_buf = Vec::new();
-            let mut stdout = stdout_reader.take(output_cap);
-            let mut stderr = stderr_reader.take(output_cap);
-
-            let _ = stdout.read_to_end(&mut stdout_buf).await;
-            let _ = stderr.read_to_end(&mut stderr_buf).await;
-            (stdout_buf, stderr_buf)
+            let ((stdout, stdout_overflowed), (stderr, stderr_overflowed)) = tokio::join!(
+                drain_capped(stdout, output_cap),
+                drain_capped(stderr, output_cap)
+            );
+            Captured {
+                stdout,
+                stderr,
+                overflowed: stdout_overflowed || stderr_overflowed,
+            }
         });
 
+        self.wait_child(child, read_task, job_path, limits, baseline, counters)
+            .await
+    }
+
+    /// Wait for the child under the timeout and collect its output and resource usage.
+    async fn wait_child(
+        &self,
+        child: &mut tokio::process::Child,
+        read_task: tokio::task::JoinHandle<Captured>,
+        job_path: &Path,
+        limits: &turbo_core::models::ExecutionLimits,
+        baseline: Usage,
+        counters: Option<CgroupCounters>,
+    ) -> Result<StageResult> {
         // Timeout
         let timeout_duration = std::time::Duration::from_millis(limits.timeout_ms);
         let start_time = std::time::Instant::now();
+        let sampler = limits.sample_interval_ms.map(|ms| {
+            let interval = Duration::from_millis(ms.max(MIN_SAMPLE_INTERVAL_MS));
+            Sampler::start(job_path.to_path_buf(), baseline.cpu_us, interval)
+        });
 
         tokio::select! {
              res = child.wait() => {
@@ -304,90 +1359,634 @@ impl LinuxSandbox {
                  // Process finished naturally
                  match res {
                      Ok(status) => {
-                         let (stdout_bytes, stderr_bytes) = read_task.await.unwrap_or_else(|_| (Vec::new(), Vec::new()));
+                         // Background processes left behind would hold the pipes open
+                         Self::kill_tree(job_path).await;
+                         let output = Self::collect_output(read_task).await;
                          let mut final_status = if status.success() {
                              StageStatus::Success
                          } else {
                              StageStatus::RuntimeError
                          };
 
-                         // Heuristic for OOM (SIGKILL = 9)
-                         if let Some(9) = status.signal() {
-                             final_status = StageStatus::MemoryLimitExceeded;
+                         let usage = Usage::read(job_path).since(baseline);
+                         // A SIGKILL is the OOM killer's if the cgroup counted one, else
+                         // RLIMIT_CPU's hard limit (a second past the soft one) if the
+                         // run used that much CPU; anything else is a plain signal
+                         let cpu_hard_limit_us = limits
+                             .cpu_rlimit_secs()
+                             .map(|secs| (secs + 1) * 1_000_000);
+                         match status.signal() {
+                             Some(nix::libc::SIGKILL) if usage.oom_kills > 0 => {
+                                 final_status = StageStatus::MemoryLimitExceeded;
+                             }
+                             Some(nix::libc::SIGKILL)
+                                 if cpu_hard_limit_us.is_some_and(|limit| usage.cpu_us >= limit) =>
+                             {
+                                 final_status = StageStatus::TimeLimitExceeded;
+                             }
+                             // Past RLIMIT_CPU's soft limit
+                             Some(nix::libc::SIGXCPU) => {
+                                 final_status = StageStatus::TimeLimitExceeded;
+                             }
+                             _ => {}
+                         }
+                         if output.overflowed
+                             && matches!(final_status, StageStatus::Success | StageStatus::RuntimeError)
+                         {
+                             final_status = StageStatus::OutputLimitExceeded;
                          }
 
+                         let signal = status.signal().map(signal_name);
+                         let termination_reason = match final_status {
+                             StageStatus::MemoryLimitExceeded => TerminationReason::MemoryLimit,
+                             StageStatus::TimeLimitExceeded => TerminationReason::TimeLimit,
+                             _ => signal.as_deref().map_or(TerminationReason::Exited, TerminationReason::from_signal),
+                         };
+
                          // Gather Resource Usage
-                         let mem_peak = Self::read_cgroup_file(&job_path.join("memory.current"))
-                             .ok()
-                             .and_then(|v| v.trim().parse::<u64>().ok())
-                             .unwrap_or(0);
-
-                         let cpu_time_us = Self::read_cgroup_file(&job_path.join("cpu.stat"))
-                             .ok()
-                             .and_then(|content| {
-                                content.lines()
-                                    .find(|l| l.starts_with("usage_usec"))
-                                    .and_then(|l| l.split_whitespace().nth(1))
-                                    .and_then(|v| v.parse::<u64>().ok())
-                             })
-                             .unwrap_or(0);
+                         let mem_peak = Self::read_memory_usage(job_path);
+                         let samples = Sampler::finish(sampler).await;
+                         let perf_counters = counters.as_ref().map(CgroupCounters::read);
 
                          Ok(StageResult {
                              status: final_status,
-                             stdout: String::from_utf8_lossy(&stdout_bytes).to_string(),
-                             stderr: String::from_utf8_lossy(&stderr_bytes).to_string(),
+                             stdout: limits.output_encoding.encode(&output.stdout),
+                             stderr: limits.output_encoding.encode(&output.stderr),
                              exit_code: status.code(),
-                             signal: status.signal().map(|s: i32| s.to_string()),
+                             signal,
                              memory_usage: Some(mem_peak),
-                             cpu_time: Some(cpu_time_us),
+                             cpu_time: Some(usage.cpu_us),
                              execution_time: Some(duration),
+                             termination_reason: Some(termination_reason),
+                             io_read_bytes: usage.io.map(|(read, _)| read),
+                             io_write_bytes: usage.io.map(|(_, written)| written),
+                             samples,
+                             perf_counters,
                          })
                      },
                      Err(e) => Err(TurboError::Io(e))
                  }
              },
              _ = tokio::time::sleep(timeout_duration) => {
-                 let _ = child.kill().await;
-
-                 // CRITICAL: Ensure all processes in the cgroup are killed
-                 // In V2, writing "1" to cgroup.kill kills all processes in the cgroup
-                 let kill_file = job_path.join("cgroup.kill");
-                 if kill_file.exists() {
-                     let _ = Self::write_cgroup_file(&kill_file, "1");
-                 }
-
                  let duration = start_time.elapsed().as_millis() as u64;
 
-                 // Await the output readers to finish reading what they can
-                 let (stdout_bytes, stderr_bytes) = read_task.await.unwrap_or_else(|_| (Vec::new(), Vec::new()));
+                 // Kill the whole tree first: grandchildren may hold the pipes open, so
+                 // the output is only complete once the cgroup is empty
+                 Self::kill_tree(job_path).await;
+                 let _ = child.kill().await;
+                 let output = Self::collect_output(read_task).await;
 
                  // Read stats
-                 let mem_peak = Self::read_cgroup_file(&job_path.join("memory.current"))
-                     .ok()
-                     .and_then(|v| v.trim().parse::<u64>().ok())
-                     .unwrap_or(0);
-
-                 let cpu_time_us = Self::read_cgroup_file(&job_path.join("cpu.stat"))
-                     .ok()
-                     .and_then(|content| {
-                        content.lines()
-                            .find(|l| l.starts_with("usage_usec"))
-                            .and_then(|l| l.split_whitespace().nth(1))
-                            .and_then(|v| v.parse::<u64>().ok())
-                     })
-                     .unwrap_or(0);
+                 let mem_peak = Self::read_memory_usage(job_path);
+                 let usage = Usage::read(job_path).since(baseline);
+                 let samples = Sampler::finish(sampler).await;
+                 let perf_counters = counters.as_ref().map(CgroupCounters::read);
 
                  Ok(StageResult {
                      status: StageStatus::TimeLimitExceeded,
-                     stdout: String::from_utf8_lossy(&stdout_bytes).to_string(),
-                     stderr: String::from_utf8_lossy(&stderr_bytes).to_string(),
+                     stdout: limits.output_encoding.encode(&output.stdout),
+                     stderr: limits.output_encoding.encode(&output.stderr),
                      exit_code: None,
                      signal: Some("SIGKILL".to_string()),
                      memory_usage: Some(mem_peak),
-                     cpu_time: Some(cpu_time_us),
+                     cpu_time: Some(usage.cpu_us),
                      execution_time: Some(duration),
+                     termination_reason: Some(TerminationReason::TimeLimit),
+                     io_read_bytes: usage.io.map(|(read, _)| read),
+                     io_write_bytes: usage.io.map(|(_, written)| written),
+                     samples,
+                     perf_counters,
                  })
              }
         }
     }
 }
+
+/// Output the readers of a run captured.
+#[derive(Debug, Default)]
+struct Captured {
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+    /// Output past `output_limit_bytes` was read and discarded
+    overflowed: bool,
+}
+
+/// Reads `reader` to its end, keeping the first `cap` bytes, and tells whether anything
+/// past them was discarded. Reading on past `cap` keeps a chatty program from blocking
+/// on a full pipe or terminal until its timeout.
+async fn drain_capped(mut reader: impl tokio::io::AsyncRead + Unpin, cap: u64) -> (Vec<u8>, bool) {
+    use tokio::io::AsyncReadExt;
+
+    let cap = usize::try_from(cap).unwrap_or(usize::MAX);
+    let mut kept = Vec::new();
+    let mut overflowed = false;
+    let mut chunk = [0u8; 8192];
+    while let Ok(n @ 1..) = reader.read(&mut chunk).await {
+        let room = cap.saturating_sub(kept.len()).min(n);
+        kept.extend_from_slice(&chunk[..room]);
+        overflowed |= room < n;
+    }
+    (kept, overflowed)
+}
+
+/// Outcome of one check of `LinuxSandbox::probe`.
+#[derive(Debug, Clone)]
+pub struct Capability {
+    /// What was checked, e.g. "cgroup v2"
+    pub name: &'static str,
+    pub available: bool,
+    /// What was found
+    pub detail: String,
+    /// What to change when unavailable
+    pub fix: Option<String>,
+}
+
+impl Capability {
+    fn ok(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            available: true,
+            detail: detail.into(),
+            fix: None,
+        }
+    }
+
+    fn missing(name: &'static str, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self {
+            name,
+            available: false,
+            detail: detail.into(),
+            fix: Some(fix.into()),
+        }
+    }
+}
+
+/// Bytes read and written across every device of a cgroup's `io.stat`, one line per
+/// device: `8:0 rbytes=4096 wbytes=0 rios=1 wios=0 dbytes=0 dios=0`.
+fn io_totals(io_stat: &str) -> (u64, u64) {
+    let mut totals = (0, 0);
+    for field in io_stat.split_whitespace() {
+        match field.split_once('=') {
+            Some(("rbytes", v)) => totals.0 += v.parse::<u64>().unwrap_or(0),
+            Some(("wbytes", v)) => totals.1 += v.parse::<u64>().unwrap_or(0),
+            _ => {}
+        }
+    }
+    totals
+}
+
+/// The `oom_kill` count of a cgroup's `memory.events`.
+fn oom_kills(memory_events: &str) -> u64 {
+    memory_events
+        .lines()
+        .find_map(|line| line.strip_prefix("oom_kill "))
+        .and_then(|count| count.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+/// Cumulative counters of a job cgroup, read before and after a run.
+#[derive(Debug, Clone, Copy)]
+struct Usage {
+    cpu_us: u64,
+    /// Bytes read and written, from `io.stat`; `None` without the io controller
+    io: Option<(u64, u64)>,
+    /// OOM kills, from `memory.events`
+    oom_kills: u64,
+}
+
+impl Usage {
+    fn read(job_path: &Path) -> Self {
+        Self {
+            cpu_us: LinuxSandbox::read_cpu_usage(job_path),
+            io: LinuxSandbox::read_io_usage(job_path),
+            oom_kills: LinuxSandbox::read_oom_kills(job_path),
+        }
+    }
+
+    /// Usage since `baseline` was read.
+    fn since(self, baseline: Usage) -> Usage {
+        let io = match (self.io, baseline.io) {
+            (Some((read, written)), Some((base_read, base_written))) => Some((
+                read.saturating_sub(base_read),
+                written.saturating_sub(base_written),
+            )),
+            _ => None,
+        };
+        Usage {
+            cpu_us: self.cpu_us.saturating_sub(baseline.cpu_us),
+            io,
+            oom_kills: self.oom_kills.saturating_sub(baseline.oom_kills),
+        }
+    }
+}
+
+/// Background task polling a job cgroup's `memory.current` and `cpu.stat` while a run
+/// is in progress.
+struct Sampler {
+    stop: tokio::sync::oneshot::Sender<()>,
+    task: tokio::task::JoinHandle<Vec<UsageSample>>,
+}
+
+impl Sampler {
+    fn start(job_path: PathBuf, cpu_baseline_us: u64, interval: Duration) -> Self {
+        let (stop, mut stopped) = tokio::sync::oneshot::channel();
+        let task = tokio::spawn(async move {
+            let started = std::time::Instant::now();
+            let mut interval = interval;
+            let mut samples = Vec::new();
+            loop {
+                samples.push(UsageSample {
+                    elapsed_ms: started.elapsed().as_millis() as u64,
+                    memory_usage: LinuxSandbox::read_memory_usage(&job_path),
+                    cpu_time: LinuxSandbox::read_cpu_usage(&job_path)
+                        .saturating_sub(cpu_baseline_us),
+                });
+                if samples.len() >= MAX_SAMPLES {
+                    samples = samples.into_iter().step_by(2).collect();
+                    interval *= 2;
+                }
+                tokio::select! {
+                    _ = &mut stopped => break,
+                    _ = tokio::time::sleep(interval) => {}
+                }
+            }
+            samples
+        });
+        Self { stop, task }
+    }
+
+    /// Stops `sampler`, if one was started, and returns its samples.
+    async fn finish(sampler: Option<Sampler>) -> Option<Vec<UsageSample>> {
+        let sampler = sampler?;
+        let _ = sampler.stop.send(());
+        sampler.task.await.ok()
+    }
+}
+
+/// Name of `signal`, e.g. "SIGSEGV", or its number if it has none.
+pub(crate) fn signal_name(signal: i32) -> String {
+    nix::sys::signal::Signal::try_from(signal)
+        .map(|s| s.as_str().to_string())
+        .unwrap_or_else(|_| signal.to_string())
+}
+
+/// Character devices the private /dev holds: name, major, minor.
+const DEV_NODES: [(&CStr, u64, u64); 4] = [
+    (c"/dev/null", 1, 3),
+    (c"/dev/zero", 1, 5),
+    (c"/dev/urandom", 1, 9),
+    (c"/dev/tty", 5, 0),
+];
+
+/// System directories remounted read-only; missing ones are skipped.
+const READ_ONLY_PATHS: [&str; 8] = [
+    "/usr", "/bin", "/sbin", "/lib", "/lib64", "/etc", "/boot", "/opt",
+];
+
+/// /proc entries that expose host kernel state, hidden behind /dev/null.
+const MASKED_PROC_PATHS: [&CStr; 4] = [
+    c"/proc/kcore",
+    c"/proc/keys",
+    c"/proc/timer_list",
+    c"/proc/sched_debug",
+];
+
+/// /proc entries that could change host kernel state, remounted read-only.
+const READ_ONLY_PROC_PATHS: [&str; 4] =
+    ["/proc/sys", "/proc/sysrq-trigger", "/proc/irq", "/proc/bus"];
+
+/// What `private_mounts` needs from outside the child, gathered before spawning: it runs
+/// in `pre_exec`, between fork and exec, where reading files or allocating is not
+/// async-signal-safe.
+struct MountPlan {
+    /// Each path to make read-only, with the mount points at or below it, parents first,
+    /// and the flags their read-only remount must repeat
+    read_only: Vec<(CString, Vec<(CString, nix::mount::MsFlags)>)>,
+}
+
+impl MountPlan {
+    /// Plans from this process's mount table, which the child's new mount namespace
+    /// starts as a copy of.
+    fn new() -> std::io::Result<Self> {
+        let mountinfo = std::fs::read_to_string("/proc/self/mountinfo")?;
+        let mut read_only = Vec::new();
+        for path in READ_ONLY_PROC_PATHS.iter().chain(&READ_ONLY_PATHS) {
+            let mut mount_points = Vec::new();
+            for mount_point in mounts_under(&mountinfo, path) {
+                let flags = locked_flags(&mount_point);
+                mount_points.push((CString::new(mount_point)?, flags));
+            }
+            read_only.push((CString::new(*path)?, mount_points));
+        }
+        Ok(Self { read_only })
+    }
+}
+
+/// Builds the sandbox's view of the filesystem in a freshly unshared mount namespace:
+/// a minimal /dev with its own /dev/shm and /dev/pts, masked and read-only parts of
+/// /proc, and read-only system paths, including every mount below them.
+/// Runs in `pre_exec`, before capabilities are dropped, so it only makes syscalls on
+/// paths prepared beforehand.
+fn private_mounts(plan: &MountPlan) -> std::io::Result<()> {
+    use nix::mount::{mount, MsFlags};
+    use nix::sys::stat::{fchmodat, makedev, mknod, FchmodatFlags, Mode, SFlag};
+    use nix::unistd::{mkdir, symlinkat};
+
+    const NONE: Option<&CStr> = None;
+
+    // Keep our mounts from propagating back to the host's namespace
+    mount(
+        NONE,
+        c"/",
+        NONE,
+        MsFlags::MS_REC | MsFlags::MS_PRIVATE,
+        NONE,
+    )?;
+
+    // Minimal /dev: a fresh tmpfs holding only the devices runtimes expect
+    mount(
+        Some(c"tmpfs"),
+        c"/dev",
+        Some(c"tmpfs"),
+        MsFlags::MS_NOSUID | MsFlags::MS_NOEXEC,
+        Some(c"mode=755,size=64k"),
+    )?;
+    let all_rw = Mode::from_bits_truncate(0o666);
+    for (path, major, minor) in DEV_NODES {
+        mknod(path, SFlag::S_IFCHR, all_rw, makedev(major, minor))?;
+        // mknod is subject to the umask
+        fchmodat(None, path, all_rw, FchmodatFlags::FollowSymlink)?;
+    }
+    for (link, target) in [
+        (c"/dev/fd", c"/proc/self/fd"),
+        (c"/dev/stdin", c"/proc/self/fd/0"),
+        (c"/dev/stdout", c"/proc/self/fd/1"),
+        (c"/dev/stderr", c"/proc/self/fd/2"),
+    ] {
+        symlinkat(target, None, link)?;
+    }
+    let dir_mode = Mode::from_bits_truncate(0o755);
+    // Shared memory for POSIX semaphores and shm_open; its pages are charged to the
+    // job's cgroup like any other memory
+    mkdir(c"/dev/shm", dir_mode)?;
+    mount(
+        Some(c"tmpfs"),
+        c"/dev/shm",
+        Some(c"tmpfs"),
+        MsFlags::MS_NOSUID | MsFlags::MS_NODEV | MsFlags::MS_NOEXEC,
+        Some(c"mode=1777"),
+    )?;
+    // A devpts instance of its own, so openpty works without seeing the host's ptys
+    mkdir(c"/dev/pts", dir_mode)?;
+    mount(
+        Some(c"devpts"),
+        c"/dev/pts",
+        Some(c"devpts"),
+        MsFlags::MS_NOSUID | MsFlags::MS_NOEXEC,
+        Some(c"newinstance,ptmxmode=0666,mode=0620"),
+    )?;
+    symlinkat(c"pts/ptmx", None, c"/dev/ptmx")?;
+
+    for path in MASKED_PROC_PATHS {
+        skip_missing(mount(
+            Some(c"/dev/null"),
+            path,
+            NONE,
+            MsFlags::MS_BIND,
+            NONE,
+        ))?;
+    }
+    for (path, mount_points) in &plan.read_only {
+        bind_read_only(path, mount_points)?;
+    }
+    Ok(())
+}
+
+/// Bind-mounts `path` onto itself with everything mounted below it, and remounts each
+/// of its `mount_points` read-only; a read-only remount only ever applies to one mount.
+fn bind_read_only(
+    path: &CStr,
+    mount_points: &[(CString, nix::mount::MsFlags)],
+) -> std::io::Result<()> {
+    use nix::mount::{mount, MsFlags};
+
+    const NONE: Option<&CStr> = None;
+    skip_missing(mount(
+        Some(path),
+        path,
+        NONE,
+        MsFlags::MS_BIND | MsFlags::MS_REC,
+        NONE,
+    ))?;
+    for (mount_point, locked) in mount_points {
+        let flags = MsFlags::MS_BIND | MsFlags::MS_REMOUNT | MsFlags::MS_RDONLY | *locked;
+        skip_missing(mount(NONE, mount_point.as_c_str(), NONE, flags, NONE))?;
+    }
+    Ok(())
+}
+
+/// Mount points at or below `path` in `mountinfo` (`/proc/<pid>/mountinfo`), parents
+/// first.
+fn mounts_under(mountinfo: &str, path: &str) -> Vec<String> {
+    let mut mount_points: Vec<String> = mountinfo
+        .lines()
+        .filter_map(|line| line.split(' ').nth(4))
+        .map(unescape_mount_point)
+        .filter(|mount_point| {
+            mount_point == path
+                || mount_point
+                    .strip_prefix(path)
+                    .is_some_and(|rest| rest.starts_with('/'))
+        })
+        .collect();
+    mount_points.sort();
+    mount_points.dedup();
+    mount_points
+}
+
+/// Undoes the octal escapes (`\040` for a space) of a mount point in mountinfo.
+fn unescape_mount_point(field: &str) -> String {
+    let mut bytes = Vec::with_capacity(field.len());
+    let mut rest = field.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = tail
+            .get(..3)
+            .filter(|digits| byte == b'\\' && digits.iter().all(|d| (b'0'..=b'7').contains(d)))
+            .map(|digits| {
+                digits
+                    .iter()
+                    .fold(0u8, |n, d| n.wrapping_mul(8).wrapping_add(d - b'0'))
+            });
+        match escaped {
+            Some(decoded) => {
+                bytes.push(decoded);
+                rest = &tail[3..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Flags of the mount at `mount_point` that a bind remount must repeat: without them the
+/// remount would clear nosuid, nodev or noexec, which fails if they are locked.
+fn locked_flags(mount_point: &str) -> nix::mount::MsFlags {
+    use nix::mount::MsFlags;
+    use nix::sys::statvfs::{statvfs, FsFlags};
+
+    let Ok(stat) = statvfs(mount_point) else {
+        return MsFlags::empty();
+    };
+    let mut flags = MsFlags::empty();
+    for (fs_flag, ms_flag) in [
+        (FsFlags::ST_NOSUID, MsFlags::MS_NOSUID),
+        (FsFlags::ST_NODEV, MsFlags::MS_NODEV),
+        (FsFlags::ST_NOEXEC, MsFlags::MS_NOEXEC),
+    ] {
+        if stat.flags().contains(fs_flag) {
+            flags |= ms_flag;
+        }
+    }
+    flags
+}
+
+/// Treats a mount on a path this host doesn't have as done.
+fn skip_missing(result: nix::Result<()>) -> std::io::Result<()> {
+    match result {
+        Err(nix::errno::Errno::ENOENT) => Ok(()),
+        other => other.map_err(std::io::Error::from),
+    }
+}
+
+/// Highest capability number probed when emptying the bounding set; numbers past the
+/// kernel's last capability fail with EINVAL and are skipped.
+const MAX_CAPABILITY: nix::libc::c_ulong = 63;
+
+/// Clears the ambient set and empties the bounding set so no exec can regain a
+/// capability, not even a file-capable or setuid binary. Runs in `pre_exec`.
+///
+/// Dropping needs CAP_SETPCAP, which a server that is not root lacks (EPERM); its runs
+/// have no capabilities to regain beyond what `no_new_privs` and the uid already deny.
+fn drop_capability_bounds() -> std::io::Result<()> {
+    // SAFETY: prctl with integer arguments only; async-signal-safe.
+    unsafe {
+        let zero: nix::libc::c_ulong = 0;
+        nix::libc::prctl(
+            nix::libc::PR_CAP_AMBIENT,
+            nix::libc::PR_CAP_AMBIENT_CLEAR_ALL as nix::libc::c_ulong,
+            zero,
+            zero,
+            zero,
+        );
+        for cap in 0..=MAX_CAPABILITY {
+            if nix::libc::prctl(nix::libc::PR_CAPBSET_DROP, cap, zero, zero, zero) != 0 {
+                let err = std::io::Error::last_os_error();
+                match err.raw_os_error() {
+                    Some(nix::libc::EINVAL) => {}
+                    Some(nix::libc::EPERM) => break,
+                    _ => return Err(err),
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[repr(C)]
+struct CapUserHeader {
+    version: u32,
+    pid: nix::libc::c_int,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct CapUserData {
+    effective: u32,
+    permitted: u32,
+    inheritable: u32,
+}
+
+/// `_LINUX_CAPABILITY_VERSION_3`, which takes two `CapUserData` words.
+const CAPABILITY_VERSION_3: u32 = 0x2008_0522;
+
+/// Empties the effective, permitted and inheritable sets of the calling process.
+fn clear_capabilities() -> std::io::Result<()> {
+    let header = CapUserHeader {
+        version: CAPABILITY_VERSION_3,
+        pid: 0,
+    };
+    let data = [CapUserData {
+        effective: 0,
+        permitted: 0,
+        inheritable: 0,
+    }; 2];
+    // SAFETY: header and data match the kernel's capset ABI for version 3.
+    let rc = unsafe { nix::libc::syscall(nix::libc::SYS_capset, &header, data.as_ptr()) };
+    if rc != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn oom_kills_reads_the_oom_kill_count() {
+        let events = "low 0\nhigh 0\nmax 12\noom 2\noom_kill 1\noom_group_kill 0\n";
+        assert_eq!(oom_kills(events), 1);
+        assert_eq!(oom_kills("low 0\nmax 0\n"), 0);
+        assert_eq!(oom_kills(""), 0);
+    }
+
+    #[test]
+    fn signal_name_names_known_signals_and_numbers_others() {
+        assert_eq!(signal_name(nix::libc::SIGSEGV), "SIGSEGV");
+        assert_eq!(signal_name(nix::libc::SIGKILL), "SIGKILL");
+        assert_eq!(signal_name(nix::libc::SIGXCPU), "SIGXCPU");
+        assert_eq!(signal_name(99), "99");
+    }
+
+    #[test]
+    fn io_totals_sums_every_device() {
+        let io_stat = "\
+8:0 rbytes=4096 wbytes=512 rios=1 wios=1 dbytes=0 dios=0
+259:0 rbytes=1000 wbytes=0 rios=2 wios=0 dbytes=8192 dios=1
+";
+        assert_eq!(io_totals(io_stat), (5096, 512));
+        assert_eq!(io_totals(""), (0, 0));
+        assert_eq!(io_totals("8:0 rbytes=oops wbytes=7"), (0, 7));
+    }
+
+    #[test]
+    fn unescape_mount_point_decodes_octal_escapes() {
+        assert_eq!(unescape_mount_point("/usr"), "/usr");
+        assert_eq!(unescape_mount_point(r"/mnt/my\040disk"), "/mnt/my disk");
+        assert_eq!(unescape_mount_point(r"/a\011b\012c\134d"), "/a\tb\nc\\d");
+        // Not a full escape, so kept as is
+        assert_eq!(unescape_mount_point(r"/a\04"), r"/a\04");
+        assert_eq!(unescape_mount_point(r"/a\089"), r"/a\089");
+    }
+
+    #[test]
+    fn mounts_under_lists_the_path_and_mounts_below_it() {
+        let mountinfo = "\
+22 1 8:1 / / rw,relatime shared:1 - ext4 /dev/sda1 rw
+23 22 8:2 / /usr rw,relatime shared:2 - ext4 /dev/sda2 rw
+24 23 0:30 / /usr/local/share rw shared:3 - tmpfs tmpfs rw
+25 22 0:31 / /usrdata rw shared:4 - tmpfs tmpfs rw
+26 23 0:32 / /usr/my\\040dir rw shared:5 - tmpfs tmpfs rw
+27 23 8:2 / /usr rw,relatime shared:2 - ext4 /dev/sda2 rw
+";
+        assert_eq!(
+            mounts_under(mountinfo, "/usr"),
+            ["/usr", "/usr/local/share", "/usr/my dir"]
+        );
+        assert_eq!(mounts_under(mountinfo, "/opt"), Vec::<String>::new());
+        assert_eq!(mounts_under("", "/usr"), Vec::<String>::new());
+    }
+}
@@ -0,0 +1,91 @@
+use crate::traits::{CommandSpec, Sandbox};
+use crate::workdir;
+use async_trait::async_trait;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::info;
+use turbo_core::config::MockSandboxConfig;
+use turbo_core::models::{StageStatus, TerminationReason};
+use turbo_core::{ExecutionLimits, Result, StageResult};
+
+/// Sandbox that never executes anything and returns a canned result for every run.
+///
+/// Useful for exercising the queue/API plumbing on hosts without sandbox privileges.
+pub struct MockSandbox {
+    /// Root path under which working directories are created.
+    pub root_path: String,
+    config: MockSandboxConfig,
+}
+
+impl MockSandbox {
+    /// Create a new MockSandbox returning the configured stdout and exit code.
+    pub fn new(root_path: String, config: MockSandboxConfig) -> Self {
+        Self { root_path, config }
+    }
+}
+
+#[async_trait]
+impl Sandbox for MockSandbox {
+    async fn init(&self, id: &str) -> Result<()> {
+        let dir = workdir::job_dir(Path::new(&self.root_path), id)?;
+        tokio::fs::create_dir_all(&dir).await?;
+        Ok(())
+    }
+
+    fn work_dir(&self, id: &str) -> PathBuf {
+        Path::new(&self.root_path).join(id)
+    }
+
+    async fn run(
+        &self,
+        id: &str,
+        spec: CommandSpec<'_>,
+        limits: Option<ExecutionLimits>,
+    ) -> Result<StageResult> {
+        info!("Mock run in sandbox {}: {} {:?}", id, spec.cmd, spec.args);
+
+        let status = if self.config.exit_code == 0 {
+            StageStatus::Success
+        } else {
+            StageStatus::RuntimeError
+        };
+
+        Ok(StageResult {
+            status,
+            stdout: limits
+                .unwrap_or_default()
+                .output_encoding
+                .encode(self.config.stdout.as_bytes()),
+            stderr: String::new(),
+            exit_code: Some(self.config.exit_code),
+            signal: None,
+            memory_usage: Some(0),
+            cpu_time: Some(0),
+            execution_time: Some(0),
+            termination_reason: Some(TerminationReason::Exited),
+            io_read_bytes: None,
+            io_write_bytes: None,
+            samples: None,
+            perf_counters: None,
+        })
+    }
+
+    async fn cleanup(&self, id: &str) -> Result<()> {
+        let _ = tokio::fs::remove_dir_all(self.work_dir(id)).await;
+        Ok(())
+    }
+
+    async fn sweep_orphans(
+        &self,
+        in_use: &(dyn for<'a> Fn(&'a str) -> bool + Sync),
+        min_age: Duration,
+    ) -> Result<usize> {
+        let stale = workdir::stale_dirs(Path::new(&self.root_path), min_age).await?;
+        let mut removed = 0;
+        for id in stale.iter().filter(|id| !in_use(id)) {
+            self.cleanup(id).await?;
+            removed += 1;
+        }
+        Ok(removed)
+    }
+}
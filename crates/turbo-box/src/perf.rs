@@ -0,0 +1,148 @@
+//! Hardware event counters of a job cgroup, like `perf stat -G`.
+//!
+//! Each event is opened with `perf_event_open(2)` in cgroup mode once per online CPU,
+//! counting whatever runs in the cgroup while it is scheduled there; a run's count is the
+//! sum. cgroup v2 always has the perf_event controller, so nothing needs delegating, but
+//! cgroup events need CAP_PERFMON (or root) unless `kernel.perf_event_paranoid` is 0 or
+//! lower. Only user space is counted, which keeps counts steady from run to run.
+
+use nix::libc;
+use std::fs::File;
+use std::io::Read;
+use std::os::fd::{AsRawFd, FromRawFd};
+use std::path::Path;
+use tracing::warn;
+use turbo_core::models::PerfCounters;
+
+const PERF_TYPE_HARDWARE: u32 = 0;
+const PERF_COUNT_HW_INSTRUCTIONS: u64 = 1;
+const PERF_COUNT_HW_CACHE_MISSES: u64 = 3;
+const PERF_COUNT_HW_BRANCH_MISSES: u64 = 5;
+/// `pid` is the fd of a cgroup directory rather than a process
+const PERF_FLAG_PID_CGROUP: libc::c_ulong = 1 << 2;
+const PERF_FLAG_FD_CLOEXEC: libc::c_ulong = 1 << 3;
+/// The `exclude_kernel` and `exclude_hv` bits of the attribute flags
+const EXCLUDE_KERNEL_AND_HV: u64 = (1 << 5) | (1 << 6);
+
+/// The first 64 bytes of `struct perf_event_attr` (`PERF_ATTR_SIZE_VER0`), all a
+/// counting event needs; the kernel treats the fields of later versions as zero.
+#[repr(C)]
+#[derive(Default)]
+struct PerfEventAttr {
+    kind: u32,
+    size: u32,
+    config: u64,
+    sample_period: u64,
+    sample_type: u64,
+    read_format: u64,
+    flags: u64,
+    wakeup_events: u32,
+    bp_type: u32,
+    config1: u64,
+}
+
+/// Open counters of one cgroup, counting from when they were opened. Each event is
+/// `None` if it could not be opened on every CPU.
+pub(crate) struct CgroupCounters {
+    instructions: Option<Vec<File>>,
+    cache_misses: Option<Vec<File>>,
+    branch_misses: Option<Vec<File>>,
+}
+
+impl CgroupCounters {
+    /// Starts counting for the cgroup at `job_path`; `None` if no event could be opened.
+    pub(crate) fn open(job_path: &Path) -> Option<Self> {
+        let cgroup = match File::open(job_path) {
+            Ok(cgroup) => cgroup,
+            Err(e) => {
+                warn!("Cannot profile {:?}: {}", job_path, e);
+                return None;
+            }
+        };
+        let cpus = online_cpus();
+        let open = |config| {
+            cpus.iter()
+                .map(|&cpu| open_event(&cgroup, config, cpu))
+                .collect::<std::io::Result<Vec<File>>>()
+        };
+        let instructions = open(PERF_COUNT_HW_INSTRUCTIONS);
+        if let Err(e) = &instructions {
+            warn!("Cannot count instructions of {:?}: {}", job_path, e);
+        }
+        let counters = Self {
+            instructions: instructions.ok(),
+            cache_misses: open(PERF_COUNT_HW_CACHE_MISSES).ok(),
+            branch_misses: open(PERF_COUNT_HW_BRANCH_MISSES).ok(),
+        };
+        if counters.instructions.is_none()
+            && counters.cache_misses.is_none()
+            && counters.branch_misses.is_none()
+        {
+            return None;
+        }
+        Some(counters)
+    }
+
+    /// Counts so far.
+    pub(crate) fn read(&self) -> PerfCounters {
+        PerfCounters {
+            instructions: self.instructions.as_deref().and_then(total),
+            cache_misses: self.cache_misses.as_deref().and_then(total),
+            branch_misses: self.branch_misses.as_deref().and_then(total),
+        }
+    }
+}
+
+fn open_event(cgroup: &File, config: u64, cpu: i32) -> std::io::Result<File> {
+    let attr = PerfEventAttr {
+        kind: PERF_TYPE_HARDWARE,
+        size: std::mem::size_of::<PerfEventAttr>() as u32,
+        config,
+        flags: EXCLUDE_KERNEL_AND_HV,
+        ..Default::default()
+    };
+    // SAFETY: attr outlives the call and is `attr.size` bytes long.
+    let fd = unsafe {
+        libc::syscall(
+            libc::SYS_perf_event_open,
+            &attr as *const PerfEventAttr,
+            cgroup.as_raw_fd(),
+            cpu,
+            -1,
+            PERF_FLAG_PID_CGROUP | PERF_FLAG_FD_CLOEXEC,
+        )
+    };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    // SAFETY: the fd was just opened and nothing else owns it.
+    Ok(unsafe { File::from_raw_fd(fd as i32) })
+}
+
+/// Sum of the per-CPU counts of one event.
+fn total(counters: &[File]) -> Option<u64> {
+    let mut sum = 0u64;
+    for mut counter in counters {
+        let mut count = [0u8; 8];
+        counter.read_exact(&mut count).ok()?;
+        sum = sum.saturating_add(u64::from_ne_bytes(count));
+    }
+    Some(sum)
+}
+
+/// CPUs in `/sys/devices/system/cpu/online`, e.g. `0-3,6`.
+fn online_cpus() -> Vec<i32> {
+    let list = std::fs::read_to_string("/sys/devices/system/cpu/online").unwrap_or_default();
+    let mut cpus = Vec::new();
+    for range in list.trim().split(',') {
+        let (first, last) = range.split_once('-').unwrap_or((range, range));
+        if let (Ok(first), Ok(last)) = (first.parse::<i32>(), last.parse::<i32>()) {
+            cpus.extend(first..=last);
+        }
+    }
+    if cpus.is_empty() {
+        let count = std::thread::available_parallelism().map_or(1, |n| n.get());
+        cpus.extend(0..count as i32);
+    }
+    cpus
+}
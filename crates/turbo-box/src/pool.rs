@@ -0,0 +1,80 @@
+use crate::traits::{Sandbox, Session};
+use std::sync::Arc;
+use tokio::sync::{Mutex, Semaphore, SemaphorePermit, TryAcquireError};
+use tracing::{info, warn};
+use turbo_core::{Result, SandboxError};
+
+/// A fixed set of pre-initialized sandboxes that are reused across jobs.
+///
+/// Creating and removing a cgroup per request is a measurable latency cost under load,
+/// so the pool opens `size` sessions up front and hands them out to workers. Sessions
+/// are reset (not destroyed) when released.
+pub struct SandboxPool<S: Sandbox + ?Sized> {
+    sandbox: Arc<S>,
+    slots: Mutex<Vec<Session>>,
+    available: Semaphore,
+}
+
+impl<S: Sandbox + ?Sized> SandboxPool<S> {
+    /// Open `size` sessions on sandboxes named `pool-{n}`.
+    pub async fn new(sandbox: Arc<S>, size: usize) -> Result<Self> {
+        let mut slots = Vec::with_capacity(size);
+        for n in 0..size {
+            let id = format!("pool-{}", n);
+            slots.push(sandbox.create_session(&id).await?);
+        }
+        info!("Sandbox pool initialized with {} slots", size);
+
+        Ok(Self {
+            sandbox,
+            slots: Mutex::new(slots),
+            available: Semaphore::new(size),
+        })
+    }
+
+    /// The sandbox backend slots are run against.
+    pub fn sandbox(&self) -> &S {
+        &self.sandbox
+    }
+
+    /// Wait for a free session and take it out of the pool.
+    pub async fn acquire(&self) -> Result<Session> {
+        let permit = self
+            .available
+            .acquire()
+            .await
+            .map_err(|_| SandboxError::PoolClosed)?;
+        self.take(permit).await
+    }
+
+    /// Take a free session if one is available right now, without waiting.
+    pub async fn try_acquire(&self) -> Result<Option<Session>> {
+        match self.available.try_acquire() {
+            Ok(permit) => self.take(permit).await.map(Some),
+            Err(TryAcquireError::NoPermits) => Ok(None),
+            Err(TryAcquireError::Closed) => Err(SandboxError::PoolClosed.into()),
+        }
+    }
+
+    /// The free session `permit` stands for. The permit is only used up once there is
+    /// one, so a mismatch never loses it.
+    async fn take(&self, permit: SemaphorePermit<'_>) -> Result<Session> {
+        let session = self
+            .slots
+            .lock()
+            .await
+            .pop()
+            .ok_or(SandboxError::PoolSlotMissing)?;
+        permit.forget();
+        Ok(session)
+    }
+
+    /// Reset a session and return it to the pool.
+    pub async fn release(&self, session: Session) {
+        if let Err(e) = self.sandbox.reset(session.id()).await {
+            warn!("Failed to reset sandbox slot {}: {}", session.id(), e);
+        }
+        self.slots.lock().await.push(session);
+        self.available.add_permits(1);
+    }
+}
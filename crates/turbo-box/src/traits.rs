@@ -1,21 +1,191 @@
+use crate::workdir::{self, DirEntry};
 use async_trait::async_trait;
-use turbo_core::{ExecutionLimits, Result, StageResult};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use turbo_core::{ExecutionLimits, Result, SandboxError, StageResult};
+
+/// A command to run inside a sandbox.
+///
+/// `cmd` is executed directly with `args` as its argv; nothing is passed through a shell,
+/// so arguments need no quoting.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CommandSpec<'a> {
+    pub cmd: &'a str,
+    pub args: &'a [String],
+    /// `KEY=VALUE` pairs added to the environment
+    pub env: &'a [String],
+    /// Working directory; `None` uses the sandbox's `work_dir`
+    pub cwd: Option<&'a Path>,
+    /// Written to the child's stdin pipe (which is then closed); `None` connects
+    /// stdin to `/dev/null`
+    pub stdin: Option<&'a [u8]>,
+    /// Attach stdin/stdout/stderr to a pseudo-terminal instead of pipes. Output is
+    /// captured as `stdout` (stderr is merged) and `stdin` is typed into the terminal.
+    pub tty: bool,
+}
+
+/// How `Sandbox::exec_interactive` relays messages between a run and its interactor.
+#[derive(Debug, Clone, Copy)]
+pub struct Protocol {
+    /// Relay whole lines with their trailing whitespace trimmed, rather than bytes as read
+    pub line_buffered: bool,
+    /// A run and interactor that both go this long without a message or CPU use are
+    /// waiting on each other, and the run ends with `IdlenessLimitExceeded`
+    pub message_timeout: Duration,
+    /// Most messages of the run the interactor may answer before the run is ended with
+    /// `OutputLimitExceeded`
+    pub max_exchanges: Option<u32>,
+}
+
+/// An initialized sandbox environment shared by several `exec` calls.
+///
+/// Files written to the session's `work_dir` by one call (e.g. compile output) are seen
+/// by the next (e.g. each testcase run). Obtained from `Sandbox::create_session` and
+/// ended with `Sandbox::destroy_session`.
+#[derive(Debug)]
+pub struct Session {
+    id: String,
+}
+
+impl Session {
+    /// Sandbox id the session runs in
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+/// A process already started inside a sandbox that is waiting for its work on stdin.
+///
+/// Used by warm pools: the expensive runtime start-up (JVM, .NET) happens before a job
+/// arrives, and the job is handed over with `Sandbox::run_warm`.
+pub struct WarmProcess {
+    /// Sandbox the process was spawned in
+    pub id: String,
+    pub(crate) child: tokio::process::Child,
+}
 
 #[async_trait]
 pub trait Sandbox: Send + Sync {
     /// Initialize the sandbox (create files, checking resources)
     async fn init(&self, id: &str) -> Result<()>;
 
+    /// Directory holding the sandbox's working state (source files, build outputs).
+    ///
+    /// Created by `init`, emptied by `reset` and removed by `cleanup`.
+    fn work_dir(&self, id: &str) -> PathBuf;
+
     /// Run a command inside the sandbox
     async fn run(
         &self,
         id: &str,
-        cmd: &str,
-        args: &[String],
-        env: &[String],
+        spec: CommandSpec<'_>,
         limits: Option<ExecutionLimits>,
     ) -> Result<StageResult>;
 
     /// Cleanup the sandbox resources
     async fn cleanup(&self, id: &str) -> Result<()>;
+
+    /// Reset a sandbox so it can be reused for another job.
+    ///
+    /// Backends that can restore their limits and stats in place should override this;
+    /// the default tears the sandbox down and initializes it again.
+    async fn reset(&self, id: &str) -> Result<()> {
+        self.cleanup(id).await?;
+        self.init(id).await
+    }
+
+    /// Initialize a sandbox and open a session on it.
+    async fn create_session(&self, id: &str) -> Result<Session> {
+        self.init(id).await?;
+        Ok(Session { id: id.to_string() })
+    }
+
+    /// Run a command in an open session.
+    ///
+    /// Limits apply to this call only; the session's files persist across calls.
+    async fn exec(
+        &self,
+        session: &Session,
+        spec: CommandSpec<'_>,
+        limits: Option<ExecutionLimits>,
+    ) -> Result<StageResult> {
+        self.run(&session.id, spec, limits).await
+    }
+
+    /// Run `spec` against `interactor` in an open session, relaying each one's stdout to
+    /// the other's stdin under `protocol`; both ignore their `stdin` and `tty`. Both run
+    /// under `limits`, measured separately. Returns the run's result and the
+    /// interactor's, which goes on until it exits or is idle after the run ended.
+    async fn exec_interactive(
+        &self,
+        _session: &Session,
+        _spec: CommandSpec<'_>,
+        _interactor: CommandSpec<'_>,
+        _protocol: Protocol,
+        _limits: Option<ExecutionLimits>,
+    ) -> Result<(StageResult, StageResult)> {
+        Err(SandboxError::Unsupported("exec_interactive".to_string()).into())
+    }
+
+    /// End a session and release its sandbox.
+    async fn destroy_session(&self, session: Session) -> Result<()> {
+        self.cleanup(&session.id).await
+    }
+
+    /// Write `contents` to `path`, relative to the session's working directory, creating
+    /// missing parent directories and replacing an existing file.
+    ///
+    /// The default and the other file methods work on `work_dir` on this host; backends
+    /// whose files live elsewhere (a container, a VM) override all three.
+    async fn write_file(&self, session: &Session, path: &Path, contents: &[u8]) -> Result<()> {
+        workdir::write_file(&self.work_dir(&session.id), path, contents).await
+    }
+
+    /// Contents of the file at `path`, relative to the session's working directory.
+    async fn read_file(&self, session: &Session, path: &Path) -> Result<Vec<u8>> {
+        workdir::read_file(&self.work_dir(&session.id), path).await
+    }
+
+    /// Entries of the directory at `path`, relative to the session's working directory
+    /// (empty for the directory itself), sorted by name.
+    async fn list_dir(&self, session: &Session, path: &Path) -> Result<Vec<DirEntry>> {
+        workdir::list_dir(&self.work_dir(&session.id), path).await
+    }
+
+    /// Clean up sandboxes left on the host, e.g. by a crashed process: those untouched for
+    /// `min_age`, not running anything and not `in_use`. Returns how many were removed.
+    ///
+    /// Backends that keep no state on the host have nothing to sweep.
+    async fn sweep_orphans(
+        &self,
+        _in_use: &(dyn for<'a> Fn(&'a str) -> bool + Sync),
+        _min_age: Duration,
+    ) -> Result<usize> {
+        Ok(0)
+    }
+
+    /// Start a long-lived process in the sandbox that blocks reading its job from stdin.
+    async fn spawn_warm(
+        &self,
+        _id: &str,
+        _cmd: &str,
+        _args: &[String],
+        _env: &[String],
+        _limits: Option<ExecutionLimits>,
+    ) -> Result<WarmProcess> {
+        Err(SandboxError::Unsupported("spawn_warm".to_string()).into())
+    }
+
+    /// Hand `input` to a warm process and wait for it to finish under `limits`.
+    ///
+    /// Fails (rather than returning a failed `StageResult`) if the process died while idle,
+    /// so callers can fall back to a cold run.
+    async fn run_warm(
+        &self,
+        _process: WarmProcess,
+        _input: Vec<u8>,
+        _limits: Option<ExecutionLimits>,
+    ) -> Result<StageResult> {
+        Err(SandboxError::Unsupported("run_warm".to_string()).into())
+    }
 }
@@ -0,0 +1,649 @@
+use crate::traits::{CommandSpec, Sandbox};
+use crate::workdir;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use tracing::{info, instrument, warn};
+use turbo_core::{
+    models::{StageStatus, TerminationReason},
+    ExecutionLimits, Result, SandboxError, StageResult, TurboError,
+};
+use windows_sys::Win32::Foundation::{
+    CloseHandle, LocalFree, ERROR_SUCCESS, HANDLE, INVALID_HANDLE_VALUE,
+};
+use windows_sys::Win32::Security::Authorization::{
+    ConvertStringSecurityDescriptorToSecurityDescriptorW, SetNamedSecurityInfoW, SDDL_REVISION_1,
+    SE_FILE_OBJECT,
+};
+use windows_sys::Win32::Security::{
+    AdjustTokenPrivileges, CreateWellKnownSid, GetSecurityDescriptorSacl, GetTokenInformation,
+    SetTokenInformation, TokenIntegrityLevel, TokenPrivileges, WinLowLabelSid, ACL,
+    LABEL_SECURITY_INFORMATION, LUID_AND_ATTRIBUTES, PSECURITY_DESCRIPTOR, SE_PRIVILEGE_REMOVED,
+    SID_AND_ATTRIBUTES, TOKEN_ADJUST_DEFAULT, TOKEN_ADJUST_PRIVILEGES, TOKEN_MANDATORY_LABEL,
+    TOKEN_PRIVILEGES, TOKEN_QUERY,
+};
+use windows_sys::Win32::System::Diagnostics::ToolHelp::{
+    CreateToolhelp32Snapshot, Thread32First, Thread32Next, TH32CS_SNAPTHREAD, THREADENTRY32,
+};
+use windows_sys::Win32::System::JobObjects::{
+    AssignProcessToJobObject, CreateJobObjectW, JobObjectBasicAccountingInformation,
+    JobObjectBasicUIRestrictions, JobObjectExtendedLimitInformation, QueryInformationJobObject,
+    SetInformationJobObject, TerminateJobObject, JOBOBJECT_BASIC_ACCOUNTING_INFORMATION,
+    JOBOBJECT_BASIC_UI_RESTRICTIONS, JOBOBJECT_EXTENDED_LIMIT_INFORMATION,
+    JOB_OBJECT_LIMIT_ACTIVE_PROCESS, JOB_OBJECT_LIMIT_DIE_ON_UNHANDLED_EXCEPTION,
+    JOB_OBJECT_LIMIT_JOB_MEMORY, JOB_OBJECT_LIMIT_JOB_TIME, JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE,
+    JOB_OBJECT_UILIMIT_DESKTOP, JOB_OBJECT_UILIMIT_DISPLAYSETTINGS, JOB_OBJECT_UILIMIT_EXITWINDOWS,
+    JOB_OBJECT_UILIMIT_GLOBALATOMS, JOB_OBJECT_UILIMIT_HANDLES, JOB_OBJECT_UILIMIT_READCLIPBOARD,
+    JOB_OBJECT_UILIMIT_SYSTEMPARAMETERS, JOB_OBJECT_UILIMIT_WRITECLIPBOARD,
+};
+use windows_sys::Win32::System::Threading::{
+    OpenProcessToken, OpenThread, ResumeThread, CREATE_NO_WINDOW, CREATE_SUSPENDED,
+    THREAD_SUSPEND_RESUME,
+};
+
+/// LUID of `SeChangeNotifyPrivilege` (bypass traverse checking), the only privilege we keep.
+const SE_CHANGE_NOTIFY_LUID: u32 = 23;
+const SE_GROUP_INTEGRITY: u32 = 0x0000_0020;
+const SECURITY_MAX_SID_SIZE: usize = 68;
+/// Low mandatory label with no-write-up, inherited by files and directories created below.
+const LOW_LABEL_SDDL: &str = "S:(ML;OICI;NW;;;LW)";
+
+/// Owned Win32 handle (job, token, thread or snapshot), closed on drop.
+struct OwnedHandle(HANDLE);
+
+// Kernel handles are process-wide and may be used from any thread.
+unsafe impl Send for OwnedHandle {}
+unsafe impl Sync for OwnedHandle {}
+
+impl Drop for OwnedHandle {
+    fn drop(&mut self) {
+        unsafe {
+            CloseHandle(self.0);
+        }
+    }
+}
+
+/// Sandbox implementation for Windows utilizing Job Objects and restricted tokens.
+///
+/// Every job gets its own Job Object which enforces:
+/// - Memory (`JobMemoryLimit`), active process count and user-mode CPU time limits.
+/// - `KILL_ON_JOB_CLOSE`, so nothing outlives `cleanup`.
+/// - UI restrictions (no clipboard, desktop switching, global atoms or foreign USER handles).
+///
+/// Processes are spawned suspended, assigned to the job, stripped of every privilege except
+/// `SeChangeNotifyPrivilege` and lowered to Low integrity before their threads are resumed.
+/// The working directory carries a Low integrity label so they can still write to it;
+/// everything else a Medium integrity user can write stays out of reach.
+pub struct WindowsSandbox {
+    /// Root path where per-sandbox working directories are created.
+    pub root_path: String,
+    jobs: Mutex<HashMap<String, Arc<OwnedHandle>>>,
+}
+
+impl WindowsSandbox {
+    /// Create a new WindowsSandbox instance.
+    pub fn new(root_path: String) -> Self {
+        Self {
+            root_path,
+            jobs: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn get_job(&self, id: &str) -> Result<Arc<OwnedHandle>> {
+        self.jobs
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(id)
+            .cloned()
+            .ok_or_else(|| SandboxError::NotInitialized(id.to_string()).into())
+    }
+
+    fn last_error(operation: &str) -> TurboError {
+        SandboxError::Setup {
+            operation: operation.to_string(),
+            source: std::io::Error::last_os_error(),
+        }
+        .into()
+    }
+}
+
+#[async_trait]
+impl Sandbox for WindowsSandbox {
+    /// Initialize a new sandbox for the given job ID.
+    ///
+    /// This creates the working directory `{root_path}\\{id}`, labelled Low integrity,
+    /// and an anonymous Job Object with UI restrictions applied.
+    #[instrument(skip(self))]
+    async fn init(&self, id: &str) -> Result<()> {
+        info!("Initializing Windows Sandbox for {}", id);
+
+        let work_dir = workdir::job_dir(Path::new(&self.root_path), id)?;
+        tokio::fs::create_dir_all(&work_dir).await?;
+        Self::label_low_integrity(&work_dir)?;
+
+        let handle = unsafe { CreateJobObjectW(std::ptr::null(), std::ptr::null()) };
+        if handle.is_null() {
+            return Err(Self::last_error("create job object"));
+        }
+        let job = Arc::new(OwnedHandle(handle));
+
+        let ui = JOBOBJECT_BASIC_UI_RESTRICTIONS {
+            UIRestrictionsClass: JOB_OBJECT_UILIMIT_DESKTOP
+                | JOB_OBJECT_UILIMIT_DISPLAYSETTINGS
+                | JOB_OBJECT_UILIMIT_EXITWINDOWS
+                | JOB_OBJECT_UILIMIT_GLOBALATOMS
+                | JOB_OBJECT_UILIMIT_HANDLES
+                | JOB_OBJECT_UILIMIT_READCLIPBOARD
+                | JOB_OBJECT_UILIMIT_SYSTEMPARAMETERS
+                | JOB_OBJECT_UILIMIT_WRITECLIPBOARD,
+        };
+        let ok = unsafe {
+            SetInformationJobObject(
+                job.0,
+                JobObjectBasicUIRestrictions,
+                &ui as *const _ as *const c_void,
+                std::mem::size_of::<JOBOBJECT_BASIC_UI_RESTRICTIONS>() as u32,
+            )
+        };
+        if ok == 0 {
+            warn!(
+                "Failed to apply UI restrictions: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+
+        self.jobs
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(id.to_string(), job);
+        Ok(())
+    }
+
+    fn work_dir(&self, id: &str) -> PathBuf {
+        Path::new(&self.root_path).join(id)
+    }
+
+    /// Run a command in the sandbox
+    #[instrument(skip(self))]
+    async fn run(
+        &self,
+        id: &str,
+        spec: CommandSpec<'_>,
+        limits: Option<ExecutionLimits>,
+    ) -> Result<StageResult> {
+        info!(
+            "Running command in sandbox {}: {} {:?}",
+            id, spec.cmd, spec.args
+        );
+
+        if spec.tty {
+            return Err(SandboxError::Unsupported("tty".to_string()).into());
+        }
+
+        let limits = limits.unwrap_or_default();
+        let job = self.get_job(id)?;
+
+        Self::apply_limits(&job, &limits)?;
+
+        let mut command = tokio::process::Command::new(spec.cmd);
+        command
+            .args(spec.args)
+            .current_dir(
+                spec.cwd
+                    .map_or_else(|| self.work_dir(id), Path::to_path_buf),
+            )
+            .envs(spec.env.iter().map(|s| {
+                let parts: Vec<&str> = s.splitn(2, '=').collect();
+                if parts.len() == 2 {
+                    (parts[0], parts[1])
+                } else {
+                    (s.as_str(), "")
+                }
+            }))
+            .stdin(if spec.stdin.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .creation_flags(CREATE_SUSPENDED | CREATE_NO_WINDOW);
+
+        let mut child = command.spawn().map_err(|e| SandboxError::SpawnFailed {
+            command: spec.cmd.to_string(),
+            source: e,
+        })?;
+
+        if let Err(e) = Self::confine(&job, &child) {
+            let _ = child.kill().await;
+            return Err(e);
+        }
+
+        if let (Some(input), Some(mut pipe)) = (spec.stdin, child.stdin.take()) {
+            let input = input.to_vec();
+            tokio::spawn(async move {
+                use tokio::io::AsyncWriteExt;
+                let _ = pipe.write_all(&input).await;
+            });
+        }
+
+        Self::monitor_child(&job, &mut child, &limits).await
+    }
+
+    #[instrument(skip(self))]
+    async fn cleanup(&self, id: &str) -> Result<()> {
+        info!("Cleaning up sandbox {}", id);
+        let job = self
+            .jobs
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(id);
+        if let Some(job) = job {
+            // KILL_ON_JOB_CLOSE covers this too, but be explicit in case a handle leaked.
+            unsafe {
+                TerminateJobObject(job.0, 1);
+            }
+        }
+        let _ = tokio::fs::remove_dir_all(self.work_dir(id)).await;
+        Ok(())
+    }
+}
+
+impl WindowsSandbox {
+    /// Applies memory, process count and CPU time limits to the Job Object.
+    fn apply_limits(job: &OwnedHandle, limits: &ExecutionLimits) -> Result<()> {
+        let mut info: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = unsafe { std::mem::zeroed() };
+        let basic = &mut info.BasicLimitInformation;
+        basic.LimitFlags =
+            JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE | JOB_OBJECT_LIMIT_DIE_ON_UNHANDLED_EXCEPTION;
+
+        if limits.memory_limit_bytes > 0 {
+            basic.LimitFlags |= JOB_OBJECT_LIMIT_JOB_MEMORY;
+            info.JobMemoryLimit = limits.memory_limit_bytes as usize;
+        }
+        if limits.pid_limit > 0 {
+            basic.LimitFlags |= JOB_OBJECT_LIMIT_ACTIVE_PROCESS;
+            basic.ActiveProcessLimit = limits.pid_limit as u32;
+        }
+        if limits.timeout_ms > 0 {
+            // Job time is expressed in 100ns ticks. Re-setting it without
+            // PRESERVE_JOB_TIME starts a fresh budget for this run.
+            basic.LimitFlags |= JOB_OBJECT_LIMIT_JOB_TIME;
+            basic.PerJobUserTimeLimit = (limits.timeout_ms * 10_000) as i64;
+        }
+
+        let ok = unsafe {
+            SetInformationJobObject(
+                job.0,
+                JobObjectExtendedLimitInformation,
+                &info as *const _ as *const c_void,
+                std::mem::size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+            )
+        };
+        if ok == 0 {
+            return Err(Self::last_error("set job limits"));
+        }
+        Ok(())
+    }
+
+    /// Assigns the suspended child to the Job Object, restricts its token and resumes it.
+    fn confine(job: &OwnedHandle, child: &tokio::process::Child) -> Result<()> {
+        let attach_failed = |reason: &str| SandboxError::AttachFailed {
+            target: "job object".to_string(),
+            source: std::io::Error::other(reason.to_string()),
+        };
+        let process = child
+            .raw_handle()
+            .ok_or_else(|| attach_failed("child exited before confinement"))?
+            as HANDLE;
+        let pid = child
+            .id()
+            .ok_or_else(|| attach_failed("child has no process id"))?;
+
+        if unsafe { AssignProcessToJobObject(job.0, process) } == 0 {
+            return Err(SandboxError::AttachFailed {
+                target: "job object".to_string(),
+                source: std::io::Error::last_os_error(),
+            }
+            .into());
+        }
+
+        Self::restrict_token(process)?;
+        Self::resume_threads(pid)
+    }
+
+    /// Removes all privileges but `SeChangeNotifyPrivilege` from the primary token
+    /// and drops it to Low integrity. Only valid while the process is still suspended.
+    fn restrict_token(process: HANDLE) -> Result<()> {
+        let mut token: HANDLE = std::ptr::null_mut();
+        let access = TOKEN_QUERY | TOKEN_ADJUST_PRIVILEGES | TOKEN_ADJUST_DEFAULT;
+        if unsafe { OpenProcessToken(process, access, &mut token) } == 0 {
+            return Err(Self::last_error("open process token"));
+        }
+        let token = OwnedHandle(token);
+
+        // 1. Remove privileges
+        let mut len = 0u32;
+        unsafe {
+            GetTokenInformation(token.0, TokenPrivileges, std::ptr::null_mut(), 0, &mut len);
+        }
+        let mut buf = vec![0u8; len as usize];
+        let ok = unsafe {
+            GetTokenInformation(
+                token.0,
+                TokenPrivileges,
+                buf.as_mut_ptr() as *mut c_void,
+                len,
+                &mut len,
+            )
+        };
+        if ok == 0 {
+            return Err(Self::last_error("query token privileges"));
+        }
+
+        let privileges = buf.as_mut_ptr() as *mut TOKEN_PRIVILEGES;
+        unsafe {
+            let count = (*privileges).PrivilegeCount as usize;
+            let entries =
+                std::ptr::addr_of_mut!((*privileges).Privileges) as *mut LUID_AND_ATTRIBUTES;
+            for i in 0..count {
+                let entry = &mut *entries.add(i);
+                if entry.Luid.LowPart == SE_CHANGE_NOTIFY_LUID && entry.Luid.HighPart == 0 {
+                    continue;
+                }
+                entry.Attributes = SE_PRIVILEGE_REMOVED;
+            }
+            if AdjustTokenPrivileges(
+                token.0,
+                0,
+                privileges,
+                len,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            ) == 0
+            {
+                return Err(Self::last_error("remove token privileges"));
+            }
+        }
+
+        // 2. Lower integrity level
+        let mut sid = [0u8; SECURITY_MAX_SID_SIZE];
+        let mut sid_len = SECURITY_MAX_SID_SIZE as u32;
+        let ok = unsafe {
+            CreateWellKnownSid(
+                WinLowLabelSid,
+                std::ptr::null_mut(),
+                sid.as_mut_ptr() as *mut c_void,
+                &mut sid_len,
+            )
+        };
+        if ok == 0 {
+            return Err(Self::last_error("create low integrity SID"));
+        }
+        let label = TOKEN_MANDATORY_LABEL {
+            Label: SID_AND_ATTRIBUTES {
+                Sid: sid.as_mut_ptr() as *mut c_void,
+                Attributes: SE_GROUP_INTEGRITY,
+            },
+        };
+        let ok = unsafe {
+            SetTokenInformation(
+                token.0,
+                TokenIntegrityLevel,
+                &label as *const _ as *const c_void,
+                std::mem::size_of::<TOKEN_MANDATORY_LABEL>() as u32 + sid_len,
+            )
+        };
+        if ok == 0 {
+            return Err(Self::last_error("lower token integrity"));
+        }
+
+        Ok(())
+    }
+
+    /// Gives `dir` and everything created in it a Low integrity label, so the Low
+    /// integrity processes of `restrict_token` can write their output there.
+    fn label_low_integrity(dir: &Path) -> Result<()> {
+        use std::os::windows::ffi::OsStrExt;
+
+        let sddl: Vec<u16> = LOW_LABEL_SDDL.encode_utf16().chain(Some(0)).collect();
+        let mut descriptor: PSECURITY_DESCRIPTOR = std::ptr::null_mut();
+        let ok = unsafe {
+            ConvertStringSecurityDescriptorToSecurityDescriptorW(
+                sddl.as_ptr(),
+                SDDL_REVISION_1,
+                &mut descriptor,
+                std::ptr::null_mut(),
+            )
+        };
+        if ok == 0 {
+            return Err(Self::last_error("build low integrity label"));
+        }
+
+        let mut sacl: *mut ACL = std::ptr::null_mut();
+        let (mut present, mut defaulted) = (0, 0);
+        let ok = unsafe {
+            GetSecurityDescriptorSacl(descriptor, &mut present, &mut sacl, &mut defaulted)
+        };
+        let result = if ok == 0 {
+            Err(Self::last_error("read low integrity label"))
+        } else {
+            let path: Vec<u16> = dir.as_os_str().encode_wide().chain(Some(0)).collect();
+            let status = unsafe {
+                SetNamedSecurityInfoW(
+                    path.as_ptr(),
+                    SE_FILE_OBJECT,
+                    LABEL_SECURITY_INFORMATION,
+                    std::ptr::null_mut(),
+                    std::ptr::null_mut(),
+                    std::ptr::null(),
+                    sacl,
+                )
+            };
+            if status == ERROR_SUCCESS {
+                Ok(())
+            } else {
+                Err(SandboxError::Setup {
+                    operation: "label work directory low integrity".to_string(),
+                    source: std::io::Error::from_raw_os_error(status as i32),
+                }
+                .into())
+            }
+        };
+        unsafe {
+            LocalFree(descriptor);
+        }
+        result
+    }
+
+    /// Resumes every thread of the given (suspended) process.
+    fn resume_threads(pid: u32) -> Result<()> {
+        let snapshot = unsafe { CreateToolhelp32Snapshot(TH32CS_SNAPTHREAD, 0) };
+        if snapshot == INVALID_HANDLE_VALUE {
+            return Err(Self::last_error("snapshot threads"));
+        }
+        let snapshot = OwnedHandle(snapshot);
+
+        let mut entry: THREADENTRY32 = unsafe { std::mem::zeroed() };
+        entry.dwSize = std::mem::size_of::<THREADENTRY32>() as u32;
+
+        let mut resumed = 0;
+        let mut more = unsafe { Thread32First(snapshot.0, &mut entry) } != 0;
+        while more {
+            if entry.th32OwnerProcessID == pid {
+                let thread = unsafe { OpenThread(THREAD_SUSPEND_RESUME, 0, entry.th32ThreadID) };
+                if !thread.is_null() {
+                    let thread = OwnedHandle(thread);
+                    if unsafe { ResumeThread(thread.0) } != u32::MAX {
+                        resumed += 1;
+                    }
+                }
+            }
+            more = unsafe { Thread32Next(snapshot.0, &mut entry) } != 0;
+        }
+
+        if resumed == 0 {
+            return Err(SandboxError::Setup {
+                operation: "resume threads".to_string(),
+                source: std::io::Error::other(format!("no thread of process {} resumed", pid)),
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Returns (peak job memory in bytes, total CPU time in µs, user CPU time in 100ns ticks).
+    fn read_stats(job: &OwnedHandle) -> (u64, u64, i64) {
+        let mut ext: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = unsafe { std::mem::zeroed() };
+        let mut acct: JOBOBJECT_BASIC_ACCOUNTING_INFORMATION = unsafe { std::mem::zeroed() };
+        unsafe {
+            QueryInformationJobObject(
+                job.0,
+                JobObjectExtendedLimitInformation,
+                &mut ext as *mut _ as *mut c_void,
+                std::mem::size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+                std::ptr::null_mut(),
+            );
+            QueryInformationJobObject(
+                job.0,
+                JobObjectBasicAccountingInformation,
+                &mut acct as *mut _ as *mut c_void,
+                std::mem::size_of::<JOBOBJECT_BASIC_ACCOUNTING_INFORMATION>() as u32,
+                std::ptr::null_mut(),
+            );
+        }
+        let cpu_us = ((acct.TotalUserTime + acct.TotalKernelTime) / 10) as u64;
+        (ext.PeakJobMemoryUsed as u64, cpu_us, acct.TotalUserTime)
+    }
+
+    /// Monitors a spawned child process, handles output capturing, applies timeouts,
+    /// and gathers the final execution results including resource usage.
+    async fn monitor_child(
+        job: &OwnedHandle,
+        child: &mut tokio::process::Child,
+        limits: &ExecutionLimits,
+    ) -> Result<StageResult> {
+        let stdout = child.stdout.take().ok_or_else(|| {
+            TurboError::Io(std::io::Error::new(
+                std::io::ErrorKind::BrokenPipe,
+                "Failed to capture stdout",
+            ))
+        })?;
+        let stderr = child.stderr.take().ok_or_else(|| {
+            TurboError::Io(std::io::Error::new(
+                std::io::ErrorKind::BrokenPipe,
+                "Failed to capture stderr",
+            ))
+        })?;
+
+        use tokio::io::AsyncReadExt;
+
+        let output_cap = limits.output_limit_bytes;
+
+        // Both pipes are read at once: a program filling stderr while stdout is still
+        // being read would otherwise block until its timeout
+        let read_task = tokio::spawn(async move {
+            let mut stdout_buf = Vec::new();
+            let mut stderr_buf = Vec::new();
+            let mut stdout = stdout.take(output_cap);
+            let mut stderr = stderr.take(output_cap);
+
+            let _ = tokio::join!(
+                stdout.read_to_end(&mut stdout_buf),
+                stderr.read_to_end(&mut stderr_buf)
+            );
+            (stdout_buf, stderr_buf)
+        });
+
+        let timeout_duration = std::time::Duration::from_millis(limits.timeout_ms);
+        let start_time = std::time::Instant::now();
+
+        tokio::select! {
+            res = child.wait() => {
+                let duration = start_time.elapsed().as_millis() as u64;
+                let status = res.map_err(TurboError::Io)?;
+                let (stdout_bytes, stderr_bytes) = read_task.await.unwrap_or_else(|_| (Vec::new(), Vec::new()));
+                let (mem_peak, cpu_time_us, user_ticks) = Self::read_stats(job);
+
+                let final_status = if status.success() {
+                    StageStatus::Success
+                } else if limits.timeout_ms > 0 && user_ticks >= (limits.timeout_ms * 10_000) as i64 {
+                    StageStatus::TimeLimitExceeded
+                } else if limits.memory_limit_bytes > 0 && mem_peak >= limits.memory_limit_bytes {
+                    StageStatus::MemoryLimitExceeded
+                } else {
+                    StageStatus::RuntimeError
+                };
+                let termination_reason = match final_status {
+                    StageStatus::TimeLimitExceeded => TerminationReason::TimeLimit,
+                    StageStatus::MemoryLimitExceeded => TerminationReason::MemoryLimit,
+                    _ => exception_reason(status.code()),
+                };
+
+                Ok(StageResult {
+                    status: final_status,
+                    stdout: limits.output_encoding.encode(&stdout_bytes),
+                    stderr: limits.output_encoding.encode(&stderr_bytes),
+                    exit_code: status.code(),
+                    signal: None,
+                    memory_usage: Some(mem_peak),
+                    cpu_time: Some(cpu_time_us),
+                    execution_time: Some(duration),
+                    termination_reason: Some(termination_reason),
+                    io_read_bytes: None,
+                    io_write_bytes: None,
+                    samples: None,
+                    perf_counters: None,
+                })
+            },
+            _ = tokio::time::sleep(timeout_duration) => {
+                // Terminating the job kills the whole process tree, not only the direct child.
+                unsafe {
+                    TerminateJobObject(job.0, 1);
+                }
+                let _ = child.wait().await;
+
+                let duration = start_time.elapsed().as_millis() as u64;
+                let (stdout_bytes, stderr_bytes) = read_task.await.unwrap_or_else(|_| (Vec::new(), Vec::new()));
+                let (mem_peak, cpu_time_us, _) = Self::read_stats(job);
+
+                Ok(StageResult {
+                    status: StageStatus::TimeLimitExceeded,
+                    stdout: limits.output_encoding.encode(&stdout_bytes),
+                    stderr: limits.output_encoding.encode(&stderr_bytes),
+                    exit_code: None,
+                    signal: None,
+                    memory_usage: Some(mem_peak),
+                    cpu_time: Some(cpu_time_us),
+                    execution_time: Some(duration),
+                    termination_reason: Some(TerminationReason::TimeLimit),
+                    io_read_bytes: None,
+                    io_write_bytes: None,
+                    samples: None,
+                    perf_counters: None,
+                })
+            }
+        }
+    }
+}
+
+/// Reason for a process that exited with `code`. A process killed by an unhandled
+/// exception exits with the exception's NTSTATUS code.
+fn exception_reason(code: Option<i32>) -> TerminationReason {
+    match code.map(|c| c as u32) {
+        // Access violation, in-page error, stack overflow
+        Some(0xC000_0005 | 0xC000_0006 | 0xC000_00FD) => TerminationReason::SegmentationFault,
+        // Integer division by zero and overflow, floating-point faults
+        Some(0xC000_0094 | 0xC000_0095 | 0xC000_008C..=0xC000_0093) => {
+            TerminationReason::ArithmeticError
+        }
+        // Fail-fast, which abort() and std::terminate use
+        Some(0xC000_0409) => TerminationReason::Aborted,
+        // Illegal and privileged instructions
+        Some(0xC000_001D | 0xC000_0096) => TerminationReason::IllegalInstruction,
+        _ => TerminationReason::Exited,
+    }
+}
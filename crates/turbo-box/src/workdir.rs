@@ -0,0 +1,164 @@
+use std::ffi::OsStr;
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use turbo_core::{Result, SandboxError, TurboError};
+
+/// Validate (creating it if needed) the root directory under which sandboxes keep
+/// their working state. Called once at startup so misconfiguration fails fast.
+pub fn init_root(root: &Path) -> Result<()> {
+    let setup_failed = |operation: &str, source: std::io::Error| SandboxError::Setup {
+        operation: format!("{} sandbox root {:?}", operation, root),
+        source,
+    };
+
+    std::fs::create_dir_all(root).map_err(|e| setup_failed("create", e))?;
+    if !root.is_dir() {
+        return Err(setup_failed("use", std::io::Error::other("not a directory")).into());
+    }
+
+    // Probe writability now instead of failing on the first job
+    let probe = root.join(".turbo-probe");
+    std::fs::write(&probe, b"").map_err(|e| setup_failed("write to", e))?;
+    let _ = std::fs::remove_file(&probe);
+    Ok(())
+}
+
+/// Path of a sandbox's working directory, refusing ids that would escape `root`.
+pub fn job_dir(root: &Path, id: &str) -> Result<PathBuf> {
+    let mut components = Path::new(id).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) => Ok(root.join(id)),
+        _ => Err(SandboxError::Setup {
+            operation: format!("create working directory for sandbox {:?}", id),
+            source: std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "sandbox id must be a single path component",
+            ),
+        }
+        .into()),
+    }
+}
+
+/// Names of the directories in `dir` last modified at least `min_age` ago.
+pub async fn stale_dirs(dir: &Path, min_age: Duration) -> std::io::Result<Vec<String>> {
+    let mut names = Vec::new();
+    if !dir.exists() {
+        return Ok(names);
+    }
+    let now = SystemTime::now();
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let Ok(metadata) = entry.metadata().await else {
+            continue;
+        };
+        let age = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| now.duration_since(modified).ok())
+            .unwrap_or_default();
+        if metadata.is_dir() && age >= min_age {
+            if let Some(name) = entry.file_name().to_str() {
+                names.push(name.to_string());
+            }
+        }
+    }
+    Ok(names)
+}
+
+/// Empty a working directory in place, keeping the directory itself.
+pub async fn clear_dir(dir: &Path) -> std::io::Result<()> {
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        if entry.file_type().await?.is_dir() {
+            tokio::fs::remove_dir_all(entry.path()).await?;
+        } else {
+            tokio::fs::remove_file(entry.path()).await?;
+        }
+    }
+    Ok(())
+}
+
+/// A file or directory in a sandbox's working directory, from `Sandbox::list_dir`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    pub name: String,
+    pub is_dir: bool,
+    /// Size in bytes; 0 for directories
+    pub size: u64,
+}
+
+/// Write `contents` to `path` under `dir`, creating missing parent directories and
+/// replacing an existing file. A symlink at `path` is replaced rather than written
+/// through, and a symlink among its parents is refused, so nothing outside `dir` is
+/// touched.
+pub async fn write_file(dir: &Path, path: &Path, contents: &[u8]) -> Result<()> {
+    let names = relative_names(path)?;
+    let Some((name, parents)) = names.split_last() else {
+        return Err(SandboxError::InvalidPath(path.to_path_buf()).into());
+    };
+    let mut target = walk(dir, path, parents, true).await?;
+    target.push(name);
+    if tokio::fs::symlink_metadata(&target)
+        .await
+        .is_ok_and(|m| m.file_type().is_symlink())
+    {
+        tokio::fs::remove_file(&target).await?;
+    }
+    tokio::fs::write(&target, contents).await?;
+    Ok(())
+}
+
+/// Contents of the file at `path` under `dir`, refusing paths through symlinks.
+pub async fn read_file(dir: &Path, path: &Path) -> Result<Vec<u8>> {
+    let target = walk(dir, path, &relative_names(path)?, false).await?;
+    Ok(tokio::fs::read(target).await?)
+}
+
+/// Entries of the directory at `path` under `dir` (`""` for `dir` itself), sorted by
+/// name.
+pub async fn list_dir(dir: &Path, path: &Path) -> Result<Vec<DirEntry>> {
+    let target = walk(dir, path, &relative_names(path)?, false).await?;
+    let mut listed = Vec::new();
+    let mut entries = tokio::fs::read_dir(target).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let metadata = tokio::fs::symlink_metadata(entry.path()).await?;
+        listed.push(DirEntry {
+            name: entry.file_name().to_string_lossy().into_owned(),
+            is_dir: metadata.is_dir(),
+            size: if metadata.is_dir() { 0 } else { metadata.len() },
+        });
+    }
+    listed.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(listed)
+}
+
+/// Components of a relative `path`, refusing absolute paths and `..`.
+fn relative_names(path: &Path) -> Result<Vec<&OsStr>> {
+    path.components()
+        .filter(|c| *c != Component::CurDir)
+        .map(|c| match c {
+            Component::Normal(name) => Ok(name),
+            _ => Err(TurboError::from(SandboxError::InvalidPath(
+                path.to_path_buf(),
+            ))),
+        })
+        .collect()
+}
+
+/// `dir` joined with `names`, each of which must be a directory rather than a symlink
+/// (or, with `create`, is created if missing); the last may also be a file.
+async fn walk(dir: &Path, path: &Path, names: &[&OsStr], create: bool) -> Result<PathBuf> {
+    let mut current = dir.to_path_buf();
+    for (i, name) in names.iter().enumerate() {
+        current.push(name);
+        match tokio::fs::symlink_metadata(&current).await {
+            Ok(m) if m.is_dir() || (!create && m.is_file() && i + 1 == names.len()) => {}
+            Ok(_) => return Err(SandboxError::InvalidPath(path.to_path_buf()).into()),
+            Err(e) if create && e.kind() == std::io::ErrorKind::NotFound => {
+                tokio::fs::create_dir(&current).await?
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(current)
+}
@@ -0,0 +1,56 @@
+//! Runs whose programs leave processes behind must come back without waiting for them.
+//! These need root and cgroup v2, so they are ignored by default:
+//! `sudo -E cargo test -p turbo-box --test kill_tree -- --ignored`.
+#![cfg(target_os = "linux")]
+
+use std::time::Duration;
+use turbo_box::linux::LinuxSandbox;
+use turbo_box::traits::{CommandSpec, Sandbox};
+use turbo_core::models::{ExecutionLimits, StageStatus};
+use turbo_core::StageResult;
+
+/// Runs `script` under `sh -c` in a fresh sandbox and reports how long the run took to
+/// come back.
+async fn run_script(id: &str, script: &str, timeout_ms: u64) -> (StageResult, Duration) {
+    let box_impl = LinuxSandbox::new("/tmp/turbo-root".to_string()).with_user(None, None, true);
+    box_impl.init(id).await.expect("init sandbox");
+
+    let limits = ExecutionLimits {
+        timeout_ms,
+        ..Default::default()
+    };
+    let args = ["-c".to_string(), script.to_string()];
+    let spec = CommandSpec {
+        cmd: "sh",
+        args: &args,
+        ..Default::default()
+    };
+    let started = std::time::Instant::now();
+    let result = box_impl.run(id, spec, Some(limits)).await;
+    let elapsed = started.elapsed();
+    box_impl.cleanup(id).await.expect("clean up sandbox");
+    (result.expect("run script"), elapsed)
+}
+
+#[tokio::test]
+#[ignore = "needs root and cgroup v2"]
+async fn timeout_kills_grandchildren_and_keeps_output() {
+    // Grandchildren inherit stdout; the run must not wait for them
+    let script = "sleep 30 & sleep 30 & echo started; wait";
+    let (result, elapsed) = run_script("kill_tree_timeout", script, 1000).await;
+    assert_eq!(result.status, StageStatus::TimeLimitExceeded);
+    assert!(
+        result.stdout.contains("started"),
+        "stdout {:?}",
+        result.stdout
+    );
+    assert!(elapsed < Duration::from_secs(5), "took {:?}", elapsed);
+}
+
+#[tokio::test]
+#[ignore = "needs root and cgroup v2"]
+async fn exit_does_not_wait_for_background_children() {
+    let (result, elapsed) = run_script("kill_tree_exit", "sleep 30 & echo done", 10000).await;
+    assert_eq!(result.status, StageStatus::Success);
+    assert!(elapsed < Duration::from_secs(5), "took {:?}", elapsed);
+}
@@ -0,0 +1,217 @@
+use crate::error::{ClientError, Result};
+use futures_util::Stream;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use std::time::Duration;
+use turbo_core::models::{JobAccepted, JobRequest, JobResult, JobState, JobStatus, Runtime};
+use uuid::Uuid;
+
+const API_KEY_HEADER: &str = "x-turbo-api-key";
+const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// How requests that failed in a way worth retrying (connection errors, timeouts, `429`,
+/// `502`, `503` and `504`) are retried. The wait doubles after each attempt.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Attempts after the first; 0 disables retries
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Client for one Turbo server.
+///
+/// Submissions are sent with an idempotency key (the request's `idempotency_key`, or a
+/// fresh one), so a retried submission never runs the job twice.
+#[derive(Debug, Clone)]
+pub struct TurboClient {
+    http: reqwest::Client,
+    base_url: String,
+    api_key: Option<String>,
+    retry: RetryPolicy,
+}
+
+impl TurboClient {
+    /// Client for the server at `base_url`, e.g. `http://localhost:3000`.
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key: None,
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    /// Send `key` as `X-Turbo-Api-Key` with every request.
+    pub fn with_api_key(mut self, key: impl Into<String>) -> Self {
+        self.api_key = Some(key.into());
+        self
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Use `http` for requests, e.g. one with timeouts or TLS settings of its own.
+    pub fn with_http_client(mut self, http: reqwest::Client) -> Self {
+        self.http = http;
+        self
+    }
+
+    /// Queue `req` without waiting for it (`POST /api/v1/jobs`).
+    pub async fn submit(&self, req: &JobRequest) -> Result<JobAccepted> {
+        let key = idempotency_key(req);
+        let url = self.url("/api/v1/jobs");
+        let res = self
+            .send(|| {
+                self.http
+                    .post(&url)
+                    .header(IDEMPOTENCY_KEY_HEADER, &key)
+                    .json(req)
+            })
+            .await?;
+        Ok(res.json().await?)
+    }
+
+    /// Run `req` and return its result once it finishes (`POST /api/v1/execute`).
+    pub async fn execute_and_wait(&self, req: &JobRequest) -> Result<JobResult> {
+        let key = idempotency_key(req);
+        let url = self.url("/api/v1/execute");
+        let res = self
+            .send(|| {
+                self.http
+                    .post(&url)
+                    .header(IDEMPOTENCY_KEY_HEADER, &key)
+                    .json(req)
+            })
+            .await?;
+        Ok(res.json().await?)
+    }
+
+    /// Lifecycle of job `id`, with its result once it has one (`GET /api/v1/jobs/{id}`).
+    pub async fn get_job(&self, id: &str) -> Result<JobStatus> {
+        let url = self.url(&format!("/api/v1/jobs/{}", id));
+        let res = self.send(|| self.http.get(&url)).await?;
+        Ok(res.json().await?)
+    }
+
+    /// Progress of job `id`, polled every `poll_interval`: its status each time its state
+    /// changes, ending with the terminal one, which carries the result. The server only
+    /// has a job's output and usage once it finishes, as it has no endpoint streaming a
+    /// running job. The stream ends after yielding an error.
+    pub fn watch_job<'a>(
+        &'a self,
+        id: &'a str,
+        poll_interval: Duration,
+    ) -> impl Stream<Item = Result<JobStatus>> + 'a {
+        // State: the last state yielded, and whether to wait before the next poll
+        futures_util::stream::unfold(Some((None, false)), move |state| async move {
+            let (mut last, mut wait): (Option<JobState>, bool) = state?;
+            loop {
+                if wait {
+                    tokio::time::sleep(poll_interval).await;
+                }
+                wait = true;
+                let status = match self.get_job(id).await {
+                    Ok(status) => status,
+                    Err(e) => return Some((Err(e), None)),
+                };
+                if last == Some(status.state) {
+                    continue;
+                }
+                last = Some(status.state);
+                let next = (!status.state.is_terminal()).then_some((last, wait));
+                return Some((Ok(status), next));
+            }
+        })
+    }
+
+    /// Every registered runtime (`GET /api/v1/runtimes`).
+    pub async fn list_runtimes(&self) -> Result<Vec<Runtime>> {
+        let url = self.url("/api/v1/runtimes");
+        let res = self.send(|| self.http.get(&url)).await?;
+        Ok(res.json().await?)
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    /// Send the request `build` makes, retried per the retry policy; the response if it
+    /// succeeded, else the server's error.
+    async fn send(&self, build: impl Fn() -> RequestBuilder) -> Result<Response> {
+        let mut backoff = self.retry.initial_backoff;
+        let mut attempt = 0;
+        loop {
+            let mut request = build();
+            if let Some(key) = &self.api_key {
+                request = request.header(API_KEY_HEADER, key);
+            }
+            let sent = request.send().await;
+            let retryable = match &sent {
+                Ok(res) => is_retryable(res.status()),
+                Err(e) => e.is_connect() || e.is_timeout(),
+            };
+            if !retryable || attempt >= self.retry.max_retries {
+                return check_response(sent?).await;
+            }
+            attempt += 1;
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(self.retry.max_backoff);
+        }
+    }
+}
+
+/// Key a submission of `req` is sent with.
+fn idempotency_key(req: &JobRequest) -> String {
+    req.idempotency_key
+        .clone()
+        .unwrap_or_else(|| Uuid::new_v4().to_string())
+}
+
+fn is_retryable(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+/// `res` if it succeeded, else an error with the server's message.
+async fn check_response(res: Response) -> Result<Response> {
+    let status = res.status();
+    if status.is_success() {
+        return Ok(res);
+    }
+    let header_id = res
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    let body = res.text().await.unwrap_or_default();
+    let json: Option<serde_json::Value> = serde_json::from_str(&body).ok();
+    let field = |name: &str| {
+        json.as_ref()
+            .and_then(|v| v.get(name))
+            .and_then(|v| v.as_str())
+            .map(str::to_string)
+    };
+    Err(ClientError::Api {
+        status,
+        message: field("message").unwrap_or(body),
+        request_id: field("request_id").or(header_id),
+    })
+}
@@ -0,0 +1,20 @@
+use reqwest::StatusCode;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ClientError {
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+
+    /// The server answered with an error status
+    #[error("Server returned {status}: {message}")]
+    Api {
+        status: StatusCode,
+        /// `message` of the error body, else the body as sent
+        message: String,
+        /// Server-side ID of the failed request, for finding it in the server's logs
+        request_id: Option<String>,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, ClientError>;
@@ -0,0 +1,30 @@
+//! Typed async client for the Turbo HTTP API.
+//!
+//! ```no_run
+//! # async fn run() -> turbo_client::Result<()> {
+//! use turbo_client::TurboClient;
+//! use turbo_client::models::{FileRequest, JobRequest};
+//!
+//! let client = TurboClient::new("http://localhost:3000");
+//! let result = client
+//!     .execute_and_wait(&JobRequest {
+//!         language: "python".to_string(),
+//!         files: vec![FileRequest {
+//!             name: Some("main.py".to_string()),
+//!             content: "print('hi')".to_string(),
+//!             encoding: None,
+//!             url: None,
+//!         }],
+//!         ..Default::default()
+//!     })
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod client;
+pub mod error;
+
+pub use client::{RetryPolicy, TurboClient};
+pub use error::{ClientError, Result};
+pub use turbo_core::models;
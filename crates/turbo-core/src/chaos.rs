@@ -0,0 +1,87 @@
+//! Fault injection for integration and load tests.
+//!
+//! Only compiled with the `chaos` feature. Each fault fires with a probability read from
+//! the environment on first use, and draws come from a PRNG seeded by `TURBO_CHAOS_SEED`
+//! so a failing run can be replayed:
+//!
+//! | Variable | Effect |
+//! |---|---|
+//! | `TURBO_CHAOS_DROP_RESULT` | probability a finished job's result is never published |
+//! | `TURBO_CHAOS_FAIL_CGROUP_WRITE` | probability a cgroup file write fails |
+//! | `TURBO_CHAOS_POP_DELAY_MS` | delay before every queue pop |
+//! | `TURBO_CHAOS_SEED` | PRNG seed (default 0) |
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// A point where a fault can be injected.
+#[derive(Debug, Clone, Copy)]
+pub enum Fault {
+    DropResult,
+    FailCgroupWrite,
+}
+
+struct Settings {
+    drop_result: f64,
+    fail_cgroup_write: f64,
+    pop_delay: Option<Duration>,
+}
+
+static SETTINGS: OnceLock<Settings> = OnceLock::new();
+static STATE: OnceLock<AtomicU64> = OnceLock::new();
+
+fn settings() -> &'static Settings {
+    SETTINGS.get_or_init(|| {
+        let probability = |name: &str| {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.parse::<f64>().ok())
+                .map_or(0.0, |p| p.clamp(0.0, 1.0))
+        };
+        Settings {
+            drop_result: probability("TURBO_CHAOS_DROP_RESULT"),
+            fail_cgroup_write: probability("TURBO_CHAOS_FAIL_CGROUP_WRITE"),
+            pop_delay: std::env::var("TURBO_CHAOS_POP_DELAY_MS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .map(Duration::from_millis),
+        }
+    })
+}
+
+/// Next value in [0, 1) from a shared splitmix64 sequence.
+fn next_unit() -> f64 {
+    let state = STATE.get_or_init(|| {
+        let seed = std::env::var("TURBO_CHAOS_SEED")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(0);
+        AtomicU64::new(seed)
+    });
+    let mut z = state
+        .fetch_add(0x9E37_79B9_7F4A_7C15, Ordering::Relaxed)
+        .wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^= z >> 31;
+    (z >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Whether `fault` should fire at this call site.
+pub fn inject(fault: Fault) -> bool {
+    let probability = match fault {
+        Fault::DropResult => settings().drop_result,
+        Fault::FailCgroupWrite => settings().fail_cgroup_write,
+    };
+    let fire = probability > 0.0 && next_unit() < probability;
+    if fire {
+        tracing::warn!("Injecting fault {:?}", fault);
+    }
+    fire
+}
+
+/// Delay to apply before a queue pop, if configured.
+pub fn pop_delay() -> Option<Duration> {
+    settings().pop_delay
+}
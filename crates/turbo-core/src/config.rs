@@ -1,14 +1,217 @@
-use serde::Deserialize;
+use crate::models::Priority;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::PathBuf;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct TurboConfig {
     pub server: ServerConfig,
     pub sandbox: SandboxConfig,
     pub redis: RedisConfig,
     pub paths: PathsConfig,
+    pub priority: PriorityConfig,
+    pub results: ResultsConfig,
+    pub limits: LimitsConfig,
+    pub max_limits: MaxLimitsConfig,
+    #[serde(default)]
+    pub audit: AuditConfig,
+    pub workspaces: WorkspacesConfig,
+    pub fetch: FetchConfig,
 }
 
-#[derive(Debug, Deserialize)]
+/// Source files the server fetches from a `url` before queueing a job.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FetchConfig {
+    /// Hosts, and for `s3://` URLs buckets, files may be fetched from; `*` allows any.
+    /// Files with a `url` are refused when empty
+    pub allowed_hosts: Vec<String>,
+    /// Most bytes fetched for one file
+    pub max_bytes: usize,
+    /// Directory this server caches fetched files in
+    pub cache_dir: String,
+    /// Seconds a fetched file is reused before it is fetched again
+    pub cache_ttl_secs: u64,
+    pub s3: S3Config,
+}
+
+/// Where `s3://bucket/key` URLs are fetched from, and the credentials to sign with.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct S3Config {
+    pub region: String,
+    /// S3-compatible endpoint, e.g. MinIO; AWS's endpoint for `region` when unset
+    pub endpoint: Option<String>,
+    /// Falls back to `AWS_ACCESS_KEY_ID`
+    pub access_key_id: Option<String>,
+    /// Falls back to `AWS_SECRET_ACCESS_KEY`
+    pub secret_access_key: Option<String>,
+}
+
+/// Projects uploaded once and referenced by later submissions.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkspacesConfig {
+    /// Seconds a workspace is kept after it was last created, updated or used
+    pub ttl_secs: u64,
+    /// Most bytes of file content one workspace may hold
+    pub max_bytes: usize,
+}
+
+/// Audit trail of executed jobs.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AuditConfig {
+    /// SQLite database each worker appends executed jobs to. No audit log when unset
+    pub path: Option<String>,
+}
+
+/// The most any job stage may use, whatever the request asks for.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaxLimitsConfig {
+    /// Longest timeout; defaults to `sandbox.max_timeout_ms`. No maximum when neither is set
+    pub timeout_ms: Option<u64>,
+    /// Most memory; defaults to `sandbox.memory_limit_mb`
+    pub memory_limit_mb: Option<u64>,
+    /// Most processes and threads at once; no maximum when unset
+    pub pid_limit: Option<u64>,
+    /// Most bytes of stdout (and of stderr) kept; no maximum when unset
+    pub output_limit_bytes: Option<u64>,
+    /// Refuse requests above a maximum with `422` instead of clamping them
+    pub reject: bool,
+}
+
+impl MaxLimitsConfig {
+    /// These maximums with an unset timeout or memory maximum taken from the `sandbox` keys
+    /// that predate this section.
+    pub fn or_sandbox(&self, sandbox: &SandboxConfig) -> Self {
+        Self {
+            timeout_ms: self.timeout_ms.or(sandbox.max_timeout_ms),
+            memory_limit_mb: self.memory_limit_mb.or(Some(sandbox.memory_limit_mb)),
+            ..self.clone()
+        }
+    }
+}
+
+/// Limits for job stages that neither the request nor the runtime's `package.yaml` set.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LimitsConfig {
+    /// Run (and testcase) timeout
+    pub run_timeout_ms: u64,
+    /// Compile timeout
+    pub compile_timeout_ms: u64,
+    /// Memory for each stage
+    pub memory_limit_mb: u64,
+    /// Processes and threads each stage may have at once
+    pub pid_limit: u64,
+    /// Bytes of stdout (and of stderr) kept from each stage; the rest is cut off
+    pub output_limit_bytes: u64,
+    /// Language (runtime name or alias) -> defaults for its jobs, from `[limits.<language>]`
+    /// tables. Filled in by `TurboConfig::with_overrides`.
+    #[serde(flatten, skip_deserializing)]
+    pub languages: HashMap<String, LanguageLimits>,
+}
+
+/// Defaults for one language's jobs; they take precedence over the runtime's `package.yaml`
+/// but not over the request.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LanguageLimits {
+    /// Run (and testcase) timeout in ms
+    pub run_timeout: Option<u64>,
+    /// Compile timeout in ms
+    pub compile_timeout: Option<u64>,
+    /// Memory for the run and compile stages: bytes, or a size such as `"256MB"`
+    #[serde(default, deserialize_with = "deserialize_size")]
+    pub memory: Option<u64>,
+}
+
+impl LanguageLimits {
+    /// Keys a `[limits.<language>]` table may set.
+    pub const KEYS: [&'static str; 3] = ["run_timeout", "compile_timeout", "memory"];
+}
+
+/// Bytes in `size`: a plain number, or one followed by `B`, `K`/`KB`/`KiB`, `M`/`MB`/`MiB`
+/// or `G`/`GB`/`GiB` (all powers of 1024, matching `memory_limit_mb`), e.g. `256MB`.
+pub fn parse_size(size: &str) -> Option<u64> {
+    let size = size.trim();
+    let split = size
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+    let shift = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 0,
+        "K" | "KB" | "KIB" => 10,
+        "M" | "MB" | "MIB" => 20,
+        "G" | "GB" | "GIB" => 30,
+        _ => return None,
+    };
+    number.parse::<u64>().ok()?.checked_mul(1 << shift)
+}
+
+/// A size given as a number of bytes or as a string for `parse_size`.
+fn deserialize_size<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Size {
+        Bytes(u64),
+        Text(String),
+    }
+    match Option::<Size>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(Size::Bytes(bytes)) => Ok(Some(bytes)),
+        Some(Size::Text(text)) => parse_size(&text).map(Some).ok_or_else(|| {
+            serde::de::Error::custom(format!("invalid size {:?}, expected e.g. \"256MB\"", text))
+        }),
+    }
+}
+
+/// How long finished job results are kept and where large ones are stored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResultsConfig {
+    /// Seconds a result (and the job's lifecycle record) is kept after it is published
+    pub ttl_secs: u64,
+    /// Results whose JSON exceeds this many bytes are written to `offload_dir` and Redis
+    /// only stores a pointer. No offloading when unset.
+    pub offload_threshold_bytes: Option<usize>,
+    /// Directory for offloaded results; must be shared by all servers (e.g. an NFS or
+    /// object storage mount)
+    pub offload_dir: String,
+    /// Seconds a finished result is reused for identical submissions instead of running
+    /// them again. No deduplication when unset.
+    pub dedup_window_secs: Option<u64>,
+    /// Seconds an `Idempotency-Key` is remembered; at most `ttl_secs`, which is the
+    /// default, so the first submission's result outlives the key
+    pub idempotency_ttl_secs: Option<u64>,
+}
+
+impl ResultsConfig {
+    /// Seconds an `Idempotency-Key` is remembered.
+    pub fn idempotency_ttl_secs(&self) -> u64 {
+        self.idempotency_ttl_secs.unwrap_or(self.ttl_secs)
+    }
+}
+
+/// Which job priorities callers may request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PriorityConfig {
+    /// Highest priority for requests without a known API key
+    pub default_max: Priority,
+    /// API key (sent as `X-Turbo-Api-Key`) -> highest priority it may request
+    #[serde(default)]
+    pub keys: HashMap<String, Priority>,
+}
+
+impl PriorityConfig {
+    /// Highest priority allowed for a request carrying `api_key`.
+    pub fn max_for(&self, api_key: Option<&str>) -> Priority {
+        api_key
+            .and_then(|key| self.keys.get(key))
+            .copied()
+            .unwrap_or(self.default_max)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct PathsConfig {
     /// Directory where runtimes are installed (e.g., /home/user/.turbo)
     pub turbo_home: String,
@@ -16,43 +219,358 @@ pub struct PathsConfig {
     pub packages_path: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct ServerConfig {
     pub host: String,
     pub port: u16,
     pub log_level: String,
+    /// Number of queue workers; defaults to `sandbox.max_concurrent_jobs`
+    pub workers: Option<usize>,
+    /// Tells this process's workers apart from other processes' on the same host, e.g.
+    /// "api" or "gpu-1". Set, worker names stay the same across restarts, so a restarted
+    /// process requeues the jobs it was cut off from at once; unset, a random id is taken
+    /// each start and those jobs wait for the dead-worker reaper
+    pub worker_id: Option<String>,
+    /// Serve HTTPS with this certificate instead of plain HTTP
+    pub tls: Option<TlsConfig>,
+    /// `unix:<path>` to serve on a Unix domain socket, or an `<ip>:<port>` to bind; every
+    /// interface on `port` when unset
+    pub listen: Option<String>,
+    /// Permissions of a `unix:` socket in octal, e.g. `"660"`; the umask decides when unset
+    pub socket_mode: Option<String>,
+    /// Largest request body accepted; larger ones are refused with `413`
+    pub max_body_bytes: usize,
+    /// Cross-origin access for browser clients
+    pub cors: CorsConfig,
+}
+
+/// Which web pages may call the API from a browser.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CorsConfig {
+    /// Origins (e.g. `https://play.example.com`) allowed, or `["*"]` for any. No CORS
+    /// headers are sent when empty.
+    pub allowed_origins: Vec<String>,
+    /// Methods allowed from those origins
+    pub allowed_methods: Vec<String>,
+}
+
+/// Where the API accepts connections.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Listen {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl ServerConfig {
+    /// Where to listen, from `listen` or else `port`.
+    pub fn listen_on(&self) -> Result<Listen, String> {
+        match self.listen.as_deref() {
+            None => Ok(Listen::Tcp(SocketAddr::from(([0, 0, 0, 0], self.port)))),
+            Some(listen) => match listen.strip_prefix("unix:") {
+                Some("") => Err("server.listen needs a socket path after \"unix:\"".to_string()),
+                Some(path) => Ok(Listen::Unix(PathBuf::from(path))),
+                None => listen.parse().map(Listen::Tcp).map_err(|_| {
+                    format!(
+                        "server.listen must be \"unix:<path>\" or \"<ip>:<port>\", not {:?}",
+                        listen
+                    )
+                }),
+            },
+        }
+    }
+
+    /// `socket_mode` as permission bits.
+    pub fn socket_mode(&self) -> Result<Option<u32>, String> {
+        self.socket_mode
+            .as_deref()
+            .map(|mode| {
+                u32::from_str_radix(mode, 8)
+                    .ok()
+                    .filter(|bits| *bits <= 0o777)
+                    .ok_or_else(|| format!("server.socket_mode must be octal, not {:?}", mode))
+            })
+            .transpose()
+    }
+}
+
+/// PEM files for HTTPS. Both are reloaded when they change.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TlsConfig {
+    /// Certificate chain, leaf first
+    pub cert: String,
+    /// Private key (PKCS#8, PKCS#1 or SEC1)
+    pub key: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct SandboxConfig {
+    /// Upper bound on sandboxes running jobs at once, across all workers
     pub max_concurrent_jobs: usize,
+    /// Most memory a job stage may request, unless `max_limits.memory_limit_mb` is set
     pub memory_limit_mb: u64,
+    /// Longest timeout a job stage may request, unless `max_limits.timeout_ms` is set
+    pub max_timeout_ms: Option<u64>,
+    /// Most sandboxes one job's testcases may run across at once (the default for jobs
+    /// that don't set `parallelism`)
+    pub max_testcase_parallelism: usize,
+    /// Longest a job may take from compile to its last run; also the default for jobs
+    /// that don't set `max_total_time_ms`. No budget when unset
+    pub max_total_time_ms: Option<u64>,
+    /// Most measured runs, and most warmup runs, a `benchmark` job may ask for
+    pub max_benchmark_iterations: u32,
+    /// Sandbox backend to use: "linux", "windows", "nsjail", "isolate" or "mock"
+    pub backend: String,
+    /// Root path where the sandbox keeps per-job working state
+    pub root_path: String,
+    /// Cgroup (v2) directory the "linux" backend creates its sandboxes' cgroups in, e.g.
+    /// under a systemd-delegated slice. Detected when unset
+    pub cgroup_path: Option<String>,
+    /// User the "linux" and "nsjail" backends run sandboxed processes as ("isolate" runs
+    /// them as each box's own user)
+    pub uid: Option<u32>,
+    /// Group the "linux" and "nsjail" backends run sandboxed processes as
+    pub gid: Option<u32>,
+    /// Let the "linux" backend run sandboxed processes as root when no `uid` is set and
+    /// the server runs as root. Refused by default
+    pub allow_root: bool,
+    /// Settings for the "nsjail" and "isolate" backends
+    pub external: ExternalSandboxConfig,
+    /// Settings for the "mock" backend
+    pub mock: MockSandboxConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExternalSandboxConfig {
+    /// Path of the `nsjail` or `isolate` binary; looked up on `PATH` when unset
+    pub binary: Option<String>,
+    /// Host directories made visible (read-only) to sandboxed processes, e.g. the
+    /// runtimes directory. "nsjail" already sees the whole filesystem read-only
+    pub bind: Vec<String>,
+    /// isolate boxes this process uses, with ids from `first_box` on. Processes sharing
+    /// a host (a server and its turbo-workers) need disjoint ranges, all below
+    /// isolate's `num_boxes`
+    pub boxes: u32,
+    /// First isolate box id this process uses
+    pub first_box: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MockSandboxConfig {
+    /// Stdout returned for every run
+    pub stdout: String,
+    /// Exit code returned for every run
+    pub exit_code: i32,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct RedisConfig {
     pub url: String,
+    /// Seconds a worker may hold a job before it is assumed dead and the job requeued.
+    /// Must exceed the longest job (compile plus all testcase runs).
+    pub visibility_timeout_secs: u64,
+    /// `list` (default) or `stream`, which reads the queue through a Redis Streams
+    /// consumer group so external worker processes can share it.
+    pub queue_mode: String,
 }
 
+/// Config file in the working directory that `turbo config set` writes. `turbo.json` or
+/// `turbo.yaml` are read too, but not written.
+pub const CONFIG_FILE: &str = "turbo.toml";
+
 impl TurboConfig {
     pub fn new() -> Result<Self, config::ConfigError> {
-        let builder = config::Config::builder()
+        Self::with_overrides(&[])
+    }
+
+    /// Like `new`, with `overrides` (dotted key, value) taking precedence over every
+    /// source; values are converted to the field's type.
+    pub fn with_overrides(overrides: &[(&str, &str)]) -> Result<Self, config::ConfigError> {
+        let mut builder = config::Config::builder()
             // Start with defaults
             .set_default("server.host", "0.0.0.0")?
             .set_default("server.port", 4000)?
             .set_default("server.log_level", "INFO")?
+            .set_default("server.max_body_bytes", 2 * 1024 * 1024)?
+            .set_default("server.cors.allowed_origins", Vec::<String>::new())?
+            .set_default(
+                "server.cors.allowed_methods",
+                vec!["GET", "POST", "PUT", "DELETE"],
+            )?
             .set_default("sandbox.max_concurrent_jobs", 64)?
             .set_default("sandbox.memory_limit_mb", 512)?
+            .set_default("sandbox.max_testcase_parallelism", 8)?
+            .set_default("sandbox.max_benchmark_iterations", 100)?
+            .set_default("sandbox.backend", default_sandbox_backend())?
+            .set_default("sandbox.root_path", "/var/turbo/sandbox")?
+            .set_default("sandbox.allow_root", false)?
+            .set_default("sandbox.external.bind", Vec::<String>::new())?
+            .set_default("sandbox.external.boxes", 1000)?
+            .set_default("sandbox.external.first_box", 0)?
+            .set_default("sandbox.mock.stdout", "")?
+            .set_default("sandbox.mock.exit_code", 0)?
             .set_default("redis.url", "redis://127.0.0.1:6379")?
+            .set_default("redis.visibility_timeout_secs", 600)?
+            .set_default("redis.queue_mode", "list")?
+            .set_default("priority.default_max", "normal")?
+            .set_default("results.ttl_secs", 3600)?
+            .set_default("results.offload_dir", "/var/turbo/results")?
             .set_default("paths.turbo_home", default_turbo_home())?
             .set_default("paths.packages_path", "./packages")?
+            .set_default("limits.run_timeout_ms", 3000)?
+            .set_default("limits.compile_timeout_ms", 10000)?
+            .set_default("limits.memory_limit_mb", 512)?
+            .set_default("limits.pid_limit", 256)?
+            .set_default("limits.output_limit_bytes", 1024)?
+            .set_default("max_limits.reject", false)?
+            .set_default("workspaces.ttl_secs", 86400)?
+            .set_default("workspaces.max_bytes", 16 * 1024 * 1024)?
+            .set_default("fetch.allowed_hosts", Vec::<String>::new())?
+            .set_default("fetch.max_bytes", 64 * 1024 * 1024)?
+            .set_default("fetch.cache_dir", "/var/turbo/fetch-cache")?
+            .set_default("fetch.cache_ttl_secs", 3600)?
+            .set_default("fetch.s3.region", "us-east-1")?
             // Merge turbo.toml if exists
             .add_source(config::File::with_name("turbo").required(false))
             // Merge environment variables (TURBO_*)
             .add_source(config::Environment::with_prefix("TURBO").separator("_"));
+        for (key, value) in overrides {
+            builder = builder.set_override(*key, *value)?;
+        }
 
-        builder.build()?.try_deserialize()
+        let config = builder.build()?;
+        let languages = language_limits(&config)?;
+        let mut turbo: Self = config.try_deserialize()?;
+        turbo.limits.languages = languages;
+        Ok(turbo)
     }
+
+    /// Check values the types alone don't constrain.
+    pub fn validate(&self) -> Result<(), config::ConfigError> {
+        let invalid = |message: String| Err(config::ConfigError::Message(message));
+        if !["list", "stream"].contains(&self.redis.queue_mode.as_str()) {
+            return invalid(format!(
+                "redis.queue_mode must be \"list\" or \"stream\", not {:?}",
+                self.redis.queue_mode
+            ));
+        }
+        let backends = ["linux", "windows", "nsjail", "isolate", "mock"];
+        if !backends.contains(&self.sandbox.backend.as_str()) {
+            return invalid(format!(
+                "sandbox.backend must be \"linux\", \"windows\", \"nsjail\", \"isolate\" or \
+                 \"mock\", not {:?}",
+                self.sandbox.backend
+            ));
+        }
+        let cgroup_path = self.sandbox.cgroup_path.as_deref();
+        if let Some(path) = cgroup_path.filter(|path| !path.starts_with('/')) {
+            return invalid(format!(
+                "sandbox.cgroup_path must be absolute, not {:?}",
+                path
+            ));
+        }
+        let listen = self
+            .server
+            .listen_on()
+            .map_err(config::ConfigError::Message)?;
+        self.server
+            .socket_mode()
+            .map_err(config::ConfigError::Message)?;
+        if matches!(listen, Listen::Unix(_)) && self.server.tls.is_some() {
+            return invalid("server.tls cannot be used with a unix: server.listen".to_string());
+        }
+        let positive = [
+            ("server.max_body_bytes", self.server.max_body_bytes as u64),
+            (
+                "sandbox.max_concurrent_jobs",
+                self.sandbox.max_concurrent_jobs as u64,
+            ),
+            ("sandbox.memory_limit_mb", self.sandbox.memory_limit_mb),
+            (
+                "sandbox.max_testcase_parallelism",
+                self.sandbox.max_testcase_parallelism as u64,
+            ),
+            (
+                "sandbox.max_benchmark_iterations",
+                self.sandbox.max_benchmark_iterations as u64,
+            ),
+            ("sandbox.external.boxes", self.sandbox.external.boxes as u64),
+            (
+                "redis.visibility_timeout_secs",
+                self.redis.visibility_timeout_secs,
+            ),
+            ("results.ttl_secs", self.results.ttl_secs),
+            ("limits.run_timeout_ms", self.limits.run_timeout_ms),
+            ("limits.compile_timeout_ms", self.limits.compile_timeout_ms),
+            ("limits.memory_limit_mb", self.limits.memory_limit_mb),
+            ("limits.pid_limit", self.limits.pid_limit),
+            ("limits.output_limit_bytes", self.limits.output_limit_bytes),
+            ("workspaces.ttl_secs", self.workspaces.ttl_secs),
+            ("workspaces.max_bytes", self.workspaces.max_bytes as u64),
+            ("fetch.max_bytes", self.fetch.max_bytes as u64),
+            ("fetch.cache_ttl_secs", self.fetch.cache_ttl_secs),
+        ];
+        for (key, value) in positive {
+            if value == 0 {
+                return invalid(format!("{} must be greater than 0", key));
+            }
+        }
+        let maximums = [
+            ("max_limits.timeout_ms", self.max_limits.timeout_ms),
+            (
+                "max_limits.memory_limit_mb",
+                self.max_limits.memory_limit_mb,
+            ),
+            ("max_limits.pid_limit", self.max_limits.pid_limit),
+            (
+                "max_limits.output_limit_bytes",
+                self.max_limits.output_limit_bytes,
+            ),
+        ];
+        for (key, value) in maximums {
+            if value == Some(0) {
+                return invalid(format!("{} must be greater than 0", key));
+            }
+        }
+        if self.results.idempotency_ttl_secs() == 0 {
+            return invalid("results.idempotency_ttl_secs must be greater than 0".to_string());
+        }
+        if self.results.idempotency_ttl_secs() > self.results.ttl_secs {
+            return invalid(format!(
+                "results.idempotency_ttl_secs must not exceed results.ttl_secs ({})",
+                self.results.ttl_secs
+            ));
+        }
+        for (language, limits) in &self.limits.languages {
+            let values = [limits.run_timeout, limits.compile_timeout, limits.memory];
+            for (key, value) in LanguageLimits::KEYS.iter().zip(values) {
+                if value == Some(0) {
+                    return invalid(format!(
+                        "limits.{}.{} must be greater than 0",
+                        language, key
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The `[limits.<language>]` tables, i.e. the entries of `limits` that are tables rather
+/// than `LimitsConfig` fields.
+fn language_limits(
+    config: &config::Config,
+) -> Result<HashMap<String, LanguageLimits>, config::ConfigError> {
+    let limits: HashMap<String, config::Value> = config.get("limits")?;
+    limits
+        .into_iter()
+        .filter(|(_, value)| matches!(value.kind, config::ValueKind::Table(_)))
+        .map(|(language, value)| {
+            let limits = value
+                .try_deserialize()
+                .map_err(|e| config::ConfigError::Message(format!("limits.{}: {}", language, e)))?;
+            Ok((language, limits))
+        })
+        .collect()
 }
 
 /// Returns a default turbo home directory.
@@ -67,3 +585,14 @@ fn default_turbo_home() -> String {
     // Fallback for when running as root with no HOME set
     "/var/lib/turbo".to_string()
 }
+
+/// The native backend for the target, or "mock" where turbo-box has none.
+fn default_sandbox_backend() -> &'static str {
+    if cfg!(windows) {
+        "windows"
+    } else if cfg!(target_os = "linux") {
+        "linux"
+    } else {
+        "mock"
+    }
+}
@@ -1,3 +1,4 @@
+use std::path::PathBuf;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -12,7 +13,7 @@ pub enum TurboError {
     Serialization(#[from] serde_json::Error),
 
     #[error("Sandbox error: {0}")]
-    Sandbox(String),
+    Sandbox(#[from] SandboxError),
 
     #[error("Compilation failed")]
     CompilationFailed,
@@ -28,3 +29,68 @@ pub enum TurboError {
 }
 
 pub type Result<T> = std::result::Result<T, TurboError>;
+
+/// Failures raised by sandbox backends, classified so callers can react per class
+/// (e.g. degrade to no-cgroup mode vs. fail the job vs. report unhealthy).
+#[derive(Error, Debug)]
+pub enum SandboxError {
+    #[error("Cgroup hierarchy unavailable at {path:?}: {source}")]
+    CgroupUnavailable {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("Permission denied to {operation} {path:?}")]
+    PermissionDenied { operation: String, path: PathBuf },
+
+    #[error(
+        "{0}; cgroup v2 is required: boot with systemd.unified_cgroup_hierarchy=1 (or \
+         cgroup_no_v1=all), or set sandbox.backend = \"mock\""
+    )]
+    CgroupV1(String),
+
+    #[error("Cgroup controller '{controller}' is not enabled at {path:?}")]
+    ControllerMissing { controller: String, path: PathBuf },
+
+    #[error("Failed to spawn '{command}': {source}")]
+    SpawnFailed {
+        command: String,
+        source: std::io::Error,
+    },
+
+    #[error("Failed to attach process to {target}: {source}")]
+    AttachFailed {
+        target: String,
+        source: std::io::Error,
+    },
+
+    #[error(
+        "Refusing to run sandboxed processes as root: set sandbox.uid and sandbox.gid to an \
+         unprivileged user, or sandbox.allow_root = true"
+    )]
+    RunAsRoot,
+
+    #[error("Sandbox {0} is not initialized")]
+    NotInitialized(String),
+
+    #[error("Sandbox backend '{0}' is not supported on this host")]
+    UnsupportedBackend(String),
+
+    #[error("Operation '{0}' is not supported by this sandbox backend")]
+    Unsupported(String),
+
+    #[error("Path {0:?} is not inside the sandbox's working directory")]
+    InvalidPath(PathBuf),
+
+    #[error("Sandbox pool is closed")]
+    PoolClosed,
+
+    #[error("Sandbox pool had a free permit but no free session")]
+    PoolSlotMissing,
+
+    #[error("Sandbox setup failed to {operation}: {source}")]
+    Setup {
+        operation: String,
+        source: std::io::Error,
+    },
+}
@@ -1,7 +1,9 @@
+#[cfg(feature = "chaos")]
+pub mod chaos;
 pub mod config;
 pub mod error;
 pub mod models;
 
 pub use config::TurboConfig;
-pub use error::{Result, TurboError};
+pub use error::{Result, SandboxError, TurboError};
 pub use models::*;
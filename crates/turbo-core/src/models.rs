@@ -1,7 +1,11 @@
+use base64::Engine;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct JobRequest {
+    /// Runtime name or alias; when empty or omitted, the server infers it from the first
+    /// file's shebang or extension
+    #[serde(default)]
     pub language: String,
     pub version: Option<String>,
     pub files: Vec<FileRequest>,
@@ -12,19 +16,428 @@ pub struct JobRequest {
     pub compile_timeout: Option<u64>,
     pub run_memory_limit: Option<u64>,
     pub compile_memory_limit: Option<u64>,
+    /// Bytes of stdout (and of stderr) kept from each stage
+    pub output_limit: Option<u64>,
+    /// Most processes and threads each stage may have at once
+    pub pid_limit: Option<u64>,
+    pub tty: Option<bool>, // Run attached to a pseudo-terminal (default: false)
+    /// Key of the logical entity this submission is for (e.g. an editor buffer). A newer
+    /// submission with the same key cancels this one if it is still queued.
+    pub supersedes: Option<String>,
+    /// Scheduling class (default: normal); limited per API key by the server's policy
+    pub priority: Option<Priority>,
+    /// Most testcases to run at once, each in its own sandbox; capped by
+    /// `sandbox.max_testcase_parallelism`, which is also the default
+    pub parallelism: Option<usize>,
+    /// Run no further testcases after the first failing one, reporting them as `Skipped`
+    /// (default: false)
+    pub stop_on_failure: Option<bool>,
+    /// Budget in milliseconds for compile plus every run; stages still pending when it
+    /// runs out are `Skipped`. Capped by `sandbox.max_total_time_ms`
+    pub max_total_time_ms: Option<u64>,
+    /// A retried submission with the same key (and API key) gets the first one's job
+    /// instead of running again; the `Idempotency-Key` header takes precedence
+    pub idempotency_key: Option<String>,
+    /// Workspace whose files the job runs with; `files` are saved to it first, replacing
+    /// same-named ones, so only changed files need sending
+    pub workspace: Option<String>,
+    /// Where the project comes from besides `files`, which are written over it
+    pub source: Option<JobSource>,
+    /// Run the program repeatedly and report timing statistics; not with `testcases`
+    pub benchmark: Option<BenchmarkRequest>,
+    /// How the run stage's stdout and stderr are returned (default: utf8)
+    pub output_encoding: Option<OutputEncoding>,
+    /// Record the run stage's memory and CPU usage every this many milliseconds (at least
+    /// 10), returned as `samples`
+    pub sample_interval_ms: Option<u64>,
+    /// Count the run stage's instructions, cache misses and branch misses, returned as
+    /// `perf_counters`
+    #[serde(default)]
+    pub profile: Option<bool>,
+    /// IANA time zone, e.g. "Europe/Berlin", exported to every stage as `TZ`
+    pub timezone: Option<String>,
+    /// Locale, e.g. "C.UTF-8", exported to every stage as `LANG` and `LC_ALL`
+    pub locale: Option<String>,
+    /// Opaque caller data, e.g. the caller's own submission ID, returned unchanged in
+    /// the job's result and webhook events
+    pub metadata: Option<serde_json::Value>,
+    /// Old calling convention: `args` name the file to run, and the runtime's scripts get
+    /// no `TURBO_ENTRYPOINT`/`TURBO_FILES` (default: false)
+    pub legacy_args: Option<bool>,
+    /// Parse compiler messages out of stderr into the result's `diagnostics`
+    /// (default: false)
+    pub diagnostics: Option<bool>,
+    /// Run the program under a memory checker and report what it finds in the result's
+    /// `analysis`; ignored on runtimes whose package does not declare the tool
+    pub analysis: Option<AnalysisTool>,
+    /// Program the run talks to over its stdin and stdout, as in interactive problems;
+    /// not with `stdin`, `testcases`, `benchmark` or `tty`
+    pub interactor: Option<Interactor>,
+}
+
+/// A judge-provided program run alongside the job's program, each one's stdout relayed
+/// to the other's stdin. Its own exit code is the verdict on the exchange.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Interactor {
+    /// Command run in the job's working directory, e.g. "python3"; not through a shell
+    pub cmd: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// How messages are relayed (default: line)
+    #[serde(default)]
+    pub buffering: Buffering,
+    /// Longest the two may go without a message while neither uses CPU, in ms, before
+    /// the run ends with `IdlenessLimitExceeded` (default: 1000)
+    pub message_timeout_ms: Option<u64>,
+    /// Most round trips: messages from the run answered by the interactor
+    pub max_exchanges: Option<u32>,
+}
+
+/// How messages between a run and its interactor are relayed.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Buffering {
+    /// Whole `\n`-terminated lines, with trailing whitespace (and `\r`) trimmed
+    #[default]
+    Line,
+    /// Bytes as they are read
+    Raw,
+}
+
+/// How a stage's captured stdout and stderr are put into `StageResult`.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputEncoding {
+    /// Text; bytes that are not valid UTF-8 are replaced with U+FFFD
+    #[default]
+    Utf8,
+    /// The raw bytes, base64-encoded, for programs writing binary data
+    Base64,
+}
+
+impl OutputEncoding {
+    /// `bytes` captured from a stage, as returned in `stdout` or `stderr`.
+    pub fn encode(self, bytes: &[u8]) -> String {
+        match self {
+            OutputEncoding::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+            OutputEncoding::Base64 => base64::engine::general_purpose::STANDARD.encode(bytes),
+        }
+    }
+
+    /// Text of `output` returned by `encode`, e.g. to compare with a testcase's expected
+    /// output.
+    pub fn decode_lossy(self, output: &str) -> String {
+        match self {
+            OutputEncoding::Utf8 => output.to_string(),
+            OutputEncoding::Base64 => base64::engine::general_purpose::STANDARD
+                .decode(output)
+                .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Repeated runs of a job's program, measured one after another in the same sandbox.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BenchmarkRequest {
+    /// Measured runs; capped by `sandbox.max_benchmark_iterations`
+    pub iterations: u32,
+    /// Unmeasured runs before the measured ones, e.g. to warm caches (default: 0)
+    #[serde(default)]
+    pub warmup: u32,
+}
+
+/// Project a job is checked out from before its `files` are written.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobSource {
+    pub git: GitSource,
+}
+
+/// Git repository shallow-cloned by the worker, cached per commit.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GitSource {
+    /// `https://` URL of the repository
+    pub url: String,
+    /// Branch, tag or full commit hash (default: the remote's `HEAD`)
+    #[serde(rename = "ref")]
+    pub reference: Option<String>,
+    /// Directory of the repository the job runs in (default: its root)
+    pub subdir: Option<String>,
+}
+
+/// Scheduling class of a job. Workers take every queued `High` job before any
+/// `Normal` one, and `Low` jobs only when nothing else is waiting.
+#[derive(
+    Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+impl Priority {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Priority::Low => "low",
+            Priority::Normal => "normal",
+            Priority::High => "high",
+        }
+    }
+}
+
+impl std::str::FromStr for Priority {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "low" => Ok(Priority::Low),
+            "normal" => Ok(Priority::Normal),
+            "high" => Ok(Priority::High),
+            other => Err(format!(
+                "unknown priority '{}' (low, normal or high)",
+                other
+            )),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Job {
     pub id: String,
     pub request: JobRequest,
+    #[serde(default)]
+    pub kind: JobKind,
+    /// API key the job was submitted with, whose webhook is told when it finishes
+    #[serde(default)]
+    pub api_key: Option<String>,
+    /// ID of the HTTP request that submitted the job, for correlating server and worker logs
+    #[serde(default)]
+    pub request_id: Option<String>,
+    /// Peer address of the submitting connection, for the audit log
+    #[serde(default)]
+    pub client_ip: Option<String>,
+    /// `X-Forwarded-For` of the submission, for the audit log
+    #[serde(default)]
+    pub forwarded_for: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobKind {
+    /// Regular user submission
+    #[default]
+    Execute,
+    /// Standard calibration workload used to score worker performance
+    Calibration,
+    /// Install of package `language` at `version` on the worker's host
+    Install,
+}
+
+/// Where a job is in its lifecycle: `queued` → `running` → `finished`, `cancelled` or
+/// `failed`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    Queued,
+    Running,
+    /// Ran to completion, whatever the verdict
+    Finished,
+    /// Superseded by a newer submission before a worker took it
+    Cancelled,
+    /// Could not be run at all (e.g. missing runtime, sandbox error)
+    Failed,
+}
+
+impl JobState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobState::Queued => "queued",
+            JobState::Running => "running",
+            JobState::Finished => "finished",
+            JobState::Cancelled => "cancelled",
+            JobState::Failed => "failed",
+        }
+    }
+
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self,
+            JobState::Finished | JobState::Cancelled | JobState::Failed
+        )
+    }
+}
+
+/// Lifecycle of a job as returned by `GET /api/v1/jobs/{id}`. Timestamps are Unix ms.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobStatus {
+    pub id: String,
+    pub state: JobState,
+    /// Worker currently or last running the job
+    pub worker: Option<String>,
+    /// Times a worker has taken the job; above 1 means it was redelivered after a
+    /// worker died
+    pub attempts: u64,
+    pub queued_at: Option<u64>,
+    pub started_at: Option<u64>,
+    pub finished_at: Option<u64>,
+    pub result: Option<JobResult>,
+}
+
+/// Where to send notifications for jobs submitted with one API key.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    pub url: String,
+    /// Deliveries are signed with `X-Turbo-Signature: sha256=<hex HMAC-SHA256 of body>`
+    pub secret: Option<String>,
+    /// Terminal states to notify about; every terminal state when empty
+    #[serde(default)]
+    pub events: Vec<JobState>,
+    /// Most events sent in one delivery
+    #[serde(default = "default_webhook_batch_size")]
+    pub batch_size: usize,
+}
+
+fn default_webhook_batch_size() -> usize {
+    1
+}
+
+impl WebhookConfig {
+    pub fn accepts(&self, state: JobState) -> bool {
+        state.is_terminal() && (self.events.is_empty() || self.events.contains(&state))
+    }
+}
+
+/// One job reaching a terminal state, as delivered to a webhook.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookEvent {
+    pub job_id: String,
+    pub state: JobState,
+    pub result: JobResult,
+    pub at: u64, // Unix ms
+}
+
+/// A batch of events that could not be delivered after every retry.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLetter {
+    pub events: Vec<WebhookEvent>,
+    /// Error from the last attempt
+    pub error: String,
+    pub attempts: u32,
+    pub failed_at: u64, // Unix ms
+}
+
+/// Response to an asynchronous submission.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobAccepted {
+    pub id: String,
+    /// Per-`supersedes`-key sequence number, when the request set one
+    pub sequence: Option<u64>,
+    /// Jobs ahead of this one in the queue
+    pub queue_depth: u64,
+    /// Estimated wait until a worker starts the job, in ms
+    pub estimated_start_ms: u64,
+}
+
+/// A job of a batch submission.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchJob {
+    pub id: String,
+    /// Per-`supersedes`-key sequence number, when the request set one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sequence: Option<u64>,
+    /// The job is an earlier submission's with the same idempotency key
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub replayed: bool,
+    /// The job's result, when the batch was submitted with `wait`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<JobResult>,
+    /// Why the job was not queued, or its result could not be waited for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<BatchJobError>,
+}
+
+/// The `code` and `message` of the error body a batch job would have got on its own.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatchJobError {
+    pub code: String,
+    pub message: String,
+}
+
+/// `422` body for a submission naming a language/version with no registered runtime: the
+/// usual `{code, message, request_id}` error body plus the runtimes to pick from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnknownRuntime {
+    pub code: String,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    pub language: String,
+    pub version: Option<String>,
+    /// Every registered runtime, to pick from
+    pub available: Vec<Runtime>,
+}
+
+/// Result of running the calibration workload on a worker.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkerScore {
+    pub worker: String,
+    /// Relative performance; 1.0 is the reference machine, higher is faster
+    pub score: f64,
+    pub cpu_time: u64,       // µs
+    pub execution_time: u64, // ms
+    pub measured_at: u64,    // Unix seconds
+}
+
+/// Liveness record a worker refreshes while it runs; it expires when the worker dies.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkerHeartbeat {
+    pub worker: String,
+    /// Hostname of the machine the worker runs on; empty from workers predating it
+    #[serde(default)]
+    pub host: String,
+    /// Job the worker holds, if any
+    pub current_job: Option<String>,
+    pub started_at: u64, // Unix seconds
+    pub last_seen: u64,  // Unix seconds
+    /// Seconds between `started_at` and `last_seen`
+    pub uptime_secs: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileRequest {
     pub name: Option<String>,
+    #[serde(default)]
     pub content: String,
     pub encoding: Option<String>, // "base64", "hex", or "utf8" (default)
+    /// `http(s)://` or `s3://` URL the server fetches the content from instead
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+/// Files to write to and remove from a workspace.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WorkspaceUpdate {
+    /// Replace same-named files already in the workspace
+    #[serde(default)]
+    pub files: Vec<FileRequest>,
+    /// Names of files to delete
+    #[serde(default)]
+    pub remove: Vec<String>,
+}
+
+/// A workspace, without its file contents.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Workspace {
+    pub id: String,
+    /// Names of its files, sorted
+    pub files: Vec<String>,
+    /// Total size of the file contents
+    pub bytes: u64,
+    /// Seconds until it expires unless used again
+    pub expires_in_secs: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -32,6 +445,10 @@ pub struct Testcase {
     pub id: String,
     pub input: String,
     pub expected_output: Option<String>,
+    /// Secret judge data: the result reports only whether the case passed, never its
+    /// input, outputs or diff
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub hidden: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -40,7 +457,174 @@ pub struct JobResult {
     pub version: String,
     pub run: Option<StageResult>,
     pub compile: Option<StageResult>,
+    /// Messages about the submitted files parsed from the stderr of `compile` (or of `run`
+    /// for runtimes that compile nothing), when the request set `diagnostics`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diagnostics: Option<Vec<Diagnostic>>,
+    /// Memory errors the request's `analysis` tool reported for the run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub analysis: Option<AnalysisReport>,
     pub testcases: Option<Vec<TestcaseResult>>,
+    /// Limits the job actually ran with; absent if it failed before they were resolved
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub effective_limits: Option<EffectiveLimits>,
+    /// Reused from an identical earlier submission (`results.dedup_window_secs`)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub cached: bool,
+    /// Statistics over the measured runs of a `benchmark` job; `run` is the last of them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub benchmark: Option<BenchmarkResult>,
+    /// The request's `metadata`, echoed back
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<serde_json::Value>,
+    /// The interactor's run, for a job with an `interactor`; `stdout` holds what it sent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interactor: Option<StageResult>,
+}
+
+/// A compiler or interpreter message about a place in a submitted file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Diagnostic {
+    /// Name of the file as submitted
+    pub file: String,
+    /// 1-based
+    pub line: u32,
+    /// 1-based; not every toolchain reports one
+    pub column: Option<u32>,
+    pub severity: Severity,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+/// Memory checker a job's run is wrapped in.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AnalysisTool {
+    /// Valgrind's memcheck
+    Valgrind,
+    /// AddressSanitizer (and LeakSanitizer), compiled into the program
+    Asan,
+}
+
+impl AnalysisTool {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AnalysisTool::Valgrind => "valgrind",
+            AnalysisTool::Asan => "asan",
+        }
+    }
+}
+
+/// What an `analysis` tool found in a run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnalysisReport {
+    pub tool: AnalysisTool,
+    /// Invalid accesses, uses of uninitialised memory, bad frees and leaks, in the order
+    /// the tool reported them
+    pub errors: Vec<MemoryError>,
+    /// Bytes still allocated and unreachable when the program exited
+    pub leaked_bytes: u64,
+}
+
+/// One error of an [`AnalysisReport`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MemoryError {
+    /// The tool's description, e.g. `Invalid read of size 4` or
+    /// `heap-buffer-overflow on address 0x602000000014`
+    pub message: String,
+    /// Innermost stack frame in a submitted file, when there is one
+    pub file: Option<String>,
+    pub line: Option<u32>,
+}
+
+/// Measurements of a benchmark's runs, in the units of the `StageResult` fields.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BenchmarkResult {
+    /// Measured runs that completed; fewer than requested if one failed or the job's
+    /// time budget ran out
+    pub iterations: u32,
+    pub warmup: u32,
+    /// Wall-clock time in ms
+    pub execution_time: BenchmarkStats,
+    pub cpu_time: BenchmarkStats,
+    /// Peak memory in bytes
+    pub memory_usage: BenchmarkStats,
+    /// Each measured run, in order
+    pub runs: Vec<BenchmarkRun>,
+}
+
+/// Distribution of one measurement across a benchmark's runs.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct BenchmarkStats {
+    pub min: u64,
+    pub median: u64,
+    pub p95: u64,
+    pub max: u64,
+}
+
+impl BenchmarkStats {
+    /// Statistics of `samples` (nearest-rank percentiles); all zero when empty.
+    pub fn of(mut samples: Vec<u64>) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+        samples.sort_unstable();
+        let rank = |p: usize| samples[(samples.len() * p).div_ceil(100).max(1) - 1];
+        Self {
+            min: samples[0],
+            median: rank(50),
+            p95: rank(95),
+            max: samples[samples.len() - 1],
+        }
+    }
+}
+
+/// Measurements of one benchmark run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BenchmarkRun {
+    pub execution_time: Option<u64>,
+    pub cpu_time: Option<u64>,
+    pub memory_usage: Option<u64>,
+}
+
+/// Limits applied to a job after runtime defaults and server maximums.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EffectiveLimits {
+    pub compile: StageLimits,
+    pub run: StageLimits,
+    /// Budget for compile plus every run, from `max_total_time_ms` or the server maximum
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_time_ms: Option<u64>,
+    /// Why a value differs from the request, e.g. "run_timeout: 60000 clamped to server
+    /// maximum 30000"
+    pub adjustments: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StageLimits {
+    pub timeout_ms: u64,
+    pub memory_limit_bytes: u64,
+    pub pid_limit: u64,
+    pub output_limit_bytes: u64,
+}
+
+impl StageLimits {
+    pub fn to_execution_limits(&self) -> ExecutionLimits {
+        ExecutionLimits {
+            timeout_ms: self.timeout_ms,
+            memory_limit_bytes: self.memory_limit_bytes,
+            pid_limit: self.pid_limit,
+            output_limit_bytes: self.output_limit_bytes,
+            ..Default::default()
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -49,9 +633,37 @@ pub struct ExecutionLimits {
     pub pid_limit: u64,
     pub file_limit: u64,
     pub timeout_ms: u64,
+    /// CPU seconds each process may use (RLIMIT_CPU), a backstop for the cgroup and the
+    /// timeout. Unset: twice `timeout_ms`, rounded up to seconds, plus one
+    #[serde(default)]
+    pub cpu_time_limit_secs: Option<u64>,
+    /// Largest file a process may write, in bytes (RLIMIT_FSIZE); 0 for no limit
+    #[serde(default = "default_file_size_limit_bytes")]
+    pub file_size_limit_bytes: u64,
     pub output_limit_bytes: u64,
     pub uid: Option<u32>, // User ID to switch to
     pub gid: Option<u32>, // Group ID to switch to
+    /// How captured stdout and stderr are returned
+    #[serde(default)]
+    pub output_encoding: OutputEncoding,
+    /// Interval at which usage is sampled into `StageResult::samples`; none when unset
+    #[serde(default)]
+    pub sample_interval_ms: Option<u64>,
+    /// Count hardware events into `StageResult::perf_counters`
+    #[serde(default)]
+    pub profile: bool,
+}
+
+fn default_file_size_limit_bytes() -> u64 {
+    256 * 1024 * 1024
+}
+
+impl ExecutionLimits {
+    /// RLIMIT_CPU for a run under these limits, if any.
+    pub fn cpu_rlimit_secs(&self) -> Option<u64> {
+        let derived = (self.timeout_ms > 0).then_some(self.timeout_ms.div_ceil(1000) * 2 + 1);
+        self.cpu_time_limit_secs.or(derived)
+    }
 }
 
 impl Default for ExecutionLimits {
@@ -60,10 +672,15 @@ impl Default for ExecutionLimits {
             memory_limit_bytes: 512 * 1024 * 1024, // 512 MB
             pid_limit: 256,
             file_limit: 2048,
-            timeout_ms: 3000,         // 3s
+            timeout_ms: 3000, // 3s
+            cpu_time_limit_secs: None,
+            file_size_limit_bytes: default_file_size_limit_bytes(),
             output_limit_bytes: 1024, // 1KB
             uid: None, // Default to no switch (or root if started as root) until configured
             gid: None,
+            output_encoding: OutputEncoding::Utf8,
+            sample_interval_ms: None,
+            profile: false,
         }
     }
 }
@@ -79,6 +696,17 @@ pub enum StageStatus {
     TimeLimitExceeded,
     MemoryLimitExceeded,
     OutputLimitExceeded,
+    /// Neither the run nor its interactor sent a message or used CPU for the interactor's
+    /// `message_timeout_ms`: each was waiting on the other
+    IdlenessLimitExceeded,
+    /// Dropped from the queue before running: superseded by a newer submission, or
+    /// cancelled through `DELETE /api/v1/jobs/:id`
+    Cancelled,
+    /// Not run because the job stopped early (`stop_on_failure` or `max_total_time_ms`)
+    Skipped,
+    /// The judge failed to run the stage (sandbox, runtime or workspace error); not a
+    /// verdict on the submitted code
+    InternalError,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -87,10 +715,89 @@ pub struct StageResult {
     pub stdout: String,
     pub stderr: String,
     pub exit_code: Option<i32>,
+    /// Name of the signal that ended the process, e.g. "SIGSEGV"
     pub signal: Option<String>,
     pub memory_usage: Option<u64>,
     pub cpu_time: Option<u64>,
     pub execution_time: Option<u64>, // Wall-clock time in ms
+    /// How the process ended; absent for stages that never started one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub termination_reason: Option<TerminationReason>,
+    /// Bytes read from block devices; absent where the sandbox cannot measure it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub io_read_bytes: Option<u64>,
+    /// Bytes written to block devices; absent where the sandbox cannot measure it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub io_write_bytes: Option<u64>,
+    /// Usage over time, when `sample_interval_ms` was set and the sandbox supports it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub samples: Option<Vec<UsageSample>>,
+    /// Hardware event counts, when `profile` was set and the sandbox supports it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub perf_counters: Option<PerfCounters>,
+}
+
+/// Resource usage of a running stage at one point in time.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct UsageSample {
+    /// Milliseconds since the process started
+    pub elapsed_ms: u64,
+    /// Memory in use in bytes
+    pub memory_usage: u64,
+    /// CPU time used so far, in the units of `StageResult::cpu_time`
+    pub cpu_time: u64,
+}
+
+/// Hardware events counted over a run, like `perf stat`. Each is absent where the CPU
+/// (or the virtual machine) does not expose it.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct PerfCounters {
+    pub instructions: Option<u64>,
+    pub cache_misses: Option<u64>,
+    pub branch_misses: Option<u64>,
+}
+
+/// How a stage's process ended, so a crash can be told apart from a failing exit code
+/// without parsing stderr.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TerminationReason {
+    /// Exited by itself, with any exit code
+    Exited,
+    /// Invalid memory access: SIGSEGV or SIGBUS, or an access violation or stack
+    /// overflow on Windows
+    SegmentationFault,
+    /// Integer division by zero or another arithmetic fault (SIGFPE)
+    ArithmeticError,
+    /// Aborted itself, e.g. a failed assertion or an uncaught C++ exception (SIGABRT)
+    Aborted,
+    /// Executed an invalid instruction (SIGILL)
+    IllegalInstruction,
+    /// Killed by the sandbox for running past its timeout
+    TimeLimit,
+    /// Killed for exceeding its memory limit
+    MemoryLimit,
+    /// Wrote past the largest file size allowed (SIGXFSZ)
+    FileSizeLimit,
+    /// Killed as it and its interactor were both idle past the message timeout
+    IdlenessLimit,
+    /// Ended by another signal, named in `signal`
+    Signal,
+}
+
+impl TerminationReason {
+    /// Reason for a process ended by the signal named `signal`, e.g. "SIGSEGV".
+    pub fn from_signal(signal: &str) -> Self {
+        match signal {
+            "SIGSEGV" | "SIGBUS" => TerminationReason::SegmentationFault,
+            "SIGFPE" => TerminationReason::ArithmeticError,
+            "SIGABRT" => TerminationReason::Aborted,
+            "SIGILL" => TerminationReason::IllegalInstruction,
+            "SIGXCPU" => TerminationReason::TimeLimit,
+            "SIGXFSZ" => TerminationReason::FileSizeLimit,
+            _ => TerminationReason::Signal,
+        }
+    }
 }
 
 impl std::fmt::Display for StageResult {
@@ -102,6 +809,9 @@ impl std::fmt::Display for StageResult {
         if let Some(signal) = &self.signal {
             writeln!(f, "Signal: {}", signal)?;
         }
+        if let Some(reason) = self.termination_reason {
+            writeln!(f, "Termination: {:?}", reason)?;
+        }
 
         if let Some(mem) = self.memory_usage {
             let (val, unit) = if mem > 1024 * 1024 * 1024 {
@@ -127,6 +837,10 @@ impl std::fmt::Display for StageResult {
             writeln!(f, "CPU Time: {:.2} {}", val, unit)?;
         }
 
+        if let (Some(read), Some(written)) = (self.io_read_bytes, self.io_write_bytes) {
+            writeln!(f, "I/O: {} B read, {} B written", read, written)?;
+        }
+
         if let Some(exec) = self.execution_time {
             let (val, unit) = if exec > 1_000 {
                 (exec as f64 / 1_000.0, "s")
@@ -150,7 +864,17 @@ impl std::fmt::Display for StageResult {
 pub struct TestcaseResult {
     pub id: String,
     pub passed: bool,
+    /// The program's stdout, cut to its first 4 KiB; `run_details` has all of it
     pub actual_output: String,
+    /// The testcase's `expected_output`, cut like `actual_output`, when the case failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected_output: Option<String>,
+    /// Unified diff of the expected against the actual output, when the case failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diff: Option<String>,
+    /// Whether the testcase was `hidden`, so its outputs are left out
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub hidden: bool,
     pub run_details: StageResult,
 }
 
@@ -160,6 +884,17 @@ pub struct Runtime {
     pub version: String,
     pub aliases: Vec<String>,
     pub runtime: Option<String>,
+    /// Architecture the runtime was built on, e.g. `x86_64`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arch: Option<String>,
+}
+
+/// Body of `POST /api/v1/packages/install`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstallPackageRequest {
+    pub name: String,
+    /// Latest in the repository if unset
+    pub version: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -168,3 +903,35 @@ pub struct Package {
     pub language_version: String,
     pub installed: bool,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn benchmark_stats_nearest_rank() {
+        let stats = BenchmarkStats::of((1..=20).rev().collect());
+        assert_eq!(stats.min, 1);
+        assert_eq!(stats.median, 10);
+        assert_eq!(stats.p95, 19);
+        assert_eq!(stats.max, 20);
+
+        let stats = BenchmarkStats::of(vec![7]);
+        assert_eq!(
+            (stats.min, stats.median, stats.p95, stats.max),
+            (7, 7, 7, 7)
+        );
+
+        let stats = BenchmarkStats::of(vec![5, 1]);
+        assert_eq!((stats.median, stats.p95), (1, 5));
+    }
+
+    #[test]
+    fn benchmark_stats_of_nothing_is_zero() {
+        let stats = BenchmarkStats::of(Vec::new());
+        assert_eq!(
+            (stats.min, stats.median, stats.p95, stats.max),
+            (0, 0, 0, 0)
+        );
+    }
+}
@@ -2,7 +2,8 @@ pub mod metadata;
 pub mod queue;
 
 pub use metadata::RedisMetadataStore;
-pub use queue::{QueueError, RedisQueue};
+pub use queue::{Delivery, QueueError, QueueMode, RedisQueue};
+use turbo_core::config::TurboConfig;
 
 #[derive(Clone)]
 pub struct TurboDb {
@@ -11,10 +12,12 @@ pub struct TurboDb {
 }
 
 impl TurboDb {
-    pub async fn new(redis_url: &str) -> anyhow::Result<Self> {
-        let queue = RedisQueue::new(redis_url)?;
-        let client = redis::Client::open(redis_url)?;
-        let metadata = RedisMetadataStore::new(client);
+    /// Connect using the `redis` and `results` sections of the config.
+    pub async fn new(config: &TurboConfig) -> anyhow::Result<Self> {
+        let queue_mode: QueueMode = config.redis.queue_mode.parse()?;
+        let queue = RedisQueue::new(&config.redis.url, queue_mode, &config.results)?;
+        let client = redis::Client::open(config.redis.url.as_str())?;
+        let metadata = RedisMetadataStore::new(client, config.results.ttl_secs);
         Ok(Self { queue, metadata })
     }
 }
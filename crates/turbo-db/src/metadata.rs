@@ -1,15 +1,50 @@
 use anyhow::Result;
 use redis::AsyncCommands;
-use turbo_core::models::Runtime;
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::time::{SystemTime, UNIX_EPOCH};
+use turbo_core::models::{
+    DeadLetter, FileRequest, JobState, JobStatus, Runtime, WebhookConfig, WebhookEvent,
+    WorkerHeartbeat, WorkerScore,
+};
+
+/// Dead letters kept per webhook; older ones are dropped.
+const DEAD_LETTERS_MAX: isize = 1000;
+
+/// Hash of counters exposed by `/metrics`, e.g. result cache hits.
+const STATS_KEY: &str = "turbo:stats";
+/// Sorted set of job ids scored by the time they were queued (Unix ms), for listing.
+const JOB_INDEX_KEY: &str = "turbo:job_index";
+
+fn job_state_key(job_id: &str) -> String {
+    format!("turbo:job_state:{}", job_id)
+}
+
+fn idempotency_key(key: &str) -> String {
+    format!("turbo:idempotency:{}", key)
+}
+
+fn workspace_key(id: &str) -> String {
+    format!("turbo:workspace:{}", id)
+}
+
+fn heartbeat_key(worker: &str) -> String {
+    format!("turbo:worker:{}:heartbeat", worker)
+}
 
 #[derive(Clone)]
 pub struct RedisMetadataStore {
     client: redis::Client,
+    /// How long a job's lifecycle record is kept after its last transition (`results.ttl_secs`)
+    job_state_ttl_secs: u64,
 }
 
 impl RedisMetadataStore {
-    pub fn new(client: redis::Client) -> Self {
-        Self { client }
+    pub fn new(client: redis::Client, job_state_ttl_secs: u64) -> Self {
+        Self {
+            client,
+            job_state_ttl_secs,
+        }
     }
 
     pub async fn add_runtime(&self, runtime: &Runtime) -> Result<()> {
@@ -21,6 +56,13 @@ impl RedisMetadataStore {
         Ok(())
     }
 
+    pub async fn remove_runtime(&self, language: &str, version: &str) -> Result<()> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let field_key = format!("{}:{}", language, version);
+        let _: () = conn.hdel("turbo:runtimes", field_key).await?;
+        Ok(())
+    }
+
     pub async fn get_runtimes(&self) -> Result<Vec<Runtime>> {
         let mut conn = self.client.get_multiplexed_async_connection().await?;
         let key = "turbo:runtimes";
@@ -32,4 +74,443 @@ impl RedisMetadataStore {
             .collect();
         Ok(runtimes)
     }
+
+    /// Register the worker and store its heartbeat for `ttl_secs`.
+    ///
+    /// Both are written atomically, so a registered worker without a heartbeat is dead.
+    pub async fn heartbeat_worker(&self, heartbeat: &WorkerHeartbeat, ttl_secs: u64) -> Result<()> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let json = serde_json::to_string(heartbeat)?;
+        let _: () = redis::pipe()
+            .atomic()
+            .sadd("turbo:workers", &heartbeat.worker)
+            .set_ex(heartbeat_key(&heartbeat.worker), json, ttl_secs)
+            .query_async(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Every registered worker with its heartbeat, `None` once that has expired.
+    pub async fn worker_heartbeats(&self) -> Result<Vec<(String, Option<WorkerHeartbeat>)>> {
+        let workers = self.list_workers().await?;
+        if workers.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let keys: Vec<String> = workers.iter().map(|w| heartbeat_key(w)).collect();
+        let jsons: Vec<Option<String>> =
+            redis::cmd("MGET").arg(&keys).query_async(&mut conn).await?;
+        Ok(workers
+            .into_iter()
+            .zip(jsons)
+            .map(|(worker, json)| {
+                let heartbeat = json.and_then(|json| serde_json::from_str(&json).ok());
+                (worker, heartbeat)
+            })
+            .collect())
+    }
+
+    /// Unregister a worker. Returns false if it was not registered, e.g. because another
+    /// server removed it first.
+    pub async fn remove_worker(&self, worker: &str) -> Result<bool> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let removed: i64 = conn.srem("turbo:workers", worker).await?;
+        Ok(removed > 0)
+    }
+
+    pub async fn list_workers(&self) -> Result<Vec<String>> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let mut workers: Vec<String> = conn.smembers("turbo:workers").await?;
+        workers.sort();
+        Ok(workers)
+    }
+
+    /// Add a finished job's wall-clock duration to its language's running average.
+    pub async fn record_job_duration(&self, language: &str, duration_ms: u64) -> Result<()> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let _: () = redis::pipe()
+            .hincr("turbo:job_durations", format!("{}:count", language), 1)
+            .hincr(
+                "turbo:job_durations",
+                format!("{}:total_ms", language),
+                duration_ms,
+            )
+            .query_async(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Average job duration in ms per language, for languages with at least one job.
+    pub async fn get_average_durations(&self) -> Result<HashMap<String, f64>> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let map: HashMap<String, u64> = conn.hgetall("turbo:job_durations").await?;
+
+        let averages = map
+            .iter()
+            .filter_map(|(field, count)| {
+                let language = field.strip_suffix(":count")?;
+                let total = map.get(&format!("{}:total_ms", language))?;
+                (*count > 0).then(|| (language.to_string(), *total as f64 / *count as f64))
+            })
+            .collect();
+        Ok(averages)
+    }
+
+    /// Record a lifecycle transition of a job, stamped with the current time.
+    ///
+    /// `worker` is set when a worker takes the job (`JobState::Running`).
+    pub async fn set_job_state(
+        &self,
+        job_id: &str,
+        state: JobState,
+        worker: Option<&str>,
+    ) -> Result<()> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let key = job_state_key(job_id);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        let stamp = match state {
+            JobState::Queued => "queued_at",
+            JobState::Running => "started_at",
+            _ => "finished_at",
+        };
+
+        let mut pipe = redis::pipe();
+        pipe.hset(&key, "state", state.as_str())
+            .hset(&key, stamp, now);
+        if let Some(worker) = worker {
+            pipe.hset(&key, "worker", worker).hincr(&key, "attempts", 1);
+        }
+        if state == JobState::Queued {
+            // Drop ids whose records have expired along the way
+            let expired = now.saturating_sub(self.job_state_ttl_secs * 1000);
+            pipe.zadd(JOB_INDEX_KEY, job_id, now)
+                .zrembyscore(JOB_INDEX_KEY, 0, expired);
+        }
+        let ttl = self.job_state_ttl_secs as i64;
+        let _: () = pipe.expire(&key, ttl).query_async(&mut conn).await?;
+        Ok(())
+    }
+
+    /// Lifecycle of a job without its result, if it was seen within the TTL.
+    pub async fn get_job_state(&self, job_id: &str) -> Result<Option<JobStatus>> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let map: HashMap<String, String> = conn.hgetall(job_state_key(job_id)).await?;
+        let Some(state) = map.get("state") else {
+            return Ok(None);
+        };
+
+        let state = serde_json::from_value(serde_json::Value::String(state.clone()))?;
+        let stamp = |field: &str| map.get(field).and_then(|v| v.parse().ok());
+        Ok(Some(JobStatus {
+            id: job_id.to_string(),
+            state,
+            worker: map.get("worker").cloned(),
+            attempts: stamp("attempts").unwrap_or(0),
+            queued_at: stamp("queued_at"),
+            started_at: stamp("started_at"),
+            finished_at: stamp("finished_at"),
+            result: None,
+        }))
+    }
+
+    /// Lifecycles of the `limit` most recently queued jobs still within the TTL, newest
+    /// first, without results.
+    pub async fn list_job_states(&self, limit: usize) -> Result<Vec<JobStatus>> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let stop = limit.max(1) as isize - 1;
+        let ids: Vec<String> = conn.zrevrange(JOB_INDEX_KEY, 0, stop).await?;
+        let mut jobs = Vec::with_capacity(ids.len());
+        for id in ids {
+            // The index is trimmed lazily, so a record may already have expired
+            if let Some(status) = self.get_job_state(&id).await? {
+                jobs.push(status);
+            }
+        }
+        Ok(jobs)
+    }
+
+    /// Remember `job_id` under the idempotency `key` for `ttl_secs`, unless the key is
+    /// already taken; returns the job id it was taken for.
+    pub async fn claim_idempotency_key(
+        &self,
+        key: &str,
+        job_id: &str,
+        ttl_secs: u64,
+    ) -> Result<Option<String>> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let key = idempotency_key(key);
+        // SET NX GET needs Redis 7, so set-if-absent and read back separately
+        let claimed: Option<String> = redis::cmd("SET")
+            .arg(&key)
+            .arg(job_id)
+            .arg("NX")
+            .arg("EX")
+            .arg(ttl_secs)
+            .query_async(&mut conn)
+            .await?;
+        if claimed.is_some() {
+            return Ok(None);
+        }
+        Ok(conn.get(&key).await?)
+    }
+
+    /// Forget the idempotency `key`, e.g. when the job claiming it could not be queued.
+    pub async fn release_idempotency_key(&self, key: &str) -> Result<()> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let _: () = conn.del(idempotency_key(key)).await?;
+        Ok(())
+    }
+
+    /// Files of a workspace sorted by name, restarting its TTL; `None` once it has expired
+    /// (or lost its last file).
+    pub async fn get_workspace(&self, id: &str, ttl_secs: u64) -> Result<Option<Vec<FileRequest>>> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let key = workspace_key(id);
+        let (stored, _): (HashMap<String, String>, bool) = redis::pipe()
+            .hgetall(&key)
+            .expire(&key, ttl_secs as i64)
+            .query_async(&mut conn)
+            .await?;
+        if stored.is_empty() {
+            return Ok(None);
+        }
+        let mut files = stored
+            .into_values()
+            .map(|json| serde_json::from_str(&json))
+            .collect::<Result<Vec<FileRequest>, _>>()?;
+        files.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(Some(files))
+    }
+
+    /// Write `files` to a workspace, replacing same-named ones, delete the files named in
+    /// `remove`, and restart its TTL. Creates the workspace if needed.
+    pub async fn update_workspace(
+        &self,
+        id: &str,
+        files: &[FileRequest],
+        remove: &[String],
+        ttl_secs: u64,
+    ) -> Result<()> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let key = workspace_key(id);
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        for name in remove {
+            pipe.hdel(&key, name);
+        }
+        for file in files {
+            let name = file.name.as_deref().unwrap_or("main");
+            pipe.hset(&key, name, serde_json::to_string(file)?);
+        }
+        let _: () = pipe
+            .expire(&key, ttl_secs as i64)
+            .query_async(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn delete_workspace(&self, id: &str) -> Result<bool> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let removed: u64 = conn.del(workspace_key(id)).await?;
+        Ok(removed > 0)
+    }
+
+    /// Add `by` to the counter `name`.
+    pub async fn incr_stat(&self, name: &str, by: i64) -> Result<()> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let _: () = conn.hincr(STATS_KEY, name, by).await?;
+        Ok(())
+    }
+
+    /// Every counter recorded with `incr_stat`.
+    pub async fn get_stats(&self) -> Result<HashMap<String, u64>> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        Ok(conn.hgetall(STATS_KEY).await?)
+    }
+
+    pub async fn set_webhook(&self, api_key: &str, webhook: &WebhookConfig) -> Result<()> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let json = serde_json::to_string(webhook)?;
+        let _: () = conn.hset("turbo:webhooks", api_key, json).await?;
+        Ok(())
+    }
+
+    pub async fn get_webhook(&self, api_key: &str) -> Result<Option<WebhookConfig>> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let json: Option<String> = conn.hget("turbo:webhooks", api_key).await?;
+        Ok(json.map(|j| serde_json::from_str(&j)).transpose()?)
+    }
+
+    /// Remove a webhook; returns false if none was configured. Undelivered events go too.
+    pub async fn delete_webhook(&self, api_key: &str) -> Result<bool> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let removed: i64 = conn.hdel("turbo:webhooks", api_key).await?;
+        let _: () = conn
+            .del(&[
+                format!("turbo:webhook:{}:events", api_key),
+                format!("turbo:webhook:{}:processing", api_key),
+            ])
+            .await?;
+        Ok(removed > 0)
+    }
+
+    /// Every configured webhook with the API key it belongs to.
+    pub async fn list_webhooks(&self) -> Result<Vec<(String, WebhookConfig)>> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let map: HashMap<String, String> = conn.hgetall("turbo:webhooks").await?;
+        Ok(map
+            .into_iter()
+            .filter_map(|(key, json)| Some((key, serde_json::from_str(&json).ok()?)))
+            .collect())
+    }
+
+    /// Queue an event for the next delivery to `api_key`'s webhook.
+    pub async fn push_webhook_event(&self, api_key: &str, event: &WebhookEvent) -> Result<()> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let json = serde_json::to_string(event)?;
+        let _: () = conn
+            .rpush(format!("turbo:webhook:{}:events", api_key), json)
+            .await?;
+        Ok(())
+    }
+
+    /// Lease delivery for `api_key`'s webhook to `owner` for `ttl_secs`, or renew the
+    /// lease `owner` already holds. Returns false while another dispatcher holds it.
+    pub async fn lease_webhook(&self, api_key: &str, owner: &str, ttl_secs: u64) -> Result<bool> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let leased: i64 = redis::Script::new(
+            r"
+            if redis.call('GET', KEYS[1]) == ARGV[1] then
+                return redis.call('EXPIRE', KEYS[1], ARGV[2])
+            end
+            if redis.call('SET', KEYS[1], ARGV[1], 'NX', 'EX', ARGV[2]) then
+                return 1
+            end
+            return 0
+            ",
+        )
+        .key(format!("turbo:webhook:{}:lease", api_key))
+        .arg(owner)
+        .arg(ttl_secs)
+        .invoke_async(&mut conn)
+        .await?;
+        Ok(leased == 1)
+    }
+
+    /// Give up `owner`'s lease on `api_key`'s webhook, if it still holds it.
+    pub async fn release_webhook(&self, api_key: &str, owner: &str) -> Result<()> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let _: i64 = redis::Script::new(
+            r"
+            if redis.call('GET', KEYS[1]) == ARGV[1] then
+                return redis.call('DEL', KEYS[1])
+            end
+            return 0
+            ",
+        )
+        .key(format!("turbo:webhook:{}:lease", api_key))
+        .arg(owner)
+        .invoke_async(&mut conn)
+        .await?;
+        Ok(())
+    }
+
+    /// The batch of `api_key`'s webhook being delivered: the one a previous lease holder
+    /// left unacknowledged, else up to `count` of the oldest queued events, moved to the
+    /// processing list until `ack_webhook_events`. Call with the webhook's lease held.
+    pub async fn take_webhook_events(
+        &self,
+        api_key: &str,
+        count: usize,
+    ) -> Result<Vec<WebhookEvent>> {
+        let Some(count) = NonZeroUsize::new(count) else {
+            return Ok(Vec::new());
+        };
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let jsons: Vec<String> = redis::Script::new(
+            r"
+            local batch = redis.call('LRANGE', KEYS[2], 0, -1)
+            if #batch > 0 then
+                return batch
+            end
+            batch = redis.call('LRANGE', KEYS[1], 0, ARGV[1] - 1)
+            if #batch > 0 then
+                redis.call('LTRIM', KEYS[1], #batch, -1)
+                redis.call('RPUSH', KEYS[2], unpack(batch))
+            end
+            return batch
+            ",
+        )
+        .key(format!("turbo:webhook:{}:events", api_key))
+        .key(format!("turbo:webhook:{}:processing", api_key))
+        .arg(count.get())
+        .invoke_async(&mut conn)
+        .await?;
+        Ok(jsons
+            .iter()
+            .filter_map(|json| serde_json::from_str(json).ok())
+            .collect())
+    }
+
+    /// Drop the batch `take_webhook_events` returned, once it was delivered or
+    /// dead-lettered.
+    pub async fn ack_webhook_events(&self, api_key: &str) -> Result<()> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let _: () = conn
+            .del(format!("turbo:webhook:{}:processing", api_key))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn push_dead_letter(&self, api_key: &str, letter: &DeadLetter) -> Result<()> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let key = format!("turbo:webhook:{}:dead", api_key);
+        let json = serde_json::to_string(letter)?;
+        let _: () = conn.lpush(&key, json).await?;
+        let _: () = conn.ltrim(&key, 0, DEAD_LETTERS_MAX - 1).await?;
+        Ok(())
+    }
+
+    /// Undeliverable batches for `api_key`'s webhook, newest first.
+    pub async fn get_dead_letters(&self, api_key: &str) -> Result<Vec<DeadLetter>> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let jsons: Vec<String> = conn
+            .lrange(format!("turbo:webhook:{}:dead", api_key), 0, -1)
+            .await?;
+        Ok(jsons
+            .iter()
+            .filter_map(|json| serde_json::from_str(json).ok())
+            .collect())
+    }
+
+    pub async fn set_worker_score(&self, score: &WorkerScore) -> Result<()> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let json = serde_json::to_string(score)?;
+        let _: () = conn
+            .hset("turbo:worker_scores", &score.worker, json)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_worker_score(&self, worker: &str) -> Result<Option<WorkerScore>> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let json: Option<String> = conn.hget("turbo:worker_scores", worker).await?;
+        Ok(json.and_then(|json| serde_json::from_str(&json).ok()))
+    }
+
+    pub async fn get_worker_scores(&self) -> Result<Vec<WorkerScore>> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let map: std::collections::HashMap<String, String> =
+            conn.hgetall("turbo:worker_scores").await?;
+
+        let mut scores: Vec<WorkerScore> = map
+            .values()
+            .filter_map(|json| serde_json::from_str(json).ok())
+            .collect();
+        scores.sort_by(|a, b| a.worker.cmp(&b.worker));
+        Ok(scores)
+    }
 }
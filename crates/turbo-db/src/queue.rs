@@ -1,7 +1,17 @@
 use futures_util::StreamExt;
-use redis::AsyncCommands;
+use redis::streams::{
+    StreamClaimReply, StreamId, StreamInfoGroupsReply, StreamPendingCountReply, StreamRangeReply,
+    StreamReadOptions, StreamReadReply,
+};
+use redis::{AsyncCommands, Direction};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::OnceCell;
 // use serde::{Deserialize, Serialize};
-use turbo_core::models::{Job, JobResult};
+use turbo_core::config::ResultsConfig;
+use turbo_core::models::{Job, JobResult, Priority};
 
 #[derive(thiserror::Error, Debug)]
 pub enum QueueError {
@@ -9,48 +19,765 @@ pub enum QueueError {
     Redis(#[from] redis::RedisError),
     #[error("Serialization error: {0}")]
     Serde(#[from] serde_json::Error),
+    #[error("Unsupported queue mode: {0}")]
+    UnsupportedMode(String),
+    #[error("Result storage error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// How the shared queue is stored in Redis, selected by `redis.queue_mode`.
+///
+/// Jobs addressed to a single worker always use a plain list, whatever the mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QueueMode {
+    /// `turbo:jobs` list; workers move jobs into their own processing list.
+    #[default]
+    List,
+    /// `turbo:stream` read through the `turbo-workers` consumer group. Redis tracks each
+    /// worker's unacknowledged entries, so external worker processes can join by
+    /// reading from the same group.
+    Stream,
+}
+
+impl FromStr for QueueMode {
+    type Err = QueueError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "list" => Ok(Self::List),
+            "stream" => Ok(Self::Stream),
+            other => Err(QueueError::UnsupportedMode(other.to_string())),
+        }
+    }
+}
+
+/// Hash of job id -> Unix time a worker took it, for the visibility timeout.
+const CLAIMED_AT_KEY: &str = "turbo:claimed_at";
+/// Longest a pop blocks on the shared queue before rechecking the worker's own list.
+const POP_TIMEOUT_SECS: f64 = 5.0;
+/// List an idle worker blocks on; every push adds a token so one wakes up. Tokens are
+/// hints only: a woken worker that finds every lane empty simply goes back to waiting.
+const WAKE_KEY: &str = "turbo:jobs:wake";
+/// Tokens kept in `WAKE_KEY`; more than there are idle workers is never useful.
+const WAKE_TOKENS_MAX: isize = 1024;
+/// Lanes of the list queue, in the order workers take from them.
+const LANES: [Priority; 3] = [Priority::High, Priority::Normal, Priority::Low];
+/// Prefix of a stored result that points to a file in the offload directory instead of
+/// holding the JSON itself.
+const OFFLOAD_POINTER_PREFIX: &str = "file:";
+/// Stream holding the shared queue in `QueueMode::Stream`.
+const STREAM_KEY: &str = "turbo:stream";
+/// Consumer group every worker reads `STREAM_KEY` through.
+const STREAM_GROUP: &str = "turbo-workers";
+/// Consumer name the requeuer claims expired stream entries under before re-adding them.
+const REQUEUE_CONSUMER: &str = "turbo-requeuer";
+/// Most pending stream entries inspected per `requeue_expired` call.
+const PENDING_SCAN_COUNT: usize = 1000;
+
+/// A job taken off the queue, held by the worker until acked.
+pub struct Delivery {
+    pub job: Job,
+    receipt: Receipt,
+}
+
+/// Where a delivered job is held until it is acked.
+enum Receipt {
+    /// Payload exactly as queued, to find it again in the processing list
+    List(String),
+    /// Entry id in `STREAM_KEY`, pending for the worker in the consumer group
+    Stream(String),
+}
+
+/// List holding queued jobs of one priority; normal jobs keep the original `turbo:jobs`.
+fn lane_key(priority: Priority) -> &'static str {
+    match priority {
+        Priority::High => "turbo:jobs:high",
+        Priority::Normal => "turbo:jobs",
+        Priority::Low => "turbo:jobs:low",
+    }
+}
+
+/// Lane a queued payload belongs in; unparseable payloads go to the normal lane.
+fn payload_lane(payload: &str) -> &'static str {
+    let priority = serde_json::from_str::<Job>(payload)
+        .map(|job| job.request.priority.unwrap_or_default())
+        .unwrap_or_default();
+    lane_key(priority)
+}
+
+fn processing_key(worker: &str) -> String {
+    format!("turbo:worker:{}:processing", worker)
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn entry_job(entry: &StreamId) -> Option<Job> {
+    let json: String = entry.get("job")?;
+    serde_json::from_str(&json).ok()
+}
+
+/// Move the first job of the highest priority non-empty lane into `processing`.
+async fn take_queued(
+    conn: &mut redis::aio::MultiplexedConnection,
+    processing: &str,
+) -> Result<Option<String>, QueueError> {
+    for lane in LANES {
+        let payload: Option<String> = conn
+            .lmove(
+                lane_key(lane),
+                processing,
+                Direction::Left,
+                Direction::Right,
+            )
+            .await?;
+        if payload.is_some() {
+            return Ok(payload);
+        }
+    }
+    Ok(None)
+}
+
+/// Wake one worker waiting in `pop_job_for`.
+async fn wake(conn: &mut redis::aio::MultiplexedConnection) -> Result<(), QueueError> {
+    let _: () = conn.rpush(WAKE_KEY, 1).await?;
+    let _: () = conn.ltrim(WAKE_KEY, -WAKE_TOKENS_MAX, -1).await?;
+    Ok(())
+}
+
+/// Turn a stored result (JSON, or a pointer to an offloaded file) back into a result.
+async fn load_result(stored: &str) -> Result<JobResult, QueueError> {
+    match stored.strip_prefix(OFFLOAD_POINTER_PREFIX) {
+        Some(path) => {
+            let json = tokio::fs::read_to_string(path).await?;
+            Ok(serde_json::from_str(&json)?)
+        }
+        None => Ok(serde_json::from_str(stored)?),
+    }
 }
 
 #[derive(Clone)]
 pub struct RedisQueue {
     client: redis::Client,
+    mode: QueueMode,
+    /// Set once the consumer group is known to exist (stream mode only)
+    group_ready: Arc<OnceCell<()>>,
+    result_ttl_secs: u64,
+    /// Directory and size threshold for results too large to keep in Redis
+    offload: Option<(PathBuf, usize)>,
 }
 
 impl RedisQueue {
-    pub fn new(redis_url: &str) -> Result<Self, QueueError> {
+    pub fn new(
+        redis_url: &str,
+        mode: QueueMode,
+        results: &ResultsConfig,
+    ) -> Result<Self, QueueError> {
         let client = redis::Client::open(redis_url)?;
-        Ok(Self { client })
+        Ok(Self {
+            client,
+            mode,
+            group_ready: Arc::new(OnceCell::new()),
+            result_ttl_secs: results.ttl_secs,
+            offload: results
+                .offload_threshold_bytes
+                .map(|threshold| (PathBuf::from(&results.offload_dir), threshold)),
+        })
+    }
+
+    pub fn mode(&self) -> QueueMode {
+        self.mode
+    }
+
+    /// Create the stream and its consumer group if this is the first server to use them.
+    async fn ensure_group(
+        &self,
+        conn: &mut redis::aio::MultiplexedConnection,
+    ) -> Result<(), QueueError> {
+        self.group_ready
+            .get_or_try_init(|| async {
+                let created: redis::RedisResult<()> = conn
+                    .xgroup_create_mkstream(STREAM_KEY, STREAM_GROUP, "0")
+                    .await;
+                match created {
+                    Ok(()) => Ok(()),
+                    Err(e) if e.code() == Some("BUSYGROUP") => Ok(()),
+                    Err(e) => Err(QueueError::Redis(e)),
+                }
+            })
+            .await?;
+        Ok(())
+    }
+
+    /// Append a job to the shared queue: its priority lane, or the stream.
+    async fn enqueue(
+        &self,
+        conn: &mut redis::aio::MultiplexedConnection,
+        job_json: &str,
+    ) -> Result<(), QueueError> {
+        match self.mode {
+            QueueMode::List => {
+                let _: () = conn.rpush(payload_lane(job_json), job_json).await?;
+                wake(conn).await?;
+            }
+            QueueMode::Stream => {
+                self.ensure_group(conn).await?;
+                let _: String = conn.xadd(STREAM_KEY, "*", &[("job", job_json)]).await?;
+            }
+        }
+        Ok(())
     }
 
     pub async fn push_job(&self, job: Job) -> Result<(), QueueError> {
         let mut conn = self.client.get_multiplexed_async_connection().await?;
         let job_json = serde_json::to_string(&job)?;
-        let _: () = conn.rpush("turbo:jobs", job_json).await?;
+        self.enqueue(&mut conn, &job_json).await
+    }
+
+    /// Queue a job under a logical key, superseding the previous job with that key.
+    ///
+    /// Returns the key's next sequence number and, if the previous job was still queued,
+    /// that job; it has been removed from the queue and its waiter should be told.
+    /// In `QueueMode::Stream` queued jobs are never removed, so the previous job always
+    /// runs and no job is returned.
+    pub async fn push_job_superseding(
+        &self,
+        key: &str,
+        job: Job,
+    ) -> Result<(u64, Option<Job>), QueueError> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let job_json = serde_json::to_string(&job)?;
+
+        let sequence: u64 = conn.incr(format!("turbo:seq:{}", key), 1).await?;
+        if self.mode == QueueMode::Stream {
+            self.enqueue(&mut conn, &job_json).await?;
+            return Ok((sequence, None));
+        }
+        // The latest job is stored verbatim so it can be matched with LREM
+        let latest_key = format!("turbo:supersede:{}", key);
+        let previous: Option<String> = conn.getset(&latest_key, &job_json).await?;
+        let _: () = conn.expire(&latest_key, 3600).await?;
+        self.enqueue(&mut conn, &job_json).await?;
+
+        let mut cancelled = None;
+        if let Some(previous) = previous {
+            // Zero removed means a worker already took it; it runs to completion.
+            let removed: i64 = conn.lrem(payload_lane(&previous), 1, &previous).await?;
+            if removed > 0 {
+                cancelled = Some(serde_json::from_str(&previous)?);
+            }
+        }
+
+        Ok((sequence, cancelled))
+    }
+
+    /// Remove a job from the queue if no worker has taken it yet; returns the removed job.
+    ///
+    /// In `QueueMode::Stream` queued entries are never removed, so this returns `None`.
+    pub async fn remove_queued(&self, job_id: &str) -> Result<Option<Job>, QueueError> {
+        if self.mode == QueueMode::Stream {
+            return Ok(None);
+        }
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        for lane in LANES {
+            let payloads: Vec<String> = conn.lrange(lane_key(lane), 0, -1).await?;
+            let found = payloads.into_iter().find(|payload| {
+                payload.contains(job_id)
+                    && serde_json::from_str::<Job>(payload).is_ok_and(|job| job.id == job_id)
+            });
+            let Some(payload) = found else {
+                continue;
+            };
+            // Zero removed means a worker took it in the meantime
+            let removed: i64 = conn.lrem(lane_key(lane), 1, &payload).await?;
+            if removed == 0 {
+                return Ok(None);
+            }
+            return Ok(Some(serde_json::from_str(&payload)?));
+        }
+        Ok(None)
+    }
+
+    /// Queue a job for one specific worker (e.g. calibration runs).
+    pub async fn push_job_to_worker(&self, worker: &str, job: Job) -> Result<(), QueueError> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let job_json = serde_json::to_string(&job)?;
+        let _: () = conn
+            .rpush(format!("turbo:worker:{}:jobs", worker), job_json)
+            .await?;
         Ok(())
     }
 
-    pub async fn pop_job(&self) -> Result<Option<Job>, QueueError> {
+    /// Take the next job for `worker`, preferring jobs addressed to it over the shared queue,
+    /// and within the shared queue the highest priority lane that has one.
+    ///
+    /// The job is moved to the worker's processing list rather than removed, and stays
+    /// there until `ack`. Jobs left behind by a crashed worker are put back on the queue
+    /// by `requeue_worker` or `requeue_expired`. Blocks for at most `POP_TIMEOUT_SECS`.
+    ///
+    /// In `QueueMode::Stream` the worker is a consumer in the group instead: entries it
+    /// was delivered but never acked (i.e. before a restart) are redelivered first.
+    pub async fn pop_job_for(&self, worker: &str) -> Result<Option<Delivery>, QueueError> {
+        #[cfg(feature = "chaos")]
+        if let Some(delay) = turbo_core::chaos::pop_delay() {
+            tokio::time::sleep(delay).await;
+        }
         let mut conn = self.client.get_multiplexed_async_connection().await?;
-        let result: Option<(String, String)> = conn.blpop("turbo:jobs", 0.0).await?;
-        match result {
-            Some((_queue, job_json)) => {
-                let job = serde_json::from_str(&job_json)?;
-                Ok(Some(job))
+        let processing = processing_key(worker);
+
+        // Addressed jobs are rare, so check them without blocking and block on the shared queue
+        let mut payload: Option<String> = conn
+            .lmove(
+                format!("turbo:worker:{}:jobs", worker),
+                &processing,
+                Direction::Left,
+                Direction::Right,
+            )
+            .await?;
+        if payload.is_none() && self.mode == QueueMode::Stream {
+            return self.read_stream(&mut conn, worker).await;
+        }
+        if payload.is_none() {
+            payload = take_queued(&mut conn, &processing).await?;
+        }
+        if payload.is_none() {
+            // BLMOVE watches a single list, so wait for a push to any lane instead
+            let _: Option<(String, String)> = conn.blpop(WAKE_KEY, POP_TIMEOUT_SECS).await?;
+            payload = take_queued(&mut conn, &processing).await?;
+        }
+        let Some(payload) = payload else {
+            return Ok(None);
+        };
+
+        let job: Job = match serde_json::from_str(&payload) {
+            Ok(job) => job,
+            Err(e) => {
+                // Drop it rather than redeliver a payload that can never be parsed
+                let _: () = conn.lrem(&processing, 1, &payload).await?;
+                return Err(e.into());
             }
+        };
+        let _: () = conn.hset(CLAIMED_AT_KEY, &job.id, unix_now()).await?;
+
+        Ok(Some(Delivery {
+            job,
+            receipt: Receipt::List(payload),
+        }))
+    }
+
+    /// Stream half of `pop_job_for`: the worker's own pending entries, then new ones.
+    async fn read_stream(
+        &self,
+        conn: &mut redis::aio::MultiplexedConnection,
+        worker: &str,
+    ) -> Result<Option<Delivery>, QueueError> {
+        self.ensure_group(conn).await?;
+
+        // Reading from id 0 returns entries already delivered to this consumer
+        let pending: StreamReadReply = conn
+            .xread_options(
+                &[STREAM_KEY],
+                &["0"],
+                &StreamReadOptions::default()
+                    .group(STREAM_GROUP, worker)
+                    .count(1),
+            )
+            .await?;
+        let mut entry = pending.keys.into_iter().flat_map(|k| k.ids).next();
+        if entry.is_none() {
+            let reply: Option<StreamReadReply> = conn
+                .xread_options(
+                    &[STREAM_KEY],
+                    &[">"],
+                    &StreamReadOptions::default()
+                        .group(STREAM_GROUP, worker)
+                        .count(1)
+                        .block((POP_TIMEOUT_SECS * 1000.0) as usize),
+                )
+                .await?;
+            entry = reply.and_then(|r| r.keys.into_iter().flat_map(|k| k.ids).next());
+        }
+        let Some(entry) = entry else {
+            return Ok(None);
+        };
+
+        match entry_job(&entry) {
+            Some(job) => Ok(Some(Delivery {
+                job,
+                receipt: Receipt::Stream(entry.id),
+            })),
+            None => {
+                // Drop it rather than redeliver an entry that can never be parsed
+                let _: () = conn.xack(STREAM_KEY, STREAM_GROUP, &[&entry.id]).await?;
+                let _: () = conn.xdel(STREAM_KEY, &[&entry.id]).await?;
+                Err(QueueError::Redis(redis::RedisError::from((
+                    redis::ErrorKind::TypeError,
+                    "Unparseable stream entry",
+                    entry.id,
+                ))))
+            }
+        }
+    }
+
+    /// Mark a delivered job as done, removing it from the worker's processing list
+    /// (or its pending entries in `QueueMode::Stream`).
+    pub async fn ack(&self, worker: &str, delivery: &Delivery) -> Result<(), QueueError> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        match &delivery.receipt {
+            Receipt::List(payload) => {
+                let _: () = conn.lrem(processing_key(worker), 1, payload).await?;
+                let _: () = conn.hdel(CLAIMED_AT_KEY, &delivery.job.id).await?;
+            }
+            Receipt::Stream(id) => {
+                let _: () = conn.xack(STREAM_KEY, STREAM_GROUP, &[id]).await?;
+                let _: () = conn.xdel(STREAM_KEY, &[id]).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Put every job in `worker`'s processing list back at the front of the queue.
+    ///
+    /// For a worker that is (re)starting: anything still listed was interrupted.
+    /// Stream entries pending for the worker are not moved; `pop_job_for` redelivers
+    /// them first, and they are included in the returned count.
+    pub async fn requeue_worker(&self, worker: &str) -> Result<usize, QueueError> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+
+        let mut requeued = 0;
+        if self.mode == QueueMode::Stream {
+            self.ensure_group(&mut conn).await?;
+            let pending: StreamPendingCountReply = conn
+                .xpending_consumer_count(
+                    STREAM_KEY,
+                    STREAM_GROUP,
+                    "-",
+                    "+",
+                    PENDING_SCAN_COUNT,
+                    worker,
+                )
+                .await?;
+            requeued += pending.ids.len();
+        }
+        requeued += self.requeue_processing(&mut conn, worker).await?;
+        Ok(requeued)
+    }
+
+    /// Put everything a dead `worker` held back on the queue: its processing list, and in
+    /// stream mode the entries pending for it, which are claimed and re-added as
+    /// `requeue_expired` does once they expire.
+    ///
+    /// The caller must make sure only one server reaps a given worker.
+    pub async fn requeue_dead_worker(&self, worker: &str) -> Result<usize, QueueError> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+
+        let mut requeued = 0;
+        if self.mode == QueueMode::Stream {
+            self.ensure_group(&mut conn).await?;
+            let pending: StreamPendingCountReply = conn
+                .xpending_consumer_count(
+                    STREAM_KEY,
+                    STREAM_GROUP,
+                    "-",
+                    "+",
+                    PENDING_SCAN_COUNT,
+                    worker,
+                )
+                .await?;
+            let ids: Vec<String> = pending.ids.into_iter().map(|p| p.id).collect();
+            if !ids.is_empty() {
+                let claimed: StreamClaimReply = conn
+                    .xclaim(STREAM_KEY, STREAM_GROUP, REQUEUE_CONSUMER, 0, &ids)
+                    .await?;
+                for entry in claimed.ids {
+                    if let Some(json) = entry.get::<String>("job") {
+                        let _: String = conn.xadd(STREAM_KEY, "*", &[("job", json)]).await?;
+                        requeued += 1;
+                    }
+                    let _: () = conn.xack(STREAM_KEY, STREAM_GROUP, &[&entry.id]).await?;
+                    let _: () = conn.xdel(STREAM_KEY, &[&entry.id]).await?;
+                }
+            }
+        }
+        requeued += self.requeue_processing(&mut conn, worker).await?;
+        Ok(requeued)
+    }
+
+    /// Move `worker`'s processing list back to the front of the queue.
+    async fn requeue_processing(
+        &self,
+        conn: &mut redis::aio::MultiplexedConnection,
+        worker: &str,
+    ) -> Result<usize, QueueError> {
+        let processing = processing_key(worker);
+
+        let mut requeued = 0;
+        // Newest first onto the front, so the oldest job ends up first in line
+        loop {
+            // Only its worker, the requeuer and the reaper touch the list; peek to pick the lane
+            let newest: Option<String> = conn.lindex(&processing, -1).await?;
+            let Some(newest) = newest else {
+                break;
+            };
+            // Nothing reads the lanes in stream mode, where the list only ever held jobs
+            // addressed to this worker
+            let target = match self.mode {
+                QueueMode::List => payload_lane(&newest).to_string(),
+                QueueMode::Stream => format!("turbo:worker:{}:jobs", worker),
+            };
+            let payload: Option<String> = conn
+                .lmove(&processing, &target, Direction::Right, Direction::Left)
+                .await?;
+            let Some(payload) = payload else {
+                break;
+            };
+            if let Ok(job) = serde_json::from_str::<Job>(&payload) {
+                let _: () = conn.hdel(CLAIMED_AT_KEY, &job.id).await?;
+            }
+            requeued += 1;
+        }
+        if requeued > 0 {
+            wake(conn).await?;
+        }
+        Ok(requeued)
+    }
+
+    /// Put jobs that `workers` have held for longer than `visibility_timeout` back at the
+    /// front of the queue, on the assumption that their worker died.
+    ///
+    /// Safe to run from several servers at once: a job is only requeued by whichever
+    /// caller removes it from the processing list.
+    pub async fn requeue_expired(
+        &self,
+        workers: &[String],
+        visibility_timeout: Duration,
+    ) -> Result<usize, QueueError> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let now = unix_now();
+
+        let mut requeued = 0;
+        for worker in workers {
+            let processing = processing_key(worker);
+            let payloads: Vec<String> = conn.lrange(&processing, 0, -1).await?;
+            for payload in payloads {
+                let Ok(job) = serde_json::from_str::<Job>(&payload) else {
+                    continue;
+                };
+                // A job moved but not yet stamped starts its clock now
+                let _: () = conn.hset_nx(CLAIMED_AT_KEY, &job.id, now).await?;
+                let claimed_at: Option<u64> = conn.hget(CLAIMED_AT_KEY, &job.id).await?;
+                match claimed_at {
+                    // Acked since the listing was taken
+                    None => continue,
+                    Some(t) if now.saturating_sub(t) < visibility_timeout.as_secs() => continue,
+                    Some(_) => {}
+                }
+
+                let removed: i64 = conn.lrem(&processing, 1, &payload).await?;
+                if removed > 0 {
+                    match self.mode {
+                        QueueMode::List => {
+                            let lane = lane_key(job.request.priority.unwrap_or_default());
+                            let _: () = conn.lpush(lane, &payload).await?;
+                            wake(&mut conn).await?;
+                        }
+                        QueueMode::Stream => self.enqueue(&mut conn, &payload).await?,
+                    }
+                    let _: () = conn.hdel(CLAIMED_AT_KEY, &job.id).await?;
+                    requeued += 1;
+                }
+            }
+        }
+        if self.mode == QueueMode::Stream {
+            requeued += self
+                .requeue_expired_entries(&mut conn, visibility_timeout)
+                .await?;
+        }
+        Ok(requeued)
+    }
+
+    /// Re-add stream entries pending for longer than `visibility_timeout` to the end of
+    /// the stream, whichever consumer held them.
+    ///
+    /// Each entry is first claimed for `REQUEUE_CONSUMER`, which resets its idle time, so
+    /// concurrent callers cannot both re-add it.
+    async fn requeue_expired_entries(
+        &self,
+        conn: &mut redis::aio::MultiplexedConnection,
+        visibility_timeout: Duration,
+    ) -> Result<usize, QueueError> {
+        self.ensure_group(conn).await?;
+        let min_idle_ms = visibility_timeout.as_millis() as usize;
+
+        let pending: StreamPendingCountReply = conn
+            .xpending_count(STREAM_KEY, STREAM_GROUP, "-", "+", PENDING_SCAN_COUNT)
+            .await?;
+        let expired: Vec<String> = pending
+            .ids
+            .into_iter()
+            .filter(|p| p.last_delivered_ms >= min_idle_ms)
+            .map(|p| p.id)
+            .collect();
+        if expired.is_empty() {
+            return Ok(0);
+        }
+
+        let claimed: StreamClaimReply = conn
+            .xclaim(
+                STREAM_KEY,
+                STREAM_GROUP,
+                REQUEUE_CONSUMER,
+                min_idle_ms,
+                &expired,
+            )
+            .await?;
+        let mut requeued = 0;
+        for entry in claimed.ids {
+            if let Some(json) = entry.get::<String>("job") {
+                let _: String = conn.xadd(STREAM_KEY, "*", &[("job", json)]).await?;
+                requeued += 1;
+            }
+            let _: () = conn.xack(STREAM_KEY, STREAM_GROUP, &[&entry.id]).await?;
+            let _: () = conn.xdel(STREAM_KEY, &[&entry.id]).await?;
+        }
+        Ok(requeued)
+    }
+
+    /// Languages of the queued jobs a new job of `priority` would wait behind, in the
+    /// order workers will take them. The stream has no lanes, so there it is every job.
+    pub async fn queued_languages(&self, priority: Priority) -> Result<Vec<String>, QueueError> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        if self.mode == QueueMode::Stream {
+            return self.queued_stream_languages(&mut conn).await;
+        }
+        let mut jobs: Vec<String> = Vec::new();
+        for lane in LANES.into_iter().filter(|lane| *lane >= priority) {
+            let queued: Vec<String> = conn.lrange(lane_key(lane), 0, -1).await?;
+            jobs.extend(queued);
+        }
+        Ok(jobs
+            .iter()
+            .filter_map(|json| serde_json::from_str::<Job>(json).ok())
+            .map(|job| job.request.language)
+            .collect())
+    }
+
+    /// Jobs waiting for a worker per priority lane, highest first. In `QueueMode::Stream`
+    /// every priority shares the stream, so all waiting entries are counted as `Normal`.
+    pub async fn queue_depths(&self) -> Result<Vec<(Priority, usize)>, QueueError> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        if self.mode == QueueMode::Stream {
+            let waiting = self.queued_stream_languages(&mut conn).await?.len();
+            return Ok(LANES
+                .into_iter()
+                .map(|lane| (lane, if lane == Priority::Normal { waiting } else { 0 }))
+                .collect());
+        }
+        let mut depths = Vec::with_capacity(LANES.len());
+        for lane in LANES {
+            let depth: usize = conn.llen(lane_key(lane)).await?;
+            depths.push((lane, depth));
+        }
+        Ok(depths)
+    }
+
+    /// Entries after the group's last delivered id have not been read by any worker yet.
+    async fn queued_stream_languages(
+        &self,
+        conn: &mut redis::aio::MultiplexedConnection,
+    ) -> Result<Vec<String>, QueueError> {
+        self.ensure_group(conn).await?;
+        let info: StreamInfoGroupsReply = conn.xinfo_groups(STREAM_KEY).await?;
+        let start = info
+            .groups
+            .into_iter()
+            .find(|g| g.name == STREAM_GROUP)
+            .map(|g| format!("({}", g.last_delivered_id))
+            .unwrap_or_else(|| "-".to_string());
+
+        let waiting: StreamRangeReply = conn.xrange(STREAM_KEY, start, "+").await?;
+        Ok(waiting
+            .ids
+            .iter()
+            .filter_map(entry_job)
+            .map(|job| job.request.language)
+            .collect())
+    }
+
+    /// Result of a finished job, if it completed within the result TTL.
+    pub async fn get_result(&self, job_id: &str) -> Result<Option<JobResult>, QueueError> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let stored: Option<String> = conn.get(format!("turbo:result:{}", job_id)).await?;
+        match stored {
+            Some(stored) => Ok(Some(load_result(&stored).await?)),
             None => Ok(None),
         }
     }
 
     pub async fn publish_result(&self, job_id: &str, result: &JobResult) -> Result<(), QueueError> {
+        #[cfg(feature = "chaos")]
+        if turbo_core::chaos::inject(turbo_core::chaos::Fault::DropResult) {
+            return Ok(());
+        }
         let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let mut stored = serde_json::to_string(result)?;
+        if let Some((dir, threshold)) = &self.offload
+            && stored.len() > *threshold
+        {
+            // Written before the pointer is published, so readers always find the file
+            tokio::fs::create_dir_all(dir).await?;
+            let path = dir.join(format!("{}.json", job_id));
+            tokio::fs::write(&path, &stored).await?;
+            stored = format!("{}{}", OFFLOAD_POINTER_PREFIX, path.display());
+        }
+        let _: () = conn
+            .publish(format!("turbo:job:{}", job_id), &stored)
+            .await?;
+        let _: () = conn
+            .set_ex(
+                format!("turbo:result:{}", job_id),
+                stored,
+                self.result_ttl_secs,
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Keep `result` under the dedup `key` for `ttl_secs`, for identical jobs to reuse.
+    /// Results above the offload threshold are not kept.
+    pub async fn cache_result(
+        &self,
+        key: &str,
+        result: &JobResult,
+        ttl_secs: u64,
+    ) -> Result<(), QueueError> {
         let json = serde_json::to_string(result)?;
-        let _: () = conn.publish(format!("turbo:job:{}", job_id), &json).await?;
+        if let Some((_, threshold)) = &self.offload
+            && json.len() > *threshold
+        {
+            return Ok(());
+        }
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
         let _: () = conn
-            .set_ex(format!("turbo:result:{}", job_id), json, 3600_u64)
+            .set_ex(format!("turbo:dedup:{}", key), json, ttl_secs)
             .await?;
         Ok(())
     }
 
+    /// Result cached under the dedup `key` by `cache_result`, if still within its window.
+    pub async fn get_cached_result(&self, key: &str) -> Result<Option<JobResult>, QueueError> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let json: Option<String> = conn.get(format!("turbo:dedup:{}", key)).await?;
+        match json {
+            Some(json) => Ok(Some(serde_json::from_str(&json)?)),
+            None => Ok(None),
+        }
+    }
+
     pub async fn wait_for_result(&self, job_id: &str) -> Result<JobResult, QueueError> {
         #[allow(deprecated)]
         let conn = self.client.get_async_connection().await?;
@@ -60,13 +787,13 @@ impl RedisQueue {
         // Check existing
         let mut multiplexed = self.client.get_multiplexed_async_connection().await?;
         let existing: Option<String> = multiplexed.get(format!("turbo:result:{}", job_id)).await?;
-        if let Some(json) = existing {
-            return Ok(serde_json::from_str(&json)?);
+        if let Some(stored) = existing {
+            return load_result(&stored).await;
         }
 
         if let Some(msg) = pubsub.on_message().next().await {
             let payload: String = msg.get_payload()?;
-            return Ok(serde_json::from_str(&payload)?);
+            return load_result(&payload).await;
         }
 
         Err(QueueError::Redis(redis::RedisError::from((
@@ -75,3 +802,19 @@ impl RedisQueue {
         ))))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queue_mode_from_str() {
+        assert_eq!("list".parse::<QueueMode>().unwrap(), QueueMode::List);
+        assert_eq!("stream".parse::<QueueMode>().unwrap(), QueueMode::Stream);
+        assert!(matches!(
+            "List".parse::<QueueMode>(),
+            Err(QueueError::UnsupportedMode(mode)) if mode == "List"
+        ));
+        assert!("".parse::<QueueMode>().is_err());
+    }
+}
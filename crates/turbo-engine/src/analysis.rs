@@ -0,0 +1,203 @@
+//! Memory-error reports of the `analysis` tools, parsed out of the run's stderr.
+//!
+//! Valgrind prefixes its lines with `==<pid>== `; an error is an unindented line such as
+//! `Invalid read of size 4`, followed by indented `at`/`by` frames ending in
+//! `(file:line)`, and leaks are the `... are definitely lost in loss record ...` blocks
+//! of `--leak-check=full`. AddressSanitizer reports `==<pid>==ERROR: AddressSanitizer:
+//! <bug> on address ...` and LeakSanitizer `Direct leak of N byte(s) ...`, each followed
+//! by `#N 0x... in function file:line:col` frames.
+
+use crate::diagnostics::submitted;
+use turbo_core::models::{AnalysisReport, AnalysisTool, MemoryError};
+
+/// Starts of the valgrind memcheck messages reported as errors.
+const VALGRIND_ERRORS: &[&str] = &[
+    "Invalid ",
+    "Mismatched free",
+    "Conditional jump or move depends on uninitialised",
+    "Use of uninitialised value",
+    "Syscall param",
+    "Source and destination overlap",
+    "Argument 'size' of function",
+    "Process terminating with default action",
+];
+
+/// The report `tool` printed to `stderr`, with each error located in the innermost frame
+/// in one of `files` (the submitted file names).
+pub fn parse(tool: AnalysisTool, stderr: &str, files: &[&str]) -> AnalysisReport {
+    let (errors, leaked_bytes) = match tool {
+        AnalysisTool::Valgrind => parse_valgrind(stderr, files),
+        AnalysisTool::Asan => parse_asan(stderr, files),
+    };
+    AnalysisReport {
+        tool,
+        errors,
+        leaked_bytes,
+    }
+}
+
+fn parse_valgrind(stderr: &str, files: &[&str]) -> (Vec<MemoryError>, u64) {
+    let mut errors = Vec::new();
+    let mut leaked_bytes = 0;
+    for body in stderr.lines().filter_map(valgrind_body) {
+        let text = body.trim();
+        if body.starts_with(char::is_whitespace) {
+            if let Some(frame) = text
+                .strip_prefix("at ")
+                .or_else(|| text.strip_prefix("by "))
+            {
+                // `0x10916B: main (main.c:6)`
+                let place = frame
+                    .rsplit_once(" (")
+                    .and_then(|(_, place)| place.strip_suffix(')'));
+                if let Some(place) = place {
+                    locate(&mut errors, place, files);
+                }
+            }
+            for kind in ["definitely lost: ", "indirectly lost: "] {
+                if let Some(bytes) = text.strip_prefix(kind).and_then(byte_count) {
+                    leaked_bytes += bytes;
+                }
+            }
+        } else if VALGRIND_ERRORS.iter().any(|start| text.starts_with(start))
+            || text.contains(" lost in loss record ")
+        {
+            errors.push(error(text));
+        }
+    }
+    (errors, leaked_bytes)
+}
+
+/// What follows the `==<pid>== ` prefix of a valgrind line.
+fn valgrind_body(line: &str) -> Option<&str> {
+    let (pid, body) = line.strip_prefix("==")?.split_once("==")?;
+    if pid.is_empty() || !pid.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some(body.strip_prefix(' ').unwrap_or(body))
+}
+
+fn parse_asan(stderr: &str, files: &[&str]) -> (Vec<MemoryError>, u64) {
+    let mut errors = Vec::new();
+    let mut leaked_bytes = 0;
+    for line in stderr.lines() {
+        let text = line.trim();
+        if let Some((_, bug)) = text.split_once("ERROR: AddressSanitizer: ") {
+            // Drop the registers: `... on address 0x602000000014 at pc 0x... bp ... sp ...`
+            errors.push(error(bug.split(" at pc ").next().unwrap_or(bug)));
+        } else if text.starts_with("Direct leak of ") || text.starts_with("Indirect leak of ") {
+            errors.push(error(text.trim_end_matches(':')));
+        } else if let Some(summary) = text.strip_prefix("SUMMARY: AddressSanitizer: ") {
+            if let Some((bytes, _)) = summary.split_once(" byte(s) leaked") {
+                leaked_bytes = bytes.trim().parse().unwrap_or(0);
+            }
+        } else if text.starts_with('#') {
+            // `#1 0x4f5 in main /box/main.c:5:3`
+            if let Some(place) = text.rsplit(' ').next() {
+                locate(&mut errors, place, files);
+            }
+        }
+    }
+    (errors, leaked_bytes)
+}
+
+fn error(message: &str) -> MemoryError {
+    MemoryError {
+        message: message.to_string(),
+        file: None,
+        line: None,
+    }
+}
+
+/// Sets the location of the last error to `place` (`file:line[:col]`) if it has none yet
+/// and `place` is in a submitted file.
+fn locate(errors: &mut [MemoryError], place: &str, files: &[&str]) {
+    let Some(error) = errors.last_mut().filter(|e| e.file.is_none()) else {
+        return;
+    };
+    let mut parts = place.split(':');
+    let (Some(path), Some(line)) = (parts.next(), parts.next()) else {
+        return;
+    };
+    if let (Some(file), Ok(line)) = (submitted(path, files), line.parse()) {
+        error.file = Some(file);
+        error.line = Some(line);
+    }
+}
+
+/// Bytes in `1,024 bytes in 2 blocks`.
+fn byte_count(text: &str) -> Option<u64> {
+    let (bytes, _) = text.split_once(" bytes")?;
+    bytes.replace(',', "").parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valgrind_errors_and_leaks() {
+        let stderr = "\
+==1234== Memcheck, a memory error detector
+==1234== Invalid read of size 4
+==1234==    at 0x10916B: helper (/usr/lib/libc.so.6)
+==1234==    by 0x109180: main (main.c:6)
+==1234==  Address 0x4a8d044 is 0 bytes after a block of size 4 alloc'd
+==1234== 40 bytes in 1 blocks are definitely lost in loss record 1 of 1
+==1234==    at 0x483877F: malloc (vg_replace_malloc.c:307)
+==1234==    by 0x10915E: main (main.c:4)
+==1234== LEAK SUMMARY:
+==1234==    definitely lost: 1,024 bytes in 2 blocks
+==1234==    indirectly lost: 16 bytes in 1 blocks
+hello from the program
+";
+        let report = parse(AnalysisTool::Valgrind, stderr, &["main.c"]);
+        assert_eq!(report.leaked_bytes, 1040);
+        assert_eq!(report.errors.len(), 2);
+        assert_eq!(report.errors[0].message, "Invalid read of size 4");
+        assert_eq!(report.errors[0].file.as_deref(), Some("main.c"));
+        assert_eq!(report.errors[0].line, Some(6));
+        assert!(report.errors[1].message.contains("definitely lost"));
+        assert_eq!(report.errors[1].line, Some(4));
+    }
+
+    #[test]
+    fn asan_errors_and_leaks() {
+        let stderr = "\
+==42==ERROR: AddressSanitizer: heap-buffer-overflow on address 0x602000000014 at pc 0x4f5 bp 0x7ff sp 0x7fe
+READ of size 4 at 0x602000000014 thread T0
+    #0 0x4f5 in main /box/submission/main.c:5:3
+    #1 0x7f0 in __libc_start_main (/lib/libc.so.6+0x21b96)
+Direct leak of 8 byte(s) in 1 object(s) allocated from:
+    #0 0x4a0 in malloc (/usr/lib/libasan.so+0x10)
+    #1 0x4f0 in make /box/submission/util.c:3:10
+SUMMARY: AddressSanitizer: 8 byte(s) leaked in 1 allocation(s).
+";
+        let report = parse(AnalysisTool::Asan, stderr, &["main.c", "util.c"]);
+        assert_eq!(report.leaked_bytes, 8);
+        assert_eq!(report.errors.len(), 2);
+        assert_eq!(
+            report.errors[0].message,
+            "heap-buffer-overflow on address 0x602000000014"
+        );
+        assert_eq!(report.errors[0].file.as_deref(), Some("main.c"));
+        assert_eq!(report.errors[0].line, Some(5));
+        assert_eq!(
+            report.errors[1].message,
+            "Direct leak of 8 byte(s) in 1 object(s) allocated from"
+        );
+        assert_eq!(report.errors[1].file.as_deref(), Some("util.c"));
+        assert_eq!(report.errors[1].line, Some(3));
+    }
+
+    #[test]
+    fn clean_run_reports_nothing() {
+        let report = parse(
+            AnalysisTool::Valgrind,
+            "==7== ERROR SUMMARY: 0 errors\n",
+            &[],
+        );
+        assert!(report.errors.is_empty());
+        assert_eq!(report.leaked_bytes, 0);
+    }
+}
@@ -0,0 +1,253 @@
+//! Compiler and interpreter messages parsed out of a stage's stderr, for editors to
+//! underline.
+//!
+//! Recognised formats:
+//! - gcc and clang: `file:line:col: error: message`
+//! - javac: `file:line: error: message`
+//! - rustc: `error[E0425]: message` followed by ` --> file:line:col`
+//! - Python: the innermost frame of a traceback (`File "file", line N`) in a submitted
+//!   file, with the exception line as the message
+//!
+//! Anything else is skipped, including messages about files that were not submitted
+//! (system headers, the standard library).
+
+use turbo_core::models::{Diagnostic, Severity};
+
+/// Messages in `stderr` about `files` (the submitted file names), in order of appearance.
+/// Paths the toolchain made absolute are turned back into the submitted names.
+pub fn parse(stderr: &str, files: &[&str]) -> Vec<Diagnostic> {
+    let lines: Vec<&str> = stderr.lines().collect();
+    let mut diagnostics = Vec::new();
+    let mut frame: Option<(String, u32)> = None;
+
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        i += 1;
+
+        if let Some((path, line_no)) = python_frame(line) {
+            if let Some(file) = submitted(path, files) {
+                frame = Some((file, line_no));
+            }
+            continue;
+        }
+        if let Some((file, line_no)) = frame.as_ref().filter(|_| is_exception(line)) {
+            diagnostics.push(Diagnostic {
+                file: file.clone(),
+                line: *line_no,
+                column: None,
+                severity: Severity::Error,
+                message: line.trim().to_string(),
+            });
+            frame = None;
+            continue;
+        }
+
+        if let Some((path, line_no, column, severity, message)) = located(line) {
+            if let Some(file) = submitted(path, files) {
+                diagnostics.push(Diagnostic {
+                    file,
+                    line: line_no,
+                    column,
+                    severity,
+                    message: message.to_string(),
+                });
+            }
+            continue;
+        }
+
+        // rustc puts the location on the next line
+        let Some((severity, message)) = rustc_header(line) else {
+            continue;
+        };
+        let location = lines
+            .get(i)
+            .and_then(|next| next.trim_start().strip_prefix("--> "))
+            .and_then(location);
+        if let Some((path, line_no, column)) = location {
+            i += 1;
+            if let Some(file) = submitted(path, files) {
+                diagnostics.push(Diagnostic {
+                    file,
+                    line: line_no,
+                    column,
+                    severity,
+                    message: message.to_string(),
+                });
+            }
+        }
+    }
+    diagnostics
+}
+
+/// `path:line:col: severity: message` (gcc, clang) or `path:line: severity: message`
+/// (javac).
+fn located(line: &str) -> Option<(&str, u32, Option<u32>, Severity, &str)> {
+    let (place, rest) = line.split_once(": ")?;
+    let (path, line_no, column) = location(place)?;
+    let (severity, message) = severity(rest)?;
+    Some((path, line_no, column, severity, message))
+}
+
+/// `error[E0425]: message` or `warning: message`, as rustc starts a message.
+fn rustc_header(line: &str) -> Option<(Severity, &str)> {
+    let (head, message) = line.split_once(": ")?;
+    let severity = match head.split('[').next()? {
+        "error" => Severity::Error,
+        "warning" => Severity::Warning,
+        _ => return None,
+    };
+    Some((severity, message))
+}
+
+/// `path:line:col` or `path:line`.
+fn location(place: &str) -> Option<(&str, u32, Option<u32>)> {
+    let (rest, last) = place.rsplit_once(':')?;
+    let last = last.parse().ok()?;
+    if let Some((path, line_no)) = rest.rsplit_once(':')
+        && let Ok(line_no) = line_no.parse()
+    {
+        return Some((path, line_no, Some(last))).filter(|(path, ..)| !path.is_empty());
+    }
+    Some((rest, last, None)).filter(|(path, ..)| !path.is_empty())
+}
+
+/// Severity and message of `error: message`, `fatal error: message`,
+/// `warning: message` or `note: message`.
+fn severity(rest: &str) -> Option<(Severity, &str)> {
+    [
+        ("error: ", Severity::Error),
+        ("fatal error: ", Severity::Error),
+        ("warning: ", Severity::Warning),
+        ("note: ", Severity::Note),
+    ]
+    .into_iter()
+    .find_map(|(prefix, severity)| Some((severity, rest.strip_prefix(prefix)?)))
+}
+
+/// Path and line of a traceback line `  File "path", line N[, in name]`.
+fn python_frame(line: &str) -> Option<(&str, u32)> {
+    let rest = line.trim_start().strip_prefix("File \"")?;
+    let (path, rest) = rest.split_once("\", line ")?;
+    let line_no = rest.split(',').next()?.trim().parse().ok()?;
+    Some((path, line_no))
+}
+
+/// Whether `line` is the `Name: message` (or bare `Name`) line ending a traceback.
+fn is_exception(line: &str) -> bool {
+    if line.starts_with(char::is_whitespace) {
+        return false;
+    }
+    let name = line.split(':').next().unwrap_or_default();
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+/// The submitted name `path` refers to, if any: the name itself, or a path ending in it.
+pub(crate) fn submitted(path: &str, files: &[&str]) -> Option<String> {
+    let path = path.strip_prefix("./").unwrap_or(path);
+    files
+        .iter()
+        .find(|name| {
+            path == **name
+                || path
+                    .strip_suffix(**name)
+                    .is_some_and(|dir| dir.ends_with('/'))
+        })
+        .map(|name| name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn place(diagnostic: &Diagnostic) -> (&str, u32, Option<u32>, Severity) {
+        (
+            diagnostic.file.as_str(),
+            diagnostic.line,
+            diagnostic.column,
+            diagnostic.severity,
+        )
+    }
+
+    #[test]
+    fn gcc_and_javac() {
+        let stderr = "\
+/box/submission/main.c: In function 'main':
+/box/submission/main.c:4:5: error: 'x' undeclared (first use in this function)
+/usr/include/stdio.h:12:1: note: declared here
+main.c:6:12: warning: unused variable 'y' [-Wunused-variable]
+Main.java:3: error: ';' expected
+";
+        let diagnostics = parse(stderr, &["main.c", "Main.java"]);
+        assert_eq!(diagnostics.len(), 3);
+        assert_eq!(
+            place(&diagnostics[0]),
+            ("main.c", 4, Some(5), Severity::Error)
+        );
+        assert_eq!(
+            diagnostics[0].message,
+            "'x' undeclared (first use in this function)"
+        );
+        assert_eq!(
+            place(&diagnostics[1]),
+            ("main.c", 6, Some(12), Severity::Warning)
+        );
+        assert_eq!(
+            place(&diagnostics[2]),
+            ("Main.java", 3, None, Severity::Error)
+        );
+    }
+
+    #[test]
+    fn rustc_location_on_next_line() {
+        let stderr = "\
+error[E0425]: cannot find value `x` in this scope
+ --> src/main.rs:2:20
+  |
+warning: unused variable: `y`
+ --> /rustc/library/core/src/lib.rs:1:1
+";
+        let diagnostics = parse(stderr, &["src/main.rs"]);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            place(&diagnostics[0]),
+            ("src/main.rs", 2, Some(20), Severity::Error)
+        );
+        assert_eq!(
+            diagnostics[0].message,
+            "cannot find value `x` in this scope"
+        );
+    }
+
+    #[test]
+    fn python_traceback_innermost_submitted_frame() {
+        let stderr = "\
+Traceback (most recent call last):
+  File \"/box/submission/main.py\", line 5, in <module>
+    helper()
+  File \"/box/submission/util.py\", line 2, in helper
+    return 1 / 0
+  File \"/usr/lib/python3.12/fractions.py\", line 9, in __truediv__
+ZeroDivisionError: division by zero
+";
+        let diagnostics = parse(stderr, &["main.py", "util.py"]);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            place(&diagnostics[0]),
+            ("util.py", 2, None, Severity::Error)
+        );
+        assert_eq!(
+            diagnostics[0].message,
+            "ZeroDivisionError: division by zero"
+        );
+    }
+
+    #[test]
+    fn unrelated_output_is_skipped() {
+        assert!(parse("make: *** [all] Error 1\nhello: world\n", &["main.c"]).is_empty());
+        assert!(parse("other.c:1:1: error: oops\n", &["main.c"]).is_empty());
+    }
+}
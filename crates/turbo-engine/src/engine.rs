@@ -0,0 +1,84 @@
+use crate::execute::{LimitCaps, execute_job, fail_job};
+use crate::warm::WarmPool;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use turbo_box::{Sandbox, SandboxPool};
+use turbo_core::config::TurboConfig;
+use turbo_core::models::{JobRequest, JobResult};
+
+/// Runs jobs in-process on a pool of sandboxes.
+///
+/// Each `execute` holds one pool session for the whole job, so at most as many jobs as
+/// the pool has sessions run at once and further calls wait for one to free up. The
+/// sessions are the `pool-{n}` sandboxes a server's workers use, so do not run an engine
+/// beside workers sharing its `sandbox.root_path`.
+pub struct Engine {
+    pool: Arc<SandboxPool<dyn Sandbox>>,
+    runtimes_dir: PathBuf,
+    caps: LimitCaps,
+    /// Warm pools of jobs not running now; each running job takes one
+    warm: Mutex<Vec<WarmPool>>,
+    /// Warm pools created so far, to number the next one
+    warm_created: AtomicUsize,
+}
+
+impl Engine {
+    /// Engine on the `sandbox` backend of `config` with `sandbox.max_concurrent_jobs`
+    /// sessions, running the runtimes installed under `TURBO_HOME/runtimes`.
+    pub async fn new(config: &TurboConfig) -> anyhow::Result<Self> {
+        let sandbox = turbo_box::create_sandbox(&config.sandbox)?;
+        let pool = SandboxPool::new(sandbox, config.sandbox.max_concurrent_jobs).await?;
+        let runtimes_dir = PathBuf::from(&config.paths.turbo_home).join("runtimes");
+        Ok(Self::with_pool(
+            Arc::new(pool),
+            runtimes_dir,
+            LimitCaps::from_config(config),
+        ))
+    }
+
+    pub fn with_pool(
+        pool: Arc<SandboxPool<dyn Sandbox>>,
+        runtimes_dir: PathBuf,
+        caps: LimitCaps,
+    ) -> Self {
+        Self {
+            pool,
+            runtimes_dir,
+            caps,
+            warm: Mutex::new(Vec::new()),
+            warm_created: AtomicUsize::new(0),
+        }
+    }
+
+    /// Run `req` and return its result. Failures of the engine itself are reported in the
+    /// result as an `InternalError` run stage, as a server would.
+    pub async fn execute(&self, req: JobRequest) -> JobResult {
+        let session = match self.pool.acquire().await {
+            Ok(session) => session,
+            Err(e) => return fail_job(&req, format!("No sandbox session: {}", e)),
+        };
+        let mut warm = self.take_warm();
+        let result = execute_job(
+            &req,
+            &self.pool,
+            &session,
+            &mut warm,
+            &self.runtimes_dir,
+            &self.caps,
+        )
+        .await;
+        self.warm
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(warm);
+        self.pool.release(session).await;
+        result
+    }
+
+    /// A warm pool no running job holds, or a new one.
+    fn take_warm(&self) -> WarmPool {
+        let idle = self.warm.lock().unwrap_or_else(|e| e.into_inner()).pop();
+        idle.unwrap_or_else(|| WarmPool::new(self.warm_created.fetch_add(1, Ordering::Relaxed)))
+    }
+}
@@ -0,0 +1,1539 @@
+//! Running one job in a sandbox: files, runtime, compile cache, compile stage, and the
+//! run stage as a single run, testcases or a benchmark.
+
+use crate::warm::{self, WarmPool};
+use crate::{analysis, diagnostics, git};
+use base64::Engine;
+use futures_util::future::join_all;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tokio::fs;
+use tracing::{error, info};
+use turbo_box::{CommandSpec, Protocol, Sandbox, SandboxPool, Session, WarmProcess};
+use turbo_core::config::{LanguageLimits, TurboConfig};
+use turbo_core::models::{
+    BenchmarkRequest, BenchmarkResult, BenchmarkRun, BenchmarkStats, Buffering, Diagnostic,
+    EffectiveLimits, ExecutionLimits, FileRequest, JobRequest, JobResult, StageLimits, StageResult,
+    StageStatus, Testcase, TestcaseResult,
+};
+use turbo_pkg::models::PackageDefinition;
+use turbo_pkg::repository::find_alias;
+
+/// Compile stage stdout when the compiled files were restored from the compile cache.
+pub const RESTORED_FROM_CACHE: &str = "Restored from cache";
+/// Appended to a sandbox's working directory to name the directory beside it holding the
+/// compiled workspace each of a job's testcases starts from
+pub const SNAPSHOT_SUFFIX: &str = ".pristine";
+/// Most of a testcase's actual and expected output, and of its diff, its result repeats
+const PREVIEW_BYTES: usize = 4096;
+/// Unchanged lines shown around a failed testcase's differing lines
+const DIFF_CONTEXT: usize = 3;
+/// Most removed, and most added, lines a failed testcase's diff shows
+const DIFF_MAX_LINES: usize = 20;
+/// How long an interactive exchange may sit idle when the job does not say
+const DEFAULT_MESSAGE_TIMEOUT_MS: u64 = 1000;
+
+/// Install directory of `lang` (a language or one of its aliases) at `ver`.
+pub fn get_runtime_path(runtimes_dir: &Path, lang: &str, ver: &str) -> PathBuf {
+    let lang = find_alias(runtimes_dir, lang).unwrap_or_else(|| lang.to_string());
+    runtimes_dir.join(lang).join(ver)
+}
+
+/// Server-wide maximums on the limits a job may request, from the `max_limits` and
+/// `sandbox` config, and the defaults for limits it leaves unset.
+#[derive(Debug, Clone)]
+pub struct LimitCaps {
+    pub memory_limit_bytes: u64,
+    pub timeout_ms: Option<u64>,
+    pub pid_limit: Option<u64>,
+    pub output_limit_bytes: Option<u64>,
+    pub testcase_parallelism: usize,
+    pub total_time_ms: Option<u64>,
+    /// Most measured runs, and most warmup runs, of a benchmark
+    pub benchmark_iterations: u32,
+    pub defaults: LimitDefaults,
+    /// `[limits.<language>]` defaults, by runtime name or alias
+    pub languages: HashMap<String, LanguageLimits>,
+}
+
+impl LimitCaps {
+    /// Caps and defaults from the `limits`, `max_limits` and `sandbox` config.
+    pub fn from_config(config: &TurboConfig) -> Self {
+        let limits = &config.limits;
+        let max = config.max_limits.or_sandbox(&config.sandbox);
+        Self {
+            memory_limit_bytes: max.memory_limit_mb.unwrap_or_default() * 1024 * 1024,
+            timeout_ms: max.timeout_ms,
+            pid_limit: max.pid_limit,
+            output_limit_bytes: max.output_limit_bytes,
+            testcase_parallelism: config.sandbox.max_testcase_parallelism.max(1),
+            total_time_ms: config.sandbox.max_total_time_ms,
+            benchmark_iterations: config.sandbox.max_benchmark_iterations.max(1),
+            defaults: LimitDefaults {
+                run_timeout_ms: limits.run_timeout_ms,
+                compile_timeout_ms: limits.compile_timeout_ms,
+                memory_limit_bytes: limits.memory_limit_mb * 1024 * 1024,
+                pid_limit: limits.pid_limit,
+                output_limit_bytes: limits.output_limit_bytes,
+            },
+            languages: limits.languages.clone(),
+        }
+    }
+}
+
+/// Limits for stages that neither the request nor the runtime set, from `limits`.
+#[derive(Debug, Clone, Copy)]
+pub struct LimitDefaults {
+    pub run_timeout_ms: u64,
+    pub compile_timeout_ms: u64,
+    pub memory_limit_bytes: u64,
+    pub pid_limit: u64,
+    pub output_limit_bytes: u64,
+}
+
+/// Pure-CPU shell loop used as the calibration workload, so no runtime needs installing.
+const CALIBRATION_SCRIPT: &str = "i=0; while [ $i -lt 300000 ]; do i=$((i+1)); done";
+
+/// Runs the standard calibration workload in the sandbox and reports it as the run stage.
+pub async fn run_calibration(
+    req: &JobRequest,
+    sandbox: &dyn Sandbox,
+    session: &Session,
+) -> JobResult {
+    let args = vec!["-c".to_string(), CALIBRATION_SCRIPT.to_string()];
+    let limits = ExecutionLimits {
+        timeout_ms: 60_000,
+        ..Default::default()
+    };
+
+    let spec = CommandSpec {
+        cmd: "sh",
+        args: &args,
+        ..Default::default()
+    };
+    match sandbox.exec(session, spec, Some(limits)).await {
+        Ok(res) => JobResult {
+            language: req.language.clone(),
+            version: req.version.clone().unwrap_or_default(),
+            run: Some(res),
+            compile: None,
+            diagnostics: None,
+            analysis: None,
+            testcases: None,
+            effective_limits: None,
+            cached: false,
+            benchmark: None,
+            interactor: None,
+            metadata: req.metadata.clone(),
+        },
+        Err(e) => fail_job(req, format!("Calibration failed: {}", e)),
+    }
+}
+
+/// Executes a single job within the sandbox.
+///
+/// 1. Writes the source files into the sandbox's working directory.
+/// 2. Resolves the runtime package (e.g., Python, C++).
+/// 3. Compiles the code (if `build.sh` exists).
+/// 4. Runs the code (single run or batched testcases).
+///
+/// Compile and every run share `session`, an open pool session; its reset is the
+/// caller's job. Testcases may also run on spare sessions of `pool`; see `run_testcases`.
+/// Runtimes with a `warm_pool` are run through a pre-started process from `warm` when
+/// one is ready, falling back to a cold start otherwise.
+/// Limits are resolved once per job by `resolve_limits` and reported in the result.
+pub async fn execute_job(
+    req: &JobRequest,
+    pool: &SandboxPool<dyn Sandbox>,
+    session: &Session,
+    warm: &mut WarmPool,
+    runtimes_dir: &Path,
+    caps: &LimitCaps,
+) -> JobResult {
+    let started = Instant::now();
+    let sandbox = pool.sandbox();
+
+    // Working state lives in the sandbox's own directory under `sandbox.root_path`
+    let temp_dir = sandbox.work_dir(session.id());
+    if let Err(e) = fs::create_dir_all(&temp_dir).await {
+        return fail_job(req, format!("Failed to create temp dir: {}", e));
+    }
+
+    // Files are written over the checkout
+    let source_commit = match &req.source {
+        Some(source) => match git::checkout(&source.git, &temp_dir).await {
+            Ok(commit) => Some(commit),
+            Err(e) => {
+                return fail_job(
+                    req,
+                    format!("Failed to check out {}: {}", source.git.url, e),
+                );
+            }
+        },
+        None => None,
+    };
+
+    for file in &req.files {
+        let name = file.name.as_deref().unwrap_or("main");
+        let content = match file_bytes(file) {
+            Ok(content) => content,
+            Err(e) => return fail_job(req, format!("Failed to decode file {}: {}", name, e)),
+        };
+        if let Err(e) = sandbox.write_file(session, Path::new(name), &content).await {
+            return fail_job(req, format!("Failed to write file {}: {}", name, e));
+        }
+    }
+
+    let version = req.version.as_deref().unwrap_or("latest");
+    let runtime_path = get_runtime_path(runtimes_dir, &req.language, version);
+
+    // Check if runtime exists
+    if !runtime_path.exists() {
+        return fail_job(req, format!("Runtime not found at {:?}", runtime_path));
+    }
+
+    let pkg_def = match PackageDefinition::from_path(runtime_path.clone()) {
+        Ok(d) => d,
+        Err(e) => return fail_job(req, format!("Invalid runtime definition: {}", e)),
+    };
+    // Set for every command the job runs on this runtime
+    let mut env = match pkg_def.env() {
+        Ok(env) => env,
+        Err(e) => return fail_job(req, format!("Invalid runtime env file: {}", e)),
+    };
+    env.extend(locale_env(req));
+    let legacy_args = req.legacy_args.unwrap_or(false);
+    if !legacy_args {
+        env.extend(file_env(req));
+    }
+    // Tools the package does not declare are ignored
+    let analysis = req.analysis.filter(|tool| {
+        pkg_def
+            .yaml
+            .analysis
+            .as_ref()
+            .is_some_and(|tools| tools.contains(tool))
+    });
+    if let Some(tool) = analysis {
+        env.push(format!("TURBO_ANALYSIS={}", tool.as_str()));
+    }
+    let mut effective = resolve_limits(req, &pkg_def, caps);
+    let benchmark = req
+        .benchmark
+        .as_ref()
+        .map(|b| clamp_benchmark(b, caps.benchmark_iterations, &mut effective.adjustments));
+    let deadline = effective
+        .total_time_ms
+        .map(|ms| started + Duration::from_millis(ms));
+
+    let mut compile_result = None;
+    let compile_script = pkg_def.path.join("compile.sh");
+
+    // Attempt caching if compile script exists
+    let user = std::env::var("USER").unwrap_or_else(|_| "unknown".to_string());
+    let cache_dir = std::env::temp_dir().join(format!("turbo-cache-{}", user));
+    let mut cache_path = None;
+
+    if compile_script.exists() {
+        // Calculate hash
+        let compile_script_content = fs::read_to_string(&compile_script)
+            .await
+            .unwrap_or_default();
+        let mut hash = calculate_job_hash(req, &compile_script_content);
+        // What gets compiled includes the checkout
+        if let (Some(source), Some(commit)) = (&req.source, &source_commit) {
+            let subdir = source.git.subdir.as_deref().unwrap_or_default();
+            let checkout = format!("{}\0{}\0{}\0{}", hash, source.git.url, commit, subdir);
+            hash = hex::encode(Sha256::digest(checkout));
+        }
+        let job_cache_path = cache_dir.join(&hash);
+
+        if job_cache_path.exists() {
+            info!("Cache hit for compile hash {}", hash);
+            // Restore from cache
+            if let Err(e) = hard_link_recursive(&job_cache_path, &temp_dir).await {
+                error!("Failed to restore from cache: {}", e);
+                // Fallback to normal compile if restore fails
+            } else {
+                // Touch cache to update modification time for LRU
+                #[cfg(unix)]
+                let _ =
+                    fs::set_permissions(&job_cache_path, std::fs::Permissions::from_mode(0o755))
+                        .await;
+                let _ = fs::write(job_cache_path.join(".touch"), "").await;
+
+                compile_result = Some(StageResult {
+                    status: StageStatus::Success,
+                    stdout: RESTORED_FROM_CACHE.to_string(),
+                    stderr: "".to_string(),
+                    ..stub_result()
+                });
+            }
+        }
+
+        cache_path = Some(job_cache_path);
+    }
+
+    if compile_result.is_none() && compile_script.exists() {
+        let compile_cmd = compile_script.to_string_lossy();
+        let filenames: Vec<String> = req
+            .files
+            .iter()
+            .map(|f| f.name.clone().unwrap_or_else(|| "main".to_string()))
+            .collect();
+
+        let limits = until_deadline(&effective.compile.to_execution_limits(), deadline);
+
+        let spec = CommandSpec {
+            cmd: &compile_cmd,
+            args: &filenames,
+            env: &env,
+            cwd: Some(temp_dir.as_path()),
+            ..Default::default()
+        };
+        match sandbox.exec(session, spec, Some(limits)).await {
+            Ok(res) => {
+                let success = res.status == StageStatus::Success;
+                compile_result = Some(res.clone());
+                if !success {
+                    let mut failed_res = res;
+                    failed_res.status = StageStatus::CompilationError;
+                    compile_result = Some(failed_res);
+                    return JobResult {
+                        language: req.language.clone(),
+                        version: version.to_string(),
+                        run: None,
+                        diagnostics: diagnostics(req, compile_result.as_ref(), None),
+                        analysis: None,
+                        compile: compile_result,
+                        testcases: None,
+                        effective_limits: Some(effective),
+                        cached: false,
+                        benchmark: None,
+                        interactor: None,
+                        metadata: req.metadata.clone(),
+                    };
+                }
+
+                // Save to cache on success
+                if let Some(path) = cache_path {
+                    if let Err(e) = copy_dir_recursive(&temp_dir, &path).await {
+                        error!("Failed to save to cache: {}", e);
+                    } else {
+                        // Touch newly created cache to ensure timestamp is fresh
+                        let _ = fs::write(path.join(".touch"), "").await;
+                    }
+                }
+            }
+            Err(e) => {
+                return fail_job(req, format!("Compile execution failed: {}", e));
+            }
+        }
+    }
+
+    let run_script = pkg_def.path.join("run.sh");
+    if !run_script.exists() {
+        return fail_job(req, format!("Run script not found at {:?}", run_script));
+    }
+
+    let run_cmd = run_script.to_string_lossy();
+    let run_args = req.args.as_deref().unwrap_or_default();
+    let tty = req.tty.unwrap_or(false);
+
+    let mut testcase_results = Vec::new();
+    let mut single_run_result = None;
+    let mut benchmark_result = None;
+    let mut interactor_result = None;
+    let entrypoint = req.files.first().filter(|_| !legacy_args);
+    let run = RunCommand {
+        cmd: &run_cmd,
+        args: req.args.as_deref(),
+        entrypoint: entrypoint.map(|f| f.name.as_deref().unwrap_or("main")),
+        env: &env,
+        tty,
+        cold: tty || req.timezone.is_some() || req.locale.is_some() || analysis.is_some(),
+        limits: ExecutionLimits {
+            output_encoding: req.output_encoding.unwrap_or_default(),
+            sample_interval_ms: req.sample_interval_ms,
+            profile: req.profile.unwrap_or(false),
+            ..effective.run.to_execution_limits()
+        },
+        pkg: &pkg_def,
+        stop_on_failure: req.stop_on_failure.unwrap_or(false),
+        deadline,
+        snapshot: None,
+    };
+
+    if let Some(testcases) = &req.testcases {
+        let parallelism = req
+            .parallelism
+            .unwrap_or(caps.testcase_parallelism)
+            .clamp(1, caps.testcase_parallelism);
+        testcase_results =
+            run_testcases(pool, session, &temp_dir, warm, &run, testcases, parallelism).await;
+    } else if deadline.is_some_and(|d| Instant::now() >= d) {
+        single_run_result = Some(StageResult {
+            status: StageStatus::Skipped,
+            ..stub_result()
+        });
+    } else if let Some(benchmark) = &benchmark {
+        let stdin = req.stdin.as_deref().unwrap_or("");
+        let (last, result) =
+            run_benchmark(sandbox, session, &temp_dir, &run, stdin, benchmark).await;
+        single_run_result = Some(last);
+        benchmark_result = Some(result);
+    } else if let Some(interactor) = &req.interactor {
+        let protocol = Protocol {
+            line_buffered: interactor.buffering == Buffering::Line,
+            message_timeout: Duration::from_millis(
+                interactor
+                    .message_timeout_ms
+                    .unwrap_or(DEFAULT_MESSAGE_TIMEOUT_MS),
+            ),
+            max_exchanges: interactor.max_exchanges,
+        };
+        let spec = CommandSpec {
+            cmd: &run_cmd,
+            args: run_args,
+            env: &env,
+            cwd: Some(temp_dir.as_path()),
+            ..Default::default()
+        };
+        let interactor_spec = CommandSpec {
+            cmd: &interactor.cmd,
+            args: &interactor.args,
+            env: &env,
+            cwd: Some(temp_dir.as_path()),
+            ..Default::default()
+        };
+        let limits = until_deadline(&run.limits, deadline);
+        let (run, judge) = match sandbox
+            .exec_interactive(session, spec, interactor_spec, protocol, Some(limits))
+            .await
+        {
+            Ok((run, judge)) => (run, Some(judge)),
+            Err(e) => (
+                StageResult {
+                    status: StageStatus::InternalError,
+                    stderr: format!("Sandbox error: {}", e),
+                    ..stub_result()
+                },
+                None,
+            ),
+        };
+        single_run_result = Some(run);
+        interactor_result = judge;
+    } else {
+        let limits = until_deadline(&run.limits, deadline);
+
+        let process = if run.cold { None } else { warm.take(&pkg_def) };
+        single_run_result = match process {
+            Some(process) => {
+                warm::run_warm(
+                    sandbox,
+                    process,
+                    &temp_dir,
+                    run.entrypoint,
+                    req.args.as_deref(),
+                    req.stdin.as_deref().unwrap_or(""),
+                    limits.clone(),
+                )
+                .await
+            }
+            None => None,
+        };
+        if single_run_result.is_none() {
+            let spec = CommandSpec {
+                cmd: &run_cmd,
+                args: run_args,
+                env: &env,
+                cwd: Some(temp_dir.as_path()),
+                stdin: req.stdin.as_deref().map(str::as_bytes),
+                tty,
+            };
+            single_run_result = Some(match sandbox.exec(session, spec, Some(limits)).await {
+                Ok(r) => r,
+                Err(e) => StageResult {
+                    status: StageStatus::InternalError,
+                    stdout: "".to_string(),
+                    stderr: format!("Sandbox error: {}", e),
+                    ..stub_result()
+                },
+            });
+        }
+    }
+
+    // Start replacements for consumed warm processes (and the first ones on a cold runtime)
+    warm.refill(sandbox, &pkg_def).await;
+
+    JobResult {
+        language: req.language.clone(),
+        version: version.to_string(),
+        diagnostics: diagnostics(req, compile_result.as_ref(), single_run_result.as_ref()),
+        analysis: analysis
+            .zip(single_run_result.as_ref())
+            .map(|(tool, run)| analysis::parse(tool, &run.stderr, &file_names(req))),
+        compile: compile_result,
+        run: single_run_result,
+        testcases: if testcase_results.is_empty() {
+            None
+        } else {
+            Some(testcase_results)
+        },
+        effective_limits: Some(effective),
+        cached: false,
+        benchmark: benchmark_result,
+        interactor: interactor_result,
+        metadata: req.metadata.clone(),
+    }
+}
+
+/// `benchmark` with both counts capped at `max`, noting any change in `notes`.
+fn clamp_benchmark(
+    benchmark: &BenchmarkRequest,
+    max: u32,
+    notes: &mut Vec<String>,
+) -> BenchmarkRequest {
+    let mut clamp = |field: &str, value: u32, min: u32| {
+        let clamped = value.clamp(min, max);
+        if clamped != value {
+            notes.push(format!(
+                "benchmark.{}: {} clamped to {}",
+                field, value, clamped
+            ));
+        }
+        clamped
+    };
+    BenchmarkRequest {
+        iterations: clamp("iterations", benchmark.iterations, 1),
+        warmup: clamp("warmup", benchmark.warmup, 0),
+    }
+}
+
+/// Runs the program `benchmark.warmup` times unmeasured, then `benchmark.iterations` times
+/// measured, one after another and never on warm processes. Stops at the first run that
+/// fails or at the job's deadline. Returns the last run (`Skipped` if none started) and
+/// statistics over the measured runs that succeeded.
+async fn run_benchmark(
+    sandbox: &dyn Sandbox,
+    session: &Session,
+    work_dir: &Path,
+    run: &RunCommand<'_>,
+    stdin: &str,
+    benchmark: &BenchmarkRequest,
+) -> (StageResult, BenchmarkResult) {
+    let mut last = None;
+    let mut runs = Vec::new();
+    for i in 0..benchmark.warmup + benchmark.iterations {
+        if run.deadline.is_some_and(|d| Instant::now() >= d) {
+            break;
+        }
+        let spec = CommandSpec {
+            cmd: run.cmd,
+            args: run.args.unwrap_or_default(),
+            env: run.env,
+            cwd: Some(work_dir),
+            stdin: Some(stdin.as_bytes()),
+            tty: run.tty,
+        };
+        let limits = until_deadline(&run.limits, run.deadline);
+        let result = match sandbox.exec(session, spec, Some(limits)).await {
+            Ok(r) => r,
+            Err(e) => StageResult {
+                status: StageStatus::InternalError,
+                stdout: "".to_string(),
+                stderr: format!("Sandbox error: {}", e),
+                ..stub_result()
+            },
+        };
+        let failed = result.status != StageStatus::Success;
+        if i >= benchmark.warmup && !failed {
+            runs.push(BenchmarkRun {
+                execution_time: result.execution_time,
+                cpu_time: result.cpu_time,
+                memory_usage: result.memory_usage,
+            });
+        }
+        last = Some(result);
+        if failed {
+            break;
+        }
+    }
+
+    let stats = |field: fn(&BenchmarkRun) -> Option<u64>| {
+        BenchmarkStats::of(runs.iter().filter_map(field).collect())
+    };
+    let result = BenchmarkResult {
+        iterations: runs.len() as u32,
+        warmup: benchmark.warmup,
+        execution_time: stats(|r| r.execution_time),
+        cpu_time: stats(|r| r.cpu_time),
+        memory_usage: stats(|r| r.memory_usage),
+        runs,
+    };
+    let last = last.unwrap_or_else(|| StageResult {
+        status: StageStatus::Skipped,
+        ..stub_result()
+    });
+    (last, result)
+}
+
+/// How each testcase's program is started.
+#[derive(Clone)]
+struct RunCommand<'a> {
+    cmd: &'a str,
+    /// The request's `args`
+    args: Option<&'a [String]>,
+    /// File warm processes are told to run first; none with `legacy_args`
+    entrypoint: Option<&'a str>,
+    /// From the runtime's `env` file
+    env: &'a [String],
+    tty: bool,
+    /// Never use warm processes: they are started on pipes and with the runtime's env
+    /// only, so terminal runs, runs with their own time zone or locale and analysis runs
+    /// start cold
+    cold: bool,
+    limits: ExecutionLimits,
+    /// Runtime the program runs on, to take warm processes for
+    pkg: &'a PackageDefinition,
+    /// Start no further testcases once one fails
+    stop_on_failure: bool,
+    /// End of the job's `max_total_time_ms` budget
+    deadline: Option<Instant>,
+    /// Compiled workspace each testcase's working directory is restored from first
+    snapshot: Option<PathBuf>,
+}
+
+/// Hands out testcase indices to the lanes of a job.
+#[derive(Default)]
+struct Schedule {
+    next: AtomicUsize,
+    stopped: AtomicBool,
+}
+
+impl Schedule {
+    /// Index of the next testcase to start, or `None` once stopped. Indices past the last
+    /// testcase mean there is nothing left.
+    fn take(&self) -> Option<usize> {
+        if self.stopped.load(Ordering::Relaxed) {
+            return None;
+        }
+        Some(self.next.fetch_add(1, Ordering::Relaxed))
+    }
+
+    fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Runs `testcases` across `session` and up to `parallelism - 1` spare sessions of `pool`,
+/// and returns the results in testcase order. `work_dir` is snapshotted once compiled and
+/// every testcase starts from a fresh copy of it, so no case sees files another changed.
+///
+/// Spare sessions are only borrowed if free right now, so a job never waits for one;
+/// when every session is held by a worker the testcases run one by one on `session`.
+/// Only `session` uses warm processes.
+///
+/// Testcases never started, because `stop_on_failure` or the job's deadline cut the run
+/// short, are reported as `Skipped`; ones already running on other lanes when a case
+/// failed still complete, and running ones are cut off at the deadline.
+async fn run_testcases(
+    pool: &SandboxPool<dyn Sandbox>,
+    session: &Session,
+    work_dir: &Path,
+    warm: &mut WarmPool,
+    run: &RunCommand<'_>,
+    testcases: &[Testcase],
+    parallelism: usize,
+) -> Vec<TestcaseResult> {
+    let sandbox = pool.sandbox();
+    let snapshot = snapshot_path(work_dir);
+    let run = &match take_snapshot(work_dir, &snapshot).await {
+        Ok(()) => RunCommand {
+            snapshot: Some(snapshot.clone()),
+            ..run.clone()
+        },
+        Err(e) => {
+            error!(
+                "Failed to snapshot {:?}, testcases share it: {}",
+                work_dir, e
+            );
+            run.clone()
+        }
+    };
+    let mut spares = Vec::new();
+    while spares.len() + 1 < parallelism.min(testcases.len()) {
+        let spare = match pool.try_acquire().await {
+            Ok(Some(spare)) => spare,
+            Ok(None) => break,
+            Err(e) => {
+                error!("Failed to take a spare sandbox session: {}", e);
+                break;
+            }
+        };
+        let dir = sandbox.work_dir(spare.id());
+        // With a snapshot, each lane restores its directory from it before every testcase
+        let copied = match &run.snapshot {
+            Some(_) => Ok(()),
+            None => copy_dir_recursive(work_dir, &dir).await,
+        };
+        if let Err(e) = copied {
+            error!("Failed to copy job files to sandbox {}: {}", spare.id(), e);
+            pool.release(spare).await;
+            break;
+        }
+        spares.push((spare, dir));
+    }
+
+    let schedule = Schedule::default();
+    let own = run_lane(
+        sandbox,
+        session,
+        work_dir,
+        Some(warm),
+        run,
+        testcases,
+        &schedule,
+    );
+    let others = spares
+        .iter()
+        .map(|(spare, dir)| run_lane(sandbox, spare, dir, None, run, testcases, &schedule));
+    let (own_results, other_results) = tokio::join!(own, join_all(others));
+    for (spare, _) in spares {
+        pool.release(spare).await;
+    }
+    if let Err(e) = fs::remove_dir_all(&snapshot).await
+        && e.kind() != std::io::ErrorKind::NotFound
+    {
+        error!("Failed to remove snapshot {:?}: {}", snapshot, e);
+    }
+
+    let mut results: Vec<Option<TestcaseResult>> = vec![None; testcases.len()];
+    for (i, result) in own_results
+        .into_iter()
+        .chain(other_results.into_iter().flatten())
+    {
+        results[i] = Some(result);
+    }
+    results
+        .into_iter()
+        .zip(testcases)
+        .map(|(result, tc)| result.unwrap_or_else(|| skipped(tc)))
+        .collect()
+}
+
+fn skipped(tc: &Testcase) -> TestcaseResult {
+    TestcaseResult {
+        id: tc.id.clone(),
+        passed: false,
+        actual_output: String::new(),
+        expected_output: None,
+        diff: None,
+        hidden: tc.hidden,
+        run_details: StageResult {
+            status: StageStatus::Skipped,
+            ..stub_result()
+        },
+    }
+}
+
+/// Runs testcases on one session, taking the next unstarted one from `schedule` until
+/// none are left. Returns each result with its testcase's index.
+async fn run_lane(
+    sandbox: &dyn Sandbox,
+    session: &Session,
+    work_dir: &Path,
+    mut warm: Option<&mut WarmPool>,
+    run: &RunCommand<'_>,
+    testcases: &[Testcase],
+    schedule: &Schedule,
+) -> Vec<(usize, TestcaseResult)> {
+    let mut results = Vec::new();
+    while let Some(i) = schedule.take() {
+        let Some(tc) = testcases.get(i) else {
+            break;
+        };
+        if run.deadline.is_some_and(|d| Instant::now() >= d) {
+            schedule.stop();
+            break;
+        }
+        // Warm processes are started on pipes, so terminal runs always start cold.
+        let process = match &mut warm {
+            Some(warm) if !run.cold => warm.take(run.pkg),
+            _ => None,
+        };
+        let result = match &run.snapshot {
+            Some(snapshot) => restore_snapshot(snapshot, work_dir).await,
+            None => Ok(()),
+        };
+        let result = match result {
+            Ok(()) => run_testcase(sandbox, session, work_dir, process, run, tc).await,
+            Err(e) => TestcaseResult {
+                run_details: StageResult {
+                    status: StageStatus::InternalError,
+                    stderr: format!("Failed to restore the compiled workspace: {}", e),
+                    ..stub_result()
+                },
+                ..skipped(tc)
+            },
+        };
+        if run.stop_on_failure
+            && !(result.passed && result.run_details.status == StageStatus::Success)
+        {
+            schedule.stop();
+        }
+        results.push((i, result));
+    }
+    results
+}
+
+async fn run_testcase(
+    sandbox: &dyn Sandbox,
+    session: &Session,
+    work_dir: &Path,
+    process: Option<WarmProcess>,
+    run: &RunCommand<'_>,
+    tc: &Testcase,
+) -> TestcaseResult {
+    info!("Batch Exec Cmd: {} {:?}", run.cmd, run.args);
+    let limits = until_deadline(&run.limits, run.deadline);
+
+    let warm_res = match process {
+        Some(process) => {
+            warm::run_warm(
+                sandbox,
+                process,
+                work_dir,
+                run.entrypoint,
+                run.args,
+                &tc.input,
+                limits.clone(),
+            )
+            .await
+        }
+        None => None,
+    };
+
+    let stage_res = match warm_res {
+        Some(r) => r,
+        None => match sandbox
+            .exec(
+                session,
+                CommandSpec {
+                    cmd: run.cmd,
+                    args: run.args.unwrap_or_default(),
+                    env: run.env,
+                    cwd: Some(work_dir),
+                    stdin: Some(tc.input.as_bytes()),
+                    tty: run.tty,
+                },
+                Some(limits),
+            )
+            .await
+        {
+            Ok(r) => r,
+            Err(e) => StageResult {
+                status: StageStatus::InternalError,
+                stdout: "".to_string(),
+                stderr: format!("Sandbox error: {}", e),
+                ..stub_result()
+            },
+        },
+    };
+
+    let actual = run.limits.output_encoding.decode_lossy(&stage_res.stdout);
+    let passed = if let Some(expected) = &tc.expected_output {
+        actual.trim() == expected.trim()
+    } else {
+        true
+    };
+
+    if tc.hidden {
+        return TestcaseResult {
+            passed,
+            run_details: StageResult {
+                stdout: String::new(),
+                stderr: String::new(),
+                ..stage_res
+            },
+            ..skipped(tc)
+        };
+    }
+    let failed_expected = tc.expected_output.as_deref().filter(|_| !passed);
+    TestcaseResult {
+        id: tc.id.clone(),
+        passed,
+        actual_output: preview(&stage_res.stdout),
+        expected_output: failed_expected.map(preview),
+        diff: failed_expected
+            .map(|expected| preview(&diff_snippet(expected.trim(), actual.trim()))),
+        hidden: false,
+        run_details: stage_res,
+    }
+}
+
+/// `output` cut to its first `PREVIEW_BYTES` bytes, at a character boundary. Base64 is
+/// cut at a multiple of 4 characters, so it still decodes.
+fn preview(output: &str) -> String {
+    let mut end = output.len().min(PREVIEW_BYTES);
+    while !output.is_char_boundary(end) {
+        end -= 1;
+    }
+    output[..end].to_string()
+}
+
+/// Unified diff of `expected` against `actual` as a single hunk: the lines between their
+/// common start and common end, with `DIFF_CONTEXT` lines around them and at most
+/// `DIFF_MAX_LINES` removed and added lines shown. Lines are split on `\n` alone, so a
+/// `\r` difference shows up as a changed line.
+fn diff_snippet(expected: &str, actual: &str) -> String {
+    let old: Vec<&str> = expected.split('\n').collect();
+    let new: Vec<&str> = actual.split('\n').collect();
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (old_changed, new_changed) = (old.len() - suffix, new.len() - suffix);
+    let start = prefix.saturating_sub(DIFF_CONTEXT);
+    let old_end = (old_changed + DIFF_CONTEXT).min(old.len());
+    let new_end = (new_changed + DIFF_CONTEXT).min(new.len());
+
+    let mut diff = format!(
+        "--- expected\n+++ actual\n@@ -{} +{} @@\n",
+        hunk_range(start, old_end),
+        hunk_range(start, new_end)
+    );
+    push_diff_lines(&mut diff, ' ', &old[start..prefix]);
+    push_diff_lines(&mut diff, '-', &old[prefix..old_changed]);
+    push_diff_lines(&mut diff, '+', &new[prefix..new_changed]);
+    push_diff_lines(&mut diff, ' ', &old[old_changed..old_end]);
+    diff
+}
+
+/// `start,count` of a unified diff hunk covering lines `start..end` (0-based).
+fn hunk_range(start: usize, end: usize) -> String {
+    match end - start {
+        0 => format!("{},0", start),
+        len => format!("{},{}", start + 1, len),
+    }
+}
+
+fn push_diff_lines(diff: &mut String, prefix: char, lines: &[&str]) {
+    for line in lines.iter().take(DIFF_MAX_LINES) {
+        diff.push(prefix);
+        diff.push_str(line);
+        diff.push('\n');
+    }
+    if lines.len() > DIFF_MAX_LINES {
+        diff.push_str(&format!(
+            "{}… {} more lines\n",
+            prefix,
+            lines.len() - DIFF_MAX_LINES
+        ));
+    }
+}
+
+/// `limits` with the timeout cut to the time left before `deadline`.
+fn until_deadline(limits: &ExecutionLimits, deadline: Option<Instant>) -> ExecutionLimits {
+    let mut limits = limits.clone();
+    if let Some(deadline) = deadline {
+        let left_ms = deadline
+            .saturating_duration_since(Instant::now())
+            .as_millis() as u64;
+        limits.timeout_ms = limits.timeout_ms.min(left_ms.max(1));
+    }
+    limits
+}
+
+/// Limits for each stage: the request's value, else the `[limits.<language>]` default,
+/// else the runtime's `limits` default, else the server default (`caps.defaults`), then
+/// clamped to `caps`. Every departure from the request is noted.
+fn resolve_limits(req: &JobRequest, pkg: &PackageDefinition, caps: &LimitCaps) -> EffectiveLimits {
+    let defaults = pkg.yaml.limits.clone().unwrap_or_default();
+    let language = std::iter::once(&pkg.yaml.name)
+        .chain(pkg.yaml.aliases.iter().flatten())
+        .find_map(|name| caps.languages.get(name))
+        .cloned()
+        .unwrap_or_default();
+    let base = caps.defaults;
+    let memory_cap = Some(caps.memory_limit_bytes);
+    let mut notes = Vec::new();
+
+    // The same for both stages
+    let pid_limit = pick_limit(
+        &mut notes,
+        "pid_limit",
+        [req.pid_limit, None, None],
+        base.pid_limit,
+        caps.pid_limit,
+    );
+    let output_limit_bytes = pick_limit(
+        &mut notes,
+        "output_limit",
+        [req.output_limit, None, None],
+        base.output_limit_bytes,
+        caps.output_limit_bytes,
+    );
+    let compile = StageLimits {
+        timeout_ms: pick_limit(
+            &mut notes,
+            "compile_timeout",
+            [
+                req.compile_timeout,
+                language.compile_timeout,
+                defaults.compile_timeout,
+            ],
+            base.compile_timeout_ms,
+            caps.timeout_ms,
+        ),
+        memory_limit_bytes: pick_limit(
+            &mut notes,
+            "compile_memory_limit",
+            [
+                req.compile_memory_limit,
+                language.memory,
+                defaults.compile_memory_limit,
+            ],
+            base.memory_limit_bytes,
+            memory_cap,
+        ),
+        pid_limit,
+        output_limit_bytes,
+    };
+    let run = StageLimits {
+        timeout_ms: pick_limit(
+            &mut notes,
+            "run_timeout",
+            [req.run_timeout, language.run_timeout, defaults.run_timeout],
+            base.run_timeout_ms,
+            caps.timeout_ms,
+        ),
+        memory_limit_bytes: pick_limit(
+            &mut notes,
+            "run_memory_limit",
+            [
+                req.run_memory_limit,
+                language.memory,
+                defaults.run_memory_limit,
+            ],
+            base.memory_limit_bytes,
+            memory_cap,
+        ),
+        pid_limit,
+        output_limit_bytes,
+    };
+
+    let total_time_ms = match (req.max_total_time_ms, caps.total_time_ms) {
+        (Some(v), Some(cap)) if v > cap => {
+            notes.push(format!(
+                "max_total_time_ms: {} clamped to server maximum {}",
+                v, cap
+            ));
+            Some(cap)
+        }
+        (Some(v), _) => Some(v),
+        (None, cap) => cap,
+    };
+
+    EffectiveLimits {
+        compile,
+        run,
+        total_time_ms,
+        adjustments: notes,
+    }
+}
+
+/// One limit from `[requested, language default, runtime default]`, falling back to
+/// `server`, capped at `cap`.
+fn pick_limit(
+    notes: &mut Vec<String>,
+    field: &str,
+    [requested, language, runtime]: [Option<u64>; 3],
+    server: u64,
+    cap: Option<u64>,
+) -> u64 {
+    let value = match (requested, language, runtime) {
+        (Some(v), _, _) => v,
+        (None, Some(v), _) => {
+            notes.push(format!(
+                "{}: {} from server defaults for the language",
+                field, v
+            ));
+            v
+        }
+        (None, None, Some(v)) => {
+            notes.push(format!("{}: {} from runtime defaults", field, v));
+            v
+        }
+        (None, None, None) => server,
+    };
+    match cap {
+        Some(cap) if value > cap => {
+            notes.push(format!(
+                "{}: {} clamped to server maximum {}",
+                field, value, cap
+            ));
+            cap
+        }
+        _ => value,
+    }
+}
+
+/// Content of a submitted file, decoded according to its `encoding`.
+fn file_bytes(file: &FileRequest) -> Result<Vec<u8>, String> {
+    match file.encoding.as_deref().unwrap_or("utf8") {
+        "utf8" => Ok(file.content.as_bytes().to_vec()),
+        "base64" => base64::engine::general_purpose::STANDARD
+            .decode(&file.content)
+            .map_err(|e| e.to_string()),
+        "hex" => hex::decode(&file.content).map_err(|e| e.to_string()),
+        other => Err(format!("unknown encoding {:?}", other)),
+    }
+}
+
+/// Messages about `req`'s files in the stderr of `compile`, or of `run` when the runtime
+/// compiles nothing; `None` unless the request set `diagnostics`.
+fn diagnostics(
+    req: &JobRequest,
+    compile: Option<&StageResult>,
+    run: Option<&StageResult>,
+) -> Option<Vec<Diagnostic>> {
+    if !req.diagnostics.unwrap_or(false) {
+        return None;
+    }
+    let stage = compile.or(run)?;
+    Some(diagnostics::parse(&stage.stderr, &file_names(req)))
+}
+
+/// Names of `req`'s files, in order.
+fn file_names(req: &JobRequest) -> Vec<&str> {
+    req.files
+        .iter()
+        .map(|f| f.name.as_deref().unwrap_or("main"))
+        .collect()
+}
+
+/// Result of a job that could not be run, reporting `err` as an `InternalError` run stage.
+pub fn fail_job(req: &JobRequest, err: String) -> JobResult {
+    JobResult {
+        language: req.language.clone(),
+        version: req.version.clone().unwrap_or_default(),
+        run: Some(StageResult {
+            status: StageStatus::InternalError,
+            stdout: "".to_string(),
+            stderr: err,
+            ..stub_result()
+        }),
+        compile: None,
+        diagnostics: None,
+        analysis: None,
+        testcases: None,
+        effective_limits: None,
+        cached: false,
+        benchmark: None,
+        interactor: None,
+        metadata: req.metadata.clone(),
+    }
+}
+
+/// A `Pending` stage with empty output and no measurements, to fill in with struct update
+/// syntax.
+pub fn stub_result() -> StageResult {
+    StageResult {
+        status: StageStatus::Pending,
+        stdout: "".into(),
+        stderr: "".into(),
+        exit_code: None,
+        signal: None,
+        memory_usage: None,
+        cpu_time: None,
+        execution_time: None,
+        termination_reason: None,
+        io_read_bytes: None,
+        io_write_bytes: None,
+        samples: None,
+        perf_counters: None,
+    }
+}
+
+// Helper for async recursive copy
+async fn copy_dir_recursive(src: &Path, dst: &Path) -> std::io::Result<()> {
+    if !dst.exists() {
+        fs::create_dir_all(dst).await?;
+    }
+    let mut entries = fs::read_dir(src).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let ty = entry.file_type().await?;
+        let src_path = entry.path();
+        let dst_path = dst.join(entry.file_name());
+        if ty.is_dir() {
+            Box::pin(copy_dir_recursive(&src_path, &dst_path)).await?;
+        } else {
+            fs::copy(&src_path, &dst_path).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Where the snapshot of the sandbox working directory `work_dir` is kept: beside it, on
+/// the same filesystem so it can be hard-linked, and outside the sandbox's reach.
+fn snapshot_path(work_dir: &Path) -> PathBuf {
+    let mut path = work_dir.as_os_str().to_owned();
+    path.push(SNAPSHOT_SUFFIX);
+    PathBuf::from(path)
+}
+
+/// Snapshots the compiled workspace `work_dir` into `snapshot`, replacing an older one.
+async fn take_snapshot(work_dir: &Path, snapshot: &Path) -> std::io::Result<()> {
+    if fs::try_exists(snapshot).await? {
+        fs::remove_dir_all(snapshot).await?;
+    }
+    link_read_only(work_dir, snapshot).await
+}
+
+/// Empties `work_dir` and fills it from `snapshot` again, undoing whatever the last
+/// testcase changed.
+async fn restore_snapshot(snapshot: &Path, work_dir: &Path) -> std::io::Result<()> {
+    turbo_box::workdir::clear_dir(work_dir).await?;
+    link_read_only(snapshot, work_dir).await
+}
+
+/// Recreates the tree at `src` in `dst` with hard links, copying where a link fails.
+/// Files are made read-only: a link shares its file with the snapshot, so a program
+/// that wrote to it in place would change it for every later testcase, whereas
+/// replacing or deleting it only affects its own copy. Symlinks are recreated as
+/// symlinks, never followed, and other special files are left out.
+async fn link_read_only(src: &Path, dst: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(dst).await?;
+    let mut entries = fs::read_dir(src).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let ty = entry.file_type().await?;
+        let src_path = entry.path();
+        let dst_path = dst.join(entry.file_name());
+        if ty.is_dir() {
+            Box::pin(link_read_only(&src_path, &dst_path)).await?;
+        } else if ty.is_symlink() {
+            git::copy_symlink(&src_path, &dst_path).await?;
+        } else if ty.is_file() {
+            if fs::hard_link(&src_path, &dst_path).await.is_err() {
+                fs::copy(&src_path, &dst_path).await?;
+            }
+            let mut perms = fs::metadata(&dst_path).await?.permissions();
+            #[cfg(unix)]
+            perms.set_mode(perms.mode() & !0o222);
+            #[cfg(not(unix))]
+            perms.set_readonly(true);
+            fs::set_permissions(&dst_path, perms).await?;
+        }
+    }
+    Ok(())
+}
+
+// Helper for async recursive hard link with fallback to copy
+async fn hard_link_recursive(src: &Path, dst: &Path) -> std::io::Result<()> {
+    if !dst.exists() {
+        fs::create_dir_all(dst).await?;
+    }
+    let mut entries = fs::read_dir(src).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let ty = entry.file_type().await?;
+        let src_path = entry.path();
+        let dst_path = dst.join(entry.file_name());
+        if ty.is_dir() {
+            Box::pin(hard_link_recursive(&src_path, &dst_path)).await?;
+        } else {
+            if fs::hard_link(&src_path, &dst_path).await.is_err() {
+                // Fallback to copy if hard link fails
+                fs::copy(&src_path, &dst_path).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// `TURBO_ENTRYPOINT` (the first file) and `TURBO_FILES` (every file, one per line), so
+/// `compile.sh` and `run.sh` need not be told the files in `args`.
+fn file_env(req: &JobRequest) -> Vec<String> {
+    let names = file_names(req);
+    let Some(entrypoint) = names.first() else {
+        return Vec::new();
+    };
+    vec![
+        format!("TURBO_ENTRYPOINT={}", entrypoint),
+        format!("TURBO_FILES={}", names.join("\n")),
+    ]
+}
+
+/// `TZ`, `LANG` and `LC_ALL` settings for the time zone and locale `req` asks for.
+fn locale_env(req: &JobRequest) -> Vec<String> {
+    let mut env = Vec::new();
+    if let Some(tz) = &req.timezone {
+        env.push(format!("TZ={}", tz));
+    }
+    if let Some(locale) = &req.locale {
+        env.push(format!("LANG={}", locale));
+        env.push(format!("LC_ALL={}", locale));
+    }
+    env
+}
+
+pub fn calculate_job_hash(req: &JobRequest, compile_script_content: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(req.language.as_bytes());
+    hasher.update(req.version.as_deref().unwrap_or("latest").as_bytes());
+    hasher.update(compile_script_content.as_bytes());
+
+    // Sort files to ensure stable hash
+    let mut files = req.files.clone();
+    files.sort_by(|a, b| a.name.cmp(&b.name));
+
+    for file in files {
+        hasher.update(file.name.as_deref().unwrap_or("main").as_bytes());
+        // The same content means different bytes in another encoding
+        hasher.update(file.encoding.as_deref().unwrap_or("utf8").as_bytes());
+        hasher.update(&file.content);
+    }
+    // Compilers may word their output, or behave, differently per locale and time zone
+    for var in locale_env(req) {
+        hasher.update(var.as_bytes());
+    }
+    // Sanitizers are compiled in
+    if let Some(tool) = req.analysis {
+        hasher.update(tool.as_str().as_bytes());
+    }
+
+    hex::encode(hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn caps() -> LimitCaps {
+        LimitCaps {
+            memory_limit_bytes: 512 << 20,
+            timeout_ms: Some(30_000),
+            pid_limit: None,
+            output_limit_bytes: Some(1 << 20),
+            testcase_parallelism: 1,
+            total_time_ms: Some(60_000),
+            benchmark_iterations: 100,
+            defaults: LimitDefaults {
+                run_timeout_ms: 3000,
+                compile_timeout_ms: 10_000,
+                memory_limit_bytes: 256 << 20,
+                pid_limit: 64,
+                output_limit_bytes: 1024,
+            },
+            languages: HashMap::new(),
+        }
+    }
+
+    fn package(yaml: &str) -> PackageDefinition {
+        PackageDefinition {
+            path: PathBuf::from("/runtimes/python/3.12"),
+            yaml: serde_yaml::from_str(yaml).unwrap(),
+        }
+    }
+
+    #[test]
+    fn pick_limit_prefers_request_then_language_then_runtime_then_server() {
+        let mut notes = Vec::new();
+        let limits = [
+            [Some(1), Some(2), Some(3)],
+            [None, Some(2), Some(3)],
+            [None, None, Some(3)],
+            [None, None, None],
+        ];
+        let picked: Vec<u64> = limits
+            .into_iter()
+            .map(|limits| pick_limit(&mut notes, "run_timeout", limits, 4, None))
+            .collect();
+        assert_eq!(picked, [1, 2, 3, 4]);
+        assert_eq!(
+            notes,
+            [
+                "run_timeout: 2 from server defaults for the language",
+                "run_timeout: 3 from runtime defaults",
+            ]
+        );
+    }
+
+    #[test]
+    fn pick_limit_clamps_to_the_cap() {
+        let mut notes = Vec::new();
+        assert_eq!(
+            pick_limit(
+                &mut notes,
+                "pid_limit",
+                [Some(500), None, None],
+                64,
+                Some(256)
+            ),
+            256
+        );
+        assert_eq!(
+            pick_limit(&mut notes, "pid_limit", [None, None, None], 64, Some(256)),
+            64
+        );
+        assert_eq!(notes, ["pid_limit: 500 clamped to server maximum 256"]);
+    }
+
+    #[test]
+    fn resolve_limits_layers_request_language_runtime_and_server() {
+        let pkg = package(
+            "name: python\nversion: 3.12.0\naliases: [py]\n\
+             limits: { run_timeout: 5000, compile_timeout: 20000, run_memory_limit: 1024 }\n",
+        );
+        let mut caps = caps();
+        caps.languages.insert(
+            "py".to_string(),
+            LanguageLimits {
+                compile_timeout: Some(15_000),
+                ..Default::default()
+            },
+        );
+        let req = JobRequest {
+            run_timeout: Some(60_000),
+            pid_limit: Some(32),
+            max_total_time_ms: Some(90_000),
+            ..Default::default()
+        };
+
+        let limits = resolve_limits(&req, &pkg, &caps);
+        assert_eq!(limits.run.timeout_ms, 30_000);
+        assert_eq!(limits.compile.timeout_ms, 15_000);
+        assert_eq!(limits.run.memory_limit_bytes, 1024);
+        assert_eq!(limits.compile.memory_limit_bytes, 256 << 20);
+        assert_eq!(limits.run.pid_limit, 32);
+        assert_eq!(limits.compile.pid_limit, 32);
+        assert_eq!(limits.run.output_limit_bytes, 1024);
+        assert_eq!(limits.total_time_ms, Some(60_000));
+        assert_eq!(
+            limits.adjustments,
+            [
+                "compile_timeout: 15000 from server defaults for the language",
+                "run_timeout: 60000 clamped to server maximum 30000",
+                "run_memory_limit: 1024 from runtime defaults",
+                "max_total_time_ms: 90000 clamped to server maximum 60000",
+            ]
+        );
+    }
+
+    #[test]
+    fn clamp_benchmark_caps_both_counts_and_needs_one_iteration() {
+        let mut notes = Vec::new();
+        let request = BenchmarkRequest {
+            iterations: 0,
+            warmup: 500,
+        };
+        let clamped = clamp_benchmark(&request, 100, &mut notes);
+        assert_eq!((clamped.iterations, clamped.warmup), (1, 100));
+        assert_eq!(
+            notes,
+            [
+                "benchmark.iterations: 0 clamped to 1",
+                "benchmark.warmup: 500 clamped to 100",
+            ]
+        );
+
+        let mut notes = Vec::new();
+        let request = BenchmarkRequest {
+            iterations: 20,
+            warmup: 3,
+        };
+        let clamped = clamp_benchmark(&request, 100, &mut notes);
+        assert_eq!((clamped.iterations, clamped.warmup), (20, 3));
+        assert!(notes.is_empty());
+    }
+
+    #[test]
+    fn job_hash_covers_file_encodings() {
+        let request = |encoding: Option<&str>| JobRequest {
+            language: "python".to_string(),
+            files: vec![FileRequest {
+                name: Some("data.bin".to_string()),
+                content: "YWJj".to_string(),
+                encoding: encoding.map(str::to_string),
+                url: None,
+            }],
+            ..Default::default()
+        };
+        let utf8 = calculate_job_hash(&request(None), "");
+        assert_eq!(calculate_job_hash(&request(Some("utf8")), ""), utf8);
+        assert_ne!(calculate_job_hash(&request(Some("base64")), ""), utf8);
+    }
+
+    #[test]
+    fn until_deadline_cuts_the_timeout_to_the_time_left() {
+        let limits = ExecutionLimits {
+            timeout_ms: 3000,
+            ..Default::default()
+        };
+        assert_eq!(until_deadline(&limits, None).timeout_ms, 3000);
+        let far = Instant::now() + Duration::from_secs(60);
+        assert_eq!(until_deadline(&limits, Some(far)).timeout_ms, 3000);
+        let near = Instant::now() + Duration::from_secs(1);
+        assert!(until_deadline(&limits, Some(near)).timeout_ms <= 1000);
+        // Never 0, which would mean no time at all rather than none left
+        let past = Instant::now() - Duration::from_secs(1);
+        assert_eq!(until_deadline(&limits, Some(past)).timeout_ms, 1);
+    }
+
+    #[test]
+    fn preview_cuts_at_a_char_boundary() {
+        assert_eq!(preview("short"), "short");
+        let text = format!("{}é", "a".repeat(PREVIEW_BYTES - 1));
+        assert_eq!(preview(&text), "a".repeat(PREVIEW_BYTES - 1));
+    }
+
+    #[test]
+    fn preview_of_base64_still_decodes() {
+        // The doc's promise rests on this
+        assert_eq!(PREVIEW_BYTES % 4, 0);
+        let encoded = base64::engine::general_purpose::STANDARD.encode(vec![7u8; PREVIEW_BYTES]);
+        let cut = preview(&encoded);
+        assert_eq!(cut.len(), PREVIEW_BYTES);
+        assert!(
+            base64::engine::general_purpose::STANDARD
+                .decode(cut)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn diff_snippet_shows_changed_lines_with_context() {
+        assert_eq!(
+            diff_snippet("a\nb\nc", "a\nx\nc"),
+            "--- expected\n+++ actual\n@@ -1,3 +1,3 @@\n a\n-b\n+x\n c\n"
+        );
+        let expected = (0..10)
+            .map(|i| i.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let actual = expected.replace('9', "nine");
+        assert_eq!(
+            diff_snippet(&expected, &actual),
+            "--- expected\n+++ actual\n@@ -7,4 +7,4 @@\n 6\n 7\n 8\n-9\n+nine\n"
+        );
+    }
+
+    #[test]
+    fn diff_snippet_caps_long_runs_of_lines() {
+        let expected = (0..25)
+            .map(|i| i.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let diff = diff_snippet(&expected, "x");
+        assert!(diff.starts_with("--- expected\n+++ actual\n@@ -1,25 +1,1 @@\n-0\n"));
+        assert!(diff.ends_with("-19\n-… 5 more lines\n+x\n"));
+    }
+}
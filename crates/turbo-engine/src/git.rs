@@ -0,0 +1,239 @@
+//! Git repositories as job sources, shallow-cloned by the worker and cached per commit.
+
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tokio::fs;
+use tokio::process::Command;
+use turbo_core::models::GitSource;
+use uuid::Uuid;
+
+/// Checkouts by repository and commit; trimmed by the garbage collector like the compile
+/// cache.
+pub const GIT_CACHE_DIR: &str = "/tmp/turbo-git-cache";
+/// How long one git command may take.
+const GIT_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Checks `source` out into `dest`, cloning it unless its commit is cached; returns the
+/// commit.
+pub async fn checkout(source: &GitSource, dest: &Path) -> anyhow::Result<String> {
+    if !source.url.starts_with("https://") {
+        anyhow::bail!("Git url {} must be https://", source.url);
+    }
+    let reference = source.reference.as_deref().unwrap_or("HEAD");
+    let commit = if is_commit(reference) {
+        reference.to_ascii_lowercase()
+    } else {
+        resolve(&source.url, reference).await?
+    };
+
+    let key = hex::encode(Sha256::digest(format!("{}\0{}", source.url, commit)));
+    let cached = Path::new(GIT_CACHE_DIR).join(key);
+    if !cached.exists() {
+        clone(&source.url, &commit, &cached).await?;
+    }
+    // Marks the checkout as recently used for the garbage collector
+    let touched = std::fs::File::open(&cached).and_then(|dir| dir.set_modified(SystemTime::now()));
+    if let Err(e) = touched {
+        tracing::warn!("Failed to touch {:?}: {}", cached, e);
+    }
+
+    let root = fs::canonicalize(&cached).await?;
+    let dir = match &source.subdir {
+        Some(subdir) => fs::canonicalize(cached.join(subdir))
+            .await
+            .map_err(|_| anyhow::anyhow!("No directory {} in {}", subdir, source.url))?,
+        None => root.clone(),
+    };
+    // A symlinked subdir could otherwise point anywhere on the worker's host
+    if !dir.starts_with(&root) || !dir.is_dir() {
+        anyhow::bail!(
+            "Git subdir {:?} is not a directory inside the repository",
+            source.subdir
+        );
+    }
+    copy_tree(&dir, dest).await?;
+    Ok(commit)
+}
+
+/// Whether `reference` is a full commit hash rather than a branch or tag.
+pub fn is_commit(reference: &str) -> bool {
+    reference.len() == 40 && reference.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Commit `reference` points to on the remote; the commit of an annotated tag rather than
+/// the tag itself.
+async fn resolve(url: &str, reference: &str) -> anyhow::Result<String> {
+    let listed = git(None, &["ls-remote", url, reference]).await?;
+    find_ref(&listed, reference).ok_or_else(|| anyhow::anyhow!("No ref {} in {}", reference, url))
+}
+
+/// Commit of `reference` in `ls-remote` output, matched by full name the way git does:
+/// `reference` itself (e.g. `HEAD`), then `refs/tags/<reference>`, then
+/// `refs/heads/<reference>`. `ls-remote` matches by suffix, so `main` also lists e.g.
+/// `refs/heads/feature/main`, which is never taken. A tag's peeled `^{}` entry wins.
+fn find_ref(listed: &str, reference: &str) -> Option<String> {
+    let refs: Vec<(&str, &str)> = listed
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .collect();
+    let commit = |name: &str| {
+        refs.iter()
+            .find(|(_, listed)| *listed == name)
+            .map(|(commit, _)| commit.to_string())
+    };
+    [
+        reference.to_string(),
+        format!("refs/tags/{}", reference),
+        format!("refs/heads/{}", reference),
+    ]
+    .iter()
+    .find_map(|name| commit(&format!("{}^{{}}", name)).or_else(|| commit(name)))
+}
+
+/// Shallow-clones `commit` of `url` into `dest` without the `.git` directory.
+async fn clone(url: &str, commit: &str, dest: &Path) -> anyhow::Result<()> {
+    let temp = PathBuf::from(format!("{}.{}.tmp", dest.display(), Uuid::new_v4()));
+    fs::create_dir_all(&temp).await?;
+    let cloned = async {
+        git(Some(&temp), &["init", "-q"]).await?;
+        git(Some(&temp), &["fetch", "-q", "--depth", "1", url, commit]).await?;
+        git(Some(&temp), &["checkout", "-q", "--detach", "FETCH_HEAD"]).await?;
+        fs::remove_dir_all(temp.join(".git")).await?;
+        fs::rename(&temp, dest).await?;
+        anyhow::Ok(())
+    }
+    .await;
+    if cloned.is_err() {
+        let _ = fs::remove_dir_all(&temp).await;
+        // Another worker may have cloned the same commit first
+        if dest.exists() {
+            return Ok(());
+        }
+    }
+    cloned
+}
+
+/// Runs git with prompts off and only `https` allowed, returning its stdout.
+async fn git(dir: Option<&Path>, args: &[&str]) -> anyhow::Result<String> {
+    let mut command = Command::new("git");
+    command
+        .args(args)
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .env("GIT_ALLOW_PROTOCOL", "https")
+        .stdin(std::process::Stdio::null())
+        .kill_on_drop(true);
+    if let Some(dir) = dir {
+        command.current_dir(dir);
+    }
+    let output = tokio::time::timeout(GIT_TIMEOUT, command.output())
+        .await
+        .map_err(|_| anyhow::anyhow!("git {} timed out", args[0]))??;
+    if !output.status.success() {
+        anyhow::bail!(
+            "git {} failed: {}",
+            args[0],
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Copies `src` into `dst`, recreating symlinks rather than following them so a
+/// repository cannot pull in files from the worker's host.
+async fn copy_tree(src: &Path, dst: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(dst).await?;
+    let mut entries = fs::read_dir(src).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let ty = entry.file_type().await?;
+        let target = dst.join(entry.file_name());
+        if ty.is_symlink() {
+            copy_symlink(&entry.path(), &target).await?;
+        } else if ty.is_dir() {
+            Box::pin(copy_tree(&entry.path(), &target)).await?;
+        } else {
+            fs::copy(entry.path(), &target).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Recreates the symlink `src` at `dst`, with the same target. Windows needs to know
+/// whether that is a directory; a dangling link becomes a file link.
+pub(crate) async fn copy_symlink(src: &Path, dst: &Path) -> std::io::Result<()> {
+    let target = fs::read_link(src).await?;
+    #[cfg(unix)]
+    return fs::symlink(target, dst).await;
+    #[cfg(windows)]
+    if fs::metadata(src).await.is_ok_and(|m| m.is_dir()) {
+        fs::symlink_dir(target, dst).await
+    } else {
+        fs::symlink_file(target, dst).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LISTED: &str = "\
+1111111111111111111111111111111111111111\trefs/heads/feature/v1
+2222222222222222222222222222222222222222\trefs/heads/v1
+3333333333333333333333333333333333333333\trefs/tags/v1
+4444444444444444444444444444444444444444\trefs/tags/v1^{}
+5555555555555555555555555555555555555555\trefs/tags/old/v1^{}
+";
+
+    #[test]
+    fn find_ref_matches_full_names_only() {
+        let listed = "\
+1111111111111111111111111111111111111111\trefs/heads/feature/main
+2222222222222222222222222222222222222222\trefs/heads/main
+";
+        assert_eq!(
+            find_ref(listed, "main").as_deref(),
+            Some("2222222222222222222222222222222222222222")
+        );
+        assert_eq!(find_ref(listed, "ain"), None);
+        let (feature, _) = listed.split_once('\n').unwrap();
+        assert_eq!(find_ref(feature, "main"), None);
+    }
+
+    #[test]
+    fn find_ref_prefers_the_peeled_tag_over_a_branch() {
+        assert_eq!(
+            find_ref(LISTED, "v1").as_deref(),
+            Some("4444444444444444444444444444444444444444")
+        );
+        assert_eq!(
+            find_ref(LISTED, "refs/heads/v1").as_deref(),
+            Some("2222222222222222222222222222222222222222")
+        );
+    }
+
+    #[test]
+    fn find_ref_takes_a_lightweight_tag_and_head() {
+        let listed = "\
+6666666666666666666666666666666666666666\tHEAD
+7777777777777777777777777777777777777777\trefs/tags/v2
+";
+        assert_eq!(
+            find_ref(listed, "v2").as_deref(),
+            Some("7777777777777777777777777777777777777777")
+        );
+        assert_eq!(
+            find_ref(listed, "HEAD").as_deref(),
+            Some("6666666666666666666666666666666666666666")
+        );
+    }
+
+    #[test]
+    fn is_commit_takes_only_full_hashes() {
+        assert!(is_commit("0123456789abcdef0123456789abcdef01234567"));
+        assert!(is_commit("0123456789ABCDEF0123456789ABCDEF01234567"));
+        assert!(!is_commit("0123456"));
+        assert!(!is_commit("main"));
+        assert!(!is_commit("v1.0.0"));
+        assert!(!is_commit("0123456789abcdef0123456789abcdef0123456g"));
+    }
+}
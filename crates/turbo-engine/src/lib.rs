@@ -0,0 +1,12 @@
+//! Job execution without Redis or HTTP: the judge the workers run, for embedding in Rust
+//! applications through [`Engine`].
+
+pub mod analysis;
+pub mod diagnostics;
+mod engine;
+pub mod execute;
+pub mod git;
+pub mod warm;
+
+pub use engine::Engine;
+pub use execute::LimitCaps;
@@ -0,0 +1,120 @@
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
+use tracing::{info, warn};
+use turbo_box::{Sandbox, WarmProcess};
+use turbo_core::models::{ExecutionLimits, StageResult};
+use turbo_core::{SandboxError, TurboError};
+use turbo_pkg::models::PackageDefinition;
+
+/// Per-worker set of pre-started runtime processes, keyed by runtime.
+///
+/// Only packages declaring `warm_pool` in their `package.yaml` participate. Processes are
+/// single-use: `take` hands one out and `refill` starts replacements after the job.
+pub struct WarmPool {
+    worker_id: usize,
+    processes: HashMap<String, VecDeque<WarmProcess>>,
+    /// Set once the backend reports it cannot spawn warm processes.
+    unsupported: bool,
+}
+
+impl WarmPool {
+    pub fn new(worker_id: usize) -> Self {
+        Self {
+            worker_id,
+            processes: HashMap::new(),
+            unsupported: false,
+        }
+    }
+
+    fn key(pkg: &PackageDefinition) -> String {
+        format!("{}-{}", pkg.yaml.name, pkg.yaml.version)
+    }
+
+    /// Take a ready warm process for the runtime, if any.
+    pub fn take(&mut self, pkg: &PackageDefinition) -> Option<WarmProcess> {
+        pkg.yaml.warm_pool.as_ref()?;
+        self.processes.get_mut(&Self::key(pkg))?.pop_front()
+    }
+
+    /// Start warm processes for the runtime until its configured size is reached.
+    pub async fn refill(&mut self, sandbox: &dyn Sandbox, pkg: &PackageDefinition) {
+        let Some(config) = &pkg.yaml.warm_pool else {
+            return;
+        };
+        if self.unsupported {
+            return;
+        }
+
+        let key = Self::key(pkg);
+        let script = pkg.path.join(&config.script);
+        let env = match pkg.env() {
+            Ok(env) => env,
+            Err(e) => {
+                warn!("Not starting warm processes for {}: {}", key, e);
+                return;
+            }
+        };
+        let queue = self.processes.entry(key.clone()).or_default();
+
+        // Each warm process gets its own sandbox so its stats are not mixed with others.
+        for n in 0..config.size {
+            let id = format!("warm-{}-{}-{}", self.worker_id, key, n);
+            if queue.iter().any(|p| p.id == id) {
+                continue;
+            }
+            if let Err(e) = sandbox.init(&id).await {
+                warn!("Failed to init warm sandbox {}: {}", id, e);
+                continue;
+            }
+
+            let cmd = script.to_string_lossy();
+            match sandbox.spawn_warm(&id, &cmd, &[], &env, None).await {
+                Ok(process) => {
+                    info!("Started warm process for {} in {}", key, id);
+                    queue.push_back(process);
+                }
+                Err(TurboError::Sandbox(SandboxError::Unsupported(_))) => {
+                    warn!("Sandbox backend does not support warm pools, disabling");
+                    self.unsupported = true;
+                    return;
+                }
+                Err(e) => warn!("Failed to start warm process for {}: {}", key, e),
+            }
+        }
+    }
+}
+
+/// Hand a job to a warm process.
+///
+/// Returns `None` if the hand-off failed (e.g. the process died while idle), in which case
+/// the caller should fall back to a cold run.
+pub async fn run_warm(
+    sandbox: &dyn Sandbox,
+    process: WarmProcess,
+    workdir: &Path,
+    entrypoint: Option<&str>,
+    args: Option<&[String]>,
+    stdin: &str,
+    limits: ExecutionLimits,
+) -> Option<StageResult> {
+    let mut header = workdir.to_string_lossy().to_string();
+    for arg in entrypoint
+        .into_iter()
+        .chain(args.unwrap_or_default().iter().map(String::as_str))
+    {
+        header.push('\t');
+        header.push_str(arg);
+    }
+    header.push('\n');
+
+    let mut input = header.into_bytes();
+    input.extend_from_slice(stdin.as_bytes());
+
+    match sandbox.run_warm(process, input, Some(limits)).await {
+        Ok(res) => Some(res),
+        Err(e) => {
+            warn!("Warm run failed, falling back to cold start: {}", e);
+            None
+        }
+    }
+}
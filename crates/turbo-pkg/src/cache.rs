@@ -1,12 +1,39 @@
-use crate::models::PackageInfo;
+use crate::models::{PackageDefinition, PackageInfo};
 use crate::repository::PackageRepository;
 use semver::Version;
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::RwLock;
+use turbo_core::models::FileRequest;
 
-/// In-memory cache of installed packages, populated at startup.
+/// Why [`PackageCache::detect`] could not pick a package.
+#[derive(Debug, thiserror::Error)]
+pub enum DetectError {
+    #[error("Cannot infer the language of {0}; set `language`")]
+    Unknown(String),
+    #[error("{file} could be {}; set `language`", .candidates.join(", "))]
+    Ambiguous {
+        file: String,
+        candidates: Vec<String>,
+    },
+}
+
+/// Packages, aliases and extensions, as one scan finds them.
+struct Scan {
+    packages: Vec<PackageInfo>,
+    aliases: HashMap<String, String>,
+    extensions: HashMap<String, Vec<String>>,
+}
+
+/// In-memory cache of installed packages, populated at startup and on `refresh`.
 pub struct PackageCache {
+    repo_path: PathBuf,
+    runtimes_dir: PathBuf,
     packages: RwLock<Vec<PackageInfo>>,
+    /// Alias -> package name, from the `aliases` in each `package.yaml`
+    aliases: RwLock<HashMap<String, String>>,
+    /// File extension -> installed packages declaring it in `extensions`, sorted
+    extensions: RwLock<HashMap<String, Vec<String>>>,
 }
 
 impl PackageCache {
@@ -15,42 +42,180 @@ impl PackageCache {
     /// - `repo_path`: Path to the package definitions (e.g., ./packages)
     /// - `runtimes_dir`: Path to installed runtimes (e.g., ~/.turbo/runtimes)
     pub async fn from_paths(repo_path: PathBuf, runtimes_dir: PathBuf) -> anyhow::Result<Self> {
-        let repo = PackageRepository::new(repo_path);
-        let repo_packages = repo.list_all().await?;
-
-        let mut packages = Vec::new();
-        for (name, version) in repo_packages {
-            let install_path = runtimes_dir.join(&name).join(&version);
-            let installed = install_path.exists();
-
-            packages.push(PackageInfo {
-                name,
-                version,
-                installed,
-            });
+        let scan = scan(&repo_path, &runtimes_dir).await?;
+        Ok(Self {
+            repo_path,
+            runtimes_dir,
+            packages: RwLock::new(scan.packages),
+            aliases: RwLock::new(scan.aliases),
+            extensions: RwLock::new(scan.extensions),
+        })
+    }
+
+    /// Scan the filesystem again, e.g. after a runtime was installed or removed.
+    ///
+    /// On error the cache keeps its previous contents.
+    pub async fn refresh(&self) -> anyhow::Result<()> {
+        let scan = scan(&self.repo_path, &self.runtimes_dir).await?;
+        *self.packages.write().unwrap_or_else(|e| e.into_inner()) = scan.packages;
+        *self.aliases.write().unwrap_or_else(|e| e.into_inner()) = scan.aliases;
+        *self.extensions.write().unwrap_or_else(|e| e.into_inner()) = scan.extensions;
+        Ok(())
+    }
+
+    /// Package name for `name`: itself if it is a package, else the package declaring it
+    /// as an alias.
+    pub fn resolve(&self, name: &str) -> Option<String> {
+        if self
+            .packages
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .any(|p| p.name == name)
+        {
+            return Some(name.to_string());
         }
+        self.aliases
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(name)
+            .cloned()
+    }
 
-        // Sort by name, then by version descending
-        packages.sort_by(|a, b| {
-            match a.name.cmp(&b.name) {
-                std::cmp::Ordering::Equal => {
-                    let ver_a = Version::parse(&a.version).unwrap_or_else(|_| Version::new(0, 0, 0));
-                    let ver_b = Version::parse(&b.version).unwrap_or_else(|_| Version::new(0, 0, 0));
-                    ver_b.cmp(&ver_a) // Descending
-                }
-                other => other,
+    /// Installed package to run `files` with, inferred from the first file: the interpreter
+    /// its shebang names (e.g. `#!/usr/bin/env python3`, matched by package name or alias),
+    /// else its extension.
+    pub fn detect(&self, files: &[FileRequest]) -> Result<String, DetectError> {
+        let Some(entry) = files.first() else {
+            return Err(DetectError::Unknown(
+                "a submission without files".to_string(),
+            ));
+        };
+        let name = entry.name.as_deref().unwrap_or("main");
+
+        let text = entry.encoding.as_deref().is_none_or(|e| e == "utf8");
+        if let Some(interpreter) = shebang_interpreter(&entry.content).filter(|_| text) {
+            // `python3.12` is run by whatever runs `python3`, or else `python`
+            let major = interpreter.split('.').next().unwrap_or(interpreter);
+            let base = interpreter.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+            let found = [interpreter, major, base]
+                .into_iter()
+                .filter_map(|candidate| self.resolve(candidate))
+                .find(|package| self.is_installed(package));
+            if let Some(package) = found {
+                return Ok(package);
             }
-        });
+        }
 
-        tracing::info!("Loaded {} packages into cache", packages.len());
+        let extension = Path::new(name).extension().and_then(|e| e.to_str());
+        let candidates = extension
+            .and_then(|e| {
+                self.extensions
+                    .read()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .get(e)
+                    .cloned()
+            })
+            .unwrap_or_default();
+        match candidates.as_slice() {
+            [] => Err(DetectError::Unknown(name.to_string())),
+            [package] => Ok(package.clone()),
+            _ => Err(DetectError::Ambiguous {
+                file: name.to_string(),
+                candidates,
+            }),
+        }
+    }
 
-        Ok(Self {
-            packages: RwLock::new(packages),
-        })
+    fn is_installed(&self, name: &str) -> bool {
+        self.packages
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .any(|p| p.name == name && p.installed)
     }
 
     /// Return a clone of all cached packages.
     pub fn list(&self) -> Vec<PackageInfo> {
-        self.packages.read().unwrap().clone()
+        self.packages
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
     }
 }
+
+/// Name of the interpreter a `#!` line starts, e.g. `python3` for `#!/usr/bin/env python3`.
+fn shebang_interpreter(content: &str) -> Option<&str> {
+    let line = content.lines().next()?.strip_prefix("#!")?;
+    let mut words = line.split_whitespace();
+    let mut program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        program = words.find(|word| !word.starts_with('-') && !word.contains('='))?;
+    }
+    Some(program)
+}
+
+/// Packages in the repository, sorted by name and then version descending, with whether
+/// each is installed; the alias map; and the extensions of installed packages.
+async fn scan(repo_path: &Path, runtimes_dir: &Path) -> anyhow::Result<Scan> {
+    let repo = PackageRepository::new(repo_path.to_path_buf());
+    let repo_packages = repo.list_all().await?;
+
+    let mut packages = Vec::new();
+    let mut aliases = HashMap::new();
+    let mut extensions: HashMap<String, Vec<String>> = HashMap::new();
+    for (name, version) in repo_packages {
+        let install_path = runtimes_dir.join(&name).join(&version);
+        let installed = install_path.exists();
+
+        match PackageDefinition::from_path(repo.path(&name, &version)) {
+            Ok(def) => {
+                for alias in def.yaml.aliases.into_iter().flatten() {
+                    aliases.entry(alias).or_insert_with(|| name.clone());
+                }
+                for extension in def
+                    .yaml
+                    .extensions
+                    .into_iter()
+                    .flatten()
+                    .filter(|_| installed)
+                {
+                    let packages = extensions.entry(extension).or_default();
+                    if !packages.contains(&name) {
+                        packages.push(name.clone());
+                    }
+                }
+            }
+            Err(e) => tracing::warn!("Skipping aliases of {}@{}: {}", name, version, e),
+        }
+
+        packages.push(PackageInfo {
+            name,
+            version,
+            installed,
+        });
+    }
+
+    // Sort by name, then by version descending
+    packages.sort_by(|a, b| {
+        match a.name.cmp(&b.name) {
+            std::cmp::Ordering::Equal => {
+                let ver_a = Version::parse(&a.version).unwrap_or_else(|_| Version::new(0, 0, 0));
+                let ver_b = Version::parse(&b.version).unwrap_or_else(|_| Version::new(0, 0, 0));
+                ver_b.cmp(&ver_a) // Descending
+            }
+            other => other,
+        }
+    });
+
+    for packages in extensions.values_mut() {
+        packages.sort();
+    }
+
+    tracing::info!("Loaded {} packages into cache", packages.len());
+    Ok(Scan {
+        packages,
+        aliases,
+        extensions,
+    })
+}
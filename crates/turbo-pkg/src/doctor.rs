@@ -0,0 +1,128 @@
+use crate::env::parse_env;
+use crate::installer::ARCH_FILE;
+use crate::models::PackageDefinition;
+use std::path::Path;
+
+/// Something wrong with an installed runtime, and what to do about it.
+#[derive(Debug, Clone)]
+pub struct Issue {
+    pub problem: String,
+    pub fix: String,
+}
+
+impl Issue {
+    fn new(problem: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self {
+            problem: problem.into(),
+            fix: fix.into(),
+        }
+    }
+}
+
+/// Result of checking one installed runtime.
+#[derive(Debug, Clone)]
+pub struct RuntimeReport {
+    pub name: String,
+    pub version: String,
+    pub issues: Vec<Issue>,
+}
+
+/// Check the runtime installed at `dir` (`<runtimes>/<name>/<version>`) without running
+/// anything: its `package.yaml`, scripts and `env` file.
+pub fn check_runtime(name: &str, version: &str, dir: &Path) -> Vec<Issue> {
+    let reinstall = reinstall_hint(name, version);
+    let mut issues = Vec::new();
+
+    let def = match PackageDefinition::from_path(dir.to_path_buf()) {
+        Ok(def) => Some(def),
+        Err(e) => {
+            issues.push(Issue::new(e.to_string(), reinstall.clone()));
+            None
+        }
+    };
+    if let Some(def) = &def {
+        if def.yaml.name != name || def.yaml.version != version {
+            issues.push(Issue::new(
+                format!(
+                    "package.yaml is {}@{} but is installed as {}@{}",
+                    def.yaml.name, def.yaml.version, name, version
+                ),
+                format!(
+                    "uninstall it and install {}@{} from its package",
+                    def.yaml.name, def.yaml.version
+                ),
+            ));
+        }
+    }
+
+    let mut scripts = vec!["run.sh".to_string()];
+    let compiled = def.as_ref().and_then(|d| d.yaml.compiled) == Some(true);
+    if compiled || dir.join("compile.sh").exists() {
+        scripts.push("compile.sh".to_string());
+    }
+    if let Some(def) = &def {
+        let yaml = &def.yaml;
+        scripts.extend(yaml.warm_pool.as_ref().map(|w| w.script.clone()));
+        scripts.extend(yaml.repl.clone());
+        scripts.extend(yaml.exec.clone());
+        scripts.extend(def.healthcheck().map(|h| h.script));
+    }
+    for script in &scripts {
+        let path = dir.join(script);
+        if !path.is_file() {
+            issues.push(Issue::new(
+                format!("{} is missing", script),
+                reinstall.clone(),
+            ));
+        } else if !is_executable(&path) {
+            issues.push(Issue::new(
+                format!("{} is not executable", script),
+                format!("chmod +x {:?}", path),
+            ));
+        }
+    }
+
+    let host = std::env::consts::ARCH;
+    if let Ok(arch) = std::fs::read_to_string(dir.join(ARCH_FILE)) {
+        if arch.trim() != host {
+            issues.push(Issue::new(
+                format!("built for {}, but this host is {}", arch.trim(), host),
+                reinstall.clone(),
+            ));
+        }
+    }
+
+    match std::fs::read_to_string(dir.join("env")) {
+        Ok(content) => {
+            if let Err(e) = parse_env(&content, dir) {
+                issues.push(Issue::new(
+                    format!("env does not parse: {}", e),
+                    format!("fix {:?}; one KEY=VALUE per line", dir.join("env")),
+                ));
+            }
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => issues.push(Issue::new(format!("env is unreadable: {}", e), reinstall)),
+    }
+
+    issues
+}
+
+/// Command rebuilding a runtime from its package.
+pub fn reinstall_hint(name: &str, version: &str) -> String {
+    format!(
+        "turbo pkg uninstall {0} -v {1} && turbo pkg install {0} -v {1}",
+        name, version
+    )
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::metadata(path).is_ok_and(|m| m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(_path: &Path) -> bool {
+    true
+}
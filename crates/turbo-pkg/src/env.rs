@@ -0,0 +1,110 @@
+use std::path::Path;
+
+/// Placeholder in `env` values replaced with the runtime's install directory.
+pub const INSTALL_DIR_VAR: &str = "${INSTALL_DIR}";
+
+/// Parse a runtime's `env` file into `KEY=VALUE` pairs.
+///
+/// One `KEY=VALUE` per line; blank lines and lines starting with `#` are skipped, and
+/// `${INSTALL_DIR}` in a value becomes `install_dir`. Values are taken literally: no
+/// quoting or other substitution. Errors name the offending line.
+pub fn parse_env(content: &str, install_dir: &Path) -> Result<Vec<(String, String)>, String> {
+    let install_dir = install_dir.to_string_lossy();
+    let mut vars = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(format!(
+                "line {}: expected KEY=VALUE, got {:?}",
+                i + 1,
+                line
+            ));
+        };
+        let key = key.trim();
+        let valid = key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            && !key.is_empty()
+            && !key.starts_with(|c: char| c.is_ascii_digit());
+        if !valid {
+            return Err(format!("line {}: invalid variable name {:?}", i + 1, key));
+        }
+        vars.push((
+            key.to_string(),
+            value.replace(INSTALL_DIR_VAR, &install_dir),
+        ));
+    }
+    Ok(vars)
+}
+
+/// Read and parse `<install_dir>/env`; a runtime without one has no variables.
+pub fn load_env(install_dir: &Path) -> anyhow::Result<Vec<(String, String)>> {
+    let path = install_dir.join("env");
+    let content = match std::fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    parse_env(&content, install_dir).map_err(|e| anyhow::anyhow!("{:?}: {}", path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(content: &str) -> Result<Vec<(String, String)>, String> {
+        parse_env(content, Path::new("/runtimes/python/3.12.0"))
+    }
+
+    fn var(key: &str, value: &str) -> (String, String) {
+        (key.to_string(), value.to_string())
+    }
+
+    #[test]
+    fn skips_comments_and_blank_lines() {
+        let content = "# Python runtime\n\nPYTHONHASHSEED=0\n   \n  # indented comment\n";
+        assert_eq!(parse(content), Ok(vec![var("PYTHONHASHSEED", "0")]));
+    }
+
+    #[test]
+    fn substitutes_the_install_dir() {
+        let content = "PATH=${INSTALL_DIR}/bin:/usr/bin\nHOME_DIR=${INSTALL_DIR}\n";
+        assert_eq!(
+            parse(content),
+            Ok(vec![
+                var("PATH", "/runtimes/python/3.12.0/bin:/usr/bin"),
+                var("HOME_DIR", "/runtimes/python/3.12.0"),
+            ])
+        );
+    }
+
+    #[test]
+    fn takes_values_literally() {
+        let content = " _OPTS = \"-O2 -g\"=x $HOME\nEMPTY=\n";
+        assert_eq!(
+            parse(content),
+            Ok(vec![var("_OPTS", " \"-O2 -g\"=x $HOME"), var("EMPTY", "")])
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_keys() {
+        for line in ["1PATH=x", "MY-VAR=x", "=x", "MY VAR=x", "PÄTH=x"] {
+            let error = parse(&format!("OK=1\n{}\n", line)).unwrap_err();
+            assert!(
+                error.starts_with("line 2: invalid variable name"),
+                "{}",
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_a_line_without_equals() {
+        assert_eq!(
+            parse("OK=1\n\nexport PATH\n"),
+            Err("line 3: expected KEY=VALUE, got \"export PATH\"".to_string())
+        );
+    }
+}
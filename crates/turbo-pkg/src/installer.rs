@@ -1,15 +1,62 @@
-use crate::models::{PackageDefinition};
-use std::path::{PathBuf};
-use std::process::Command;
+use crate::local::LocalPackage;
+use crate::models::{HealthcheckConfig, PackageDefinition};
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use std::sync::Arc;
+use std::time::Duration;
 use tokio::fs;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::Command;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::task::JoinHandle;
+use turbo_box::{CommandSpec, Sandbox};
+use turbo_core::models::{ExecutionLimits, StageStatus};
+
+/// File in an install directory holding the architecture the runtime was built on.
+pub const ARCH_FILE: &str = "arch";
 
 pub struct Installer {
     runtimes_dir: PathBuf,
+    /// Where smoke tests run; without one they run as plain processes, like `build.sh`
+    sandbox: Option<Arc<dyn Sandbox>>,
+    /// Receives progress messages and build output; without one, build output goes to
+    /// this process's stdout and stderr
+    log: Option<UnboundedSender<String>>,
 }
 
 impl Installer {
     pub fn new(runtimes_dir: PathBuf) -> Self {
-        Self { runtimes_dir }
+        Self {
+            runtimes_dir,
+            sandbox: None,
+            log: None,
+        }
+    }
+
+    /// Send progress and build output to `log`, line by line.
+    pub fn with_log(mut self, log: UnboundedSender<String>) -> Self {
+        self.log = Some(log);
+        self
+    }
+
+    /// Run smoke tests in `sandbox`.
+    pub fn with_sandbox(mut self, sandbox: Arc<dyn Sandbox>) -> Self {
+        self.sandbox = Some(sandbox);
+        self
+    }
+
+    /// Install from a `.tpkg` (or other tarball) without unpacking it by hand first.
+    pub async fn install_archive(&self, archive: &Path) -> anyhow::Result<()> {
+        let package = LocalPackage::open(archive).await?;
+        let problems = package.def.validate();
+        if !problems.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Invalid package in {:?}: {}",
+                archive,
+                problems.join("; ")
+            ));
+        }
+        self.install(&package.def).await
     }
 
     pub async fn install(&self, def: &PackageDefinition) -> anyhow::Result<()> {
@@ -19,32 +66,48 @@ impl Installer {
         let install_dir = self.runtimes_dir.join(pkg_name).join(pkg_version);
 
         if install_dir.exists() {
-            tracing::info!(
+            self.note(format!(
                 "Package {}@{} is already installed at {:?}",
-                pkg_name,
-                pkg_version,
-                install_dir
-            );
+                pkg_name, pkg_version, install_dir
+            ));
             return Ok(());
         }
 
-        tracing::info!(
+        self.note(format!(
             "Installing {}@{} from {:?}",
-            pkg_name,
-            pkg_version,
-            def.path
-        );
+            pkg_name, pkg_version, def.path
+        ));
+
+        let arch = std::env::consts::ARCH;
+        if !def.supports_arch(arch) {
+            return Err(anyhow::anyhow!(
+                "{}@{} does not support {} (only {})",
+                pkg_name,
+                pkg_version,
+                arch,
+                def.yaml.arch.as_deref().unwrap_or_default().join(", ")
+            ));
+        }
 
         // 1. Run build.sh
         // Canonicalize path to ensure reliable execution independent of CWD
         let abs_pkg_path = def.path.canonicalize().map_err(|e| {
             anyhow::anyhow!("Failed to canonicalize package path {:?}: {}", def.path, e)
         })?;
-        let build_script = abs_pkg_path.join("build.sh");
+        let abs_def = PackageDefinition {
+            path: abs_pkg_path.clone(),
+            yaml: def.yaml.clone(),
+        };
+        let Some(build_script) = abs_def.build_script(arch) else {
+            return Err(anyhow::anyhow!(
+                "Neither build-{}.sh nor build.sh found in {:?}",
+                arch,
+                abs_pkg_path
+            ));
+        };
 
-        if !build_script.exists() {
-            return Err(anyhow::anyhow!("build.sh not found at {:?}", build_script));
-        }
+        // Refuse to build from payloads that do not match package.yaml
+        def.verify_files()?;
 
         // Make executable
         #[cfg(unix)]
@@ -60,10 +123,14 @@ impl Installer {
 
         // Execute build.sh
         // Pass install_dir as argument $1
-        let status = Command::new(&build_script)
+        let mut build = Command::new(&build_script);
+        build
             .arg(&install_dir)
-            .current_dir(&abs_pkg_path)
-            .status()
+            .env("TURBO_ARCH", arch)
+            .current_dir(&abs_pkg_path);
+        let status = self
+            .run_logged(build)
+            .await
             .map_err(|e| anyhow::anyhow!("Failed to execute build.sh: {}", e))?;
 
         if !status.success() {
@@ -97,6 +164,34 @@ impl Installer {
             }
         }
 
+        if let Some(warm) = &def.yaml.warm_pool {
+            let warm_script = abs_pkg_path.join(&warm.script);
+            if !warm_script.exists() {
+                let _ = fs::remove_dir_all(&install_dir).await;
+                return Err(anyhow::anyhow!(
+                    "warm_pool script not found at {:?}",
+                    warm_script
+                ));
+            }
+            fs::copy(&warm_script, install_dir.join(&warm.script)).await?;
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                let mut perms = std::fs::metadata(install_dir.join(&warm.script))?.permissions();
+                perms.set_mode(0o755);
+                std::fs::set_permissions(install_dir.join(&warm.script), perms)?;
+            }
+        }
+
+        for script in [&def.yaml.repl, &def.yaml.exec].into_iter().flatten() {
+            let result =
+                install_script(&abs_pkg_path.join(script), &install_dir.join(script)).await;
+            if let Err(e) = result {
+                let _ = fs::remove_dir_all(&install_dir).await;
+                return Err(e);
+            }
+        }
+
         let env_file = abs_pkg_path.join("env");
         if env_file.exists() {
             fs::copy(&env_file, install_dir.join("env")).await?;
@@ -108,8 +203,193 @@ impl Installer {
             install_dir.join("package.yaml"),
         )
         .await?;
+        // Binaries only run on the architecture they were built for
+        fs::write(install_dir.join(ARCH_FILE), arch).await?;
+
+        if let Some(check) = def.healthcheck() {
+            let result = self.smoke_test(&check, &abs_pkg_path, &install_dir).await;
+            if let Err(e) = result {
+                // Never leave a broken build where workers would pick it up
+                let _ = fs::remove_dir_all(&install_dir).await;
+                return Err(anyhow::anyhow!(
+                    "Smoke test of {}@{} failed, install rolled back: {}",
+                    pkg_name,
+                    pkg_version,
+                    e
+                ));
+            }
+        }
+
+        self.note(format!(
+            "Successfully installed {}@{}",
+            pkg_name, pkg_version
+        ));
+        Ok(())
+    }
+
+    /// Copy the healthcheck script into `install_dir` and run it with `install_dir` as `$1`.
+    async fn smoke_test(
+        &self,
+        check: &HealthcheckConfig,
+        pkg_path: &Path,
+        install_dir: &Path,
+    ) -> anyhow::Result<()> {
+        let script = install_dir.join(&check.script);
+        install_script(&pkg_path.join(&check.script), &script).await?;
+        self.run_healthcheck(check, install_dir).await
+    }
+
+    /// Run the smoke test of the runtime installed at `install_dir` again; `Ok(false)`
+    /// if it has none.
+    pub async fn check_health(&self, install_dir: &Path) -> anyhow::Result<bool> {
+        let def = PackageDefinition::from_path(install_dir.to_path_buf())?;
+        let Some(check) = def.healthcheck() else {
+            return Ok(false);
+        };
+        self.run_healthcheck(&check, install_dir).await?;
+        Ok(true)
+    }
+
+    /// Run an installed healthcheck script with `install_dir` as `$1`.
+    async fn run_healthcheck(
+        &self,
+        check: &HealthcheckConfig,
+        install_dir: &Path,
+    ) -> anyhow::Result<()> {
+        let script = install_dir.join(&check.script);
+        self.note(format!("Running smoke test {:?}", script));
+        let env = PackageDefinition::from_path(install_dir.to_path_buf())?.env()?;
+
+        let Some(sandbox) = &self.sandbox else {
+            let run = Command::new(&script)
+                .arg(install_dir)
+                .envs(env.iter().filter_map(|var| var.split_once('=')))
+                .current_dir(install_dir)
+                .kill_on_drop(true)
+                .output();
+            let output = tokio::time::timeout(Duration::from_millis(check.timeout_ms), run)
+                .await
+                .map_err(|_| anyhow::anyhow!("timed out after {} ms", check.timeout_ms))??;
+            if !output.status.success() {
+                return Err(anyhow::anyhow!(
+                    "{}: {}",
+                    output.status,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ));
+            }
+            return Ok(());
+        };
 
-        tracing::info!("Successfully installed {}@{}", pkg_name, pkg_version);
+        let id = format!("install-{}", std::process::id());
+        let session = sandbox.create_session(&id).await?;
+        let cmd = script.to_string_lossy();
+        let args = [install_dir.to_string_lossy().to_string()];
+        let spec = CommandSpec {
+            cmd: &cmd,
+            args: &args,
+            env: &env,
+            cwd: None,
+            stdin: None,
+            tty: false,
+        };
+        let limits = ExecutionLimits {
+            timeout_ms: check.timeout_ms,
+            output_limit_bytes: 64 * 1024,
+            ..Default::default()
+        };
+        let result = sandbox.exec(&session, spec, Some(limits)).await;
+        if let Err(e) = sandbox.destroy_session(session).await {
+            tracing::warn!("Failed to clean up smoke test sandbox {}: {}", id, e);
+        }
+        let result = result?;
+        if result.status != StageStatus::Success {
+            return Err(anyhow::anyhow!(
+                "{:?}: {}",
+                result.status,
+                result.stderr.trim()
+            ));
+        }
         Ok(())
     }
+
+    /// Log a progress message, and send it to the install log if there is one.
+    fn note(&self, message: String) {
+        tracing::info!("{}", message);
+        if let Some(log) = &self.log {
+            let _ = log.send(message);
+        }
+    }
+
+    /// Run `command` to completion. With an install log, its stdout and stderr lines are
+    /// sent there; otherwise they go to this process's own.
+    async fn run_logged(&self, mut command: Command) -> std::io::Result<ExitStatus> {
+        let Some(log) = &self.log else {
+            return command.status().await;
+        };
+        command.stdout(Stdio::piped()).stderr(Stdio::piped());
+        let mut child = command.spawn()?;
+        let stdout = child
+            .stdout
+            .take()
+            .map(|out| forward_lines(out, log.clone()));
+        let stderr = child
+            .stderr
+            .take()
+            .map(|err| forward_lines(err, log.clone()));
+        let status = child.wait().await?;
+        for task in [stdout, stderr].into_iter().flatten() {
+            let _ = task.await;
+        }
+        Ok(status)
+    }
+
+    /// Remove an installed runtime, and its language directory once no version is left.
+    pub async fn uninstall(&self, name: &str, version: &str) -> anyhow::Result<()> {
+        let lang_dir = self.runtimes_dir.join(name);
+        let install_dir = lang_dir.join(version);
+        if !install_dir.exists() {
+            return Err(anyhow::anyhow!(
+                "Package {}@{} is not installed",
+                name,
+                version
+            ));
+        }
+        fs::remove_dir_all(&install_dir).await?;
+
+        let mut remaining = fs::read_dir(&lang_dir).await?;
+        if remaining.next_entry().await?.is_none() {
+            fs::remove_dir(&lang_dir).await?;
+        }
+        tracing::info!("Uninstalled {}@{}", name, version);
+        Ok(())
+    }
+}
+
+/// Send each line read from `stream` to `log`.
+fn forward_lines<R>(stream: R, log: UnboundedSender<String>) -> JoinHandle<()>
+where
+    R: AsyncRead + Unpin + Send + 'static,
+{
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(stream).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            let _ = log.send(line);
+        }
+    })
+}
+
+/// Copy a declared entrypoint script into the install directory and make it executable.
+async fn install_script(src: &Path, dst: &Path) -> anyhow::Result<()> {
+    if !src.exists() {
+        return Err(anyhow::anyhow!("Entrypoint script not found at {:?}", src));
+    }
+    fs::copy(src, dst).await?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mut perms = std::fs::metadata(dst)?.permissions();
+        perms.set_mode(0o755);
+        std::fs::set_permissions(dst, perms)?;
+    }
+    Ok(())
 }
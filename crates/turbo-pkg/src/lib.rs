@@ -1,8 +1,12 @@
 pub mod cache;
+pub mod doctor;
+pub mod env;
 pub mod installer;
+pub mod local;
 pub mod manager;
 pub mod models;
+pub mod remote;
 pub mod repository;
 
-pub use cache::PackageCache;
+pub use cache::{DetectError, PackageCache};
 pub use models::*;
@@ -0,0 +1,123 @@
+use crate::models::PackageDefinition;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Extension of packed packages; see `pack`.
+pub const TPKG_EXTENSION: &str = "tpkg";
+
+/// A package definition read from a local directory or tarball instead of the repository.
+///
+/// Tarballs are unpacked into a temporary directory that is removed on drop.
+pub struct LocalPackage {
+    pub def: PackageDefinition,
+    extracted: Option<PathBuf>,
+}
+
+impl LocalPackage {
+    /// Open the package at `path`: a directory holding `package.yaml`, or a `.tpkg`,
+    /// `.tar`, `.tar.gz` or `.tgz` archive of one (at its top level or in a single
+    /// directory).
+    pub async fn open(path: &Path) -> anyhow::Result<Self> {
+        if path.is_dir() {
+            return Ok(PackageDefinition::from_path(path.to_path_buf())?.into());
+        }
+        if !path.is_file() {
+            return Err(anyhow::anyhow!("Local package {:?} not found", path));
+        }
+
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or(0);
+        let dest = std::env::temp_dir().join(format!("turbo-pkg-{}-{}", std::process::id(), nanos));
+        let archive = path.to_path_buf();
+        let unpack_dir = dest.clone();
+        let unpacked = tokio::task::spawn_blocking(move || unpack(&archive, &unpack_dir)).await?;
+        if let Err(e) = unpacked {
+            let _ = std::fs::remove_dir_all(&dest);
+            return Err(e);
+        }
+
+        let def = package_root(&dest).and_then(PackageDefinition::from_path);
+        match def {
+            Ok(def) => Ok(Self {
+                def,
+                extracted: Some(dest),
+            }),
+            Err(e) => {
+                let _ = std::fs::remove_dir_all(&dest);
+                Err(e)
+            }
+        }
+    }
+}
+
+impl From<PackageDefinition> for LocalPackage {
+    fn from(def: PackageDefinition) -> Self {
+        Self {
+            def,
+            extracted: None,
+        }
+    }
+}
+
+impl Drop for LocalPackage {
+    fn drop(&mut self) {
+        if let Some(dir) = &self.extracted {
+            let _ = std::fs::remove_dir_all(dir);
+        }
+    }
+}
+
+fn unpack(archive: &Path, dest: &Path) -> anyhow::Result<()> {
+    let name = archive.to_string_lossy();
+    let file = std::fs::File::open(archive)?;
+    std::fs::create_dir_all(dest)?;
+    // `unpack` refuses entries that would land outside `dest`
+    if name.ends_with(".tar.gz") || name.ends_with(".tgz") || name.ends_with(".tpkg") {
+        tar::Archive::new(GzDecoder::new(file)).unpack(dest)?;
+    } else if name.ends_with(".tar") {
+        tar::Archive::new(file).unpack(dest)?;
+    } else {
+        return Err(anyhow::anyhow!(
+            "Unsupported package archive {:?}; expected .tpkg, .tar, .tar.gz or .tgz",
+            archive
+        ));
+    }
+    Ok(())
+}
+
+/// The unpacked directory holding `package.yaml`.
+fn package_root(dir: &Path) -> anyhow::Result<PathBuf> {
+    if dir.join("package.yaml").is_file() {
+        return Ok(dir.to_path_buf());
+    }
+    let mut subdirs = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            subdirs.push(entry.path());
+        }
+    }
+    match subdirs.as_slice() {
+        [single] if single.join("package.yaml").is_file() => Ok(single.clone()),
+        _ => Err(anyhow::anyhow!("No package.yaml found in archive")),
+    }
+}
+
+/// Pack a package directory into a `.tpkg` archive at `out`.
+///
+/// A `.tpkg` is a gzipped tarball of the package directory with `package.yaml` at its
+/// top level. Entries carry no timestamps or owners, so packing the same files twice
+/// gives the same checksum.
+pub fn pack(def: &PackageDefinition, out: &Path) -> anyhow::Result<()> {
+    let file = std::fs::File::create(out)?;
+    let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+    builder.mode(tar::HeaderMode::Deterministic);
+    builder.append_dir_all(".", &def.path)?;
+    builder.into_inner()?.finish()?;
+    Ok(())
+}
@@ -1,11 +1,22 @@
+use crate::doctor::{self, Issue, RuntimeReport};
 use crate::installer::Installer;
+use crate::local::LocalPackage;
+use crate::models::PackageDefinition;
+use crate::remote::RemoteRepository;
 // use crate::models::{PackageVersion};
 use crate::repository::PackageRepository;
-use std::path::{PathBuf};
+use semver::{Version, VersionReq};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use turbo_box::Sandbox;
 
 pub struct PackageManager {
     installer: Installer,
     repository: PackageRepository,
+    /// Used instead of `repository` when set
+    registry: Option<RemoteRepository>,
     runtimes_dir: PathBuf,
 }
 
@@ -15,17 +26,375 @@ impl PackageManager {
         Self {
             installer: Installer::new(runtimes_dir.clone()),
             repository: PackageRepository::new(repo_path),
+            registry: None,
             runtimes_dir,
         }
     }
 
+    /// Run post-install smoke tests in `sandbox`.
+    pub fn with_sandbox(mut self, sandbox: Arc<dyn Sandbox>) -> Self {
+        self.installer = self.installer.with_sandbox(sandbox);
+        self
+    }
+
+    /// Send install progress and build output to `log`; see `Installer::with_log`.
+    pub fn with_log(mut self, log: tokio::sync::mpsc::UnboundedSender<String>) -> Self {
+        self.installer = self.installer.with_log(log);
+        self
+    }
+
+    /// Resolve packages from a remote registry instead of the local repository.
+    pub fn with_registry(mut self, registry: RemoteRepository) -> Self {
+        self.registry = Some(registry);
+        self
+    }
+
+    async fn resolve(&self, name: &str, version: Option<&str>) -> anyhow::Result<LocalPackage> {
+        match &self.registry {
+            Some(registry) => registry.resolve(name, version).await,
+            None => Ok(self.repository.resolve(name, version).await?.into()),
+        }
+    }
+
+    /// Install a package, after installing any of its `depends` that no installed
+    /// version satisfies.
     pub async fn install(&self, name: &str, version: Option<&str>) -> anyhow::Result<()> {
-        let def = self.repository.resolve(name, version).await?;
-        self.installer.install(&def).await
+        let package = self.resolve(name, version).await?;
+        self.install_with_deps(&package.def, &mut Vec::new()).await
+    }
+
+    /// Install `def` after its dependencies, depth first. `chain` holds the packages
+    /// whose dependencies are being installed, to report cycles.
+    fn install_with_deps<'a>(
+        &'a self,
+        def: &'a PackageDefinition,
+        chain: &'a mut Vec<String>,
+    ) -> Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send + 'a>> {
+        Box::pin(async move {
+            chain.push(def.yaml.name.clone());
+            for dep in def.yaml.depends.iter().flatten() {
+                if chain.contains(&dep.name) {
+                    return Err(anyhow::anyhow!(
+                        "Dependency cycle: {} -> {}",
+                        chain.join(" -> "),
+                        dep.name
+                    ));
+                }
+                let req = VersionReq::parse(&dep.version_req).map_err(|e| {
+                    anyhow::anyhow!(
+                        "{} has invalid version_req {:?} for {}: {}",
+                        def.yaml.name,
+                        dep.version_req,
+                        dep.name,
+                        e
+                    )
+                })?;
+                if self.installed_matching(&dep.name, &req)?.is_some() {
+                    continue;
+                }
+
+                let version = self
+                    .available_matching(&dep.name, &req)
+                    .await?
+                    .ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "{} needs {} {}, but no available version matches",
+                            def.yaml.name,
+                            dep.name,
+                            req
+                        )
+                    })?;
+                tracing::info!(
+                    "Installing {}@{}, needed by {}",
+                    dep.name,
+                    version,
+                    def.yaml.name
+                );
+                let package = self.resolve(&dep.name, Some(&version)).await?;
+                self.install_with_deps(&package.def, chain).await?;
+            }
+            chain.pop();
+            self.installer.install(def).await
+        })
+    }
+
+    /// Highest installed version of `name` matching `req`.
+    fn installed_matching(&self, name: &str, req: &VersionReq) -> anyhow::Result<Option<String>> {
+        let lang_dir = self.runtimes_dir.join(name);
+        if !lang_dir.is_dir() {
+            return Ok(None);
+        }
+        let mut best: Option<Version> = None;
+        for entry in std::fs::read_dir(&lang_dir)? {
+            let entry = entry?;
+            let Ok(version) = Version::parse(&entry.file_name().to_string_lossy()) else {
+                continue;
+            };
+            if entry.file_type()?.is_dir() && req.matches(&version) {
+                best = best.max(Some(version));
+            }
+        }
+        Ok(best.map(|v| v.to_string()))
+    }
+
+    /// Highest version of `name` in the repository (or registry) matching `req`.
+    async fn available_matching(
+        &self,
+        name: &str,
+        req: &VersionReq,
+    ) -> anyhow::Result<Option<String>> {
+        let packages = match &self.registry {
+            Some(registry) => registry.list_all().await?,
+            None => self.repository.list_all().await?,
+        };
+        Ok(packages
+            .into_iter()
+            .filter(|(pkg, _)| pkg == name)
+            .filter_map(|(_, version)| Version::parse(&version).ok())
+            .filter(|version| req.matches(version))
+            .max()
+            .map(|v| v.to_string()))
+    }
+
+    /// Install from a package directory or tarball at `path`, bypassing the repository.
+    pub async fn install_local(
+        &self,
+        path: &Path,
+        name: &str,
+        version: Option<&str>,
+    ) -> anyhow::Result<()> {
+        let package = self.open_local(path, name, version).await?;
+        self.install_with_deps(&package.def, &mut Vec::new()).await
+    }
+
+    /// Open a local package and check it is `name` (at `version`, if given) and valid.
+    async fn open_local(
+        &self,
+        path: &Path,
+        name: &str,
+        version: Option<&str>,
+    ) -> anyhow::Result<LocalPackage> {
+        let package = LocalPackage::open(path).await?;
+        let yaml = &package.def.yaml;
+        if yaml.name != name {
+            return Err(anyhow::anyhow!(
+                "{:?} contains package {}, not {}",
+                path,
+                yaml.name,
+                name
+            ));
+        }
+        if let Some(version) = version.filter(|v| *v != yaml.version) {
+            return Err(anyhow::anyhow!(
+                "{:?} contains {}@{}, not version {}",
+                path,
+                yaml.name,
+                yaml.version,
+                version
+            ));
+        }
+
+        let problems = package.def.validate();
+        if !problems.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Invalid package at {:?}: {}",
+                path,
+                problems.join("; ")
+            ));
+        }
+        Ok(package)
+    }
+
+    /// Installed versions of `name`: `version` alone if given, otherwise all of them.
+    /// Fails if none match.
+    pub fn installed_versions(
+        &self,
+        name: &str,
+        version: Option<&str>,
+    ) -> anyhow::Result<Vec<String>> {
+        let lang_dir = self.runtimes_dir.join(name);
+        let versions = match version {
+            Some(version) if lang_dir.join(version).is_dir() => vec![version.to_string()],
+            Some(_) => Vec::new(),
+            None if lang_dir.is_dir() => {
+                let mut versions = Vec::new();
+                for entry in std::fs::read_dir(&lang_dir)? {
+                    let entry = entry?;
+                    if entry.file_type()?.is_dir() {
+                        versions.push(entry.file_name().to_string_lossy().to_string());
+                    }
+                }
+                versions.sort();
+                versions
+            }
+            None => Vec::new(),
+        };
+        if versions.is_empty() {
+            let which = version.map_or_else(|| name.to_string(), |v| format!("{}@{}", name, v));
+            return Err(anyhow::anyhow!("Package {} is not installed", which));
+        }
+        Ok(versions)
+    }
+
+    /// Remove installed versions of `name` (see `installed_versions`); returns them.
+    pub async fn uninstall(
+        &self,
+        name: &str,
+        version: Option<&str>,
+    ) -> anyhow::Result<Vec<String>> {
+        let versions = self.installed_versions(name, version)?;
+        for version in &versions {
+            self.installer.uninstall(name, version).await?;
+        }
+        Ok(versions)
+    }
+
+    /// Check every installed runtime (see `doctor::check_runtime`), sorted by name and
+    /// version. With `smoke`, runtimes without other issues also run their smoke test.
+    pub async fn doctor(&self, smoke: bool) -> anyhow::Result<Vec<RuntimeReport>> {
+        let mut reports = Vec::new();
+        for (name, versions) in self.installed()? {
+            for version in versions {
+                let dir = self.runtimes_dir.join(&name).join(&version);
+                let mut issues = doctor::check_runtime(&name, &version, &dir);
+                if smoke && issues.is_empty() {
+                    if let Err(e) = self.installer.check_health(&dir).await {
+                        issues.push(Issue {
+                            problem: format!("smoke test failed: {}", e),
+                            fix: doctor::reinstall_hint(&name, &version),
+                        });
+                    }
+                }
+                reports.push(RuntimeReport {
+                    name: name.clone(),
+                    version,
+                    issues,
+                });
+            }
+        }
+        Ok(reports)
+    }
+
+    /// Installed versions beyond the newest `keep` of each language, as `(name, version)`.
+    /// Versions that are not semver are never pruned. `keep` must be at least 1; removing
+    /// every version of a language is `uninstall`'s job.
+    pub fn prune_candidates(&self, keep: usize) -> anyhow::Result<Vec<(String, String)>> {
+        if keep == 0 {
+            anyhow::bail!("Pruning must keep at least one version of each language");
+        }
+        let mut candidates = Vec::new();
+        for (name, versions) in self.installed()? {
+            let mut versions: Vec<Version> = versions
+                .iter()
+                .filter_map(|v| Version::parse(v).ok())
+                .collect();
+            versions.sort_by(|a, b| b.cmp(a));
+            for version in versions.into_iter().skip(keep) {
+                candidates.push((name.clone(), version.to_string()));
+            }
+        }
+        Ok(candidates)
+    }
+
+    /// Remove all but the newest `keep` versions of each language; returns what was removed.
+    pub async fn prune(&self, keep: usize) -> anyhow::Result<Vec<(String, String)>> {
+        let candidates = self.prune_candidates(keep)?;
+        for (name, version) in &candidates {
+            self.installer.uninstall(name, version).await?;
+        }
+        Ok(candidates)
+    }
+
+    /// Disk usage of every installed runtime, sorted by name and version.
+    pub fn disk_usage(&self) -> anyhow::Result<Vec<crate::models::RuntimeUsage>> {
+        let mut usage = Vec::new();
+        for (name, versions) in self.installed()? {
+            for version in versions {
+                let bytes = dir_size(&self.runtimes_dir.join(&name).join(&version))?;
+                usage.push(crate::models::RuntimeUsage {
+                    name: name.clone(),
+                    version,
+                    bytes,
+                });
+            }
+        }
+        Ok(usage)
+    }
+
+    /// Installed `(language, versions)`, sorted by language and version name.
+    fn installed(&self) -> anyhow::Result<Vec<(String, Vec<String>)>> {
+        let mut installed = Vec::new();
+        if !self.runtimes_dir.is_dir() {
+            return Ok(installed);
+        }
+        for lang in std::fs::read_dir(&self.runtimes_dir)? {
+            let lang = lang?;
+            if !lang.file_type()?.is_dir() {
+                continue;
+            }
+            let mut versions = Vec::new();
+            for ver in std::fs::read_dir(lang.path())? {
+                let ver = ver?;
+                if ver.file_type()?.is_dir() {
+                    versions.push(ver.file_name().to_string_lossy().to_string());
+                }
+            }
+            versions.sort();
+            installed.push((lang.file_name().to_string_lossy().to_string(), versions));
+        }
+        installed.sort();
+        Ok(installed)
+    }
+
+    /// Resolve a package and report what installing it would do, without installing.
+    pub async fn plan_install(
+        &self,
+        name: &str,
+        version: Option<&str>,
+    ) -> anyhow::Result<crate::models::InstallPlan> {
+        let package = self.resolve(name, version).await?;
+        self.plan(&package.def)
+    }
+
+    /// Like `plan_install`, for a package directory or tarball at `path`.
+    pub async fn plan_install_local(
+        &self,
+        path: &Path,
+        name: &str,
+        version: Option<&str>,
+    ) -> anyhow::Result<crate::models::InstallPlan> {
+        let package = self.open_local(path, name, version).await?;
+        self.plan(&package.def)
+    }
+
+    fn plan(&self, def: &PackageDefinition) -> anyhow::Result<crate::models::InstallPlan> {
+        let install_dir = self
+            .runtimes_dir
+            .join(&def.yaml.name)
+            .join(&def.yaml.version);
+
+        Ok(crate::models::InstallPlan {
+            name: def.yaml.name.clone(),
+            version: def.yaml.version.clone(),
+            installed: install_dir.exists(),
+            install_dir,
+            package_bytes: dir_size(&def.path)?,
+            download_size: def.yaml.download_size,
+            install_size: def.yaml.install_size,
+        })
+    }
+
+    /// Resolve a package from the repository and check it; see `PackageDefinition::validate`.
+    pub async fn validate(&self, name: &str, version: Option<&str>) -> anyhow::Result<Vec<String>> {
+        let package = self.resolve(name, version).await?;
+        Ok(package.def.validate())
     }
 
     pub async fn list_available(&self) -> anyhow::Result<Vec<crate::models::PackageInfo>> {
-        let repo_packages = self.repository.list_all().await?;
+        let repo_packages = match &self.registry {
+            Some(registry) => registry.list_all().await?,
+            None => self.repository.list_all().await?,
+        };
         let mut result = Vec::new();
 
         for (name, version) in repo_packages {
@@ -42,3 +411,169 @@ impl PackageManager {
         Ok(result)
     }
 }
+
+/// Total size of the regular files under `dir`.
+fn dir_size(dir: &Path) -> std::io::Result<u64> {
+    let mut total = 0;
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if metadata.is_dir() {
+            total += dir_size(&entry.path())?;
+        } else if metadata.is_file() {
+            total += metadata.len();
+        }
+    }
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory for test `name`.
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("turbo-pkg-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Adds `name@version` to the repository at `repo`; its `build.sh` appends
+    /// `name@version` to `log`.
+    fn add_package(repo: &Path, log: &Path, name: &str, version: &str, depends: &str) {
+        let dir = repo.join(name).join(version);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("package.yaml"),
+            format!(
+                "name: {}\nversion: {}\ndepends: [{}]\n",
+                name, version, depends
+            ),
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("build.sh"),
+            format!("#!/bin/sh\necho {}@{} >> {:?}\n", name, version, log),
+        )
+        .unwrap();
+    }
+
+    fn built(log: &Path) -> Vec<String> {
+        std::fs::read_to_string(log)
+            .unwrap_or_default()
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn installs_dependencies_first_at_the_highest_matching_version() {
+        let dir = scratch("deps");
+        let (repo, log) = (dir.join("repo"), dir.join("build.log"));
+        add_package(&repo, &log, "base", "1.0.0", "");
+        add_package(&repo, &log, "base", "1.2.0", "");
+        add_package(&repo, &log, "base", "2.0.0", "");
+        add_package(
+            &repo,
+            &log,
+            "lib",
+            "1.0.0",
+            "{name: base, version_req: '^1'}",
+        );
+        let app_depends = "{name: lib, version_req: '*'}, {name: base, version_req: '>=1, <2'}";
+        add_package(&repo, &log, "app", "1.0.0", app_depends);
+
+        let manager = PackageManager::new(dir.join("home"), repo);
+        manager.install("app", None).await.unwrap();
+        assert_eq!(built(&log), ["base@1.2.0", "lib@1.0.0", "app@1.0.0"]);
+        let runtimes = dir.join("home").join("runtimes");
+        assert!(runtimes.join("base").join("1.2.0").is_dir());
+        assert!(!runtimes.join("base").join("2.0.0").exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn an_installed_match_satisfies_a_dependency() {
+        let dir = scratch("installed");
+        let (repo, log) = (dir.join("repo"), dir.join("build.log"));
+        add_package(&repo, &log, "base", "1.0.0", "");
+        add_package(&repo, &log, "base", "1.2.0", "");
+        add_package(
+            &repo,
+            &log,
+            "app",
+            "1.0.0",
+            "{name: base, version_req: '^1'}",
+        );
+
+        let manager = PackageManager::new(dir.join("home"), repo);
+        manager.install("base", Some("1.0.0")).await.unwrap();
+        manager.install("app", None).await.unwrap();
+        assert_eq!(built(&log), ["base@1.0.0", "app@1.0.0"]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn reports_dependency_cycles_and_unmatched_requirements() {
+        let dir = scratch("cycle");
+        let (repo, log) = (dir.join("repo"), dir.join("build.log"));
+        add_package(&repo, &log, "a", "1.0.0", "{name: b, version_req: '*'}");
+        add_package(&repo, &log, "b", "1.0.0", "{name: a, version_req: '*'}");
+        add_package(&repo, &log, "c", "1.0.0", "{name: a, version_req: '^2'}");
+        add_package(
+            &repo,
+            &log,
+            "d",
+            "1.0.0",
+            "{name: a, version_req: 'not a req'}",
+        );
+
+        let manager = PackageManager::new(dir.join("home"), repo);
+        let cycle = manager.install("a", None).await.unwrap_err();
+        assert_eq!(cycle.to_string(), "Dependency cycle: a -> b -> a");
+        let unmatched = manager.install("c", None).await.unwrap_err();
+        assert_eq!(
+            unmatched.to_string(),
+            "c needs a ^2, but no available version matches"
+        );
+        let invalid = manager.install("d", None).await.unwrap_err();
+        assert!(invalid.to_string().starts_with("d has invalid version_req"));
+        assert!(built(&log).is_empty());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn prune_keeps_the_newest_semver_versions() {
+        let dir = scratch("prune");
+        let runtimes = dir.join("runtimes");
+        for version in ["1.0.0", "1.9.0", "1.10.0", "nightly"] {
+            std::fs::create_dir_all(runtimes.join("python").join(version)).unwrap();
+        }
+        std::fs::create_dir_all(runtimes.join("go").join("1.22.0")).unwrap();
+
+        let manager = PackageManager::new(dir.clone(), dir.join("repo"));
+        let pruned = |name: &str, version: &str| (name.to_string(), version.to_string());
+        assert_eq!(
+            manager.prune_candidates(1).unwrap(),
+            [pruned("python", "1.9.0"), pruned("python", "1.0.0")]
+        );
+        assert_eq!(manager.prune(2).await.unwrap(), [pruned("python", "1.0.0")]);
+        assert!(!runtimes.join("python").join("1.0.0").exists());
+        assert!(runtimes.join("python").join("nightly").is_dir());
+        assert!(manager.prune_candidates(5).unwrap().is_empty());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn prune_refuses_to_keep_nothing() {
+        let dir = scratch("prune-zero");
+        std::fs::create_dir_all(dir.join("runtimes").join("python").join("3.12.0")).unwrap();
+        let manager = PackageManager::new(dir.clone(), dir.join("repo"));
+        assert!(manager.prune(0).await.is_err());
+        assert!(dir.join("runtimes").join("python").join("3.12.0").is_dir());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -1,5 +1,8 @@
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use sha2::{Digest, Sha256};
+use std::ffi::OsStr;
+use std::path::{Component, Path, PathBuf};
+use turbo_core::models::AnalysisTool;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PackageYaml {
@@ -7,7 +10,117 @@ pub struct PackageYaml {
     pub version: String,
     pub description: Option<String>,
     pub aliases: Option<Vec<String>>,
+    /// Extensions (without the dot) of the source files this runtime runs, e.g. `py`, for
+    /// submissions that name no language
+    pub extensions: Option<Vec<String>>,
     pub compiled: Option<bool>,
+    pub warm_pool: Option<WarmPoolConfig>,
+    /// Script starting an interactive REPL for session mode (conventionally `repl.sh`)
+    pub repl: Option<String>,
+    /// Script running one snippet in an open session (conventionally `exec.sh`)
+    ///
+    /// Invoked in the session's working directory with the snippet's file name as `$1`.
+    pub exec: Option<String>,
+    /// Binary payloads shipped in the package directory (patches, prebuilt wrappers),
+    /// checked against their checksums before `build.sh` runs
+    pub files: Option<Vec<PackageFile>>,
+    /// Approximate bytes `build.sh` downloads, for install planning
+    pub download_size: Option<u64>,
+    /// Approximate bytes the installed runtime occupies, for install planning
+    pub install_size: Option<u64>,
+    /// Limits for jobs on this runtime that do not set their own
+    pub limits: Option<PackageLimits>,
+    /// Packages installed before this one, e.g. `python` for a `jupyter` package
+    pub depends: Option<Vec<PackageDependency>>,
+    /// Architectures the package builds on (as in `std::env::consts::ARCH`, e.g. `x86_64`,
+    /// `aarch64`); all of them if unset. A `build-<arch>.sh` is used instead of `build.sh`
+    /// on that architecture.
+    pub arch: Option<Vec<String>>,
+    /// Smoke test run after installing; a failure rolls the install back. Without this
+    /// block a `test.sh` in the package directory is run with the defaults.
+    pub healthcheck: Option<HealthcheckConfig>,
+    /// Memory checkers `compile.sh` and `run.sh` apply when a job asks for one; they get
+    /// its name (`valgrind`, `asan`) as `TURBO_ANALYSIS`
+    pub analysis: Option<Vec<AnalysisTool>>,
+}
+
+/// Script checking a freshly installed runtime, e.g. that `python --version` works.
+///
+/// Run in the sandbox with the install directory as `$1`; a non-zero exit, timeout or
+/// other failure means the build is broken.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthcheckConfig {
+    /// Script in the package directory
+    #[serde(default = "default_healthcheck_script")]
+    pub script: String,
+    #[serde(default = "default_healthcheck_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+impl Default for HealthcheckConfig {
+    fn default() -> Self {
+        Self {
+            script: default_healthcheck_script(),
+            timeout_ms: default_healthcheck_timeout_ms(),
+        }
+    }
+}
+
+fn default_healthcheck_script() -> String {
+    "test.sh".to_string()
+}
+
+fn default_healthcheck_timeout_ms() -> u64 {
+    30_000
+}
+
+/// Another package that must be installed first.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackageDependency {
+    pub name: String,
+    /// Semver requirement, e.g. `>=3.12, <4` or `*`
+    pub version_req: String,
+}
+
+/// Per-runtime default limits, in the same units as the job request fields.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PackageLimits {
+    pub run_timeout: Option<u64>,          // ms
+    pub compile_timeout: Option<u64>,      // ms
+    pub run_memory_limit: Option<u64>,     // bytes
+    pub compile_memory_limit: Option<u64>, // bytes
+}
+
+/// A file in the package directory that `build.sh` uses, pinned by checksum.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackageFile {
+    /// Path relative to the package directory
+    pub path: String,
+    /// Hex-encoded SHA-256 of the file's contents
+    pub sha256: String,
+}
+
+/// Pre-started runtime processes kept per worker to skip cold-start latency.
+///
+/// The script is started in the sandbox ahead of time and must block until it reads
+/// a job from stdin: one line `<workdir>\t<entrypoint>\t<arg>\t<arg>...` (without the
+/// entrypoint for `legacy_args` jobs), followed by the program's stdin. Each process serves exactly one job and is then replaced.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WarmPoolConfig {
+    /// Script in the package directory that starts the warm runtime
+    #[serde(default = "default_warm_script")]
+    pub script: String,
+    /// Number of warm processes kept per worker
+    #[serde(default = "default_warm_size")]
+    pub size: usize,
+}
+
+fn default_warm_script() -> String {
+    "warm.sh".to_string()
+}
+
+fn default_warm_size() -> usize {
+    1
 }
 
 #[derive(Debug, Clone)]
@@ -16,6 +129,29 @@ pub struct PackageDefinition {
     pub yaml: PackageYaml,
 }
 
+/// Disk space taken by one installed runtime.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuntimeUsage {
+    pub name: String,
+    pub version: String,
+    pub bytes: u64,
+}
+
+/// What `PackageManager::install` would do, without doing it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstallPlan {
+    pub name: String,
+    /// Version after resolving "latest"
+    pub version: String,
+    pub install_dir: PathBuf,
+    /// Already installed; the install would be a no-op
+    pub installed: bool,
+    /// Bytes in the package directory (scripts and bundled files)
+    pub package_bytes: u64,
+    pub download_size: Option<u64>,
+    pub install_size: Option<u64>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PackageInfo {
     pub name: String,
@@ -34,4 +170,169 @@ impl PackageDefinition {
 
         Ok(Self { path, yaml })
     }
+
+    /// Path of the REPL entrypoint, if the package declares one.
+    pub fn repl_script(&self) -> Option<PathBuf> {
+        self.yaml.repl.as_ref().map(|s| self.path.join(s))
+    }
+
+    /// Whether the package can be installed on `arch`.
+    pub fn supports_arch(&self, arch: &str) -> bool {
+        self.yaml
+            .arch
+            .as_ref()
+            .is_none_or(|archs| archs.iter().any(|a| a == arch))
+    }
+
+    /// Build script for `arch`: `build-<arch>.sh` if the package has one, else `build.sh`.
+    pub fn build_script(&self, arch: &str) -> Option<PathBuf> {
+        [format!("build-{}.sh", arch), "build.sh".to_string()]
+            .into_iter()
+            .map(|name| self.path.join(name))
+            .find(|path| path.is_file())
+    }
+
+    /// Smoke test to run after installing: the `healthcheck` block, or the default
+    /// `test.sh` if the package ships one.
+    pub fn healthcheck(&self) -> Option<HealthcheckConfig> {
+        match &self.yaml.healthcheck {
+            Some(check) => Some(check.clone()),
+            None => {
+                let check = HealthcheckConfig::default();
+                self.path.join(&check.script).is_file().then_some(check)
+            }
+        }
+    }
+
+    /// Variables from the `env` file as `KEY=VALUE` strings, for a command's environment;
+    /// see `env::parse_env`.
+    pub fn env(&self) -> anyhow::Result<Vec<String>> {
+        let vars = crate::env::load_env(&self.path)?;
+        Ok(vars
+            .into_iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect())
+    }
+
+    /// Path of the session exec entrypoint, if the package declares one.
+    pub fn exec_script(&self) -> Option<PathBuf> {
+        self.yaml.exec.as_ref().map(|s| self.path.join(s))
+    }
+
+    /// Check the package directory against its `package.yaml`.
+    ///
+    /// Returns one message per problem; an empty list means the package is valid.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if self.yaml.name.trim().is_empty() {
+            problems.push("name is empty".to_string());
+        }
+        if self.yaml.version.trim().is_empty() {
+            problems.push("version is empty".to_string());
+        }
+        if !self.path.join("run.sh").is_file() {
+            problems.push("run.sh is missing".to_string());
+        }
+        // Every supported architecture needs a build script
+        match &self.yaml.arch {
+            Some(archs) if archs.is_empty() => problems.push("arch is empty".to_string()),
+            Some(archs) => {
+                for arch in archs {
+                    if self.build_script(arch).is_none() {
+                        problems.push(format!("neither build-{}.sh nor build.sh exists", arch));
+                    }
+                }
+            }
+            None if !self.path.join("build.sh").is_file() => {
+                problems.push("build.sh is missing".to_string());
+            }
+            None => {}
+        }
+
+        let declared = [
+            (
+                "warm_pool.script",
+                self.yaml.warm_pool.as_ref().map(|w| &w.script),
+            ),
+            ("repl", self.yaml.repl.as_ref()),
+            ("exec", self.yaml.exec.as_ref()),
+            (
+                "healthcheck.script",
+                self.yaml.healthcheck.as_ref().map(|h| &h.script),
+            ),
+        ];
+        for (field, script) in declared {
+            let Some(script) = script else {
+                continue;
+            };
+            // Scripts are copied flat into the install directory
+            if Path::new(script).file_name() != Some(OsStr::new(script)) {
+                problems.push(format!("{} must be a file name, got {:?}", field, script));
+            } else if !self.path.join(script).is_file() {
+                problems.push(format!("{} script {} is missing", field, script));
+            }
+        }
+
+        for dep in self.yaml.depends.iter().flatten() {
+            if dep.name == self.yaml.name {
+                problems.push("package depends on itself".to_string());
+            } else if let Err(e) = semver::VersionReq::parse(&dep.version_req) {
+                problems.push(format!(
+                    "dependency {} has invalid version_req {:?}: {}",
+                    dep.name, dep.version_req, e
+                ));
+            }
+        }
+
+        if let Err(e) = self.env() {
+            problems.push(format!("env file is invalid: {}", e));
+        }
+
+        for file in self.yaml.files.iter().flatten() {
+            if let Err(problem) = self.check_file(file) {
+                problems.push(problem);
+            }
+        }
+
+        problems
+    }
+
+    /// Check every file listed under `files` against its checksum.
+    pub fn verify_files(&self) -> anyhow::Result<()> {
+        for file in self.yaml.files.iter().flatten() {
+            self.check_file(file)
+                .map_err(|problem| anyhow::anyhow!(problem))?;
+        }
+        Ok(())
+    }
+
+    fn check_file(&self, file: &PackageFile) -> Result<(), String> {
+        let relative = Path::new(&file.path);
+        if !relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+        {
+            return Err(format!(
+                "file {} must be a relative path inside the package",
+                file.path
+            ));
+        }
+
+        let path = self.path.join(relative);
+        let mut reader = std::fs::File::open(&path)
+            .map_err(|e| format!("file {} cannot be read: {}", file.path, e))?;
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut reader, &mut hasher)
+            .map_err(|e| format!("file {} cannot be read: {}", file.path, e))?;
+
+        let actual = hex::encode(hasher.finalize());
+        if !actual.eq_ignore_ascii_case(&file.sha256) {
+            return Err(format!(
+                "file {} has sha256 {}, expected {}",
+                file.path, actual, file.sha256
+            ));
+        }
+        Ok(())
+    }
 }
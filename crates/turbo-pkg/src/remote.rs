@@ -0,0 +1,444 @@
+use crate::local::LocalPackage;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use reqwest::Url;
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+/// Signed list of the packages a registry serves, fetched from `<registry>/index.json`.
+///
+/// `<registry>/index.json.sig` holds the hex-encoded Ed25519 signature of the exact
+/// bytes of `index.json`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegistryIndex {
+    pub packages: Vec<RegistryEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegistryEntry {
+    pub name: String,
+    pub version: String,
+    /// Tarball of the package directory, absolute or relative to the registry URL
+    pub url: String,
+    /// Hex-encoded SHA-256 of the tarball
+    pub sha256: String,
+}
+
+/// Package repository served over HTTPS instead of a local `packages/` directory.
+///
+/// Tarballs are cached under `cache_dir` by name and version and reused while their
+/// checksum still matches the index. The last verified index is cached as well and
+/// used when the registry cannot be reached.
+pub struct RemoteRepository {
+    base: Url,
+    key: VerifyingKey,
+    cache_dir: PathBuf,
+    client: reqwest::Client,
+}
+
+impl RemoteRepository {
+    /// `public_key` is the hex-encoded Ed25519 key the index must be signed with.
+    pub fn new(url: &str, public_key: &str, cache_dir: PathBuf) -> anyhow::Result<Self> {
+        // Without the trailing slash, relative tarball URLs would replace the last segment
+        let base = if url.ends_with('/') {
+            Url::parse(url)?
+        } else {
+            Url::parse(&format!("{}/", url))?
+        };
+        check_scheme(&base)?;
+
+        let key: [u8; 32] = hex::decode(public_key.trim())?
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Registry public key must be 32 bytes"))?;
+        let key = VerifyingKey::from_bytes(&key)
+            .map_err(|e| anyhow::anyhow!("Invalid registry public key: {}", e))?;
+
+        Ok(Self {
+            base,
+            key,
+            cache_dir,
+            client: reqwest::Client::new(),
+        })
+    }
+
+    /// Download `name` (latest version if `version` is none) and unpack it.
+    pub async fn resolve(&self, name: &str, version: Option<&str>) -> anyhow::Result<LocalPackage> {
+        let index = self.index().await?;
+        let entry = match version {
+            Some(version) => index
+                .packages
+                .iter()
+                .find(|e| e.name == name && e.version == version)
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "Version '{}' of package '{}' not found in registry",
+                        version,
+                        name
+                    )
+                })?,
+            None => index
+                .packages
+                .iter()
+                .filter(|e| e.name == name)
+                .filter_map(|e| Version::parse(&e.version).ok().map(|v| (v, e)))
+                .max_by(|a, b| a.0.cmp(&b.0))
+                .map(|(_, e)| e)
+                .ok_or_else(|| anyhow::anyhow!("Package '{}' not found in registry", name))?,
+        };
+
+        let tarball = self.fetch_tarball(entry).await?;
+        LocalPackage::open(&tarball).await
+    }
+
+    /// Every `(name, version)` in the index, sorted by name and then version.
+    pub async fn list_all(&self) -> anyhow::Result<Vec<(String, String)>> {
+        let index = self.index().await?;
+        let mut packages: Vec<_> = index
+            .packages
+            .into_iter()
+            .filter(|e| Version::parse(&e.version).is_ok())
+            .map(|e| (e.name, e.version))
+            .collect();
+        packages.sort_by(|a, b| {
+            let ver = |v: &str| Version::parse(v).unwrap_or_else(|_| Version::new(0, 0, 0));
+            a.0.cmp(&b.0).then_with(|| ver(&a.1).cmp(&ver(&b.1)))
+        });
+        Ok(packages)
+    }
+
+    /// Fetch and verify the index, falling back to the last verified copy on failure.
+    async fn index(&self) -> anyhow::Result<RegistryIndex> {
+        let index_path = self.cache_dir.join("index.json");
+        let sig_path = self.cache_dir.join("index.json.sig");
+
+        let (body, sig) = match self.fetch_index().await {
+            Ok(fetched) => fetched,
+            Err(e) => {
+                let body = tokio::fs::read(&index_path).await;
+                let sig = tokio::fs::read(&sig_path).await;
+                let (Ok(body), Ok(sig)) = (body, sig) else {
+                    return Err(e);
+                };
+                tracing::warn!("Registry unreachable ({}), using the cached index", e);
+                (body, sig)
+            }
+        };
+
+        // Re-verified even when cached, so a tampered cache is never trusted
+        let sig = hex::decode(String::from_utf8_lossy(&sig).trim())?;
+        let sig = Signature::from_slice(&sig)
+            .map_err(|e| anyhow::anyhow!("Malformed registry index signature: {}", e))?;
+        self.key
+            .verify(&body, &sig)
+            .map_err(|_| anyhow::anyhow!("Registry index signature does not match"))?;
+        let index: RegistryIndex = serde_json::from_slice(&body)?;
+
+        tokio::fs::create_dir_all(&self.cache_dir).await?;
+        tokio::fs::write(&index_path, &body).await?;
+        tokio::fs::write(&sig_path, hex::encode(sig.to_bytes())).await?;
+        Ok(index)
+    }
+
+    async fn fetch_index(&self) -> anyhow::Result<(Vec<u8>, Vec<u8>)> {
+        let body = self.get(self.base.join("index.json")?).await?;
+        let sig = self.get(self.base.join("index.json.sig")?).await?;
+        Ok((body, sig))
+    }
+
+    /// Path of the entry's tarball in the cache, downloading it if missing or stale.
+    async fn fetch_tarball(&self, entry: &RegistryEntry) -> anyhow::Result<PathBuf> {
+        check_file_names(&entry.name, &entry.version)?;
+
+        let path = self
+            .cache_dir
+            .join("packages")
+            .join(format!("{}-{}.tar.gz", entry.name, entry.version));
+        if let Ok(cached) = tokio::fs::read(&path).await {
+            if sha256_hex(&cached).eq_ignore_ascii_case(&entry.sha256) {
+                return Ok(path);
+            }
+            tracing::warn!(
+                "Cached {:?} does not match the registry index, refetching",
+                path
+            );
+        }
+
+        let url = self.base.join(&entry.url)?;
+        check_scheme(&url)?;
+        tracing::info!("Downloading {}@{} from {}", entry.name, entry.version, url);
+        let bytes = self.get(url).await?;
+        let actual = sha256_hex(&bytes);
+        if !actual.eq_ignore_ascii_case(&entry.sha256) {
+            return Err(anyhow::anyhow!(
+                "Checksum mismatch for {}@{}: expected {}, got {}",
+                entry.name,
+                entry.version,
+                entry.sha256,
+                actual
+            ));
+        }
+
+        let dir = self.cache_dir.join("packages");
+        tokio::fs::create_dir_all(&dir).await?;
+        // Write then rename, so an interrupted download never looks cached
+        let partial = path.with_extension("part");
+        tokio::fs::write(&partial, &bytes).await?;
+        tokio::fs::rename(&partial, &path).await?;
+        Ok(path)
+    }
+
+    async fn get(&self, url: Url) -> anyhow::Result<Vec<u8>> {
+        let response = self.client.get(url.clone()).send().await?;
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!("GET {} failed: {}", url, response.status()));
+        }
+        Ok(response.bytes().await?.to_vec())
+    }
+}
+
+/// Add a packed package to the registry directory `registry_dir` and re-sign its index.
+///
+/// The archive is copied to `packages/<name>-<version>.tpkg` and listed in `index.json`;
+/// serving the directory over HTTPS makes it a registry. `signing_key` is the hex-encoded
+/// Ed25519 secret key matching the public key clients are configured with. Publishing a
+/// version that is already listed is refused.
+pub async fn publish(
+    registry_dir: &Path,
+    archive: &Path,
+    signing_key: &str,
+) -> anyhow::Result<RegistryEntry> {
+    let secret: [u8; 32] = hex::decode(signing_key.trim())?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Registry signing key must be 32 bytes"))?;
+    let key = SigningKey::from_bytes(&secret);
+
+    let package = LocalPackage::open(archive).await?;
+    let problems = package.def.validate();
+    if !problems.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Invalid package in {:?}: {}",
+            archive,
+            problems.join("; ")
+        ));
+    }
+    let (name, version) = (&package.def.yaml.name, &package.def.yaml.version);
+    check_file_names(name, version)?;
+
+    let index_path = registry_dir.join("index.json");
+    let mut index = match tokio::fs::read(&index_path).await {
+        Ok(body) => serde_json::from_slice(&body)?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => RegistryIndex {
+            packages: Vec::new(),
+        },
+        Err(e) => return Err(e.into()),
+    };
+    if index
+        .packages
+        .iter()
+        .any(|e| &e.name == name && &e.version == version)
+    {
+        return Err(anyhow::anyhow!("{}@{} is already published", name, version));
+    }
+
+    let bytes = tokio::fs::read(archive).await?;
+    let url = format!(
+        "packages/{}-{}.{}",
+        name,
+        version,
+        crate::local::TPKG_EXTENSION
+    );
+    tokio::fs::create_dir_all(registry_dir.join("packages")).await?;
+    tokio::fs::write(registry_dir.join(&url), &bytes).await?;
+
+    let entry = RegistryEntry {
+        name: name.clone(),
+        version: version.clone(),
+        url,
+        sha256: sha256_hex(&bytes),
+    };
+    index.packages.push(entry.clone());
+
+    let body = serde_json::to_vec_pretty(&index)?;
+    let sig = key.sign(&body);
+    tokio::fs::write(&index_path, &body).await?;
+    tokio::fs::write(
+        registry_dir.join("index.json.sig"),
+        hex::encode(sig.to_bytes()),
+    )
+    .await?;
+    Ok(entry)
+}
+
+/// Registries must use HTTPS; plain HTTP is only allowed to the local host, for testing.
+fn check_scheme(url: &Url) -> anyhow::Result<()> {
+    let local = matches!(url.host_str(), Some("localhost" | "127.0.0.1" | "[::1]"));
+    match url.scheme() {
+        "https" => Ok(()),
+        "http" if local => Ok(()),
+        _ => Err(anyhow::anyhow!("Registry URL {} must use https", url)),
+    }
+}
+
+/// Names and versions become cache and registry file names, so keep them plain.
+fn check_file_names(name: &str, version: &str) -> anyhow::Result<()> {
+    for part in [name, version] {
+        let plain = part
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_' | '+'));
+        if part.is_empty() || part.starts_with('.') || !plain {
+            return Err(anyhow::anyhow!(
+                "Invalid package name or version {:?}",
+                part
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    const SECRET: [u8; 32] = [7; 32];
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("turbo-remote-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn public_key() -> String {
+        hex::encode(SigningKey::from_bytes(&SECRET).verifying_key().to_bytes())
+    }
+
+    /// `index` as served: its body and the hex signature of it.
+    fn signed(index: &RegistryIndex) -> (Vec<u8>, Vec<u8>) {
+        let body = serde_json::to_vec(index).unwrap();
+        let sig = SigningKey::from_bytes(&SECRET).sign(&body);
+        (body, hex::encode(sig.to_bytes()).into_bytes())
+    }
+
+    fn index(sha256: &str) -> RegistryIndex {
+        RegistryIndex {
+            packages: vec![RegistryEntry {
+                name: "python".to_string(),
+                version: "3.12.0".to_string(),
+                url: "packages/python-3.12.0.tpkg".to_string(),
+                sha256: sha256.to_string(),
+            }],
+        }
+    }
+
+    /// Serves `files` by path over plain HTTP on localhost; returns the base URL.
+    async fn serve(files: HashMap<&'static str, Vec<u8>>) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = vec![0; 4096];
+                let n = stream.read(&mut request).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&request[..n]);
+                let path = request.split_whitespace().nth(1).unwrap_or("/");
+                let (status, body) = match files.get(path.trim_start_matches('/')) {
+                    Some(body) => ("200 OK", body.clone()),
+                    None => ("404 Not Found", Vec::new()),
+                };
+                let head = format!(
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    status,
+                    body.len()
+                );
+                let _ = stream.write_all(head.as_bytes()).await;
+                let _ = stream.write_all(&body).await;
+            }
+        });
+        format!("http://127.0.0.1:{}", addr.port())
+    }
+
+    /// A registry nothing listens on, so only the cached index is there to use.
+    const UNREACHABLE: &str = "http://127.0.0.1:1";
+
+    #[tokio::test]
+    async fn a_signed_index_is_listed_and_cached() {
+        let (body, sig) = signed(&index(&"0".repeat(64)));
+        let files = HashMap::from([("index.json", body.clone()), ("index.json.sig", sig)]);
+        let cache = scratch("signed");
+        let registry = RemoteRepository::new(&serve(files).await, &public_key(), cache.clone());
+        let listed = registry.unwrap().list_all().await.unwrap();
+        assert_eq!(listed, [("python".to_string(), "3.12.0".to_string())]);
+        assert_eq!(std::fs::read(cache.join("index.json")).unwrap(), body);
+
+        // The verified copy is used while the registry is down
+        let offline = RemoteRepository::new(UNREACHABLE, &public_key(), cache.clone()).unwrap();
+        assert_eq!(offline.list_all().await.unwrap(), listed);
+        let _ = std::fs::remove_dir_all(&cache);
+    }
+
+    #[tokio::test]
+    async fn a_tampered_index_is_refused() {
+        let (mut body, sig) = signed(&index(&"0".repeat(64)));
+        body.extend_from_slice(b" ");
+        let files = HashMap::from([("index.json", body), ("index.json.sig", sig)]);
+        let cache = scratch("tampered");
+        let registry = RemoteRepository::new(&serve(files).await, &public_key(), cache.clone());
+        let error = registry.unwrap().list_all().await.unwrap_err();
+        assert_eq!(error.to_string(), "Registry index signature does not match");
+        assert!(!cache.join("index.json").exists());
+    }
+
+    #[tokio::test]
+    async fn a_tampered_cached_index_is_refused() {
+        let (body, sig) = signed(&index(&"0".repeat(64)));
+        let cache = scratch("tampered-cache");
+        std::fs::create_dir_all(&cache).unwrap();
+        let tampered = String::from_utf8(body).unwrap().replace("3.12.0", "3.13.0");
+        std::fs::write(cache.join("index.json"), tampered).unwrap();
+        std::fs::write(cache.join("index.json.sig"), sig).unwrap();
+
+        let offline = RemoteRepository::new(UNREACHABLE, &public_key(), cache.clone()).unwrap();
+        let error = offline.list_all().await.unwrap_err();
+        assert_eq!(error.to_string(), "Registry index signature does not match");
+        let _ = std::fs::remove_dir_all(&cache);
+    }
+
+    #[tokio::test]
+    async fn a_tarball_with_the_wrong_hash_is_refused() {
+        let tarball = b"not the published tarball".to_vec();
+        let (body, sig) = signed(&index(&sha256_hex(b"the published tarball")));
+        let files = HashMap::from([
+            ("index.json", body),
+            ("index.json.sig", sig),
+            ("packages/python-3.12.0.tpkg", tarball.clone()),
+        ]);
+        let cache = scratch("hash");
+        let registry = RemoteRepository::new(&serve(files).await, &public_key(), cache.clone());
+        let Err(error) = registry.unwrap().resolve("python", None).await else {
+            panic!("a tarball with the wrong hash was unpacked");
+        };
+        assert!(
+            error
+                .to_string()
+                .starts_with("Checksum mismatch for python@3.12.0"),
+            "{}",
+            error
+        );
+        assert!(!cache.join("packages").join("python-3.12.0.tar.gz").exists());
+        let _ = std::fs::remove_dir_all(&cache);
+    }
+
+    #[test]
+    fn registries_need_https_except_on_localhost() {
+        let key = public_key();
+        assert!(RemoteRepository::new("http://example.com", &key, scratch("scheme")).is_err());
+        assert!(RemoteRepository::new("https://example.com", &key, scratch("scheme")).is_ok());
+        assert!(RemoteRepository::new("http://localhost:8080", &key, scratch("scheme")).is_ok());
+    }
+}
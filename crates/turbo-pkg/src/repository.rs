@@ -11,12 +11,22 @@ impl PackageRepository {
         Self { root }
     }
 
+    /// Directory of one version of a package.
+    pub fn path(&self, name: &str, version: &str) -> PathBuf {
+        self.root.join(name).join(version)
+    }
+
     pub async fn resolve(
         &self,
         name: &str,
         version: Option<&str>,
     ) -> anyhow::Result<PackageDefinition> {
-        let pkg_dir = self.root.join(name);
+        let mut pkg_dir = self.root.join(name);
+        if !pkg_dir.exists() {
+            if let Some(canonical) = find_alias(&self.root, name) {
+                pkg_dir = self.root.join(canonical);
+            }
+        }
         if !pkg_dir.exists() {
             return Err(anyhow::anyhow!(
                 "Package '{}' not found in repository at {:?}",
@@ -113,3 +123,30 @@ impl PackageRepository {
         Ok(packages)
     }
 }
+
+/// Name of the package under `root` (laid out `<name>/<version>/package.yaml`, like both
+/// the repository and the runtimes directory) that declares `alias` in its `aliases`.
+///
+/// Returns `None` if no package does, or if `alias` is already a package name there.
+pub fn find_alias(root: &Path, alias: &str) -> Option<String> {
+    if root.join(alias).is_dir() {
+        return None;
+    }
+    let mut names: Vec<_> = std::fs::read_dir(root).ok()?.flatten().collect();
+    // Deterministic when two packages claim the same alias
+    names.sort_by_key(|entry| entry.file_name());
+    for entry in names {
+        let Ok(versions) = std::fs::read_dir(entry.path()) else {
+            continue;
+        };
+        for version in versions.flatten() {
+            let Ok(def) = PackageDefinition::from_path(version.path()) else {
+                continue;
+            };
+            if def.yaml.aliases.iter().flatten().any(|a| a == alias) {
+                return Some(entry.file_name().to_string_lossy().to_string());
+            }
+        }
+    }
+    None
+}
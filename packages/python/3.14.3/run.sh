@@ -1,7 +1,10 @@
 #!/bin/bash
 DIR="$( cd "$( dirname "${BASH_SOURCE[0]}" )" >/dev/null 2>&1 && pwd )"
-if [ $# -eq 0 ]; then
+if [ -n "$TURBO_ENTRYPOINT" ]; then
+    "$DIR/bin/python3" "$TURBO_ENTRYPOINT" "$@"
+elif [ $# -eq 0 ]; then
     "$DIR/bin/python3" "main.py"
 else
+    # legacy_args: the arguments start with the file to run
     "$DIR/bin/python3" "$@"
 fi